@@ -0,0 +1,126 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::hierarchy::TypeDeclaration;
+use crate::imports::ResolvedClass;
+use crate::line_index;
+use crate::ranking::Proximity;
+use crate::Backend;
+
+impl Backend {
+    /// The declaration of the annotation type an annotation names, or of the element an argument
+    /// of one names, as in `@Retry(times = 3)`.
+    pub fn annotation_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let parent_node = name_node.parent()?;
+        if parent_node.kind() == "element_value_pair" && parent_node.child_by_field_name("key") == Some(name_node) {
+            let annotation_node = parent_node.parent()?.parent()?;
+            let (declaring_uri, declaration) = self.annotation_type(uri, annotation_node, source)?;
+            let element_name = name_node.utf8_text(source.as_bytes()).unwrap();
+            return self.type_member_location(&declaring_uri, &declaration.name, element_name, Some(&[]));
+        }
+        // the name is the last segment of a qualified one like `@javax.annotation.Nonnull`
+        let mut annotation_node = parent_node;
+        if annotation_node.kind() == "scoped_identifier" {
+            if annotation_node.child_by_field_name("name") != Some(name_node) {
+                return None;
+            }
+            annotation_node = annotation_node.parent()?;
+        }
+        if !is_annotation(annotation_node) {
+            return None;
+        }
+        let (declaring_uri, declaration) = self.annotation_type(uri, annotation_node, source)?;
+        return self.type_location(&declaring_uri, &declaration.name);
+    }
+
+    /// The elements of the annotation whose arguments the cursor is in, when it's where the name
+    /// of one goes, with their types and defaults. Elements already given are left out.
+    pub fn annotation_element_items(&self, uri: &str, tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+        let line = source.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character);
+        let before_name = typed
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+            .trim_end();
+        if !before_name.ends_with(['(', ',']) {
+            return Vec::new();
+        }
+        let delimiter_point = Point {
+            row: position.line as usize,
+            column: before_name.len() - 1,
+        };
+        let mut arguments_node = match tree.root_node().descendant_for_point_range(delimiter_point, delimiter_point) {
+            Some(delimiter_node) => delimiter_node,
+            None => return Vec::new(),
+        };
+        // a trailing comma leaves an error node in the arguments
+        while arguments_node.kind() != "annotation_argument_list" {
+            arguments_node = match arguments_node.parent() {
+                Some(parent_node) if arguments_node.kind() == "(" || arguments_node.kind() == "," || arguments_node.kind() == "ERROR" => parent_node,
+                _ => return Vec::new(),
+            };
+        }
+        let (declaring_uri, declaration) = match arguments_node.parent().and_then(|n| self.annotation_type(uri, n, source)) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let given: Vec<&str> = arguments_node
+            .named_children(&mut arguments_node.walk())
+            .filter(|n| n.kind() == "element_value_pair")
+            .filter_map(|n| n.child_by_field_name("key"))
+            .map(|n| n.utf8_text(source.as_bytes()).unwrap())
+            .collect();
+        let defaults = self.element_defaults(&declaring_uri, &declaration.name);
+        let mut items = Vec::new();
+        for element in &declaration.methods {
+            if element.modifiers.is_static || given.contains(&element.name.as_str()) {
+                continue;
+            }
+            let mut detail = format!("{} {}()", element.return_type, element.name);
+            if let Some((_, default)) = defaults.iter().find(|(name, _)| *name == element.name) {
+                detail = format!("{} default {}", detail, default);
+            }
+            items.push(CompletionItem {
+                label: element.name.clone(),
+                kind: Some(CompletionItemKind::PROPERTY),
+                detail: Some(detail),
+                insert_text: Some(format!("{} = ", element.name)),
+                sort_text: Proximity::Member.key(),
+                ..CompletionItem::default()
+            });
+        }
+        return items;
+    }
+
+    /// Whether `class` is an annotation type.
+    pub fn is_annotation_type(&self, class: &ResolvedClass) -> bool {
+        return match class {
+            ResolvedClass::Source(source_uri, class_name) => {
+                let simple_name = class_name.rsplit('.').next().unwrap_or(class_name);
+                self.type_declaration_map
+                    .get(source_uri)
+                    .map(|declarations| {
+                        declarations
+                            .iter()
+                            .any(|declaration| declaration.name == simple_name && declaration.kind == "annotation_type_declaration")
+                    })
+                    .unwrap_or(false)
+            }
+            ResolvedClass::Dependency(class) => class.declaration.kind == "annotation_type_declaration",
+        };
+    }
+
+    /// The annotation type `annotation_node` names, with the URI of the document declaring it.
+    fn annotation_type(&self, uri: &str, annotation_node: Node, source: &str) -> Option<(String, TypeDeclaration)> {
+        if !is_annotation(annotation_node) {
+            return None;
+        }
+        let written = annotation_node.child_by_field_name("name")?.utf8_text(source.as_bytes()).unwrap();
+        return self
+            .locate_type(uri, written)
+            .filter(|(_, declaration)| declaration.kind == "annotation_type_declaration");
+    }
+}
+
+fn is_annotation(node: Node) -> bool {
+    return node.kind() == "annotation" || node.kind() == "marker_annotation";
+}
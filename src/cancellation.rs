@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use crate::progress::WorkDone;
+
+/// How long a request runs between giving way at its checkpoints.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Lets a long-running request be cancelled. The server reads messages on the same task it
+/// handles requests on, so it only learns of a `$/cancelRequest`, and newer requests only get
+/// their turn, when the running request gives way. Requests do at checkpoints on the boundaries
+/// of their traversals, every few milliseconds; a cancelled one is dropped at its next
+/// checkpoint and answered with `RequestCancelled` rather than run to the end.
+///
+/// No lock or borrow of the stores may be held across a checkpoint, since a change handled
+/// meanwhile may write to them.
+///
+/// A request whose progress is shown can also be cancelled by the user through it, in which
+/// case it stops early with what it found so far.
+#[derive(Debug)]
+pub struct Cancellation {
+    last_checkpoint: Instant,
+    progress: Option<WorkDone>,
+}
+
+impl Default for Cancellation {
+    fn default() -> Cancellation {
+        return Cancellation::with_progress(None);
+    }
+}
+
+impl Cancellation {
+    pub fn with_progress(progress: Option<WorkDone>) -> Cancellation {
+        return Cancellation {
+            last_checkpoint: Instant::now(),
+            progress,
+        };
+    }
+
+    pub fn progress(&self) -> Option<&WorkDone> {
+        return self.progress.as_ref();
+    }
+
+    /// Whether the user cancelled the request through its progress.
+    pub fn is_cancelled(&self) -> bool {
+        return self.progress.as_ref().map(WorkDone::is_cancelled).unwrap_or(false);
+    }
+
+    /// Gives way, when the request has run for a while since it last did, letting the server
+    /// drop it there if it's been cancelled.
+    pub async fn checkpoint(&mut self) {
+        if self.last_checkpoint.elapsed() < CHECKPOINT_INTERVAL {
+            return;
+        }
+        tokio::task::yield_now().await;
+        self.last_checkpoint = Instant::now();
+    }
+}
@@ -0,0 +1,378 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::debug;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Location, TextDocumentIdentifier, Url};
+use tree_sitter::{Node, Parser};
+
+use crate::class_file::{self, ClassFile, Member};
+use crate::classpath;
+use crate::dependency_index::DependencyClass;
+use crate::jar::Jar;
+use crate::line_index::LineIndex;
+use crate::literals;
+use crate::Backend;
+
+impl Backend {
+    /// Handles `javals/classFileContents`, which returns the text behind a `jar:` URI that goto
+    /// definition led to: a source file of a sources jar, or for a class file, its declarations
+    /// as Java without the code. `None` when the jar or the entry doesn't exist.
+    pub async fn class_file_contents(&self, params: TextDocumentIdentifier) -> Result<Option<String>> {
+        debug!("class_file_contents {}", params.uri);
+        let (jar_path, entry_name) = match split_jar_uri(params.uri.as_str()) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let contents = tokio::task::spawn_blocking(move || entry_contents(&jar_path, &entry_name)).await.ok().flatten();
+        return Ok(contents);
+    }
+
+    /// Where the dependency declaring the type or member named by `name_node` declares it, in its
+    /// sources jar or else in the declarations of its class file. Types are looked up by name,
+    /// fields and methods on the type of their receiver and its supertypes.
+    pub fn dependency_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let token = name_node.utf8_text(source.as_bytes()).unwrap();
+        let parent = name_node.parent()?;
+        if name_node.kind() == "type_identifier" {
+            let class = match parent.kind() {
+                "scoped_type_identifier" => {
+                    // up to the name, so the `Map` of `Map.Entry` leads to `Map`
+                    let scoped_name = &source[parent.start_byte()..name_node.end_byte()];
+                    self.dependency_class(uri, scoped_name).or_else(|| self.dependency_class(uri, token))
+                }
+                _ => self.dependency_class(uri, token),
+            }?;
+            return dependency_location(&class, None);
+        }
+        let is_field = parent.kind() == "field_access" && parent.child_by_field_name("field") == Some(name_node);
+        let is_method = parent.kind() == "method_invocation" && parent.child_by_field_name("name") == Some(name_node);
+        let receiver_type = if is_method {
+            self.invocation_receiver_type(uri, parent, source)?
+        } else if is_field {
+            self.expression_type(uri, parent.child_by_field_name("object")?, source)?
+        } else {
+            return None;
+        };
+        let class = self.member_owner(uri, &receiver_type, token, is_method)?;
+        return dependency_location(&class, Some(token));
+    }
+
+    /// The dependency class declaring the field or method `member_name`, found by walking up from
+    /// `type_name`, which may be declared in the workspace.
+    fn member_owner(&self, uri: &str, type_name: &str, member_name: &str, is_method: bool) -> Option<Arc<DependencyClass>> {
+        let mut pending = vec![type_name.to_string()];
+        let mut visited: Vec<String> = Vec::new();
+        while let Some(type_name) = pending.pop() {
+            if visited.contains(&type_name) {
+                continue;
+            }
+            visited.push(type_name.clone());
+            let (declaring_uri, declaration) = match self.locate_type(uri, &type_name) {
+                Some(found) => found,
+                None => continue,
+            };
+            let declares = if is_method {
+                declaration.methods.iter().any(|method| method.name == member_name)
+            } else {
+                declaration.fields.iter().any(|field| field.name == member_name)
+            };
+            if declares {
+                if !declaring_uri.starts_with("jar:") {
+                    return None;
+                }
+                return self.dependency_class(uri, &type_name);
+            }
+            pending.extend(declaration.supertypes.iter().rev().cloned());
+        }
+        return None;
+    }
+}
+
+/// The location of `class`, or of its member `member_name`, in the text served for it.
+pub fn dependency_location(class: &DependencyClass, member_name: Option<&str>) -> Option<Location> {
+    let (uri, text) = class_text(class)?;
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_java::language()).ok()?;
+    let tree = parser.parse(literals::parseable_text(&text).as_ref(), None)?;
+    let class_name = class_file::qualified_name(&class.binary_name);
+    let type_name_node = classpath::declared_class_names(&tree, &text)
+        .into_iter()
+        .find(|(declared_name, _)| *declared_name == class_name)
+        .map(|(_, name_node)| name_node);
+    let name_node = match (type_name_node, member_name) {
+        (Some(type_name_node), Some(member_name)) => Some(
+            type_name_node
+                .parent()
+                .and_then(|declaration| declaration.child_by_field_name("body"))
+                .and_then(|body| member_name_node(body, member_name, &text))
+                .unwrap_or(type_name_node),
+        ),
+        (type_name_node, _) => type_name_node,
+    };
+    let range = name_node
+        .map(|name_node| LineIndex::new(&text).range(name_node.start_position(), name_node.end_position()))
+        .unwrap_or_default();
+    return Some(Location {
+        uri: Url::parse(&uri).ok()?,
+        range,
+    });
+}
+
+/// The name node of the first field, enum constant or method named `member_name` in a type body.
+pub fn member_name_node<'a>(body_node: Node<'a>, member_name: &str, source: &str) -> Option<Node<'a>> {
+    for child in body_node.named_children(&mut body_node.walk()) {
+        let name_node = match child.kind() {
+            "method_declaration" | "enum_constant" => child.child_by_field_name("name"),
+            "field_declaration" | "constant_declaration" => child
+                .children_by_field_name("declarator", &mut child.walk())
+                .find_map(|declarator| declarator.child_by_field_name("name").filter(|name| name.utf8_text(source.as_bytes()).unwrap() == member_name)),
+            "enum_body_declarations" => member_name_node(child, member_name, source),
+            _ => None,
+        };
+        if let Some(name_node) = name_node.filter(|name| name.utf8_text(source.as_bytes()).unwrap() == member_name) {
+            return Some(name_node);
+        }
+    }
+    return None;
+}
+
+/// The URI and text to show for `class`: its source file from the archive it was read from or
+/// the sources jar next to its jar, or the declarations of its top-level class when there's none.
+fn class_text(class: &DependencyClass) -> Option<(String, String)> {
+    if let Some(source_entry) = &class.source_entry {
+        return Some((class.uri(), entry_contents(&class.jar, source_entry)?));
+    }
+    let outer_name = class.binary_name.split('$').next().unwrap_or(&class.binary_name);
+    let package_directory = outer_name.rsplit_once('/').map(|(directory, _)| format!("{}/", directory)).unwrap_or_default();
+    let source_file = class.source_file.clone().unwrap_or_else(|| format!("{}.java", class_file::simple_name(outer_name)));
+    let source_entry = format!("{}{}", package_directory, source_file);
+    if let Some(sources_jar) = sources_jar(&class.jar) {
+        if let Some(text) = entry_contents(&sources_jar, &source_entry) {
+            return Some((jar_uri(&sources_jar, &source_entry), text));
+        }
+    }
+    let class_entry = format!("{}.class", outer_name);
+    let text = entry_contents(&class.jar, &class_entry)?;
+    return Some((jar_uri(&class.jar, &class_entry), text));
+}
+
+/// The sources jar published next to `jar`: beside it in a Maven repository, or in a sibling
+/// directory in the Gradle cache, which keeps each file in a directory named after its hash.
+fn sources_jar(jar: &Path) -> Option<PathBuf> {
+    let file_name = format!("{}-sources.jar", jar.file_stem()?.to_string_lossy());
+    let directory = jar.parent()?;
+    let sibling = directory.join(&file_name);
+    if sibling.is_file() {
+        return Some(sibling);
+    }
+    let version_directory = directory.parent()?;
+    for entry in fs::read_dir(version_directory).ok()?.flatten() {
+        let candidate = entry.path().join(&file_name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+    return None;
+}
+
+/// Like `jar:file:///lib/a.jar!/p/A.class`.
+pub fn jar_uri(jar: &Path, entry_name: &str) -> String {
+    let jar_uri = Url::from_file_path(jar).map(|uri| uri.to_string()).unwrap_or_default();
+    return format!("jar:{}!/{}", jar_uri, entry_name);
+}
+
+/// The jar and the entry in it a `jar:` URI names.
+pub fn split_jar_uri(uri: &str) -> Option<(PathBuf, String)> {
+    let (jar_uri, entry_name) = uri.strip_prefix("jar:")?.split_once("!/")?;
+    let jar_path = Url::parse(jar_uri).ok()?.to_file_path().ok()?;
+    return Some((jar_path, entry_name.to_string()));
+}
+
+/// The text of a source entry, or the declarations of a top-level class file entry and of the
+/// classes nested in it.
+pub fn entry_contents(jar_path: &Path, entry_name: &str) -> Option<String> {
+    let jar = Jar::open(jar_path).ok()?;
+    let binary_name = match entry_name.strip_suffix(".class") {
+        Some(binary_name) => binary_name,
+        None => {
+            let entry = jar.entries.iter().find(|entry| entry.name == entry_name)?;
+            return Some(String::from_utf8_lossy(&jar.read(entry)?).to_string());
+        }
+    };
+    let class_file = read_class(&jar, binary_name)?;
+    let mut text = format!(
+        "// Declarations of {} from {}, which comes without sources.\n",
+        entry_name,
+        jar_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+    );
+    if let Some((package, _)) = binary_name.rsplit_once('/') {
+        text.push_str(&format!("package {};\n\n", package.replace('/', ".")));
+    }
+    write_class(&jar, &class_file, 0, &mut text);
+    return Some(text);
+}
+
+fn read_class(jar: &Jar, binary_name: &str) -> Option<ClassFile> {
+    let entry_name = format!("{}.class", binary_name);
+    let entry = jar.entries.iter().find(|entry| entry.name == entry_name)?;
+    return class_file::parse(&jar.read(entry)?);
+}
+
+/// Writes a class's declaration with its fields, methods and member classes, every method body
+/// replaced by a comment.
+fn write_class(jar: &Jar, class_file: &ClassFile, depth: usize, text: &mut String) {
+    let indent = "    ".repeat(depth);
+    let access_flags = class_file.inner_access_flags.unwrap_or(class_file.access_flags);
+    let is_annotation = access_flags & class_file::ACC_ANNOTATION != 0;
+    let is_interface = access_flags & class_file::ACC_INTERFACE != 0;
+    let is_enum = access_flags & class_file::ACC_ENUM != 0;
+    let simple_name = class_file::simple_name(&class_file.name);
+    let keyword = if is_annotation {
+        "@interface"
+    } else if is_interface {
+        "interface"
+    } else if is_enum {
+        "enum"
+    } else {
+        "class"
+    };
+    // interfaces are abstract, enums final or abstract, by definition
+    let mut header_flags = access_flags & !class_file::ACC_SYNCHRONIZED;
+    if is_interface || is_enum {
+        header_flags &= !(class_file::ACC_ABSTRACT | class_file::ACC_FINAL);
+    }
+    let type_parameters = class_file.signature.as_deref().map(class_file::type_parameters).unwrap_or_default();
+    text.push_str(&format!("{}{}{} {}", indent, modifier_keywords(header_flags), keyword, simple_name));
+    if !type_parameters.is_empty() {
+        text.push_str(&format!("<{}>", type_parameters.join(", ")));
+    }
+    let superclass = class_file
+        .super_class
+        .as_deref()
+        .filter(|superclass| !is_interface && !matches!(*superclass, "java/lang/Object" | "java/lang/Enum"));
+    if let Some(superclass) = superclass {
+        text.push_str(&format!(" extends {}", class_file::qualified_name(superclass)));
+    }
+    let interfaces: Vec<String> = class_file
+        .interfaces
+        .iter()
+        .filter(|interface| !is_annotation || interface.as_str() != "java/lang/annotation/Annotation")
+        .map(|interface| class_file::qualified_name(interface))
+        .collect();
+    if !interfaces.is_empty() {
+        text.push_str(&format!(" {} {}", if is_interface { "extends" } else { "implements" }, interfaces.join(", ")));
+    }
+    text.push_str(" {\n");
+
+    let member_indent = "    ".repeat(depth + 1);
+    let is_shown = |member: &&Member| member.access_flags & (class_file::ACC_PRIVATE | class_file::ACC_SYNTHETIC) == 0;
+    let constants: Vec<&str> = class_file
+        .fields
+        .iter()
+        .filter(|field| is_enum && field.access_flags & class_file::ACC_ENUM != 0)
+        .map(|field| field.name.as_str())
+        .collect();
+    if is_enum {
+        text.push_str(&format!("{}{};\n", member_indent, constants.join(", ")));
+    }
+    for field in class_file.fields.iter().filter(is_shown).filter(|field| !constants.contains(&field.name.as_str())) {
+        let field_type = match class_file::descriptor_type(&field.descriptor) {
+            Some((field_type, _)) => field_type,
+            None => continue,
+        };
+        let field_flags = if is_interface { 0 } else { field.access_flags };
+        text.push_str(&format!("{}{}{} {};\n", member_indent, modifier_keywords(field_flags), field_type, field.name));
+    }
+    for method in class_file.methods.iter().filter(is_shown) {
+        if method.access_flags & class_file::ACC_BRIDGE != 0 || method.name == "<clinit>" {
+            continue;
+        }
+        if is_enum && ((method.name == "values" && method.descriptor.starts_with("()")) || method.name == "valueOf") {
+            continue;
+        }
+        let (mut parameter_types, return_type) = match class_file::method_types(&method.descriptor) {
+            Some(types) => types,
+            None => continue,
+        };
+        let is_constructor = method.name == "<init>";
+        if is_constructor {
+            // the name and ordinal of enum constants, and the enclosing instance of inner
+            // classes, are passed to constructors without being declared
+            let is_inner = class_file.inner_access_flags.map(|flags| flags & class_file::ACC_STATIC == 0).unwrap_or(false);
+            let hidden = if is_enum { 2 } else if is_inner && !is_interface { 1 } else { 0 };
+            parameter_types.drain(..hidden.min(parameter_types.len()));
+        }
+        let is_abstract = method.access_flags & (class_file::ACC_ABSTRACT | class_file::ACC_NATIVE) != 0;
+        let mut method_flags = method.access_flags;
+        if is_interface {
+            method_flags &= !(class_file::ACC_PUBLIC | class_file::ACC_ABSTRACT);
+        }
+        if is_enum && is_constructor {
+            method_flags &= !class_file::ACC_PRIVATE;
+        }
+        let mut line = format!("{}{}", member_indent, modifier_keywords(method_flags));
+        if is_interface && !is_abstract && method.access_flags & class_file::ACC_STATIC == 0 {
+            line.push_str("default ");
+        }
+        let method_type_parameters = method.signature.as_deref().map(class_file::type_parameters).unwrap_or_default();
+        if !method_type_parameters.is_empty() {
+            line.push_str(&format!("<{}> ", method_type_parameters.join(", ")));
+        }
+        if is_constructor {
+            line.push_str(simple_name);
+        } else {
+            line.push_str(&format!("{} {}", return_type, method.name));
+        }
+        let parameters: Vec<String> = parameter_types
+            .iter()
+            .enumerate()
+            .map(|(index, parameter_type)| format!("{} arg{}", parameter_type, index))
+            .collect();
+        line.push_str(&format!("({})", parameters.join(", ")));
+        line.push_str(if is_abstract { ";\n" } else { " { /* compiled code */ }\n" });
+        text.push_str(&line);
+    }
+
+    let nested_prefix = format!("{}$", class_file.name);
+    let mut nested_names: Vec<&str> = jar
+        .entries
+        .iter()
+        .filter_map(|entry| entry.name.strip_suffix(".class")?.strip_prefix(&nested_prefix))
+        .filter(|nested_name| !nested_name.contains('$') && !nested_name.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+    nested_names.sort();
+    for nested_name in nested_names {
+        let nested = match read_class(jar, &format!("{}{}", nested_prefix, nested_name)) {
+            Some(nested) => nested,
+            None => continue,
+        };
+        let nested_flags = nested.inner_access_flags.unwrap_or(nested.access_flags);
+        if nested_flags & (class_file::ACC_PRIVATE | class_file::ACC_SYNTHETIC) != 0 {
+            continue;
+        }
+        text.push('\n');
+        write_class(jar, &nested, depth + 1, text);
+    }
+    text.push_str(&format!("{}}}\n", indent));
+}
+
+/// The Java modifiers the access flags stand for, each followed by a space.
+fn modifier_keywords(access_flags: u16) -> String {
+    let keywords = [
+        (class_file::ACC_PUBLIC, "public"),
+        (class_file::ACC_PROTECTED, "protected"),
+        (class_file::ACC_PRIVATE, "private"),
+        (class_file::ACC_ABSTRACT, "abstract"),
+        (class_file::ACC_STATIC, "static"),
+        (class_file::ACC_FINAL, "final"),
+        (class_file::ACC_SYNCHRONIZED, "synchronized"),
+        (class_file::ACC_NATIVE, "native"),
+    ];
+    return keywords
+        .iter()
+        .filter(|(flag, _)| access_flags & flag != 0)
+        .map(|(_, keyword)| format!("{} ", keyword))
+        .collect();
+}
@@ -0,0 +1,505 @@
+/// Access flags of classes and their members, as the class file format defines them.
+pub const ACC_PUBLIC: u16 = 0x0001;
+pub const ACC_PRIVATE: u16 = 0x0002;
+pub const ACC_PROTECTED: u16 = 0x0004;
+pub const ACC_STATIC: u16 = 0x0008;
+pub const ACC_FINAL: u16 = 0x0010;
+pub const ACC_SYNCHRONIZED: u16 = 0x0020;
+pub const ACC_BRIDGE: u16 = 0x0040;
+pub const ACC_NATIVE: u16 = 0x0100;
+pub const ACC_INTERFACE: u16 = 0x0200;
+pub const ACC_ABSTRACT: u16 = 0x0400;
+pub const ACC_SYNTHETIC: u16 = 0x1000;
+pub const ACC_ANNOTATION: u16 = 0x2000;
+pub const ACC_ENUM: u16 = 0x4000;
+
+const MAGIC: u32 = 0xCAFE_BABE;
+
+#[derive(Debug, Clone)]
+enum Constant {
+    Utf8(String),
+    Class(u16), // index of the name
+    Other,
+}
+
+/// A field or method as a class file declares it.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub access_flags: u16,
+    pub name: String,
+    pub descriptor: String,
+    // the generic signature, when it has type variables or arguments
+    pub signature: Option<String>,
+}
+
+/// What a class file says about the class it defines. Names are binary names, like
+/// `java/util/Map$Entry`.
+#[derive(Debug, Clone)]
+pub struct ClassFile {
+    pub name: String,
+    pub access_flags: u16,
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+    pub fields: Vec<Member>,
+    pub methods: Vec<Member>,
+    pub signature: Option<String>,
+    // the name of the source file the class was compiled from, without its directory
+    pub source_file: Option<String>,
+    // the flags of the class as declared, when it's nested: the class's own flags only keep
+    // whether it's public
+    pub inner_access_flags: Option<u16>,
+    // the classes a sealed class or interface permits to extend or implement it
+    pub permitted_subclasses: Vec<String>,
+}
+
+/// Reads a class file, skipping the code and what else the index has no use for. `None` when it
+/// isn't one or it's damaged.
+pub fn parse(data: &[u8]) -> Option<ClassFile> {
+    let mut reader = Reader { data, offset: 0 };
+    if reader.u32()? != MAGIC {
+        return None;
+    }
+    reader.skip(4)?; // minor and major version
+    let constant_count = reader.u16()?;
+    let mut constants = vec![Constant::Other];
+    while constants.len() < constant_count as usize {
+        let tag = reader.u8()?;
+        let constant = match tag {
+            1 => {
+                let length = reader.u16()? as usize;
+                Constant::Utf8(modified_utf8(reader.bytes(length)?))
+            }
+            7 => Constant::Class(reader.u16()?),
+            8 | 16 | 19 | 20 => {
+                reader.skip(2)?;
+                Constant::Other
+            }
+            15 => {
+                reader.skip(3)?;
+                Constant::Other
+            }
+            3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => {
+                reader.skip(4)?;
+                Constant::Other
+            }
+            5 | 6 => {
+                // takes two entries
+                reader.skip(8)?;
+                constants.push(Constant::Other);
+                Constant::Other
+            }
+            _ => return None,
+        };
+        constants.push(constant);
+    }
+    let pool = ConstantPool { constants };
+    let access_flags = reader.u16()?;
+    let name = pool.class_name(reader.u16()?)?;
+    let super_class = pool.class_name(reader.u16()?);
+    let interface_count = reader.u16()?;
+    let mut interfaces = Vec::new();
+    for _ in 0..interface_count {
+        interfaces.push(pool.class_name(reader.u16()?)?);
+    }
+    let fields = members(&mut reader, &pool)?;
+    let methods = members(&mut reader, &pool)?;
+    let mut class_file = ClassFile {
+        name,
+        access_flags,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        signature: None,
+        source_file: None,
+        inner_access_flags: None,
+        permitted_subclasses: Vec::new(),
+    };
+    let attribute_count = reader.u16()?;
+    for _ in 0..attribute_count {
+        let attribute_name = pool.utf8(reader.u16()?)?;
+        let length = reader.u32()? as usize;
+        let mut attribute = Reader {
+            data: reader.bytes(length)?,
+            offset: 0,
+        };
+        match attribute_name.as_str() {
+            "Signature" => class_file.signature = pool.utf8(attribute.u16()?),
+            "SourceFile" => class_file.source_file = pool.utf8(attribute.u16()?),
+            "InnerClasses" => {
+                let class_count = attribute.u16()?;
+                for _ in 0..class_count {
+                    let inner_class = pool.class_name(attribute.u16()?);
+                    attribute.skip(4)?; // outer class and simple name
+                    let inner_access_flags = attribute.u16()?;
+                    if inner_class.as_deref() == Some(class_file.name.as_str()) {
+                        class_file.inner_access_flags = Some(inner_access_flags);
+                    }
+                }
+            }
+            "PermittedSubclasses" => {
+                let class_count = attribute.u16()?;
+                for _ in 0..class_count {
+                    class_file.permitted_subclasses.extend(pool.class_name(attribute.u16()?));
+                }
+            }
+            _ => {}
+        }
+    }
+    return Some(class_file);
+}
+
+fn members(reader: &mut Reader, pool: &ConstantPool) -> Option<Vec<Member>> {
+    let count = reader.u16()?;
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let mut member = Member {
+            access_flags: reader.u16()?,
+            name: pool.utf8(reader.u16()?)?,
+            descriptor: pool.utf8(reader.u16()?)?,
+            signature: None,
+        };
+        let attribute_count = reader.u16()?;
+        for _ in 0..attribute_count {
+            let attribute_name = pool.utf8(reader.u16()?)?;
+            let length = reader.u32()? as usize;
+            let attribute = reader.bytes(length)?;
+            if attribute_name == "Signature" && length == 2 {
+                member.signature = pool.utf8(u16::from_be_bytes([attribute[0], attribute[1]]));
+            }
+        }
+        members.push(member);
+    }
+    return Some(members);
+}
+
+struct ConstantPool {
+    constants: Vec<Constant>,
+}
+
+impl ConstantPool {
+    fn utf8(&self, index: u16) -> Option<String> {
+        return match self.constants.get(index as usize)? {
+            Constant::Utf8(text) => Some(text.clone()),
+            _ => None,
+        };
+    }
+
+    fn class_name(&self, index: u16) -> Option<String> {
+        return match self.constants.get(index as usize)? {
+            Constant::Class(name_index) => self.utf8(*name_index),
+            _ => None,
+        };
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        return Some(bytes);
+    }
+
+    fn skip(&mut self, length: usize) -> Option<()> {
+        self.bytes(length)?;
+        return Some(());
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        return Some(self.bytes(1)?[0]);
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        return Some(u16::from_be_bytes([bytes[0], bytes[1]]));
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        return Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    }
+}
+
+/// Decodes the modified UTF-8 of class files, which encodes characters outside the basic plane
+/// as surrogate pairs of three bytes each, and NUL in two bytes.
+fn modified_utf8(bytes: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index] as u16;
+        let continuation = |offset: usize| bytes.get(index + offset).map(|b| (*b & 0x3F) as u16).unwrap_or(0);
+        if byte < 0x80 {
+            units.push(byte);
+            index += 1;
+        } else if byte & 0xE0 == 0xC0 {
+            units.push(((byte & 0x1F) << 6) | continuation(1));
+            index += 2;
+        } else {
+            units.push(((byte & 0x0F) << 12) | (continuation(1) << 6) | continuation(2));
+            index += 3;
+        }
+    }
+    return String::from_utf16_lossy(&units);
+}
+
+/// The Java type a field descriptor, or the start of a method descriptor's rest, describes, by
+/// simple name, like `String[]`, and the length of the descriptor it took.
+pub fn descriptor_type(descriptor: &str) -> Option<(String, usize)> {
+    let primitive = match descriptor.as_bytes().first()? {
+        b'B' => "byte",
+        b'C' => "char",
+        b'D' => "double",
+        b'F' => "float",
+        b'I' => "int",
+        b'J' => "long",
+        b'S' => "short",
+        b'Z' => "boolean",
+        b'V' => "void",
+        b'L' => {
+            let end = descriptor.find(';')?;
+            return Some((simple_name(&descriptor[1..end]).to_string(), end + 1));
+        }
+        b'[' => {
+            let (element_type, length) = descriptor_type(&descriptor[1..])?;
+            return Some((format!("{}[]", element_type), length + 1));
+        }
+        _ => return None,
+    };
+    return Some((primitive.to_string(), 1));
+}
+
+/// The parameter types and return type a method descriptor, like `(ILjava/lang/String;)V`,
+/// describes.
+pub fn method_types(descriptor: &str) -> Option<(Vec<String>, String)> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut parameter_types = Vec::new();
+    while !rest.starts_with(')') {
+        let (parameter_type, length) = descriptor_type(rest)?;
+        parameter_types.push(parameter_type);
+        rest = &rest[length..];
+    }
+    let (return_type, _) = descriptor_type(&rest[1..])?;
+    return Some((parameter_types, return_type));
+}
+
+/// The names of the type parameters a class or method signature starts with, like `K` and `V`
+/// in `<K:Ljava/lang/Object;V:Ljava/lang/Object;>Ljava/lang/Object;`.
+pub fn type_parameters(signature: &str) -> Vec<String> {
+    let bytes = signature.as_bytes();
+    let mut names = Vec::new();
+    if bytes.first() != Some(&b'<') {
+        return names;
+    }
+    let mut index = 1;
+    while index < bytes.len() && bytes[index] != b'>' {
+        let name_end = match signature[index..].find(':') {
+            Some(name_end) => index + name_end,
+            None => break,
+        };
+        names.push(signature[index..name_end].to_string());
+        index = name_end;
+        // a class bound, possibly empty, and interface bounds, each after a colon
+        while index < bytes.len() && bytes[index] == b':' {
+            index += 1;
+            if index < bytes.len() && matches!(bytes[index], b'L' | b'T' | b'[') {
+                index = skip_type_signature(bytes, index);
+            }
+        }
+    }
+    return names;
+}
+
+/// The index just past the reference type signature at `index`.
+fn skip_type_signature(bytes: &[u8], mut index: usize) -> usize {
+    while index < bytes.len() && bytes[index] == b'[' {
+        index += 1;
+    }
+    if index < bytes.len() && !matches!(bytes[index], b'L' | b'T') {
+        return index + 1;
+    }
+    let mut depth = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'<' => depth += 1,
+            b'>' => depth -= 1,
+            b';' if depth == 0 => return index + 1,
+            _ => {}
+        }
+        index += 1;
+    }
+    return index;
+}
+
+/// The simple name of a binary name, `Entry` for `java/util/Map$Entry`.
+pub fn simple_name(binary_name: &str) -> &str {
+    let name = binary_name.rsplit('/').next().unwrap_or(binary_name);
+    return name.rsplit('$').next().unwrap_or(name);
+}
+
+/// The fully qualified name of a binary name, `java.util.Map.Entry` for `java/util/Map$Entry`.
+pub fn qualified_name(binary_name: &str) -> String {
+    return binary_name.replace(['/', '$'], ".");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The constant pool of a class file being written.
+    struct Pool {
+        bytes: Vec<u8>,
+        count: u16,
+    }
+
+    impl Pool {
+        fn utf8(&mut self, text: &str) -> u16 {
+            self.bytes.push(1);
+            self.bytes.extend((text.len() as u16).to_be_bytes());
+            self.bytes.extend(text.as_bytes());
+            self.count += 1;
+            return self.count - 1;
+        }
+
+        fn class(&mut self, name: &str) -> u16 {
+            let name_index = self.utf8(name);
+            self.bytes.push(7);
+            self.bytes.extend(name_index.to_be_bytes());
+            self.count += 1;
+            return self.count - 1;
+        }
+    }
+
+    /// A class file for `public class p.Box<T> extends Object implements Comparable<T>`, nested
+    /// as a `protected static` class, with a field and a method.
+    fn box_class() -> Vec<u8> {
+        let mut pool = Pool { bytes: Vec::new(), count: 1 };
+        // a long constant, which takes two entries, and an integer
+        pool.bytes.push(5);
+        pool.bytes.extend(1u64.to_be_bytes());
+        pool.bytes.push(3);
+        pool.bytes.extend(2u32.to_be_bytes());
+        pool.count += 3;
+        let this_class = pool.class("p/Outer$Box");
+        let super_class = pool.class("java/lang/Object");
+        let interface = pool.class("java/lang/Comparable");
+        let field_name = pool.utf8("value");
+        let field_descriptor = pool.utf8("Ljava/lang/Object;");
+        let field_signature = pool.utf8("TT;");
+        let method_name = pool.utf8("get");
+        let method_descriptor = pool.utf8("(I[J)Ljava/util/List;");
+        let signature = pool.utf8("Signature");
+        let class_signature = pool.utf8("<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;");
+        let source_file = pool.utf8("SourceFile");
+        let source_file_name = pool.utf8("Outer.java");
+        let inner_classes = pool.utf8("InnerClasses");
+        let code = pool.utf8("Code");
+
+        let mut data = Vec::new();
+        data.extend(MAGIC.to_be_bytes());
+        data.extend([0, 0, 0, 61]);
+        data.extend(pool.count.to_be_bytes());
+        data.extend(&pool.bytes);
+        for value in [ACC_PUBLIC, this_class, super_class, 1, interface] {
+            data.extend(value.to_be_bytes());
+        }
+        // the field, with its signature
+        for value in [1, ACC_PRIVATE, field_name, field_descriptor, 1, signature] {
+            data.extend(u16::to_be_bytes(value));
+        }
+        data.extend(2u32.to_be_bytes());
+        data.extend(field_signature.to_be_bytes());
+        // the method, with code that's skipped
+        for value in [1, ACC_PUBLIC | ACC_ABSTRACT, method_name, method_descriptor, 1, code] {
+            data.extend(u16::to_be_bytes(value));
+        }
+        data.extend(3u32.to_be_bytes());
+        data.extend([0xB1, 0, 0]);
+        // the class attributes
+        data.extend(3u16.to_be_bytes());
+        data.extend(signature.to_be_bytes());
+        data.extend(2u32.to_be_bytes());
+        data.extend(class_signature.to_be_bytes());
+        data.extend(source_file.to_be_bytes());
+        data.extend(2u32.to_be_bytes());
+        data.extend(source_file_name.to_be_bytes());
+        data.extend(inner_classes.to_be_bytes());
+        data.extend(10u32.to_be_bytes());
+        for value in [1, this_class, 0, 0, ACC_PROTECTED | ACC_STATIC] {
+            data.extend(u16::to_be_bytes(value));
+        }
+        return data;
+    }
+
+    #[test]
+    fn parses_a_class_file() {
+        let class_file = parse(&box_class()).unwrap();
+        assert_eq!(class_file.name, "p/Outer$Box");
+        assert_eq!(class_file.access_flags, ACC_PUBLIC);
+        assert_eq!(class_file.inner_access_flags, Some(ACC_PROTECTED | ACC_STATIC));
+        assert_eq!(class_file.super_class.as_deref(), Some("java/lang/Object"));
+        assert_eq!(class_file.interfaces, vec!["java/lang/Comparable".to_string()]);
+        assert_eq!(class_file.source_file.as_deref(), Some("Outer.java"));
+        assert_eq!(type_parameters(class_file.signature.as_deref().unwrap()), vec!["T".to_string()]);
+        assert_eq!(class_file.fields.len(), 1);
+        assert_eq!(class_file.fields[0].name, "value");
+        assert_eq!(class_file.fields[0].signature.as_deref(), Some("TT;"));
+        assert_eq!(class_file.methods.len(), 1);
+        assert_eq!(class_file.methods[0].name, "get");
+        assert_eq!(class_file.methods[0].signature, None);
+    }
+
+    #[test]
+    fn rejects_damaged_class_files() {
+        let data = box_class();
+        assert!(parse(&data[..data.len() - 1]).is_none());
+        assert!(parse(&data[4..]).is_none());
+        assert!(parse(b"").is_none());
+    }
+
+    #[test]
+    fn describes_descriptor_types() {
+        assert_eq!(
+            method_types("(I[JLjava/util/Map$Entry;)[[Ljava/lang/String;"),
+            Some((
+                vec!["int".to_string(), "long[]".to_string(), "Entry".to_string()],
+                "String[][]".to_string()
+            ))
+        );
+        assert_eq!(method_types("()V"), Some((Vec::new(), "void".to_string())));
+        assert_eq!(method_types("(Q)V"), None);
+        assert_eq!(descriptor_type("Ljava/lang/String"), None);
+    }
+
+    #[test]
+    fn finds_the_type_parameters_of_signatures() {
+        assert_eq!(
+            type_parameters("<K:Ljava/lang/Object;V::Ljava/lang/Comparable<-TV;>;>Ljava/lang/Object;"),
+            vec!["K".to_string(), "V".to_string()]
+        );
+        assert_eq!(type_parameters("<T:[Ljava/lang/Object;U:TT;>()V"), vec!["T".to_string(), "U".to_string()]);
+        assert_eq!(type_parameters("Ljava/lang/Object;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn decodes_modified_utf8() {
+        assert_eq!(modified_utf8("plain é".as_bytes()), "plain é");
+        assert_eq!(modified_utf8(&[b'a', 0xC0, 0x80, b'b']), "a\0b");
+        // U+1F600 as a surrogate pair of three bytes each
+        assert_eq!(modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]), "\u{1F600}");
+    }
+
+    #[test]
+    fn names_binary_names() {
+        assert_eq!(simple_name("java/util/Map$Entry"), "Entry");
+        assert_eq!(simple_name("Top"), "Top");
+        assert_eq!(qualified_name("java/util/Map$Entry"), "java.util.Map.Entry");
+    }
+}
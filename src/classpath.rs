@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+use dashmap::DashMap;
+use tree_sitter::{Node, Tree};
+
+use crate::hierarchy;
+use crate::package_trie::PackageTrie;
+use crate::references::package_name;
+
+/// Where the definition of a fully qualified class name comes from. Of several providers,
+/// workspace sources win, ordered by URI, and then jars, in classpath order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassOrigin {
+    Source(String), // document uri
+    Jar(PathBuf),
+}
+
+/// Tracks every provider of each fully qualified class name so that conflicting definitions can be
+/// reported and resolution can pick the same winner every time.
+#[derive(Debug, Default)]
+pub struct ClassRegistry {
+    origins: DashMap<String, Vec<ClassOrigin>>,
+    source_classes: DashMap<String, (String, Vec<String>)>, // document uri -> package, declared class names
+    jar_classes: DashMap<PathBuf, Vec<String>>,
+    // the jars of every classpath in the order they're looked up in, for providers looked up
+    // without a classpath of their own
+    classpath: RwLock<Vec<PathBuf>>,
+    // the packages of the top-level classes
+    packages: RwLock<PackageTrie>,
+}
+
+impl ClassRegistry {
+    /// Replaces the classes declared by the source document at `uri`, in `package`, and returns
+    /// the other documents whose duplicate status may have changed as a result.
+    pub fn register_source(&self, uri: &str, package: &str, class_names: Vec<String>) -> Vec<String> {
+        let origin = ClassOrigin::Source(uri.to_string());
+        let (previous_package, previous) = self
+            .source_classes
+            .insert(uri.to_string(), (package.to_string(), class_names.clone()))
+            .unwrap_or_default();
+        {
+            let mut packages = self.packages.write().unwrap_or_else(PoisonError::into_inner);
+            for simple_name in top_level_classes(&previous_package, &previous) {
+                packages.remove(&previous_package, simple_name);
+            }
+            for simple_name in top_level_classes(package, &class_names) {
+                packages.insert(package, simple_name);
+            }
+        }
+        self.replace_origin(&origin, &previous, &class_names);
+        return self.affected_sources(&previous, &class_names, Some(uri));
+    }
+
+    /// Forgets the classes declared by the source document at `uri` and returns the other
+    /// documents whose duplicate status may have changed as a result.
+    pub fn remove_source(&self, uri: &str) -> Vec<String> {
+        let affected = self.register_source(uri, "", Vec::new());
+        self.source_classes.remove(uri);
+        return affected;
+    }
+
+    /// Replaces the classes of the jar at `jar`, and returns the source documents whose duplicate
+    /// status may have changed as a result. The jar's packages are kept by the dependency index.
+    pub fn register_jar(&self, jar: &Path, class_names: Vec<String>) -> Vec<String> {
+        let origin = ClassOrigin::Jar(jar.to_path_buf());
+        let previous = self.jar_classes.insert(jar.to_path_buf(), class_names.clone()).unwrap_or_default();
+        self.replace_origin(&origin, &previous, &class_names);
+        return self.affected_sources(&previous, &class_names, None);
+    }
+
+    /// Forgets the classes of the jar at `jar` and returns the source documents whose duplicate
+    /// status may have changed as a result.
+    pub fn remove_jar(&self, jar: &Path) -> Vec<String> {
+        let affected = self.register_jar(jar, Vec::new());
+        self.jar_classes.remove(jar);
+        return affected;
+    }
+
+    /// Sets the order jars are looked up in when no classpath is given.
+    pub fn set_classpath(&self, classpath: Vec<PathBuf>) {
+        *self.classpath.write().unwrap_or_else(PoisonError::into_inner) = classpath;
+    }
+
+    fn replace_origin(&self, origin: &ClassOrigin, previous: &[String], class_names: &[String]) {
+        for class_name in previous {
+            if let Some(mut origins) = self.origins.get_mut(class_name) {
+                origins.retain(|o| o != origin);
+            }
+            self.origins.remove_if(class_name, |_, origins| origins.is_empty());
+        }
+        for class_name in class_names {
+            self.origins.entry(class_name.clone()).or_default().push(origin.clone());
+        }
+    }
+
+    /// The source documents other than `uri` that provide one of `previous` or `class_names`.
+    fn affected_sources(&self, previous: &[String], class_names: &[String], uri: Option<&str>) -> Vec<String> {
+        let mut affected = Vec::new();
+        for class_name in previous.iter().chain(class_names.iter()) {
+            let origins = match self.origins.get(class_name) {
+                Some(origins) => origins.clone(),
+                None => continue,
+            };
+            for other in origins {
+                if let ClassOrigin::Source(other_uri) = other {
+                    if Some(other_uri.as_str()) != uri && !affected.contains(&other_uri) {
+                        affected.push(other_uri);
+                    }
+                }
+            }
+        }
+        return affected;
+    }
+
+    /// Every provider of `class_name`, winner first.
+    pub fn providers(&self, class_name: &str) -> Vec<ClassOrigin> {
+        return self.providers_on(class_name, None);
+    }
+
+    /// Every provider of `class_name` among the sources and the jars of `classpath`, winner
+    /// first. Without a classpath, the jars of every classpath count.
+    pub fn providers_on(&self, class_name: &str, classpath: Option<&[PathBuf]>) -> Vec<ClassOrigin> {
+        let mut providers = match self.origins.get(class_name) {
+            Some(origins) => origins.clone(),
+            None => return Vec::new(),
+        };
+        let every_classpath = self.classpath.read().unwrap_or_else(PoisonError::into_inner);
+        let classpath = classpath.unwrap_or(&every_classpath);
+        providers.retain(|origin| match origin {
+            ClassOrigin::Source(_) => true,
+            ClassOrigin::Jar(jar) => classpath.contains(jar),
+        });
+        providers.sort_by(|a, b| compare_origins(a, b, classpath));
+        providers.dedup();
+        return providers;
+    }
+
+    /// The classes of each jar of `classpath` that a jar before it provides too, by the jar they
+    /// resolve to instead.
+    pub fn shadowed_classes(&self, classpath: &[PathBuf]) -> Vec<(PathBuf, PathBuf, Vec<String>)> {
+        let mut shadowed = Vec::new();
+        for jar in classpath {
+            let class_names = match self.jar_classes.get(jar) {
+                Some(class_names) => class_names.clone(),
+                None => continue,
+            };
+            let mut by_winner: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+            for class_name in class_names {
+                let winner = self.providers_on(&class_name, Some(classpath)).into_iter().find_map(|origin| match origin {
+                    ClassOrigin::Jar(winner) => Some(winner),
+                    ClassOrigin::Source(_) => None,
+                });
+                match winner {
+                    Some(winner) if winner != *jar => by_winner.entry(winner).or_default().push(class_name),
+                    _ => {}
+                }
+            }
+            for (winner, mut class_names) in by_winner {
+                class_names.sort();
+                shadowed.push((jar.clone(), winner, class_names));
+            }
+        }
+        return shadowed;
+    }
+
+    /// The packages of the workspace's classes, with their top-level classes.
+    pub fn packages(&self) -> RwLockReadGuard<'_, PackageTrie> {
+        return self.packages.read().unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// Sources before jars, sources by URI and jars by their position on `classpath`.
+fn compare_origins(a: &ClassOrigin, b: &ClassOrigin, classpath: &[PathBuf]) -> Ordering {
+    let position = |jar: &PathBuf| classpath.iter().position(|entry| entry == jar);
+    return match (a, b) {
+        (ClassOrigin::Source(a), ClassOrigin::Source(b)) => a.cmp(b),
+        (ClassOrigin::Source(_), ClassOrigin::Jar(_)) => Ordering::Less,
+        (ClassOrigin::Jar(_), ClassOrigin::Source(_)) => Ordering::Greater,
+        (ClassOrigin::Jar(a), ClassOrigin::Jar(b)) => position(a).cmp(&position(b)),
+    };
+}
+
+/// The simple names of the top-level classes among `class_names`, declared in `package`.
+fn top_level_classes<'a>(package: &str, class_names: &'a [String]) -> impl Iterator<Item = &'a str> + 'a {
+    let prefix = if package.is_empty() { String::new() } else { format!("{}.", package) };
+    return class_names
+        .iter()
+        .filter_map(move |class_name| class_name.strip_prefix(prefix.as_str()))
+        .filter(|simple_name| !simple_name.contains('.'));
+}
+
+/// The fully qualified names of the classes a compilation unit declares, with the name node of
+/// each declaration. Local and anonymous classes aren't addressable and are skipped.
+pub fn declared_class_names<'a>(tree: &'a Tree, source: &str) -> Vec<(String, Node<'a>)> {
+    let package = package_name(tree, source);
+    let mut class_names = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if !hierarchy::is_type_declaration(node.kind()) {
+            continue;
+        }
+        let name_node = match node.child_by_field_name("name") {
+            Some(name_node) => name_node,
+            None => continue,
+        };
+        let mut segments = vec![name_node.utf8_text(source.as_bytes()).unwrap().to_string()];
+        let mut addressable = true;
+        let mut current = node;
+        while let Some(parent) = current.parent() {
+            if hierarchy::is_type_declaration(parent.kind()) {
+                match parent.child_by_field_name("name") {
+                    Some(parent_name) => segments.push(parent_name.utf8_text(source.as_bytes()).unwrap().to_string()),
+                    None => addressable = false,
+                }
+            } else if matches!(parent.kind(), "block" | "object_creation_expression" | "constructor_body") {
+                addressable = false;
+            }
+            current = parent;
+        }
+        if !addressable {
+            continue;
+        }
+        if let Some(package) = &package {
+            segments.push(package.clone());
+        }
+        segments.reverse();
+        class_names.push((segments.join("."), name_node));
+    }
+    return class_names;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{ClassOrigin, ClassRegistry};
+
+    #[test]
+    fn orders_sources_before_jars_in_classpath_order() {
+        let registry = ClassRegistry::default();
+        let (first, second) = (PathBuf::from("/lib/first.jar"), PathBuf::from("/lib/second.jar"));
+        registry.set_classpath(vec![first.clone(), second.clone()]);
+        registry.register_jar(&second, vec!["p.A".to_string()]);
+        registry.register_jar(&first, vec!["p.A".to_string(), "p.B".to_string()]);
+        let affected = registry.register_source("file:///src/p/A.java", "p", vec!["p.A".to_string()]);
+        assert!(affected.is_empty());
+        assert_eq!(
+            registry.providers("p.A"),
+            vec![
+                ClassOrigin::Source("file:///src/p/A.java".to_string()),
+                ClassOrigin::Jar(first.clone()),
+                ClassOrigin::Jar(second.clone()),
+            ]
+        );
+        // a classpath of its own leaves out the jars that aren't on it
+        let only_second = [second.clone()];
+        assert_eq!(registry.providers_on("p.B", Some(&only_second)), Vec::new());
+        assert_eq!(
+            registry.shadowed_classes(&[first.clone(), second.clone()]),
+            vec![(second.clone(), first.clone(), vec!["p.A".to_string()])]
+        );
+        assert_eq!(registry.remove_jar(&first), vec!["file:///src/p/A.java".to_string()]);
+        assert_eq!(registry.providers("p.B"), Vec::new());
+    }
+}
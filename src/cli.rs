@@ -0,0 +1,57 @@
+use std::env;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use crate::logging::LogDestination;
+use crate::maven;
+
+/// A language server for Java. It speaks the Language Server Protocol over standard input and
+/// output, which is the only transport it has.
+#[derive(Debug, Parser)]
+#[command(name = "javals", version, about)]
+pub struct Args {
+    /// Communicate over standard input and output, as the server does anyway
+    #[arg(long)]
+    #[allow(dead_code)] // accepted for the clients that pass it
+    pub stdio: bool,
+
+    /// Where to log to
+    #[arg(long, value_enum, default_value_t = LogTarget::Stderr)]
+    pub log: LogTarget,
+
+    /// The file to log to, when logging to a file [default: $XDG_STATE_HOME/javals/log.txt]
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// What to log, like `debug` or `info,lsp::jdk=debug`, until the client's settings say
+    /// otherwise [default: $JAVALS_LOG, or else info]
+    #[arg(long, value_name = "DIRECTIVES")]
+    pub log_level: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogTarget {
+    File,
+    Stderr,
+    None,
+}
+
+impl Args {
+    pub fn log_destination(&self) -> LogDestination {
+        return match self.log {
+            LogTarget::File => LogDestination::File(self.log_file.clone().unwrap_or_else(default_log_file)),
+            LogTarget::Stderr => LogDestination::Stderr,
+            LogTarget::None => LogDestination::None,
+        };
+    }
+}
+
+/// The file to log to when none is given: in the server's state directory, rather than in
+/// whichever directory the editor starts it in.
+fn default_log_file() -> PathBuf {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| maven::home_directory().join(".local").join("state"));
+    return state_home.join("javals").join("log.txt");
+}
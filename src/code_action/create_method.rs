@@ -0,0 +1,241 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
+
+use crate::code_action::edit::{insert_member_edit, member_indentation, EditBuilder};
+use crate::code_action::node_at;
+use crate::diagnostics::UNRESOLVED_METHOD;
+use crate::{hierarchy, resolve, source_set, Backend, TokenType};
+
+/// Offers to generate a stub in the receiver's class for every invocation flagged by an
+/// unresolved-method diagnostic.
+pub fn create_method_actions(backend: &Backend, uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics {
+        if diagnostic.code != Some(NumberOrString::String(UNRESOLVED_METHOD.to_string())) {
+            continue;
+        }
+        if let Some(action) = create_method_action(backend, uri, diagnostic) {
+            actions.push(action);
+        }
+    }
+    return actions;
+}
+
+fn create_method_action(backend: &Backend, uri: &Url, diagnostic: &Diagnostic) -> Option<CodeActionOrCommand> {
+    let (receiver_type, stub) = {
+        let tree = backend.parsed_document_map.get(uri.as_str())?;
+        let source_text = backend.document_map.get(uri.as_str())?;
+        let invocation_node = node_at(&tree, &source_text, diagnostic.range.start, "method_invocation")?;
+        let receiver_type = backend.invocation_receiver_type(uri.as_str(), invocation_node, &source_text)?;
+        let stub = MethodStub::from_invocation(backend, uri.as_str(), invocation_node, &source_text, &receiver_type)?;
+        (receiver_type, stub)
+    };
+
+    // the receiver's class may live in any visible document; prefer the lowest uri, like type lookup
+    let mut target_uris: Vec<String> = backend
+        .type_declaration_map
+        .iter()
+        .filter(|entry| entry.value().iter().any(|declaration| declaration.name == receiver_type))
+        .map(|entry| entry.key().clone())
+        .filter(|target_uri| source_set::is_visible(uri.as_str(), target_uri))
+        .collect();
+    target_uris.sort();
+    for target_uri in target_uris {
+        let (tree, source_text) = match backend.document_source(&target_uri) {
+            Some(target_document) => target_document,
+            None => continue,
+        };
+        let class_node = tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre).find(|node| {
+            hierarchy::is_type_declaration(node.kind())
+                && node
+                    .child_by_field_name("name")
+                    .map(|n| n.utf8_text(source_text.as_bytes()).unwrap() == receiver_type)
+                    .unwrap_or(false)
+        });
+        let class_node = match class_node {
+            Some(class_node) => class_node,
+            None => continue,
+        };
+        let body_node = class_node.child_by_field_name("body")?;
+        let is_interface = class_node.kind() == "interface_declaration";
+        let same_document = target_uri == uri.as_str();
+        let target_url = Url::parse(&target_uri).ok()?;
+        let style = backend.editor_config(&target_url);
+        let unit = style.indent_unit().unwrap_or_else(|| "    ".to_string());
+        let max_width = style
+            .max_line_length
+            .map(|max_line_length| max_line_length.saturating_sub(display_width(&member_indentation(&source_text, body_node, &unit))));
+        let member = stub.render(is_interface, same_document, &unit, max_width);
+        let mut builder = EditBuilder::new();
+        builder.push(&target_url, insert_member_edit(&source_text, body_node, &member, &unit));
+        let title = format!("Create method '{}' in type '{}'", stub.signature(), receiver_type);
+        return Some(CodeActionOrCommand::CodeAction(builder.into_quick_fix(title, diagnostic)));
+    }
+    return None;
+}
+
+struct MethodStub {
+    name: String,
+    return_type: String,
+    parameters: Vec<(String, String)>, // (type, name)
+    is_static: bool,
+}
+
+impl MethodStub {
+    fn from_invocation(backend: &Backend, uri: &str, invocation_node: Node, source: &str, receiver_type: &str) -> Option<MethodStub> {
+        let name = invocation_node.child_by_field_name("name")?.utf8_text(source.as_bytes()).unwrap().to_string();
+        let mut parameters: Vec<(String, String)> = Vec::new();
+        if let Some(arguments_node) = invocation_node.child_by_field_name("arguments") {
+            for argument_node in arguments_node.named_children(&mut arguments_node.walk()) {
+                if argument_node.kind().ends_with("comment") {
+                    continue;
+                }
+                let argument_type = backend
+                    .expression_type(uri, argument_node, source)
+                    .unwrap_or_else(|| "Object".to_string());
+                let base_name = match argument_node.kind() {
+                    "identifier" => argument_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    _ => parameter_name_for_type(&argument_type),
+                };
+                let mut parameter_name = base_name.clone();
+                let mut suffix = 1;
+                while parameters.iter().any(|(_, existing)| *existing == parameter_name) {
+                    suffix += 1;
+                    parameter_name = format!("{}{}", base_name, suffix);
+                }
+                parameters.push((argument_type, parameter_name));
+            }
+        }
+        let is_static = match invocation_node.child_by_field_name("object") {
+            Some(object_node) => {
+                let is_variable = matches!(
+                    backend.find_declaration(uri, object_node, receiver_type).map(|l| l.token_type),
+                    Some(TokenType::MemberVariable(_)) | Some(TokenType::ParameterName(_)) | Some(TokenType::LocalVariable(_))
+                );
+                object_node.kind() == "identifier"
+                    && object_node.utf8_text(source.as_bytes()).unwrap() == receiver_type
+                    && !is_variable
+            }
+            None => in_static_context(invocation_node),
+        };
+        return Some(MethodStub {
+            name,
+            return_type: expected_type(backend, uri, invocation_node, source),
+            parameters,
+            is_static,
+        });
+    }
+
+    fn signature(&self) -> String {
+        let parameter_types: Vec<&str> = self.parameters.iter().map(|(t, _)| t.as_str()).collect();
+        return format!("{}({})", self.name, parameter_types.join(", "));
+    }
+
+    /// The stub as a member declaration indented by `unit`. When the header would be wider than
+    /// `max_width`, each parameter goes on a line of its own, indented as continuation lines are.
+    fn render(&self, is_interface: bool, same_document: bool, unit: &str, max_width: Option<usize>) -> String {
+        let visibility = if same_document { "private" } else { "public" };
+        let modifiers = match (is_interface, self.is_static) {
+            (true, true) => "static ".to_string(),
+            (true, false) => String::new(),
+            (false, true) => format!("{} static ", visibility),
+            (false, false) => format!("{} ", visibility),
+        };
+        let ending = if is_interface && !self.is_static { ";" } else { " {" };
+        let header = format!("{}{} {}(", modifiers, self.return_type, self.name);
+        let parameters: Vec<String> = self.parameters.iter().map(|(t, n)| format!("{} {}", t, n)).collect();
+        let mut declaration = format!("{}{}){}", header, parameters.join(", "), ending);
+        if max_width.map(|max_width| display_width(&declaration) > max_width).unwrap_or(false) && !parameters.is_empty() {
+            let continuation = unit.repeat(2);
+            declaration = format!("{}\n{}{}){}", header, continuation, parameters.join(&format!(",\n{}", continuation)), ending);
+        }
+        if ending == ";" {
+            return declaration;
+        }
+        return format!("{}\n{}throw new UnsupportedOperationException(\"Not implemented\");\n}}", declaration, unit);
+    }
+}
+
+/// Columns taken by `text`, counting a tab as four.
+fn display_width(text: &str) -> usize {
+    return text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum();
+}
+
+/// The type the surrounding code expects the invocation to produce.
+fn expected_type(backend: &Backend, uri: &str, invocation_node: Node, source: &str) -> String {
+    let parent = match invocation_node.parent() {
+        Some(parent) => parent,
+        None => return "void".to_string(),
+    };
+    let expected = match parent.kind() {
+        "expression_statement" => Some("void".to_string()),
+        "variable_declarator" => parent
+            .parent()
+            .and_then(|declaration| declaration.child_by_field_name("type"))
+            .map(|type_node| type_node.utf8_text(source.as_bytes()).unwrap().to_string())
+            .filter(|declared_type| declared_type != "var"),
+        "assignment_expression" => parent
+            .child_by_field_name("left")
+            .and_then(|left| backend.expression_type(uri, left, source)),
+        "return_statement" => {
+            let mut current = parent.parent();
+            let mut return_type = None;
+            while let Some(node) = current {
+                if node.kind() == "method_declaration" {
+                    return_type = node
+                        .child_by_field_name("type")
+                        .map(|type_node| type_node.utf8_text(source.as_bytes()).unwrap().to_string());
+                    break;
+                }
+                if node.kind() == "lambda_expression" {
+                    break;
+                }
+                current = node.parent();
+            }
+            return_type
+        }
+        // the condition of a `do` is parenthesized, and those of `if` and `while` are conditions
+        "parenthesized_expression" if parent.parent().map(|n| n.kind()) == Some("do_statement") => Some("boolean".to_string()),
+        "condition" => Some("boolean".to_string()),
+        "unary_expression" => Some("boolean".to_string()),
+        _ => None,
+    };
+    return expected.unwrap_or_else(|| "Object".to_string());
+}
+
+fn in_static_context(node: Node) -> bool {
+    let mut current = node.parent();
+    while let Some(candidate) = current {
+        match candidate.kind() {
+            "method_declaration" | "field_declaration" => return crate::diagnostics::has_modifier(candidate, "static"),
+            "static_initializer" => return true,
+            "constructor_declaration" | "class_body" => return false,
+            _ => {}
+        };
+        current = candidate.parent();
+    }
+    return false;
+}
+
+/// A conventional parameter name for a value of `type_name`, e.g. `String` becomes `string` and
+/// `int` becomes `i`.
+pub fn parameter_name_for_type(type_name: &str) -> String {
+    let element = resolve::erase(type_name).trim_end_matches("[]").to_string();
+    let name = match element.as_str() {
+        "int" | "long" | "short" | "byte" => "i".to_string(),
+        "double" | "float" => "d".to_string(),
+        "boolean" => "b".to_string(),
+        "char" => "c".to_string(),
+        _ => {
+            let mut chars = element.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+                None => "arg".to_string(),
+            }
+        }
+    };
+    if type_name.ends_with("[]") {
+        return format!("{}s", name);
+    }
+    return name;
+}
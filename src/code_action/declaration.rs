@@ -0,0 +1,136 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::code_action::edit::{line_indentation, node_text, EditBuilder};
+use crate::code_action::node_at;
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+/// The nodes whose children are a list of statements.
+const STATEMENT_LIST_KINDS: [&str; 3] = ["block", "constructor_body", "switch_block_statement_group"];
+
+/// Offers "Split variable declaration" on a local declaration with an initializer and "Join
+/// variable declaration" on a declaration immediately followed by the assignment of its variable.
+pub fn declaration_actions(backend: &Backend, uri: &Url, tree: &Tree, source: &str, range: Range) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    let declaration_node = match node_at(tree, source, range.start, "local_variable_declaration") {
+        Some(declaration_node) => Some(declaration_node),
+        None => node_at(tree, source, range.start, "expression_statement")
+            .and_then(|statement| statement.prev_named_sibling())
+            .filter(|sibling| sibling.kind() == "local_variable_declaration"),
+    };
+    let declaration_node = match declaration_node {
+        Some(declaration_node) => declaration_node,
+        None => return actions,
+    };
+    if let Some(action) = split_declaration(backend, uri, declaration_node, source) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    if let Some(action) = join_declaration(uri, declaration_node, source) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    return actions;
+}
+
+fn declarators(declaration_node: Node) -> Vec<Node> {
+    return declaration_node
+        .named_children(&mut declaration_node.walk())
+        .filter(|n| n.kind() == "variable_declarator")
+        .collect();
+}
+
+/// `int x = compute();` becomes `int x;` followed by `x = compute();`.
+fn split_declaration(backend: &Backend, uri: &Url, declaration_node: Node, source: &str) -> Option<CodeAction> {
+    // the assignments need to be statements of their own, which a `for` initializer can't hold
+    let parent_kind = declaration_node.parent()?.kind();
+    if !STATEMENT_LIST_KINDS.contains(&parent_kind) {
+        return None;
+    }
+    let declarators = declarators(declaration_node);
+    if declarators.iter().all(|d| d.child_by_field_name("value").is_none()) {
+        return None;
+    }
+    if declarators.iter().any(|d| d.child_by_field_name("dimensions").is_some()) {
+        return None;
+    }
+    let type_node = declaration_node.child_by_field_name("type")?;
+    let mut declared_type = node_text(type_node, source).to_string();
+    if declared_type == "var" {
+        // `var` needs an initializer, so the type has to be spelled out
+        let value_node = declarators[0].child_by_field_name("value")?;
+        if declarators.len() != 1 {
+            return None;
+        }
+        declared_type = backend.expression_type(uri.as_str(), value_node, source)?;
+        if declared_type == "Object" && value_node.kind() == "null_literal" {
+            return None;
+        }
+    }
+    let modifiers = declaration_node
+        .named_children(&mut declaration_node.walk())
+        .find(|n| n.kind() == "modifiers")
+        .map(|n| format!("{} ", node_text(n, source)))
+        .unwrap_or_default();
+    let indentation = line_indentation(source, declaration_node.start_position().row);
+
+    let mut names = Vec::new();
+    let mut assignments = Vec::new();
+    for declarator in &declarators {
+        let name = node_text(declarator.child_by_field_name("name")?, source);
+        names.push(name);
+        if let Some(value_node) = declarator.child_by_field_name("value") {
+            let value = if value_node.kind() == "array_initializer" {
+                format!("new {}{}", declared_type, node_text(value_node, source))
+            } else {
+                node_text(value_node, source).to_string()
+            };
+            assignments.push(format!("{} = {};", name, value));
+        }
+    }
+    let mut new_text = format!("{}{} {};", modifiers, declared_type, names.join(", "));
+    for assignment in assignments {
+        new_text.push_str(&format!("\n{}{}", indentation, assignment));
+    }
+    let mut builder = EditBuilder::new();
+    builder.replace_node(uri, source, declaration_node, new_text);
+    return Some(builder.into_action("Split variable declaration", CodeActionKind::REFACTOR_REWRITE));
+}
+
+/// `int x;` immediately followed by `x = compute();` becomes `int x = compute();`.
+fn join_declaration(uri: &Url, declaration_node: Node, source: &str) -> Option<CodeAction> {
+    let declarators = declarators(declaration_node);
+    if declarators.len() != 1 || declarators[0].child_by_field_name("value").is_some() {
+        return None;
+    }
+    let declarator = declarators[0];
+    let name = node_text(declarator.child_by_field_name("name")?, source);
+    let statement = declaration_node.next_named_sibling()?;
+    if statement.kind() != "expression_statement" {
+        return None;
+    }
+    let assignment = statement.named_child(0)?;
+    if assignment.kind() != "assignment_expression" {
+        return None;
+    }
+    let operator = assignment.child_by_field_name("operator").map(|n| node_text(n, source));
+    let left = assignment.child_by_field_name("left")?;
+    if operator != Some("=") || left.kind() != "identifier" || node_text(left, source) != name {
+        return None;
+    }
+    let value = node_text(assignment.child_by_field_name("right")?, source);
+    let declaration_text = node_text(declaration_node, source);
+    let declarator_end = declarator.end_byte() - declaration_node.start_byte();
+    let new_text = format!(
+        "{} = {}{}",
+        &declaration_text[..declarator_end],
+        value,
+        &declaration_text[declarator_end..]
+    );
+    let mut builder = EditBuilder::new();
+    builder.replace(
+        uri,
+        LineIndex::new(source).range(declaration_node.start_position(), statement.end_position()),
+        new_text,
+    );
+    return Some(builder.into_action("Join variable declaration", CodeActionKind::REFACTOR_REWRITE));
+}
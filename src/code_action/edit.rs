@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point};
+
+use crate::editorconfig::EditorConfig;
+use crate::line_index::LineIndex;
+
+/// Accumulates text edits across documents and turns them into a `WorkspaceEdit` or a ready-made
+/// code action.
+#[derive(Debug, Default)]
+pub struct EditBuilder {
+    changes: HashMap<Url, Vec<TextEdit>>,
+}
+
+impl EditBuilder {
+    pub fn new() -> EditBuilder {
+        return EditBuilder::default();
+    }
+
+    pub fn replace(&mut self, uri: &Url, range: Range, new_text: impl Into<String>) -> &mut EditBuilder {
+        self.changes.entry(uri.clone()).or_default().push(TextEdit {
+            range,
+            new_text: new_text.into(),
+        });
+        return self;
+    }
+
+    pub fn replace_node(&mut self, uri: &Url, source: &str, node: Node, new_text: impl Into<String>) -> &mut EditBuilder {
+        return self.replace(uri, node_range(source, node), new_text);
+    }
+
+    pub fn insert(&mut self, uri: &Url, position: Position, new_text: impl Into<String>) -> &mut EditBuilder {
+        return self.replace(uri, Range { start: position, end: position }, new_text);
+    }
+
+    pub fn push(&mut self, uri: &Url, edit: TextEdit) -> &mut EditBuilder {
+        self.changes.entry(uri.clone()).or_default().push(edit);
+        return self;
+    }
+
+    pub fn build(self) -> WorkspaceEdit {
+        return WorkspaceEdit {
+            changes: Some(self.changes),
+            ..WorkspaceEdit::default()
+        };
+    }
+
+    pub fn into_action(self, title: impl Into<String>, kind: CodeActionKind) -> CodeAction {
+        return CodeAction {
+            title: title.into(),
+            kind: Some(kind),
+            edit: Some(self.build()),
+            ..CodeAction::default()
+        };
+    }
+
+    /// A quick fix resolving `diagnostic`.
+    pub fn into_quick_fix(self, title: impl Into<String>, diagnostic: &Diagnostic) -> CodeAction {
+        let mut action = self.into_action(title, CodeActionKind::QUICKFIX);
+        action.diagnostics = Some(vec![diagnostic.clone()]);
+        return action;
+    }
+}
+
+pub fn node_range(source: &str, node: Node) -> Range {
+    return LineIndex::new(source).range(node.start_position(), node.end_position());
+}
+
+pub fn node_text<'a>(node: Node, source: &'a str) -> &'a str {
+    return node.utf8_text(source.as_bytes()).unwrap();
+}
+
+pub fn line_indentation(source: &str, row: usize) -> String {
+    let line = source.lines().nth(row).unwrap_or("");
+    return line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+}
+
+/// One level of indentation: the configured one, or else a guess from how far `statement` is
+/// indented relative to the line opening its enclosing block, defaulting to four spaces.
+pub fn indent_unit(source: &str, statement: Node, style: &EditorConfig) -> String {
+    if let Some(unit) = style.indent_unit() {
+        return unit;
+    }
+    let statement_indentation = line_indentation(source, statement.start_position().row);
+    let block_indentation = match statement.parent() {
+        Some(parent) => line_indentation(source, parent.start_position().row),
+        None => String::new(),
+    };
+    match statement_indentation.strip_prefix(block_indentation.as_str()) {
+        Some(unit) if !unit.is_empty() => unit.to_string(),
+        _ => "    ".to_string(),
+    }
+}
+
+/// Re-indents `text`, whose first line has already had its indentation stripped, moving every
+/// line from `from` to `to`.
+pub fn reindent(text: &str, from: &str, to: &str) -> String {
+    return text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                return format!("{}{}", to, line);
+            }
+            if line.trim().is_empty() {
+                return String::new();
+            }
+            match line.strip_prefix(from) {
+                Some(rest) => format!("{}{}", to, rest),
+                None => format!("{}{}", to, line.trim_start()),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+}
+
+/// The indentation of the members of a class body: that of its first member, or one `unit`
+/// deeper than the class when it has none on a line of its own.
+pub fn member_indentation(source: &str, body_node: Node, unit: &str) -> String {
+    return match body_node.named_child(0) {
+        Some(first_member) if first_member.start_position().row != body_node.start_position().row => {
+            line_indentation(source, first_member.start_position().row)
+        }
+        _ => format!("{}{}", line_indentation(source, body_node.start_position().row), unit),
+    };
+}
+
+/// An edit appending `member` as the last member of a class body. `member` is written without
+/// base indentation; each of its lines is indented as [`member_indentation`] says.
+pub fn insert_member_edit(source: &str, body_node: Node, member: &str, unit: &str) -> TextEdit {
+    let class_indentation = line_indentation(source, body_node.start_position().row);
+    let member_indentation = member_indentation(source, body_node, unit);
+    let indented_member = member
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", member_indentation, line)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let lines = LineIndex::new(source);
+    let closing_brace = body_node.end_position();
+    let closing_row = closing_brace.row;
+    let closing_column = closing_brace.column.saturating_sub(1);
+    let line = lines.line(closing_row);
+    let brace_on_own_line = line[..closing_column.min(line.len())].chars().all(char::is_whitespace);
+    let has_members = body_node.named_child_count() > 0;
+    if brace_on_own_line {
+        let separator = if has_members { "\n" } else { "" };
+        let position = Position {
+            line: closing_row as u32,
+            character: 0,
+        };
+        return TextEdit {
+            range: Range { start: position, end: position },
+            new_text: format!("{}{}\n", separator, indented_member),
+        };
+    }
+    let position = lines.position(Point {
+        row: closing_row,
+        column: closing_column,
+    });
+    return TextEdit {
+        range: Range { start: position, end: position },
+        new_text: format!("\n{}\n{}", indented_member, class_indentation),
+    };
+}
@@ -0,0 +1,207 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::code_action::edit::{indent_unit, line_indentation, node_range, node_text, reindent, EditBuilder};
+use crate::code_action::node_at;
+use crate::editorconfig::EditorConfig;
+use crate::line_index::LineIndex;
+
+/// Precedence of unary expressions and primaries, which never need parentheses around them.
+const UNARY_PRECEDENCE: u8 = 12;
+
+/// Offers "Invert if" when the cursor is on the header or `else` of an if statement. With an else
+/// branch the branches are swapped; without one, the if becomes a guard clause that leaves the
+/// method or loop iteration early.
+pub fn invert_if_actions(uri: &Url, tree: &Tree, source: &str, range: Range, style: &EditorConfig) -> Vec<CodeActionOrCommand> {
+    return match invert_if(uri, tree, source, range, style) {
+        Some(action) => vec![CodeActionOrCommand::CodeAction(action)],
+        None => Vec::new(),
+    };
+}
+
+fn invert_if(uri: &Url, tree: &Tree, source: &str, range: Range, style: &EditorConfig) -> Option<CodeAction> {
+    let if_node = node_at(tree, source, range.start, "if_statement")?;
+    let condition_node = if_node.child_by_field_name("condition")?.named_child(0)?;
+    let consequence_node = if_node.child_by_field_name("consequence")?;
+    let alternative_node = if_node.child_by_field_name("alternative");
+    let point = LineIndex::new(source).point(range.start);
+    let on_header = point < consequence_node.start_position();
+    let on_else = alternative_node
+        .map(|alternative_node| point >= consequence_node.end_position() && point < alternative_node.start_position())
+        .unwrap_or(false);
+    if !on_header && !on_else {
+        return None;
+    }
+
+    let (negated_condition, _) = negate(condition_node, source);
+    let indentation = line_indentation(source, if_node.start_position().row);
+    let unit = indent_unit(source, if_node, style);
+    let mut builder = EditBuilder::new();
+    match alternative_node {
+        Some(alternative_node) => {
+            let then_text = if alternative_node.kind() == "if_statement" {
+                // an else-if can't become the then branch without braces, or it would take the new else
+                format!(
+                    "{{\n{}\n{}}}",
+                    reindent(node_text(alternative_node, source), &indentation, &format!("{}{}", indentation, unit)),
+                    indentation
+                )
+            } else {
+                node_text(alternative_node, source).to_string()
+            };
+            builder.replace_node(uri, source, condition_node, negated_condition);
+            builder.replace_node(uri, source, consequence_node, then_text);
+            builder.replace_node(uri, source, alternative_node, node_text(consequence_node, source));
+        }
+        None => {
+            let jump = guard_jump(if_node)?;
+            let mut new_text = format!(
+                "if ({}) {{\n{}{}{}\n{}}}",
+                negated_condition, indentation, unit, jump, indentation
+            );
+            let body: Vec<Node> = if consequence_node.kind() == "block" {
+                consequence_node.named_children(&mut consequence_node.walk()).collect()
+            } else {
+                vec![consequence_node]
+            };
+            if let (Some(first), Some(last)) = (body.first(), body.last()) {
+                let body_text = &source[first.start_byte()..last.end_byte()];
+                let body_indentation = line_indentation(source, first.start_position().row);
+                new_text.push_str(&format!("\n{}", reindent(body_text, &body_indentation, &indentation)));
+            }
+            builder.replace(uri, node_range(source, if_node), new_text);
+        }
+    }
+    return Some(builder.into_action("Invert if", CodeActionKind::REFACTOR_REWRITE));
+}
+
+/// The statement that leaves early when `if_node` is the last statement of a void method,
+/// constructor or loop body, since only then does skipping the rest of the block change nothing.
+fn guard_jump(if_node: Node) -> Option<&'static str> {
+    let mut next = if_node.next_named_sibling();
+    while let Some(sibling) = next {
+        if !sibling.kind().ends_with("comment") {
+            return None;
+        }
+        next = sibling.next_named_sibling();
+    }
+    let block_node = if_node.parent()?;
+    if block_node.kind() == "constructor_body" {
+        return Some("return;");
+    }
+    if block_node.kind() != "block" {
+        return None;
+    }
+    let owner_node = block_node.parent()?;
+    return match owner_node.kind() {
+        "method_declaration" => match owner_node.child_by_field_name("type") {
+            Some(type_node) if type_node.kind() == "void_type" => Some("return;"),
+            _ => None,
+        },
+        "for_statement" | "enhanced_for_statement" | "while_statement" | "do_statement" => Some("continue;"),
+        _ => None,
+    };
+}
+
+/// The text of `node` negated, and the precedence of its top-level operator. `&&` and `||` are
+/// rewritten by De Morgan's laws, comparisons flip their operator and double negations cancel.
+fn negate(node: Node, source: &str) -> (String, u8) {
+    match node.kind() {
+        "parenthesized_expression" => {
+            if let Some(inner_node) = node.named_child(0) {
+                return negate(inner_node, source);
+            }
+        }
+        "unary_expression" => {
+            let operator = node.child_by_field_name("operator").map(|n| n.kind());
+            if let (Some("!"), Some(operand_node)) = (operator, node.child_by_field_name("operand")) {
+                let mut operand_node = operand_node;
+                while operand_node.kind() == "parenthesized_expression" {
+                    operand_node = match operand_node.named_child(0) {
+                        Some(inner_node) => inner_node,
+                        None => break,
+                    };
+                }
+                return (node_text(operand_node, source).to_string(), precedence(operand_node));
+            }
+        }
+        "true" => return ("false".to_string(), UNARY_PRECEDENCE),
+        "false" => return ("true".to_string(), UNARY_PRECEDENCE),
+        "binary_expression" => {
+            let operator = node.child_by_field_name("operator").map(|n| n.kind()).unwrap_or("");
+            let (left_node, right_node) = match (node.child_by_field_name("left"), node.child_by_field_name("right")) {
+                (Some(left_node), Some(right_node)) => (left_node, right_node),
+                _ => return negate_by_prefix(node, source),
+            };
+            let de_morgan = match operator {
+                "&&" => Some("||"),
+                "||" => Some("&&"),
+                _ => None,
+            };
+            if let Some(negated_operator) = de_morgan {
+                let negated_precedence = operator_precedence(negated_operator);
+                let operand = |operand_node: Node| {
+                    let (text, operand_precedence) = negate(operand_node, source);
+                    if operand_precedence < negated_precedence {
+                        return format!("({})", text);
+                    }
+                    return text;
+                };
+                let text = format!("{} {} {}", operand(left_node), negated_operator, operand(right_node));
+                return (text, negated_precedence);
+            }
+            let flipped = match operator {
+                "==" => Some("!="),
+                "!=" => Some("=="),
+                "<" => Some(">="),
+                ">=" => Some("<"),
+                ">" => Some("<="),
+                "<=" => Some(">"),
+                _ => None,
+            };
+            if let Some(flipped) = flipped {
+                let text = format!("{} {} {}", node_text(left_node, source), flipped, node_text(right_node, source));
+                return (text, operator_precedence(flipped));
+            }
+        }
+        _ => {}
+    }
+    return negate_by_prefix(node, source);
+}
+
+fn negate_by_prefix(node: Node, source: &str) -> (String, u8) {
+    let text = node_text(node, source);
+    if precedence(node) < UNARY_PRECEDENCE {
+        return (format!("!({})", text), UNARY_PRECEDENCE);
+    }
+    return (format!("!{}", text), UNARY_PRECEDENCE);
+}
+
+fn precedence(node: Node) -> u8 {
+    return match node.kind() {
+        "assignment_expression" | "lambda_expression" => 0,
+        "ternary_expression" => 1,
+        "binary_expression" => node
+            .child_by_field_name("operator")
+            .map(|n| operator_precedence(n.kind()))
+            .unwrap_or(0),
+        "instanceof_expression" => operator_precedence("instanceof"),
+        _ => UNARY_PRECEDENCE,
+    };
+}
+
+fn operator_precedence(operator: &str) -> u8 {
+    return match operator {
+        "||" => 2,
+        "&&" => 3,
+        "|" => 4,
+        "^" => 5,
+        "&" => 6,
+        "==" | "!=" => 7,
+        "<" | ">" | "<=" | ">=" | "instanceof" => 8,
+        "<<" | ">>" | ">>>" => 9,
+        "+" | "-" => 10,
+        "*" | "/" | "%" => 11,
+        _ => 0,
+    };
+}
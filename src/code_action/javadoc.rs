@@ -0,0 +1,110 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::code_action::edit::{line_indentation, node_text, EditBuilder};
+use crate::hierarchy;
+use crate::line_index::LineIndex;
+
+const DOCUMENTED_KINDS: [&str; 6] = [
+    "class_declaration",
+    "interface_declaration",
+    "enum_declaration",
+    "record_declaration",
+    "method_declaration",
+    "constructor_declaration",
+];
+
+/// Offers "Add Javadoc" on the header of an undocumented type, method or constructor, inserting a
+/// comment with `@param`, `@return` and `@throws` tags for its signature.
+pub fn javadoc_actions(uri: &Url, tree: &Tree, source: &str, range: Range) -> Vec<CodeActionOrCommand> {
+    let point = LineIndex::new(source).point(range.start);
+    let mut node = match tree.root_node().named_descendant_for_point_range(point, point) {
+        Some(node) => node,
+        None => return Vec::new(),
+    };
+    while !DOCUMENTED_KINDS.contains(&node.kind()) {
+        node = match node.parent() {
+            Some(parent) => parent,
+            None => return Vec::new(),
+        };
+    }
+    let declaration_node = node;
+    if let Some(body_node) = declaration_node.child_by_field_name("body") {
+        if point >= body_node.start_position() {
+            return Vec::new();
+        }
+    }
+    if has_javadoc(declaration_node, source) {
+        return Vec::new();
+    }
+
+    let indentation = line_indentation(source, declaration_node.start_position().row);
+    let mut lines = vec!["/**".to_string(), " * ".to_string()];
+    let tags = javadoc_tags(declaration_node, source);
+    if !tags.is_empty() {
+        lines.push(" *".to_string());
+    }
+    for tag in tags {
+        lines.push(format!(" * {}", tag));
+    }
+    lines.push(" */".to_string());
+    let comment = lines
+        .into_iter()
+        .map(|line| format!("{}\n{}", line, indentation))
+        .collect::<String>();
+    let mut builder = EditBuilder::new();
+    builder.insert(uri, LineIndex::new(source).position(declaration_node.start_position()), comment);
+    let action = builder.into_action("Add Javadoc", CodeActionKind::REFACTOR);
+    return vec![CodeActionOrCommand::CodeAction(action)];
+}
+
+fn has_javadoc(declaration_node: Node, source: &str) -> bool {
+    return match declaration_node.prev_named_sibling() {
+        Some(previous) => previous.kind() == "block_comment" && node_text(previous, source).starts_with("/**"),
+        None => false,
+    };
+}
+
+/// Block tags for the type parameters, parameters, return value and declared exceptions, in the
+/// order the Javadoc style guide lists them.
+pub fn javadoc_tags(declaration_node: Node, source: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    if let Some(type_parameters_node) = declaration_node.child_by_field_name("type_parameters") {
+        for name in hierarchy::type_parameter_names(type_parameters_node, source) {
+            tags.push(format!("@param <{}>", name));
+        }
+    }
+    if let Some(parameters_node) = declaration_node.child_by_field_name("parameters") {
+        for parameter_node in parameters_node.named_children(&mut parameters_node.walk()) {
+            let name_node = match parameter_node.kind() {
+                "formal_parameter" => parameter_node.child_by_field_name("name"),
+                "spread_parameter" => parameter_node
+                    .named_children(&mut parameter_node.walk())
+                    .find(|n| n.kind() == "variable_declarator")
+                    .and_then(|n| n.child_by_field_name("name")),
+                _ => None,
+            };
+            if let Some(name_node) = name_node {
+                tags.push(format!("@param {}", node_text(name_node, source)));
+            }
+        }
+    }
+    if declaration_node.kind() == "method_declaration" {
+        let returns_value = declaration_node
+            .child_by_field_name("type")
+            .map(|type_node| type_node.kind() != "void_type")
+            .unwrap_or(false);
+        if returns_value {
+            tags.push("@return".to_string());
+        }
+    }
+    let throws_node = declaration_node
+        .named_children(&mut declaration_node.walk())
+        .find(|n| n.kind() == "throws");
+    if let Some(throws_node) = throws_node {
+        for exception_node in throws_node.named_children(&mut throws_node.walk()) {
+            tags.push(format!("@throws {}", node_text(exception_node, source)));
+        }
+    }
+    return tags;
+}
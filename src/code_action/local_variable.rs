@@ -0,0 +1,399 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::code_action::edit::{line_indentation, node_range, node_text, EditBuilder};
+use crate::code_action::node_at;
+use crate::code_action::side_effects::{collect_declared_names, evaluation, is_read, local_names, Effects, Evaluation};
+use crate::code_action::surround::is_statement_container;
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+/// Expressions worth naming. Plain variables, `this`, `null` and lambdas, which need a target
+/// type, are left out.
+const EXTRACTABLE_KINDS: [&str; 22] = [
+    "binary_expression",
+    "unary_expression",
+    "ternary_expression",
+    "cast_expression",
+    "instanceof_expression",
+    "parenthesized_expression",
+    "method_invocation",
+    "object_creation_expression",
+    "array_creation_expression",
+    "field_access",
+    "array_access",
+    "string_literal",
+    "character_literal",
+    "decimal_integer_literal",
+    "hex_integer_literal",
+    "octal_integer_literal",
+    "binary_integer_literal",
+    "decimal_floating_point_literal",
+    "hex_floating_point_literal",
+    "true",
+    "false",
+    "class_literal",
+];
+
+/// Expressions that bind looser than a method call or field access, so need parentheses when
+/// they replace a variable inside another expression.
+const COMPOUND_KINDS: [&str; 8] = [
+    "binary_expression",
+    "unary_expression",
+    "ternary_expression",
+    "cast_expression",
+    "instanceof_expression",
+    "assignment_expression",
+    "lambda_expression",
+    "update_expression",
+];
+
+const JAVA_KEYWORDS: [&str; 50] = [
+    "abstract", "assert", "boolean", "break", "byte", "case", "catch", "char", "class", "const", "continue", "default",
+    "do", "double", "else", "enum", "extends", "final", "finally", "float", "for", "goto", "if", "implements",
+    "import", "instanceof", "int", "interface", "long", "native", "new", "package", "private", "protected",
+    "public", "return", "short", "static", "strictfp", "super", "switch", "synchronized", "this", "throw",
+    "throws", "transient", "try", "void", "volatile", "while",
+];
+
+/// Offers "Extract local variable" on a selected expression and "Inline local variable" on a local
+/// declaration. When the refactoring would run code in a different order, a different number of
+/// times or not at all, the action is still listed but disabled, with the reason.
+pub fn local_variable_actions(backend: &Backend, uri: &Url, tree: &Tree, source: &str, range: Range) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    if let Some(action) = extract_variable(backend, uri, tree, source, range) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    if let Some(action) = inline_variable(uri, tree, source, range) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    return actions;
+}
+
+fn disabled_action(title: &str, kind: CodeActionKind, reason: &str) -> CodeAction {
+    return CodeAction {
+        title: title.to_string(),
+        kind: Some(kind),
+        disabled: Some(CodeActionDisabled {
+            reason: reason.to_string(),
+        }),
+        ..CodeAction::default()
+    };
+}
+
+/// The statement directly inside a block that contains `node`.
+fn enclosing_statement(node: Node) -> Option<Node> {
+    let mut statement = node;
+    loop {
+        let parent = statement.parent()?;
+        if is_statement_container(parent.kind()) {
+            return Some(statement);
+        }
+        statement = parent;
+    }
+}
+
+/// `foo(a.size() + 1)` becomes `var size = a.size();` followed by `foo(size + 1)`, unless hoisting
+/// the expression ahead of its statement would reorder side effects or change how often it runs.
+fn extract_variable(backend: &Backend, uri: &Url, tree: &Tree, source: &str, range: Range) -> Option<CodeAction> {
+    const TITLE: &str = "Extract local variable";
+    if range.start == range.end {
+        return None;
+    }
+    let lines = LineIndex::new(source);
+    let (start, end) = (lines.point(range.start), lines.point(range.end));
+    let expression_node = tree.root_node().named_descendant_for_point_range(start, end)?;
+    if expression_node.start_position() != start || expression_node.end_position() != end {
+        return None;
+    }
+    if !EXTRACTABLE_KINDS.contains(&expression_node.kind()) {
+        return None;
+    }
+    let parent_node = expression_node.parent()?;
+    let unsuitable = match parent_node.kind() {
+        "assignment_expression" => parent_node.child_by_field_name("left") == Some(expression_node),
+        "update_expression" => true,
+        // a call used as a statement may return nothing
+        "expression_statement" => true,
+        // already a variable
+        "variable_declarator" => true,
+        _ => false,
+    };
+    if unsuitable {
+        return None;
+    }
+    let statement_node = enclosing_statement(expression_node)?;
+    let mut declared_in_statement = HashSet::new();
+    collect_declared_names(statement_node, source, &mut declared_in_statement);
+
+    let locals = local_names(statement_node, source);
+    let effects = Effects::of(expression_node, source, &locals);
+    if effects.local_reads.iter().any(|name| declared_in_statement.contains(name)) {
+        // it uses a variable that doesn't exist yet before the statement
+        return None;
+    }
+    let reason = match evaluation(expression_node, statement_node) {
+        Evaluation::Deferred => Some("The expression runs later, inside a lambda or anonymous class"),
+        Evaluation::Repeated if effects.has_side_effects() || effects.allocates || effects.reads_anything() => {
+            Some("The expression is evaluated on every iteration of the loop")
+        }
+        Evaluation::Conditional if effects.has_side_effects() || effects.allocates || effects.reads_heap => {
+            Some("The expression is only evaluated under a condition")
+        }
+        _ => {
+            let mut before = Effects::default();
+            before.add_before(statement_node, expression_node, source, &locals);
+            if before.interferes_with(&effects) {
+                Some("Code evaluated before the expression could change its value or depend on its side effects")
+            } else {
+                None
+            }
+        }
+    };
+    if let Some(reason) = reason {
+        return Some(disabled_action(TITLE, CodeActionKind::REFACTOR_EXTRACT, reason));
+    }
+
+    let mut taken = locals;
+    collect_read_names(statement_node.parent()?, source, &mut taken);
+    let name = unique_name(&suggested_name(expression_node, source), &taken);
+    let declared_type = extracted_type(backend, uri, expression_node, source);
+    let indentation = line_indentation(source, statement_node.start_position().row);
+    let mut builder = EditBuilder::new();
+    builder.insert(
+        uri,
+        lines.position(statement_node.start_position()),
+        format!("{} {} = {};\n{}", declared_type, name, node_text(expression_node, source), indentation),
+    );
+    builder.replace_node(uri, source, expression_node, name);
+    return Some(builder.into_action(TITLE, CodeActionKind::REFACTOR_EXTRACT));
+}
+
+/// The declared type when it can be read off the expression, otherwise `var`.
+fn extracted_type(backend: &Backend, uri: &Url, expression_node: Node, source: &str) -> String {
+    let type_node = match expression_node.kind() {
+        "object_creation_expression" | "cast_expression" => expression_node.child_by_field_name("type"),
+        _ => None,
+    };
+    if let Some(type_node) = type_node {
+        let type_text = node_text(type_node, source);
+        if type_text.contains("<>") {
+            return "var".to_string();
+        }
+        return type_text.to_string();
+    }
+    return backend
+        .expression_type(uri.as_str(), expression_node, source)
+        .filter(|expression_type| expression_type != "Object")
+        .unwrap_or_else(|| "var".to_string());
+}
+
+/// A name after what the expression computes: `getName()` suggests `name`, `new Parser()` suggests
+/// `parser`, and anything else `value`.
+pub fn suggested_name(expression_node: Node, source: &str) -> String {
+    let base = match expression_node.kind() {
+        "method_invocation" => expression_node.child_by_field_name("name").map(|n| node_text(n, source).to_string()),
+        "field_access" => expression_node.child_by_field_name("field").map(|n| node_text(n, source).to_string()),
+        "object_creation_expression" | "cast_expression" => expression_node.child_by_field_name("type").map(|type_node| {
+            let type_text = node_text(type_node, source);
+            let simple_name = type_text.split('<').next().unwrap_or(type_text);
+            simple_name.rsplit('.').next().unwrap_or(simple_name).to_string()
+        }),
+        "string_literal" => Some("text".to_string()),
+        "parenthesized_expression" => {
+            return match expression_node.named_child(0) {
+                Some(inner_node) => suggested_name(inner_node, source),
+                None => "value".to_string(),
+            };
+        }
+        _ => None,
+    };
+    let base = match base {
+        Some(base) => base,
+        None => return "value".to_string(),
+    };
+    let base = match base.strip_prefix("get") {
+        Some(rest) if rest.starts_with(|c: char| c.is_uppercase()) => rest.to_string(),
+        _ => base,
+    };
+    let mut chars = base.chars();
+    let name = match chars.next() {
+        Some(first) => format!("{}{}", first.to_lowercase(), chars.as_str()),
+        None => return "value".to_string(),
+    };
+    if JAVA_KEYWORDS.contains(&name.as_str()) {
+        return format!("{}Value", name);
+    }
+    return name;
+}
+
+fn unique_name(name: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(name) {
+        return name.to_string();
+    }
+    let mut suffix = 2;
+    while taken.contains(&format!("{}{}", name, suffix)) {
+        suffix += 1;
+    }
+    return format!("{}{}", name, suffix);
+}
+
+/// Adds the names of the variables read in `node`, which a new local mustn't shadow.
+fn collect_read_names(node: Node, source: &str, names: &mut HashSet<String>) {
+    if node.kind() == "identifier" && is_read(node) {
+        names.insert(node_text(node, source).to_string());
+    }
+    for child in node.named_children(&mut node.walk()) {
+        collect_read_names(child, source, names);
+    }
+}
+
+/// `var size = a.size();` followed by `foo(size + 1)` becomes `foo(a.size() + 1)`, unless the
+/// initializer would then run more than once, not at all, or after code it must precede.
+fn inline_variable(uri: &Url, tree: &Tree, source: &str, range: Range) -> Option<CodeAction> {
+    const TITLE: &str = "Inline local variable";
+    let declaration_node = node_at(tree, source, range.start, "local_variable_declaration")?;
+    let block_node = declaration_node.parent()?;
+    if !is_statement_container(block_node.kind()) {
+        return None;
+    }
+    let mut cursor = declaration_node.walk();
+    let declarators: Vec<Node> = declaration_node.children_by_field_name("declarator", &mut cursor).collect();
+    let declarator_node = *declarators.first()?;
+    if declarators.len() != 1 || declarator_node.child_by_field_name("dimensions").is_some() {
+        return None;
+    }
+    let value_node = declarator_node.child_by_field_name("value")?;
+    if value_node.kind() == "array_initializer" {
+        return None;
+    }
+    let name = node_text(declarator_node.child_by_field_name("name")?, source);
+
+    let mut scope = Vec::new();
+    let mut next = declaration_node.next_named_sibling();
+    while let Some(statement_node) = next {
+        scope.push(statement_node);
+        next = statement_node.next_named_sibling();
+    }
+    let mut usages = Vec::new();
+    for statement_node in &scope {
+        collect_usages(*statement_node, name, source, &mut usages);
+    }
+    let mut assigned = false;
+    for usage_node in &usages {
+        let parent_node = usage_node.parent()?;
+        assigned |= parent_node.kind() == "update_expression"
+            || (parent_node.kind() == "assignment_expression" && parent_node.child_by_field_name("left") == Some(*usage_node));
+    }
+    if assigned {
+        return None;
+    }
+
+    let locals = local_names(declaration_node, source);
+    let effects = Effects::of(value_node, source, &locals);
+    let reason = inline_blocker(&effects, &scope, &usages, source, &locals);
+    if let Some(reason) = reason {
+        return Some(disabled_action(TITLE, CodeActionKind::REFACTOR_INLINE, reason));
+    }
+
+    let value = node_text(value_node, source);
+    let mut builder = EditBuilder::new();
+    for usage_node in &usages {
+        let parent_kind = usage_node.parent().map(|n| n.kind()).unwrap_or("");
+        let standalone = matches!(
+            parent_kind,
+            "argument_list" | "parenthesized_expression" | "condition" | "variable_declarator" | "return_statement" | "array_initializer"
+        );
+        if COMPOUND_KINDS.contains(&value_node.kind()) && !standalone {
+            builder.replace_node(uri, source, *usage_node, format!("({})", value));
+        } else {
+            builder.replace_node(uri, source, *usage_node, value);
+        }
+    }
+    builder.replace(uri, statement_deletion_range(declaration_node, source), "");
+    return Some(builder.into_action(TITLE, CodeActionKind::REFACTOR_INLINE));
+}
+
+fn collect_usages<'a>(node: Node<'a>, name: &str, source: &str, usages: &mut Vec<Node<'a>>) {
+    if node.kind() == "identifier" && is_read(node) && node_text(node, source) == name {
+        usages.push(node);
+    }
+    for child in node.named_children(&mut node.walk()) {
+        collect_usages(child, name, source, usages);
+    }
+}
+
+/// Why moving an initializer with `effects` to each of `usages` would change what the code does.
+fn inline_blocker(
+    effects: &Effects,
+    scope: &[Node],
+    usages: &[Node],
+    source: &str,
+    locals: &HashSet<String>,
+) -> Option<&'static str> {
+    let pure = effects.is_pure();
+    let last_usage = match usages.last() {
+        Some(last_usage) => *last_usage,
+        None if pure => return None,
+        None => return Some("The variable is unused, so inlining would drop the side effects of its initializer"),
+    };
+    if usages.len() > 1 && !pure {
+        return Some("The initializer has side effects and would be evaluated once per use");
+    }
+    let mut between = Effects::default();
+    for usage_node in usages {
+        let statement_node = scope.iter().find(|s| s.start_byte() <= usage_node.start_byte() && usage_node.end_byte() <= s.end_byte())?;
+        match evaluation(*usage_node, *statement_node) {
+            Evaluation::Once => {}
+            Evaluation::Conditional if pure => {}
+            Evaluation::Conditional => return Some("The variable is only used under a condition, where the initializer might not run"),
+            Evaluation::Deferred if effects.reads_heap => {
+                return Some("The variable is used in a lambda or anonymous class, which could run after the initializer's value changed");
+            }
+            _ if !pure => return Some("The variable is used in a loop or lambda, where the initializer would run repeatedly"),
+            _ if effects.reads_anything() => {
+                // the initializer would observe every change made while the loop or lambda runs
+                for statement_node in scope {
+                    between.add(*statement_node, source, locals);
+                }
+            }
+            _ => {}
+        }
+    }
+    for statement_node in scope {
+        if statement_node.end_byte() <= last_usage.start_byte() {
+            between.add(*statement_node, source, locals);
+        } else {
+            between.add_before(*statement_node, last_usage, source, locals);
+            break;
+        }
+    }
+    if between.interferes_with(effects) {
+        return Some("Code between the declaration and the use could change the initializer's value or depend on its side effects");
+    }
+    return None;
+}
+
+/// The range of `statement_node`, widened to its whole lines when nothing else shares them.
+fn statement_deletion_range(statement_node: Node, source: &str) -> Range {
+    let range = node_range(source, statement_node);
+    let start_line = source.lines().nth(statement_node.start_position().row).unwrap_or("");
+    let end_line = source.lines().nth(statement_node.end_position().row).unwrap_or("");
+    let alone = start_line[..statement_node.start_position().column].trim().is_empty()
+        && end_line[statement_node.end_position().column..].trim().is_empty();
+    if !alone {
+        return range;
+    }
+    return Range {
+        start: Position {
+            line: range.start.line,
+            character: 0,
+        },
+        end: Position {
+            line: range.end.line + 1,
+            character: 0,
+        },
+    };
+}
@@ -0,0 +1,79 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::diagnostics::MISSING_OVERRIDE;
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+mod create_method;
+mod declaration;
+mod edit;
+mod invert_if;
+mod javadoc;
+mod local_variable;
+mod side_effects;
+mod surround;
+
+use edit::{line_indentation, EditBuilder};
+
+pub use create_method::{create_method_actions, parameter_name_for_type};
+pub use declaration::declaration_actions;
+pub use invert_if::invert_if_actions;
+pub use javadoc::{javadoc_actions, javadoc_tags};
+pub use local_variable::{local_variable_actions, suggested_name};
+pub use surround::{is_statement_container, surround_with_try_catch_actions};
+
+/// Offers to insert `@Override` above every method flagged by a missing-override diagnostic.
+pub fn add_override_actions(uri: &Url, tree: &Tree, source: &str, diagnostics: &[Diagnostic]) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    for diagnostic in diagnostics {
+        if diagnostic.code != Some(NumberOrString::String(MISSING_OVERRIDE.to_string())) {
+            continue;
+        }
+        let method_node = match node_at(tree, source, diagnostic.range.start, "method_declaration") {
+            Some(method_node) => method_node,
+            None => continue,
+        };
+        let start = method_node.start_position();
+        let indentation = line_indentation(source, start.row);
+        let mut builder = EditBuilder::new();
+        builder.insert(uri, LineIndex::new(source).position(start), format!("@Override\n{}", indentation));
+        let mut action = builder.into_quick_fix("Add @Override annotation", diagnostic);
+        action.is_preferred = Some(true);
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    return actions;
+}
+
+/// Rewrites the line breaks of every edit, which are generated as `\n`, to the ones each target
+/// document should use.
+pub fn use_line_endings(backend: &Backend, actions: &mut [CodeActionOrCommand]) {
+    for action in actions {
+        let changes = match action {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                edit: Some(WorkspaceEdit { changes: Some(changes), .. }),
+                ..
+            }) => changes,
+            _ => continue,
+        };
+        for (uri, edits) in changes.iter_mut() {
+            let line_ending = backend.line_ending(uri);
+            if line_ending == "\n" {
+                continue;
+            }
+            for edit in edits {
+                edit.new_text = edit.new_text.replace("\r\n", "\n").replace('\n', line_ending);
+            }
+        }
+    }
+}
+
+/// The innermost node of `kind` enclosing `position`.
+pub fn node_at<'a>(tree: &'a Tree, source: &str, position: Position, kind: &str) -> Option<Node<'a>> {
+    let point = LineIndex::new(source).point(position);
+    let mut node = tree.root_node().named_descendant_for_point_range(point, point)?;
+    while node.kind() != kind {
+        node = node.parent()?;
+    }
+    return Some(node);
+}
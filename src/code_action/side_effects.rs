@@ -0,0 +1,404 @@
+use std::collections::HashSet;
+
+use tree_sitter::Node;
+
+use crate::code_action::edit::node_text;
+
+/// Kinds whose body runs on its own schedule rather than where it's written.
+const DEFERRED_KINDS: [&str; 2] = ["lambda_expression", "class_body"];
+
+/// Kinds that declare the locals of a method, constructor or initializer.
+const CALLABLE_KINDS: [&str; 5] = [
+    "method_declaration",
+    "constructor_declaration",
+    "compact_constructor_declaration",
+    "static_initializer",
+    "class_body",
+];
+
+/// What evaluating some code may observe or change, as far as reordering it against other code is
+/// concerned. Locals only change through assignments and increments written in the method itself,
+/// while fields, array elements and whatever they reference may also change during any call.
+#[derive(Debug, Default)]
+pub struct Effects {
+    /// method or constructor calls, whose effects are unknown
+    pub calls: bool,
+    /// array creations and constructor calls, which produce a new object every time
+    pub allocates: bool,
+    pub reads_heap: bool,
+    pub writes_heap: bool,
+    pub local_reads: HashSet<String>,
+    pub local_writes: HashSet<String>,
+}
+
+impl Effects {
+    pub fn of(node: Node, source: &str, locals: &HashSet<String>) -> Effects {
+        let mut effects = Effects::default();
+        effects.add(node, source, locals);
+        return effects;
+    }
+
+    /// Adds the effects of evaluating `node`. Lambda and anonymous class bodies are skipped, since
+    /// creating them runs none of their code.
+    pub fn add(&mut self, node: Node, source: &str, locals: &HashSet<String>) {
+        match node.kind() {
+            kind if DEFERRED_KINDS.contains(&kind) => return,
+            "method_invocation" | "explicit_constructor_invocation" => self.calls = true,
+            "object_creation_expression" => {
+                self.calls = true;
+                self.allocates = true;
+            }
+            "array_creation_expression" => self.allocates = true,
+            "field_access" => {
+                // static fields read through their class, like `System.out`, are taken to be constants
+                let through_class = node
+                    .child_by_field_name("object")
+                    .map(|object_node| is_class_name(object_node, source, locals))
+                    .unwrap_or(false);
+                self.reads_heap |= !through_class;
+            }
+            "array_access" => self.reads_heap = true,
+            "assignment_expression" => {
+                let compound = node.child_by_field_name("operator").map(|n| n.kind()) != Some("=");
+                if let Some(left_node) = node.child_by_field_name("left") {
+                    self.add_target(left_node, compound, source, locals);
+                }
+                if let Some(right_node) = node.child_by_field_name("right") {
+                    self.add(right_node, source, locals);
+                }
+                return;
+            }
+            "update_expression" => {
+                if let Some(operand_node) = node.named_child(0) {
+                    self.add_target(operand_node, true, source, locals);
+                }
+                return;
+            }
+            "identifier" => {
+                if is_read(node) && !is_class_name(node, source, locals) {
+                    let name = node_text(node, source);
+                    if locals.contains(name) {
+                        self.local_reads.insert(name.to_string());
+                    } else {
+                        self.reads_heap = true;
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+        for child in node.named_children(&mut node.walk()) {
+            self.add(child, source, locals);
+        }
+    }
+
+    /// Adds the effects of assigning to `target`, which compound assignments and increments also read.
+    fn add_target(&mut self, target: Node, compound: bool, source: &str, locals: &HashSet<String>) {
+        match target.kind() {
+            "identifier" => {
+                let name = node_text(target, source).to_string();
+                if locals.contains(&name) {
+                    if compound {
+                        self.local_reads.insert(name.clone());
+                    }
+                    self.local_writes.insert(name);
+                } else {
+                    self.reads_heap |= compound;
+                    self.writes_heap = true;
+                }
+            }
+            "parenthesized_expression" => {
+                if let Some(inner_node) = target.named_child(0) {
+                    self.add_target(inner_node, compound, source, locals);
+                }
+            }
+            _ => {
+                // the object or array and the index are evaluated as usual before the store
+                self.writes_heap = true;
+                self.reads_heap |= compound;
+                for child in target.named_children(&mut target.walk()) {
+                    if child.kind() != "identifier" || target.child_by_field_name("field") != Some(child) {
+                        self.add(child, source, locals);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the effects of the parts of `node` evaluated before `target`, taking source order as
+    /// evaluation order, which Java follows for operands, arguments and statements alike.
+    pub fn add_before(&mut self, node: Node, target: Node, source: &str, locals: &HashSet<String>) {
+        for child in node.named_children(&mut node.walk()) {
+            if child.end_byte() <= target.start_byte() {
+                self.add(child, source, locals);
+            } else if child.start_byte() <= target.start_byte() && target.end_byte() <= child.end_byte() && child != target {
+                self.add_before(child, target, source, locals);
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn has_side_effects(&self) -> bool {
+        return self.calls || self.writes_heap || !self.local_writes.is_empty();
+    }
+
+    /// Whether evaluating the code again, or elsewhere, yields an equal value without changing anything.
+    pub fn is_pure(&self) -> bool {
+        return !self.has_side_effects() && !self.allocates;
+    }
+
+    pub fn reads_anything(&self) -> bool {
+        return self.reads_heap || !self.local_reads.is_empty();
+    }
+
+    /// Whether evaluating `self` and `other` in the opposite order could change what either of
+    /// them computes or does.
+    pub fn interferes_with(&self, other: &Effects) -> bool {
+        let overlaps = |a: &HashSet<String>, b: &HashSet<String>| a.intersection(b).next().is_some();
+        if overlaps(&self.local_writes, &other.local_reads)
+            || overlaps(&other.local_writes, &self.local_reads)
+            || overlaps(&self.local_writes, &other.local_writes)
+        {
+            return true;
+        }
+        let changes_heap = |effects: &Effects| effects.calls || effects.writes_heap;
+        let observes_heap = |effects: &Effects| effects.calls || effects.writes_heap || effects.reads_heap;
+        return (changes_heap(self) && observes_heap(other)) || (changes_heap(other) && observes_heap(self));
+    }
+}
+
+/// How often, and when, code nested in a statement runs compared to the statement itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Evaluation {
+    Once,
+    Conditional,
+    Repeated,
+    Deferred,
+}
+
+/// How `node` is evaluated relative to the enclosing `statement`: on an `&&`/`||` right operand,
+/// a conditional branch or a `case` it may not run at all, in a loop condition, update or body it
+/// may run many times, and in a lambda or anonymous class it runs whenever that code is called.
+pub fn evaluation(node: Node, statement: Node) -> Evaluation {
+    let mut result = Evaluation::Once;
+    let mut child = node;
+    while child != statement {
+        let parent = match child.parent() {
+            Some(parent) => parent,
+            None => break,
+        };
+        let is_field = |field: &str| parent.child_by_field_name(field) == Some(child);
+        let here = match parent.kind() {
+            kind if DEFERRED_KINDS.contains(&kind) => Evaluation::Deferred,
+            "binary_expression" => {
+                let operator = parent.child_by_field_name("operator").map(|n| n.kind());
+                if (operator == Some("&&") || operator == Some("||")) && is_field("right") {
+                    Evaluation::Conditional
+                } else {
+                    Evaluation::Once
+                }
+            }
+            "ternary_expression" | "if_statement" if !is_field("condition") => Evaluation::Conditional,
+            "switch_block_statement_group" | "switch_rule" | "catch_clause" | "finally_clause" => Evaluation::Conditional,
+            "while_statement" | "do_statement" => Evaluation::Repeated,
+            "for_statement" if !is_field("init") => Evaluation::Repeated,
+            "enhanced_for_statement" if is_field("body") => Evaluation::Repeated,
+            _ => Evaluation::Once,
+        };
+        result = result.max(here);
+        child = parent;
+    }
+    return result;
+}
+
+/// The names of the parameters and local variables declared in the method, constructor or
+/// initializer around `node`, including those of nested lambdas and blocks.
+pub fn local_names(node: Node, source: &str) -> HashSet<String> {
+    let mut callable = node;
+    while !CALLABLE_KINDS.contains(&callable.kind()) {
+        callable = match callable.parent() {
+            Some(parent) => parent,
+            None => break,
+        };
+    }
+    let mut names = HashSet::new();
+    collect_declared_names(callable, source, &mut names);
+    return names;
+}
+
+/// Adds the names of the variables declared anywhere in `node`.
+pub fn collect_declared_names(node: Node, source: &str, names: &mut HashSet<String>) {
+    let name_node = match node.kind() {
+        "variable_declarator" | "formal_parameter" | "catch_formal_parameter" | "enhanced_for_statement" | "instanceof_expression" => {
+            node.child_by_field_name("name")
+        }
+        "lambda_expression" => node.child_by_field_name("parameters").filter(|n| n.kind() == "identifier"),
+        "inferred_parameters" => {
+            for parameter_node in node.named_children(&mut node.walk()) {
+                names.insert(node_text(parameter_node, source).to_string());
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(name_node) = name_node.filter(|n| n.kind() == "identifier") {
+        names.insert(node_text(name_node, source).to_string());
+    }
+    for child in node.named_children(&mut node.walk()) {
+        // the members of nested classes aren't locals
+        if child.kind() != "class_body" {
+            collect_declared_names(child, source, names);
+        }
+    }
+}
+
+/// Whether `node` looks like a class used as a qualifier: a capitalized name that isn't a local.
+fn is_class_name(node: Node, source: &str, locals: &HashSet<String>) -> bool {
+    if node.kind() != "identifier" {
+        return false;
+    }
+    let name = node_text(node, source);
+    return name.starts_with(|c: char| c.is_uppercase()) && !locals.contains(name);
+}
+
+/// Whether the identifier is read as a variable, rather than naming a method, a field selected
+/// from an object, a label or a declaration.
+pub fn is_read(identifier: Node) -> bool {
+    let parent = match identifier.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    let is_field = |field: &str| parent.child_by_field_name(field) == Some(identifier);
+    return match parent.kind() {
+        "method_invocation" => !is_field("name"),
+        "field_access" => !is_field("field"),
+        "variable_declarator" | "formal_parameter" | "catch_formal_parameter" | "enhanced_for_statement" | "instanceof_expression" => {
+            !is_field("name")
+        }
+        "lambda_expression" => !is_field("parameters"),
+        // `list::add` reads `list`, while the name after `::` selects a method
+        "method_reference" => parent.named_child(0) == Some(identifier),
+        "inferred_parameters" | "labeled_statement" | "break_statement" | "continue_statement" => false,
+        _ => true,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::{Parser, Tree};
+
+    use super::*;
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        return parser.parse(source, None).unwrap();
+    }
+
+    /// The outermost node whose text is `text`.
+    fn find<'a>(node: Node<'a>, source: &str, text: &str) -> Option<Node<'a>> {
+        if node_text(node, source) == text {
+            return Some(node);
+        }
+        return node.named_children(&mut node.walk()).find_map(|child| find(child, source, text));
+    }
+
+    /// The effects of the expression `text` in a method with the locals `a` and `b`, a field `f`
+    /// and the body `body`.
+    fn effects_in(body: &str, text: &str) -> Effects {
+        let source = format!("class C {{ int f; int[] xs; void m(int a) {{ int b = 0; {} }} }}", body);
+        let tree = parse(&source);
+        let node = find(tree.root_node(), &source, text).unwrap();
+        return Effects::of(node, &source, &local_names(node, &source));
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        return names.iter().map(|name| name.to_string()).collect();
+    }
+
+    #[test]
+    fn tells_locals_from_the_heap() {
+        let effects = effects_in("int c = a + f;", "a + f");
+        assert_eq!(effects.local_reads, names(&["a"]));
+        assert!(effects.reads_heap);
+        assert!(effects.is_pure());
+
+        let effects = effects_in("b += xs[a]++;", "b += xs[a]++");
+        assert_eq!(effects.local_reads, names(&["a", "b"]));
+        assert_eq!(effects.local_writes, names(&["b"]));
+        assert!(effects.reads_heap && effects.writes_heap);
+        assert!(effects.has_side_effects());
+    }
+
+    #[test]
+    fn takes_classes_for_constants_and_calls_for_anything() {
+        let effects = effects_in("System.out.println(a);", "System.out.println(a)");
+        assert!(effects.calls);
+        assert!(!effects.reads_heap);
+        assert_eq!(effects.local_reads, names(&["a"]));
+
+        let effects = effects_in("Object o = new int[a];", "new int[a]");
+        assert!(effects.allocates && !effects.calls);
+        assert!(!effects.has_side_effects() && !effects.is_pure());
+    }
+
+    #[test]
+    fn skips_the_bodies_of_lambdas() {
+        let effects = effects_in("Runnable r = () -> f++;", "() -> f++");
+        assert!(!effects.has_side_effects());
+        assert!(!effects.reads_anything());
+    }
+
+    #[test]
+    fn finds_interfering_effects() {
+        let read_a = effects_in("int c = a;", "int c = a;");
+        let write_a = effects_in("a++;", "a++;");
+        let read_b = effects_in("int c = b;", "int c = b;");
+        let read_f = effects_in("int c = f;", "int c = f;");
+        let call = effects_in("m(1);", "m(1);");
+        assert!(read_a.interferes_with(&write_a));
+        assert!(write_a.interferes_with(&read_a));
+        assert!(!read_b.interferes_with(&write_a));
+        assert!(!read_a.interferes_with(&read_f));
+        assert!(call.interferes_with(&read_f));
+        assert!(!call.interferes_with(&read_a));
+    }
+
+    #[test]
+    fn adds_what_runs_before_a_node() {
+        let source = "class C { void m(int a, int b) { g(a++, h(), b); } }";
+        let tree = parse(source);
+        let statement = find(tree.root_node(), source, "g(a++, h(), b);").unwrap();
+        let target = find(tree.root_node(), source, "h()").unwrap();
+        let mut effects = Effects::default();
+        effects.add_before(statement, target, source, &local_names(statement, source));
+        assert_eq!(effects.local_writes, names(&["a"]));
+        assert!(!effects.calls);
+        assert!(!effects.local_reads.contains("b"));
+    }
+
+    #[test]
+    fn tells_how_often_nested_code_runs() {
+        let source = "class C { void m(boolean c) { if (c && d()) { e(); } for (int i = init(); i < n(); i++) { f(); } run(() -> g()); } }";
+        let tree = parse(source);
+        let body = find(tree.root_node(), source, "m").unwrap().parent().unwrap().child_by_field_name("body").unwrap();
+        let statements: Vec<Node> = body.named_children(&mut body.walk()).collect();
+        let evaluation_of = |statement: Node, text: &str| evaluation(find(statement, source, text).unwrap(), statement);
+        assert_eq!(evaluation_of(statements[0], "c"), Evaluation::Once);
+        assert_eq!(evaluation_of(statements[0], "d()"), Evaluation::Conditional);
+        assert_eq!(evaluation_of(statements[0], "e()"), Evaluation::Conditional);
+        assert_eq!(evaluation_of(statements[1], "init()"), Evaluation::Once);
+        assert_eq!(evaluation_of(statements[1], "n()"), Evaluation::Repeated);
+        assert_eq!(evaluation_of(statements[1], "f()"), Evaluation::Repeated);
+        assert_eq!(evaluation_of(statements[2], "g()"), Evaluation::Deferred);
+    }
+
+    #[test]
+    fn collects_the_locals_of_the_method() {
+        let source = "class C { int f; void m(int a) { int b; for (String s : list) {} run(x -> x); new Object() { int g; }; } }";
+        let tree = parse(source);
+        let node = find(tree.root_node(), source, "int b;").unwrap();
+        assert_eq!(local_names(node, source), names(&["a", "b", "s", "x"]));
+    }
+}
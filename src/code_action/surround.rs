@@ -0,0 +1,423 @@
+use dashmap::DashMap;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::code_action::edit::{indent_unit, line_indentation, reindent, EditBuilder};
+use crate::editorconfig::EditorConfig;
+use crate::hierarchy::{self, TypeDeclaration};
+use crate::line_index::LineIndex;
+
+/// Types from the JDK that implement `AutoCloseable` and commonly appear as locals.
+const JDK_AUTO_CLOSEABLE_TYPES: [&str; 24] = [
+    "AutoCloseable",
+    "Closeable",
+    "InputStream",
+    "OutputStream",
+    "FileInputStream",
+    "FileOutputStream",
+    "BufferedInputStream",
+    "BufferedOutputStream",
+    "ObjectInputStream",
+    "ObjectOutputStream",
+    "Reader",
+    "Writer",
+    "FileReader",
+    "FileWriter",
+    "BufferedReader",
+    "BufferedWriter",
+    "InputStreamReader",
+    "PrintWriter",
+    "Scanner",
+    "Connection",
+    "Statement",
+    "PreparedStatement",
+    "ResultSet",
+    "Stream",
+];
+
+/// Unchecked exceptions from the JDK, which never need to be caught.
+const JDK_UNCHECKED_EXCEPTIONS: [&str; 12] = [
+    "RuntimeException",
+    "Error",
+    "IllegalArgumentException",
+    "IllegalStateException",
+    "NullPointerException",
+    "UnsupportedOperationException",
+    "IndexOutOfBoundsException",
+    "ArrayIndexOutOfBoundsException",
+    "ArithmeticException",
+    "ClassCastException",
+    "NumberFormatException",
+    "ConcurrentModificationException",
+];
+
+/// Superclasses of checked exceptions from the JDK, for the ones commonly thrown together.
+const JDK_EXCEPTION_SUPERCLASSES: [(&str, &str); 8] = [
+    ("IOException", "Exception"),
+    ("FileNotFoundException", "IOException"),
+    ("EOFException", "IOException"),
+    ("UnsupportedEncodingException", "IOException"),
+    ("MalformedURLException", "IOException"),
+    ("UnknownHostException", "IOException"),
+    ("ReflectiveOperationException", "Exception"),
+    ("ClassNotFoundException", "ReflectiveOperationException"),
+];
+
+/// Offers to wrap the statements covered by `range` in a try/catch block, and in a
+/// try-with-resources block when the first selected statement declares an `AutoCloseable`. Offers
+/// neither when a statement after them uses a variable they declare, which the block would hide.
+pub fn surround_with_try_catch_actions(
+    uri: &Url,
+    tree: &Tree,
+    source: &str,
+    range: Range,
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    style: &EditorConfig,
+) -> Vec<CodeActionOrCommand> {
+    let lines = LineIndex::new(source);
+    let statements = selected_statements(tree, &lines, range);
+    if statements.is_empty() || declares_variable_used_after(&statements, source) {
+        return Vec::new();
+    }
+    let first = statements[0];
+    let last = statements[statements.len() - 1];
+    let indentation = line_indentation(source, first.start_position().row);
+    let unit = indent_unit(source, first, style);
+    let exception_types = thrown_exception_types(uri.as_str(), tree, source, &statements, type_declaration_map);
+    let catch_type = if exception_types.is_empty() {
+        "Exception".to_string()
+    } else {
+        exception_types.join(" | ")
+    };
+    let replace_range = lines.range(first.start_position(), last.end_position());
+    let body_text = |nodes: &[Node]| -> String {
+        let start = match nodes.first() {
+            Some(node) => node.start_byte(),
+            None => return String::new(),
+        };
+        let end = nodes[nodes.len() - 1].end_byte();
+        return reindent(&source[start..end], &indentation, &format!("{}{}", indentation, unit));
+    };
+    let catch_clause = format!(
+        "}} catch ({} e) {{\n{}{}e.printStackTrace();\n{}}}",
+        catch_type, indentation, unit, indentation
+    );
+
+    let mut actions = Vec::new();
+    let try_catch = format!("try {{\n{}\n{}{}", body_text(&statements), indentation, catch_clause);
+    actions.push(surround_action("Surround with try/catch", uri, replace_range, try_catch));
+
+    if let Some(resource) = auto_closeable_resource(uri.as_str(), first, source, type_declaration_map) {
+        let rest = &statements[1..];
+        let body = if rest.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", body_text(rest))
+        };
+        let try_with_resources = format!("try ({}) {{\n{}{}{}", resource, body, indentation, catch_clause);
+        actions.push(surround_action("Surround with try-with-resources", uri, replace_range, try_with_resources));
+    }
+    return actions;
+}
+
+fn surround_action(title: &str, uri: &Url, range: Range, new_text: String) -> CodeActionOrCommand {
+    let mut builder = EditBuilder::new();
+    builder.replace(uri, range, new_text);
+    return CodeActionOrCommand::CodeAction(builder.into_action(title, CodeActionKind::REFACTOR));
+}
+
+/// Returns the consecutive statements of the innermost block that overlap `range`.
+pub fn selected_statements<'a>(tree: &'a Tree, lines: &LineIndex, range: Range) -> Vec<Node<'a>> {
+    let start = lines.point(range.start);
+    let end = lines.point(range.end);
+    let mut statement = match tree.root_node().named_descendant_for_point_range(start, start) {
+        Some(node) => node,
+        None => return Vec::new(),
+    };
+    loop {
+        let parent = match statement.parent() {
+            Some(parent) => parent,
+            None => return Vec::new(),
+        };
+        if is_statement_container(parent.kind()) && statement.is_named() {
+            break;
+        }
+        statement = parent;
+    }
+    let mut statements = vec![statement];
+    let mut next = statement.next_named_sibling();
+    while let Some(sibling) = next {
+        if sibling.start_position() >= end {
+            break;
+        }
+        statements.push(sibling);
+        next = sibling.next_named_sibling();
+    }
+    return statements;
+}
+
+pub fn is_statement_container(kind: &str) -> bool {
+    return matches!(kind, "block" | "constructor_body" | "switch_block_statement_group");
+}
+
+/// Whether one of `statements` declares a local variable that a statement after them in the same
+/// block uses.
+fn declares_variable_used_after(statements: &[Node], source: &str) -> bool {
+    let mut names = Vec::new();
+    for statement in statements.iter().filter(|n| n.kind() == "local_variable_declaration") {
+        for declarator in statement.named_children(&mut statement.walk()).filter(|n| n.kind() == "variable_declarator") {
+            if let Some(name_node) = declarator.child_by_field_name("name") {
+                names.push(name_node.utf8_text(source.as_bytes()).unwrap());
+            }
+        }
+    }
+    if names.is_empty() {
+        return false;
+    }
+    let mut next = statements[statements.len() - 1].next_named_sibling();
+    while let Some(sibling) = next {
+        for node in tree_sitter_traversal::traverse(sibling.walk(), tree_sitter_traversal::Order::Pre) {
+            if node.kind() == "identifier" && !is_member_name(node) && names.contains(&node.utf8_text(source.as_bytes()).unwrap()) {
+                return true;
+            }
+        }
+        next = sibling.next_named_sibling();
+    }
+    return false;
+}
+
+/// Whether `node` names a field or method of some object, rather than a variable.
+fn is_member_name(node: Node) -> bool {
+    let parent = match node.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    let field = match parent.kind() {
+        "field_access" => "field",
+        "method_invocation" => "name",
+        _ => return false,
+    };
+    return parent.child_by_field_name(field) == Some(node);
+}
+
+/// Collects the checked exceptions thrown by `throw` statements and by invocations of methods
+/// and constructors declared in the same file. An exception whose superclass is also thrown is
+/// left out, since a multi-catch can't list both.
+fn thrown_exception_types(
+    uri: &str,
+    tree: &Tree,
+    source: &str,
+    statements: &[Node],
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+) -> Vec<String> {
+    let mut thrown: Vec<String> = Vec::new();
+    for statement in statements {
+        for node in tree_sitter_traversal::traverse(statement.walk(), tree_sitter_traversal::Order::Pre) {
+            let candidates = match node.kind() {
+                "throw_statement" => {
+                    let created_type = node
+                        .named_child(0)
+                        .filter(|n| n.kind() == "object_creation_expression")
+                        .and_then(|n| n.child_by_field_name("type"));
+                    match created_type {
+                        Some(type_node) => vec![hierarchy::erased_type(type_node, source)],
+                        None => Vec::new(),
+                    }
+                }
+                "method_invocation" => {
+                    let name = node.child_by_field_name("name").map(|n| n.utf8_text(source.as_bytes()).unwrap());
+                    match name {
+                        Some(name) => declared_throws(tree, source, "method_declaration", name),
+                        None => Vec::new(),
+                    }
+                }
+                "object_creation_expression" => {
+                    let name = node.child_by_field_name("type").map(|n| hierarchy::erased_type(n, source));
+                    match name {
+                        Some(name) => declared_throws(tree, source, "constructor_declaration", &name),
+                        None => Vec::new(),
+                    }
+                }
+                _ => continue,
+            };
+            for candidate in candidates {
+                if !thrown.contains(&candidate) && is_checked_exception(uri, &candidate, type_declaration_map) {
+                    thrown.push(candidate);
+                }
+            }
+        }
+    }
+    let superclasses: Vec<Vec<String>> = thrown
+        .iter()
+        .map(|exception_type| exception_superclasses(uri, exception_type, type_declaration_map))
+        .collect();
+    return thrown
+        .iter()
+        .zip(superclasses)
+        .filter(|(_, superclasses)| !superclasses.iter().any(|superclass| thrown.contains(superclass)))
+        .map(|(exception_type, _)| exception_type.clone())
+        .collect();
+}
+
+/// The superclasses of the exception `name`, nearest first, as far as the workspace and the JDK
+/// exceptions listed above tell, and ending with `Exception` when they don't reach it.
+fn exception_superclasses(uri: &str, name: &str, type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>) -> Vec<String> {
+    let mut superclasses: Vec<String> = Vec::new();
+    let mut current = name.to_string();
+    for _ in 0..32 {
+        let superclass = match JDK_EXCEPTION_SUPERCLASSES.iter().find(|(exception, _)| *exception == current) {
+            Some((_, superclass)) => Some(superclass.to_string()),
+            None => hierarchy::find_type_declaration(type_declaration_map, uri, &current)
+                .and_then(|declaration| declaration.supertypes.first().cloned()),
+        };
+        current = match superclass {
+            Some(superclass) if !superclasses.contains(&superclass) => superclass,
+            _ => break,
+        };
+        superclasses.push(current.clone());
+    }
+    if name != "Exception" && !superclasses.iter().any(|superclass| superclass == "Exception" || superclass == "Throwable") {
+        superclasses.push("Exception".to_string());
+    }
+    return superclasses;
+}
+
+fn declared_throws(tree: &Tree, source: &str, declaration_kind: &str, name: &str) -> Vec<String> {
+    let mut thrown = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != declaration_kind {
+            continue;
+        }
+        let declared_name = node.child_by_field_name("name").map(|n| n.utf8_text(source.as_bytes()).unwrap());
+        if declared_name != Some(name) {
+            continue;
+        }
+        let throws_node = node.named_children(&mut node.walk()).find(|n| n.kind() == "throws");
+        if let Some(throws_node) = throws_node {
+            for type_node in throws_node.named_children(&mut throws_node.walk()) {
+                let thrown_type = hierarchy::erased_type(type_node, source);
+                if !thrown.contains(&thrown_type) {
+                    thrown.push(thrown_type);
+                }
+            }
+        }
+    }
+    return thrown;
+}
+
+fn is_checked_exception(uri: &str, name: &str, type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>) -> bool {
+    let mut current = name.to_string();
+    for _ in 0..32 {
+        if JDK_UNCHECKED_EXCEPTIONS.contains(&current.as_str()) {
+            return false;
+        }
+        let superclass = hierarchy::find_type_declaration(type_declaration_map, uri, &current)
+            .and_then(|declaration| declaration.supertypes.first().cloned());
+        current = match superclass {
+            Some(superclass) => superclass,
+            None => return true,
+        };
+    }
+    return true;
+}
+
+/// The resource specification text for a local declaration of an `AutoCloseable` type.
+fn auto_closeable_resource(
+    uri: &str,
+    statement: Node,
+    source: &str,
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+) -> Option<String> {
+    if statement.kind() != "local_variable_declaration" {
+        return None;
+    }
+    let declarators: Vec<Node> = statement
+        .named_children(&mut statement.walk())
+        .filter(|n| n.kind() == "variable_declarator")
+        .collect();
+    if declarators.len() != 1 || declarators[0].child_by_field_name("value").is_none() {
+        return None;
+    }
+    let type_node = statement.child_by_field_name("type")?;
+    let type_name = hierarchy::erased_type(type_node, source);
+    if !is_auto_closeable(uri, &type_name, type_declaration_map) {
+        return None;
+    }
+    let text = statement.utf8_text(source.as_bytes()).unwrap();
+    return Some(text.trim_end_matches(';').trim_end().to_string());
+}
+
+fn is_auto_closeable(uri: &str, name: &str, type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>) -> bool {
+    let mut pending = vec![name.to_string()];
+    let mut visited = Vec::new();
+    while let Some(current) = pending.pop() {
+        if JDK_AUTO_CLOSEABLE_TYPES.contains(&current.as_str()) {
+            return true;
+        }
+        if visited.contains(&current) {
+            continue;
+        }
+        if let Some(declaration) = hierarchy::find_type_declaration(type_declaration_map, uri, &current) {
+            pending.extend(declaration.supertypes);
+        }
+        visited.push(current);
+    }
+    return false;
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        return parser.parse(source, None).unwrap();
+    }
+
+    /// The titles and texts of the surround actions for the statements from the start of `selected`
+    /// to its end, in a method with the body `body`.
+    fn surround(body: &str, selected: &str) -> Vec<(String, String)> {
+        let source = format!("class C {{\n    void m() throws Exception {{\n        {}\n    }}\n}}\n", body);
+        let tree = parse(&source);
+        let uri = Url::parse("file:///C.java").unwrap();
+        let lines = LineIndex::new(&source);
+        let start = source.find(selected).unwrap();
+        let start_point = tree.root_node().descendant_for_byte_range(start, start).unwrap().start_position();
+        let end_point = tree.root_node().descendant_for_byte_range(start + selected.len() - 1, start + selected.len()).unwrap().end_position();
+        let range = lines.range(start_point, end_point);
+        let actions = surround_with_try_catch_actions(&uri, &tree, &source, range, &DashMap::new(), &EditorConfig::default());
+        return actions
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => {
+                    let edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+                    (action.title, edits[0].new_text.clone())
+                }
+                CodeActionOrCommand::Command(command) => (command.title, String::new()),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn catches_only_the_superclass_of_exceptions_thrown_together() {
+        let actions = surround(
+            "throw new FileNotFoundException(); throw new IOException(); throw new ClassNotFoundException();",
+            "throw new FileNotFoundException(); throw new IOException(); throw new ClassNotFoundException();",
+        );
+        assert!(actions[0].1.contains("catch (IOException | ClassNotFoundException e)"), "{}", actions[0].1);
+
+        let actions = surround("throw new IOException(); throw new Exception();", "throw new IOException(); throw new Exception();");
+        assert!(actions[0].1.contains("catch (Exception e)"), "{}", actions[0].1);
+    }
+
+    #[test]
+    fn leaves_out_selections_declaring_variables_used_after_them() {
+        assert!(surround("int a = 1; a++; System.out.println(a);", "int a = 1; a++;").is_empty());
+        // the same name as a field of another object isn't a use
+        assert_eq!(surround("int a = 1; a++; System.out.println(this.a);", "int a = 1; a++;").len(), 1);
+        assert_eq!(surround("int a = 1; a++; System.out.println(1);", "int a = 1; a++;").len(), 1);
+    }
+}
@@ -0,0 +1,103 @@
+use log::{debug, info};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower_lsp::jsonrpc::{Error, Result};
+use tower_lsp::lsp_types::request::ApplyWorkspaceEdit;
+use tower_lsp::lsp_types::*;
+
+use crate::error;
+use crate::format;
+use crate::Backend;
+
+/// Organizes the imports of the document whose URI is the one argument.
+pub const ORGANIZE_IMPORTS: &str = "javals.organizeImports";
+/// Drops the workspace index and rebuilds it from what is on disk. Takes no arguments.
+pub const REINDEX_WORKSPACE: &str = "javals.reindexWorkspace";
+/// Applies the workspace edit a refactoring computed, given as `ApplyRefactorArguments`.
+pub const APPLY_REFACTOR: &str = "javals.applyRefactor";
+/// Records that the completion item whose label is the one argument was accepted, so that it
+/// ranks higher the next time. Completion items carry it as their command.
+pub const COMPLETION_ACCEPTED: &str = "javals.completionAccepted";
+
+/// The commands advertised in the `executeCommandProvider` capability.
+pub const COMMANDS: [&str; 4] = [ORGANIZE_IMPORTS, REINDEX_WORKSPACE, APPLY_REFACTOR, COMPLETION_ACCEPTED];
+
+/// The argument of `javals.applyRefactor`. The label is shown by clients that undo edits in
+/// named steps.
+#[derive(Debug, Deserialize)]
+pub struct ApplyRefactorArguments {
+    pub label: Option<String>,
+    pub edit: WorkspaceEdit,
+}
+
+impl Backend {
+    /// Handles `workspace/executeCommand`. Commands that edit documents ask the client to apply
+    /// the edit and return its `ApplyWorkspaceEditResponse`, or `null` when there was nothing to
+    /// change.
+    pub async fn execute(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        debug!("execute_command {} {:?}", params.command, params.arguments);
+        let execution = async {
+            return match params.command.as_str() {
+                ORGANIZE_IMPORTS => self.organize_imports(argument(&params.arguments, 0)?).await,
+                REINDEX_WORKSPACE => {
+                    let indexed_files = self.rebuild_workspace_index().await;
+                    Ok(Some(json!({ "indexedFiles": indexed_files })))
+                }
+                APPLY_REFACTOR => {
+                    let arguments: ApplyRefactorArguments = argument(&params.arguments, 0)?;
+                    self.apply_workspace_edit(arguments.label, arguments.edit).await
+                }
+                COMPLETION_ACCEPTED => {
+                    self.recent_completions.record(argument(&params.arguments, 0)?);
+                    Ok(None)
+                }
+                command => Err(Error::invalid_params(format!("unknown command {}", command))),
+            };
+        };
+        return self.answer("workspace/executeCommand", execution).await;
+    }
+
+    async fn organize_imports(&self, uri: Url) -> Result<Option<Value>> {
+        let (edit, version) = {
+            let unknown_document = || error::Error::UnknownDocument(uri.to_string());
+            let tree = self.parsed_document_map.get(uri.as_str()).ok_or_else(unknown_document)?;
+            let source_text = self.document_map.get(uri.as_str()).ok_or_else(unknown_document)?;
+            (format::organize_imports_edit(&tree, &source_text), self.document_version(uri.as_str()))
+        };
+        let edit = match edit {
+            Some(edit) => edit,
+            None => return Ok(None),
+        };
+        // versioned, so the client refuses the edit if the document changed in the meantime
+        let workspace_edit = WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: vec![OneOf::Left(edit)],
+            }])),
+            ..WorkspaceEdit::default()
+        };
+        return self.apply_workspace_edit(Some("Organize imports".to_string()), workspace_edit).await;
+    }
+
+    /// Sends `edit` to the client through `workspace/applyEdit`.
+    pub async fn apply_workspace_edit(&self, label: Option<String>, edit: WorkspaceEdit) -> Result<Option<Value>> {
+        let response = self
+            .client
+            .send_request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams { label, edit })
+            .await?;
+        if !response.applied {
+            info!("workspace edit not applied: {:?}", response.failure_reason);
+        }
+        return Ok(serde_json::to_value(response).ok());
+    }
+}
+
+/// Deserializes the argument at `index`, which must be there.
+fn argument<T: DeserializeOwned>(arguments: &[Value], index: usize) -> Result<T> {
+    let value = arguments
+        .get(index)
+        .cloned()
+        .ok_or_else(|| Error::invalid_params(format!("missing argument {}", index)))?;
+    return serde_json::from_value(value).map_err(|error| Error::invalid_params(error.to_string()));
+}
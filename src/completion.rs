@@ -0,0 +1,1230 @@
+use std::collections::{BTreeSet, HashSet};
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::diagnostics::has_modifier;
+use crate::hierarchy;
+use crate::imports::{ImportScope, Imports, ResolvedClass};
+use crate::line_index::{self, LineIndex};
+use crate::modifiers::{Modifiers, Visibility};
+use crate::ranking::{self, Proximity};
+use crate::source_set::SourceSet;
+use crate::{code_action, javadoc, keywords, members, references, resolve};
+use crate::{Backend, TokenType};
+
+/// Kinds that end the search for an enclosing type body: inside them the cursor is in code, not
+/// at a member declaration.
+const CODE_KINDS: [&str; 5] = ["block", "constructor_body", "lambda_expression", "argument_list", "formal_parameters"];
+
+/// What typing after a field commits it with, like the `.` of a further access.
+const FIELD_COMMIT_CHARACTERS: [&str; 2] = [".", ";"];
+
+/// What typing after a method, when it isn't a snippet already holding its arguments, commits it
+/// with.
+const METHOD_COMMIT_CHARACTERS: [&str; 1] = ["("];
+
+/// What typing after a class or package commits it with.
+const TYPE_COMMIT_CHARACTERS: [&str; 2] = [".", "("];
+
+/// The most classes completing a simple name that are listed at once.
+const MAX_CLASS_ITEMS: usize = 100;
+
+/// The `Object` methods a class may override, with the number of parameters they take.
+const OBJECT_METHODS: [(&str, usize); 5] = [("toString", 0), ("equals", 1), ("hashCode", 0), ("clone", 0), ("finalize", 0)];
+
+/// The public methods every object inherits from `Object`, with their parameter types.
+const PUBLIC_OBJECT_METHODS: [(&str, &[&str]); 9] = [
+    ("equals", &["Object"]),
+    ("getClass", &[]),
+    ("hashCode", &[]),
+    ("notify", &[]),
+    ("notifyAll", &[]),
+    ("toString", &[]),
+    ("wait", &[]),
+    ("wait", &["long"]),
+    ("wait", &["long", "int"]),
+];
+
+/// The type whose body the cursor is in, as far as generating an override needs to know it.
+struct EnclosingType<'a> {
+    body_node: Node<'a>,
+    // `None` for anonymous classes
+    name: Option<String>,
+    type_parameters: Vec<String>,
+    is_enum: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldKind {
+    Primitive,
+    // boxed name of `float` or `double`, whose values compare through `Float.compare`/`Double.compare`
+    FloatingPoint(&'static str),
+    Array,
+    Reference,
+}
+
+/// What resolving a completion item fills in, kept in its `data` in between the requests, so
+/// that the list itself is quick to make.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "resolve", rename_all = "camelCase")]
+enum ItemData {
+    /// the detail and documentation of a member of `receiver_type` declared by `type_name`
+    #[serde(rename_all = "camelCase")]
+    Member {
+        uri: String,
+        receiver_type: String,
+        declaring_uri: String,
+        type_name: String,
+        name: String,
+        // `None` for fields
+        parameter_types: Option<Vec<String>>,
+    },
+    /// the imports an override needs in the document at `uri`
+    #[serde(rename_all = "camelCase")]
+    Imports { uri: String, imports: Vec<String> },
+}
+
+impl ItemData {
+    fn into_value(self) -> Option<serde_json::Value> {
+        return serde_json::to_value(self).ok();
+    }
+}
+
+/// An instance field or record component taking part in `equals`, `hashCode` and `toString`.
+struct Field {
+    name: String,
+    kind: FieldKind,
+}
+
+impl Backend {
+    pub async fn completion_items(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+        debug!("completion {} {:?}", uri, position);
+        let tree = match self.parsed_document_map.get(uri.as_str()) {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+        let source_text = match self.document_map.get(uri.as_str()) {
+            Some(source_text) => source_text,
+            None => return Ok(None),
+        };
+        let line = source_text.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character);
+        // `:` is a trigger for the second colon of a method reference, not for the colon of a
+        // label, a case, a conditional or an enhanced for loop
+        let trigger_character = params.context.as_ref().and_then(|context| context.trigger_character.as_deref());
+        if trigger_character == Some(":") && !typed.ends_with("::") {
+            return Ok(None);
+        }
+        let as_snippets = self.can_complete_snippets.load(Ordering::Acquire);
+        let mut items = object_override_items(uri.as_str(), &tree, &source_text, position, as_snippets);
+        items.extend(self.override_items(uri.as_str(), &tree, &source_text, position));
+        items.extend(self.annotation_element_items(uri.as_str(), &tree, &source_text, position));
+        items.extend(self.member_items(uri.as_str(), &tree, &source_text, position));
+        items.extend(self.postfix_items(&tree, &source_text, position));
+        items.extend(keywords::keyword_items(&tree, &source_text, position));
+        items.extend(javadoc::javadoc_items(&tree, &source_text, position));
+        items.extend(self.package_items(&source_text, position));
+        let (class_items, is_incomplete) = self.class_name_items(uri.as_str(), &tree, &source_text, position);
+        items.extend(class_items);
+        if items.is_empty() {
+            return Ok(None);
+        }
+        let word = &typed[typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$').len()..];
+        ranking::rank(&mut items, word, &self.recent_completions);
+        // clients that can't resolve the edits of an item later need them in the list
+        if !self.can_resolve_completion_edits.load(Ordering::Acquire) {
+            for item in items.iter_mut() {
+                let imports = match item.data.clone().and_then(|data| serde_json::from_value(data).ok()) {
+                    Some(ItemData::Imports { imports, .. }) => imports,
+                    _ => continue,
+                };
+                item.additional_text_edits = import_edits(&tree, &source_text, &imports);
+                item.data = None;
+            }
+        }
+        if is_incomplete {
+            return Ok(Some(CompletionResponse::List(CompletionList { is_incomplete, items })));
+        }
+        return Ok(Some(CompletionResponse::Array(items)));
+    }
+
+    /// The classes of the workspace and the dependencies whose simple names match the word typed,
+    /// as a prefix ignoring case or by the humps of their camelCase, with the import each needs
+    /// left for the item to be resolved with. When there are too many, only the best matches are
+    /// given, those of the document's package and its imports before the rest, and the list is
+    /// incomplete, so that the client asks again as more is typed.
+    pub fn class_name_items(&self, uri: &str, tree: &Tree, source: &str, position: Position) -> (Vec<CompletionItem>, bool) {
+        let line = source.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character);
+        let before_word = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+        let word = &typed[before_word.len()..];
+        // after `@` only annotation types go, few enough to list before anything is typed
+        let is_annotation = before_word.ends_with('@');
+        if (word.is_empty() && !is_annotation) || word.starts_with(|c: char| c.is_ascii_digit()) || before_word.trim_end().ends_with('.') {
+            return (Vec::new(), false);
+        }
+        let first_word = typed.split_whitespace().next().unwrap_or("");
+        if first_word == "import" || first_word == "package" {
+            return (Vec::new(), false);
+        }
+        let word_start = Point {
+            row: position.line as usize,
+            column: before_word.len(),
+        };
+        if keywords::is_in_text(tree, word_start) {
+            return (Vec::new(), false);
+        }
+
+        let is_match = |class_name: &str| ranking::match_quality(word, class_name).is_some();
+        let mut found: BTreeSet<(String, String)> = self.class_registry.packages().find_classes(&is_match).into_iter().collect();
+        found.extend(self.dependency_index.packages().find_classes(&is_match));
+
+        let package = references::package_name(tree, source).unwrap_or_default();
+        let imports = Imports::of(tree, source);
+        let classpath = self.dependency_classpath(uri);
+        let source_set = SourceSet::of(uri);
+        let mut ranked: Vec<(ranking::MatchQuality, Proximity, String, String, bool)> = Vec::new();
+        for (class_package, simple_name) in found {
+            let class_name = if class_package.is_empty() { simple_name.clone() } else { format!("{}.{}", class_package, simple_name) };
+            let (needs_import, scope) = if class_package == package {
+                (false, ImportScope::Package)
+            } else if imports.is_imported(&class_package, &class_name) {
+                (false, ImportScope::OnDemand)
+            } else if class_package.is_empty() || imports.imports_other(&class_name) {
+                // the unnamed package can't be imported from, and a simple name only once
+                continue;
+            } else {
+                (true, ImportScope::OnDemand)
+            };
+            let class = match self.available_class(uri, scope, &class_name, classpath.as_deref()) {
+                Some(class) => class,
+                None => continue,
+            };
+            // main sources are never offered the classes of test sources
+            if let ResolvedClass::Source(class_uri, _) = &class {
+                if !source_set.can_see(SourceSet::of(class_uri)) {
+                    continue;
+                }
+            }
+            if is_annotation && !self.is_annotation_type(&class) {
+                continue;
+            }
+            let proximity = match class {
+                _ if !needs_import => Proximity::Package,
+                ResolvedClass::Source(..) => Proximity::Workspace,
+                ResolvedClass::Dependency(_) => Proximity::Library,
+            };
+            let quality = match ranking::match_quality(word, &simple_name) {
+                Some(quality) => quality,
+                None => continue,
+            };
+            ranked.push((quality, proximity, simple_name, class_name, needs_import));
+        }
+        ranked.sort();
+        let is_incomplete = ranked.len() > MAX_CLASS_ITEMS;
+        ranked.truncate(MAX_CLASS_ITEMS);
+
+        let items = ranked
+            .into_iter()
+            .map(|(_, proximity, simple_name, class_name, needs_import)| {
+                let data = if needs_import {
+                    let data = ItemData::Imports {
+                        uri: uri.to_string(),
+                        imports: vec![class_name.clone()],
+                    };
+                    data.into_value()
+                } else {
+                    None
+                };
+                return CompletionItem {
+                    sort_text: proximity.key(),
+                    label: simple_name,
+                    kind: Some(CompletionItemKind::CLASS),
+                    detail: Some(class_name),
+                    commit_characters: commit_characters(&TYPE_COMMIT_CHARACTERS),
+                    data,
+                    ..CompletionItem::default()
+                };
+            })
+            .collect();
+        return (items, is_incomplete);
+    }
+
+    /// The packages and classes completing the name in a `package` or `import` declaration: the
+    /// packages in the one written up to the last dot, of the workspace and the dependencies, and
+    /// in an import the top-level classes of that package too.
+    pub fn package_items(&self, source: &str, position: Position) -> Vec<CompletionItem> {
+        let line = source.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character).trim_start();
+        let (keyword, written) = match typed.split_once(char::is_whitespace) {
+            Some((keyword, written)) => (keyword, written.trim_start()),
+            None => return Vec::new(),
+        };
+        let written = match keyword {
+            "import" => match written.strip_prefix("static") {
+                Some(after_static) if after_static.starts_with(char::is_whitespace) => after_static.trim_start(),
+                _ => written,
+            },
+            "package" => written,
+            _ => return Vec::new(),
+        };
+        if !written.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '.') {
+            return Vec::new();
+        }
+        let package = written.rsplit_once('.').map(|(package, _)| package).unwrap_or("");
+        let qualified = |name: &str| if package.is_empty() { name.to_string() } else { format!("{}.{}", package, name) };
+
+        let mut subpackages: BTreeSet<String> = self.class_registry.packages().subpackages(package).into_iter().collect();
+        subpackages.extend(self.dependency_index.packages().subpackages(package));
+        let mut items: Vec<CompletionItem> = subpackages
+            .into_iter()
+            .map(|subpackage| CompletionItem {
+                detail: Some(qualified(&subpackage)),
+                label: subpackage,
+                kind: Some(CompletionItemKind::MODULE),
+                sort_text: Proximity::Workspace.key(),
+                commit_characters: commit_characters(&TYPE_COMMIT_CHARACTERS[..1]),
+                ..CompletionItem::default()
+            })
+            .collect();
+        // classes of the unnamed package can't be imported
+        if keyword == "import" && !package.is_empty() {
+            let mut classes: BTreeSet<String> = self.class_registry.packages().classes(package).into_iter().collect();
+            classes.extend(self.dependency_index.packages().classes(package));
+            items.extend(classes.into_iter().map(|class_name| CompletionItem {
+                detail: Some(qualified(&class_name)),
+                label: class_name,
+                kind: Some(CompletionItemKind::CLASS),
+                sort_text: Proximity::Package.key(),
+                commit_characters: commit_characters(&TYPE_COMMIT_CHARACTERS[..1]),
+                ..CompletionItem::default()
+            }));
+        }
+        return items;
+    }
+
+    /// Handles `completionItem/resolve`, filling in what the list left out of the item the user
+    /// picked: the declaration and Javadoc of a member, or the imports an override needs.
+    pub async fn resolve_completion_item(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+        debug!("completion_resolve {}", item.label);
+        let data = match item.data.clone().and_then(|data| serde_json::from_value(data).ok()) {
+            Some(data) => data,
+            None => return Ok(item),
+        };
+        match data {
+            ItemData::Member {
+                uri,
+                receiver_type,
+                declaring_uri,
+                type_name,
+                name,
+                parameter_types,
+            } => {
+                let hierarchy = self.type_hierarchy(&uri, &receiver_type);
+                let declaration = match hierarchy.types.iter().find(|(found_uri, found)| *found_uri == declaring_uri && found.name == type_name) {
+                    Some((_, declaration)) => declaration,
+                    None => return Ok(item),
+                };
+                item.detail = match &parameter_types {
+                    Some(parameter_types) => declaration
+                        .methods
+                        .iter()
+                        .find(|method| method.name == name && method.parameter_types == *parameter_types)
+                        .map(|method| member_detail(&method.modifiers, format!("{}.{}({})", declaration.name, method.name, parameter_types.join(", ")))),
+                    None => declaration
+                        .fields
+                        .iter()
+                        .find(|field| field.name == name)
+                        .map(|field| member_detail(&field.modifiers, format!("{} {}.{}", field.field_type, declaration.name, field.name))),
+                };
+                let javadoc = self.member_javadoc(&declaring_uri, &declaration.name, &name, parameter_types.as_deref());
+                item.documentation = javadoc.map(|javadoc| {
+                    Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: javadoc,
+                    })
+                });
+            }
+            ItemData::Imports { uri, imports } => {
+                let tree = match self.parsed_document_map.get(&uri) {
+                    Some(tree) => tree,
+                    None => return Ok(item),
+                };
+                let source_text = match self.document_map.get(&uri) {
+                    Some(source_text) => source_text,
+                    None => return Ok(item),
+                };
+                item.additional_text_edits = import_edits(&tree, &source_text, &imports);
+            }
+        }
+        return Ok(item);
+    }
+
+    /// The fields and methods of the receiver's type and its indexed supertypes when the cursor
+    /// follows `receiver.`, only the static ones for a class name. Members that Java's access
+    /// rules hide at the cursor are left out unless `completion.showInaccessibleMembers` is set.
+    pub fn member_items(&self, uri: &str, tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+        let line = source.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character);
+        let before_name = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+        // a method reference like `String::valueOf` names a method as an access does
+        let separator = if before_name.ends_with("::") {
+            "::"
+        } else if before_name.ends_with('.') {
+            "."
+        } else {
+            return Vec::new();
+        };
+        let is_method_reference = separator == "::";
+        let dot_point = Point {
+            row: position.line as usize,
+            column: before_name.len() - separator.len(),
+        };
+        let dot_end = Point {
+            row: dot_point.row,
+            column: before_name.len(),
+        };
+        let dot_node = match tree.root_node().descendant_for_point_range(dot_point, dot_end) {
+            Some(dot_node) if dot_node.kind() == separator => dot_node,
+            _ => return Vec::new(),
+        };
+        let receiver_node = match access_receiver(dot_node) {
+            Some(receiver_node) => receiver_node,
+            None => return Vec::new(),
+        };
+        let receiver_text = receiver_node.utf8_text(source.as_bytes()).unwrap();
+        let is_class_receiver = match receiver_node.kind() {
+            "identifier" | "type_identifier" => {
+                let is_variable = self
+                    .find_declaration(uri, receiver_node, receiver_text)
+                    .map(|location| !matches!(location.token_type, TokenType::ClassName))
+                    .unwrap_or(false);
+                !is_variable
+                    && self.inherited_field(uri, receiver_node, source).is_none()
+                    && self.static_import_owner(uri, receiver_text, false).is_none()
+            }
+            "field_access" => self.dotted_name_class(uri, receiver_node, source).is_some(),
+            _ => false,
+        };
+        // `Interface.super.` leaves the qualifier beside `super` rather than under it
+        let super_qualifier_node = receiver_node.prev_named_sibling().filter(|_| receiver_node.kind() == "super");
+        let receiver_type = match super_qualifier_node {
+            Some(qualifier_node) => resolve::qualified_super_type(qualifier_node, source),
+            None => self.expression_type(uri, receiver_node, source),
+        };
+        let receiver_type = match receiver_type {
+            Some(receiver_type) => receiver_type,
+            None => return Vec::new(),
+        };
+        let access = AccessSite {
+            uri,
+            package: references::package_name(tree, source),
+            type_name: resolve::enclosing_type_name(dot_node, source),
+        };
+        let show_all = self.config.read().unwrap_or_else(PoisonError::into_inner).show_inaccessible_members;
+        let as_snippets = self.can_complete_snippets.load(Ordering::Acquire);
+
+        let item_data = |declaring_uri: &str, type_name: &str, name: &str, parameter_types: Option<&[String]>| {
+            let data = ItemData::Member {
+                uri: uri.to_string(),
+                receiver_type: receiver_type.clone(),
+                declaring_uri: declaring_uri.to_string(),
+                type_name: type_name.to_string(),
+                name: name.to_string(),
+                parameter_types: parameter_types.map(<[String]>::to_vec),
+            };
+            return data.into_value();
+        };
+
+        let mut items = Vec::new();
+        let mut seen_fields: HashSet<String> = HashSet::new();
+        let mut seen_methods: HashSet<(String, Vec<String>)> = HashSet::new();
+        for (index, (declaring_uri, declaration)) in self.type_hierarchy(uri, &receiver_type).types.iter().enumerate() {
+            let is_receiver_type = index == 0;
+            let proximity = if is_receiver_type { Proximity::Member } else { Proximity::of_declaring_uri(declaring_uri) };
+            if is_receiver_type
+                && !show_all
+                && !self.is_accessible(&access, declaring_uri, &declaration.name, declaration.modifiers.visibility)
+            {
+                // members inherited through an accessible subtype stay accessible, those of an
+                // inaccessible type itself don't
+                return Vec::new();
+            }
+            for field in &declaration.fields {
+                if is_method_reference || (is_class_receiver && !field.modifiers.is_static) {
+                    continue;
+                }
+                if !show_all && !self.is_accessible(&access, declaring_uri, &declaration.name, field.modifiers.visibility) {
+                    continue;
+                }
+                if !seen_fields.insert(field.name.clone()) {
+                    // hidden by a field of a subtype
+                    continue;
+                }
+                let is_constant = field.modifiers.is_static && field.modifiers.is_final;
+                items.push(CompletionItem {
+                    label: field.name.clone(),
+                    kind: Some(if is_constant { CompletionItemKind::CONSTANT } else { CompletionItemKind::FIELD }),
+                    sort_text: proximity.key(),
+                    commit_characters: commit_characters(&FIELD_COMMIT_CHARACTERS),
+                    data: item_data(declaring_uri, &declaration.name, &field.name, None),
+                    ..CompletionItem::default()
+                });
+            }
+            for method in &declaration.methods {
+                // a method reference through a class may name an instance method, which takes
+                // the receiver as its first argument
+                if is_class_receiver && !method.modifiers.is_static && !is_method_reference {
+                    continue;
+                }
+                // static methods of interfaces are only called through the interface's name
+                let is_through_interface_name = is_receiver_type && is_class_receiver;
+                if !declaration.is_inherited(method) && !is_through_interface_name {
+                    continue;
+                }
+                if !show_all && !self.is_accessible(&access, declaring_uri, &declaration.name, method.modifiers.visibility) {
+                    continue;
+                }
+                if !seen_methods.insert((method.name.clone(), method.parameter_types.clone())) {
+                    // overridden in a subtype
+                    continue;
+                }
+                if is_method_reference && items.iter().any(|item: &CompletionItem| item.label == method.name) {
+                    // a reference names every overload at once
+                    continue;
+                }
+                let mut item = CompletionItem {
+                    label: method.name.clone(),
+                    kind: Some(CompletionItemKind::METHOD),
+                    sort_text: proximity.key(),
+                    data: item_data(declaring_uri, &declaration.name, &method.name, Some(&method.parameter_types)),
+                    ..CompletionItem::default()
+                };
+                // the name is all a reference takes
+                if !is_method_reference {
+                    if as_snippets {
+                        item.insert_text = Some(call_snippet(&method.name, &method.parameter_types, &method.parameter_names));
+                        item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+                    } else {
+                        item.commit_characters = commit_characters(&METHOD_COMMIT_CHARACTERS);
+                    }
+                }
+                items.push(item);
+            }
+        }
+        if !is_class_receiver || is_method_reference {
+            for (name, parameter_types) in PUBLIC_OBJECT_METHODS {
+                let parameter_types: Vec<String> = parameter_types.iter().map(|t| t.to_string()).collect();
+                if !seen_methods.insert((name.to_string(), parameter_types.clone())) {
+                    continue;
+                }
+                if is_method_reference && items.iter().any(|item: &CompletionItem| item.label == name) {
+                    continue;
+                }
+                let mut item = CompletionItem {
+                    label: name.to_string(),
+                    kind: Some(CompletionItemKind::METHOD),
+                    detail: Some(format!("Object.{}({})", name, parameter_types.join(", "))),
+                    sort_text: Proximity::Library.key(),
+                    ..CompletionItem::default()
+                };
+                if !is_method_reference {
+                    if as_snippets {
+                        item.insert_text = Some(call_snippet(name, &parameter_types, &[]));
+                        item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+                    } else {
+                        item.commit_characters = commit_characters(&METHOD_COMMIT_CHARACTERS);
+                    }
+                }
+                items.push(item);
+            }
+        }
+        if is_method_reference && is_class_receiver {
+            items.push(CompletionItem {
+                label: "new".to_string(),
+                kind: Some(CompletionItemKind::CONSTRUCTOR),
+                detail: Some(format!("{}::new", receiver_type)),
+                sort_text: Proximity::Member.key(),
+                ..CompletionItem::default()
+            });
+        }
+        return items;
+    }
+
+    /// Overrides of the methods the type whose body the cursor is in inherits and doesn't declare,
+    /// like those of the interface an anonymous class implements, with the type arguments it
+    /// passes its supertypes filled in. Abstract methods are implemented with a body to fill in,
+    /// and others call the method they override. Final, static and private methods, and
+    /// package-private ones of other packages, can't be overridden, nor can default methods of
+    /// interfaces the type doesn't implement itself be called.
+    pub fn override_items(&self, uri: &str, tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+        let as_snippet = self.can_complete_snippets.load(Ordering::Acquire);
+        let member_start = match member_start(source, position, as_snippet) {
+            Some(member_start) => member_start,
+            None => return Vec::new(),
+        };
+        let enclosing_type = match enclosing_type(tree, source, position) {
+            Some(enclosing_type) => enclosing_type,
+            None => return Vec::new(),
+        };
+        // an anonymous class inherits from the type it's created as, an enum constant's body from
+        // its enum, and a named type from its supertypes
+        let owner_node = match enclosing_type.body_node.parent() {
+            Some(owner_node) => owner_node,
+            None => return Vec::new(),
+        };
+        let (written_type, first_supertype) = match owner_node.kind() {
+            "object_creation_expression" => match owner_node.child_by_field_name("type") {
+                Some(type_node) => (type_node.utf8_text(source.as_bytes()).unwrap().to_string(), 0),
+                None => return Vec::new(),
+            },
+            "enum_constant" => match owner_node.parent().and_then(|n| n.parent()).and_then(|n| resolve::class_name(n, source)) {
+                Some(enum_name) => (enum_name, 0),
+                None => return Vec::new(),
+            },
+            _ => match &enclosing_type.name {
+                Some(name) => (name.clone(), 1),
+                None => return Vec::new(),
+            },
+        };
+        let type_name = resolve::erase(&written_type);
+        let type_arguments = resolve::written_type_arguments(&written_type);
+        let declared = declared_methods(enclosing_type.body_node, source);
+        let package = references::package_name(tree, source);
+        let hierarchy = self.type_hierarchy(uri, &type_name);
+        // the supertypes whose default methods can be called through `Interface.super`
+        let direct_supertypes: Vec<&str> = match (first_supertype, hierarchy.types.first()) {
+            (0, _) => vec![type_name.as_str()],
+            (_, Some((_, declaration))) => declaration
+                .supertypes
+                .iter()
+                .map(|supertype| supertype.rsplit('.').next().unwrap_or(supertype))
+                .collect(),
+            (_, None) => Vec::new(),
+        };
+
+        let mut items = Vec::new();
+        let mut seen_methods: HashSet<(String, Vec<String>)> = HashSet::new();
+        for (index, (declaring_uri, declaration)) in hierarchy.types.iter().enumerate() {
+            let arguments = match index {
+                0 => type_arguments.clone(),
+                _ => self.supertype_arguments(uri, &type_name, &type_arguments, &declaration.name),
+            };
+            for method in &declaration.methods {
+                if !seen_methods.insert((method.name.clone(), method.parameter_types.clone())) {
+                    // implemented or redeclared by a subtype
+                    continue;
+                }
+                let modifiers = &method.modifiers;
+                if index < first_supertype
+                    || modifiers.is_static
+                    || modifiers.is_final
+                    || modifiers.visibility == Visibility::Private
+                    || resolve::OBJECT_METHOD_NAMES.contains(&method.name.as_str())
+                    || declared.iter().any(|(name, count)| *name == method.name && *count == method.parameter_types.len())
+                {
+                    continue;
+                }
+                if modifiers.visibility == Visibility::Package && self.package_of(declaring_uri) != package {
+                    continue;
+                }
+                let is_interface = declaration.kind == "interface_declaration";
+                let super_call = match (modifiers.is_abstract, is_interface) {
+                    (true, _) => None,
+                    (false, false) => Some("super".to_string()),
+                    (false, true) if direct_supertypes.contains(&declaration.name.as_str()) => Some(format!("{}.super", declaration.name)),
+                    (false, true) => continue,
+                };
+                // type variables the type isn't given arguments for are erased
+                let substitute = |written: &str| {
+                    let substituted = match members::substituted_type(written, &declaration.type_parameters, &arguments) {
+                        Some(substituted) => substituted,
+                        None if declaration.type_parameters.contains(&resolve::erase(written)) => "Object".to_string(),
+                        None => written.to_string(),
+                    };
+                    return unqualified(&substituted);
+                };
+                let parameter_types: Vec<String> = self
+                    .written_parameter_types(declaring_uri, &declaration.name, method)
+                    .unwrap_or_else(|| method.parameter_types.clone())
+                    .iter()
+                    .map(|parameter_type| substitute(parameter_type))
+                    .collect();
+                let mut parameters: Vec<String> = Vec::new();
+                let mut parameter_names: Vec<String> = Vec::new();
+                for parameter_type in &parameter_types {
+                    let base_name = code_action::parameter_name_for_type(parameter_type);
+                    let mut parameter_name = base_name.clone();
+                    let mut suffix = 1;
+                    while parameter_names.contains(&parameter_name) {
+                        suffix += 1;
+                        parameter_name = format!("{}{}", base_name, suffix);
+                    }
+                    parameters.push(format!("{} {}", parameter_type, parameter_name));
+                    parameter_names.push(parameter_name);
+                }
+                let mut words: Vec<String> = modifiers.visibility.keyword().map(|keyword| keyword.to_string()).into_iter().collect();
+                if !method.type_parameters.is_empty() {
+                    words.push(format!("<{}>", method.type_parameters.join(", ")));
+                }
+                words.push(substitute(&method.return_type));
+                let signature = format!("{} {}({})", words.join(" "), method.name, parameters.join(", "));
+                let label = format!("{}({})", method.name, parameter_types.join(", "));
+                let item = match super_call {
+                    Some(super_call) => {
+                        let call = format!("{}.{}({})", super_call, method.name, parameter_names.join(", "));
+                        let body = if method.return_type == "void" { format!("{};", call) } else { format!("return {};", call) };
+                        let mut item = member_start.override_item(label, &method.name, &signature, &body);
+                        item.detail = Some(format!("Override {}.{}", declaration.name, method.name));
+                        item
+                    }
+                    None => {
+                        let body = "throw new UnsupportedOperationException(\"Not implemented\");";
+                        let mut item = member_start.override_item(label, &method.name, &signature, body);
+                        item.detail = Some(format!("Implement {}.{}", declaration.name, method.name));
+                        item
+                    }
+                };
+                items.push(item);
+            }
+        }
+        return items;
+    }
+
+    /// Whether a member declared with `visibility` in `declaring_type` may be accessed from the
+    /// site. Private members are taken to be accessible within the declaring document, which holds
+    /// the top-level class they're private to.
+    fn is_accessible(&self, site: &AccessSite, declaring_uri: &str, declaring_type: &str, visibility: Visibility) -> bool {
+        return match visibility {
+            Visibility::Public => true,
+            Visibility::Private => declaring_uri == site.uri,
+            Visibility::Package => self.package_of(declaring_uri) == site.package,
+            Visibility::Protected => {
+                self.package_of(declaring_uri) == site.package
+                    || site
+                        .type_name
+                        .as_ref()
+                        .map(|site_type| self.is_subtype(site.uri, site_type, declaring_type))
+                        .unwrap_or(false)
+            }
+        };
+    }
+
+    /// Whether `subtype` is `supertype` or extends or implements it through indexed types.
+    fn is_subtype(&self, uri: &str, subtype: &str, supertype: &str) -> bool {
+        let hierarchy = self.type_hierarchy(uri, subtype);
+        return subtype == supertype
+            || hierarchy.types.iter().any(|(_, declaration)| declaration.name == supertype)
+            || hierarchy.missing.iter().any(|name| name == supertype);
+    }
+}
+
+/// The declaration of a member as shown next to it, modifiers first.
+fn member_detail(modifiers: &Modifiers, declaration: String) -> String {
+    let mut words = modifiers.keywords();
+    words.push(&declaration);
+    return words.join(" ");
+}
+
+/// Where a member is accessed from.
+struct AccessSite<'a> {
+    uri: &'a str,
+    package: Option<String>,
+    // `None` outside of named types
+    type_name: Option<String>,
+}
+
+/// Ready-made overrides of `toString`, `equals`, `hashCode`, `clone` and `finalize` when the cursor
+/// is where a member of a class body may be declared. The generated bodies use the instance fields
+/// of the class and don't depend on its supertypes being indexed. Enums may only override
+/// `toString`, and anonymous classes, having no name to cast to, get no `equals` or `hashCode`.
+/// The imports the bodies need are left for the item to be resolved with.
+pub fn object_override_items(uri: &str, tree: &Tree, source: &str, position: Position, as_snippet: bool) -> Vec<CompletionItem> {
+    let member_start = match member_start(source, position, as_snippet) {
+        Some(member_start) => member_start,
+        None => return Vec::new(),
+    };
+    let enclosing_type = match enclosing_type(tree, source, position) {
+        Some(enclosing_type) => enclosing_type,
+        None => return Vec::new(),
+    };
+    let declared = declared_methods(enclosing_type.body_node, source);
+    let fields = instance_fields(enclosing_type.body_node, source, position.line as usize);
+
+    let mut items = Vec::new();
+    for (name, parameter_count) in OBJECT_METHODS {
+        if declared.iter().any(|(declared_name, count)| declared_name == name && *count == parameter_count) {
+            continue;
+        }
+        if enclosing_type.is_enum && name != "toString" {
+            continue;
+        }
+        if enclosing_type.name.is_none() && (name == "equals" || name == "hashCode") {
+            continue;
+        }
+        let (signature, body, imports) = match name {
+            "toString" => (
+                "public String toString()".to_string(),
+                to_string_body(&enclosing_type, &fields),
+                array_imports(&fields),
+            ),
+            "equals" => (
+                "public boolean equals(Object o)".to_string(),
+                equals_body(&enclosing_type, &fields),
+                equals_imports(&fields),
+            ),
+            "hashCode" => ("public int hashCode()".to_string(), hash_code_body(&fields), hash_code_imports(&fields)),
+            "clone" => {
+                // a covariant return type spares callers the cast
+                let (type_name, body) = match &enclosing_type.name {
+                    Some(type_name) => {
+                        let type_name = generic_type(type_name, &enclosing_type.type_parameters);
+                        let body = format!("return ({}) super.clone();", type_name);
+                        (type_name, body)
+                    }
+                    None => ("Object".to_string(), "return super.clone();".to_string()),
+                };
+                (
+                    format!("protected {} clone() throws CloneNotSupportedException", type_name),
+                    body,
+                    Vec::new(),
+                )
+            }
+            _ => (
+                "protected void finalize() throws Throwable".to_string(),
+                "super.finalize();".to_string(),
+                Vec::new(),
+            ),
+        };
+        let mut item = member_start.override_item(format!("{}()", name), name, &signature, &body);
+        item.detail = Some(format!("Override Object.{}", name));
+        item.tags = if name == "finalize" { Some(vec![CompletionItemTag::DEPRECATED]) } else { None };
+        if !imports.is_empty() {
+            let data = ItemData::Imports {
+                uri: uri.to_string(),
+                imports: imports.into_iter().map(str::to_string).collect(),
+            };
+            item.data = data.into_value();
+        }
+        items.push(item);
+    }
+    return items;
+}
+
+/// The expression the `.` or `::` of `dot_node` follows, which an incomplete access may have
+/// left elsewhere than beside it.
+pub fn access_receiver(dot_node: Node) -> Option<Node> {
+    // an incomplete access like `this.value.` leaves the dot alone in an error node
+    let mut receiver_node = match dot_node.prev_named_sibling() {
+        Some(receiver_node) => receiver_node,
+        None => dot_node.parent().filter(|n| n.kind() == "ERROR")?.prev_named_sibling()?,
+    };
+    while receiver_node.kind() == "ERROR" && receiver_node.named_child_count() == 1 {
+        receiver_node = receiver_node.named_child(0).unwrap();
+    }
+    // so does one ending a lambda's body, as in `item -> item.`, leaving it after the lambda
+    if receiver_node.kind() == "lambda_expression" {
+        receiver_node = receiver_node.child_by_field_name("body")?;
+    }
+    return Some(receiver_node);
+}
+
+fn commit_characters(characters: &[&str]) -> Option<Vec<String>> {
+    return Some(characters.iter().map(|character| character.to_string()).collect());
+}
+
+/// A call of the method `method_name` as a snippet, its arguments placeholders named after the
+/// parameters, or after their types where the names aren't known, and the cursor left after the
+/// call.
+fn call_snippet(method_name: &str, parameter_types: &[String], parameter_names: &[String]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for (index, parameter_type) in parameter_types.iter().enumerate() {
+        let name = match parameter_names.get(index) {
+            Some(name) => name.clone(),
+            None => {
+                let base_name = code_action::parameter_name_for_type(&unqualified(parameter_type));
+                let mut name = base_name.clone();
+                let mut suffix = 1;
+                while names.contains(&name) {
+                    suffix += 1;
+                    name = format!("{}{}", base_name, suffix);
+                }
+                name
+            }
+        };
+        names.push(name);
+    }
+    let placeholders: Vec<String> = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| format!("${{{}:{}}}", index + 1, name))
+        .collect();
+    return format!("{}({})$0", method_name, placeholders.join(", "));
+}
+
+/// Where the cursor starts a member declaration: after nothing on its line but modifiers, a
+/// return type and the start of a name.
+struct MemberStart {
+    indentation: String,
+    range: Range,
+    // what was typed before the name, so clients keep matching `public String to` against items
+    typed_before_name: String,
+    // whether items are snippets, which select the body once inserted
+    as_snippet: bool,
+}
+
+impl MemberStart {
+    /// An item replacing what was typed with an overriding method, `body` indented under the
+    /// `signature` it's given.
+    fn override_item(&self, label: String, name: &str, signature: &str, body: &str) -> CompletionItem {
+        let (signature, body) = match self.as_snippet {
+            true => (escape_snippet(signature), format!("${{1:{}}}", escape_snippet(body))),
+            false => (signature.to_string(), body.to_string()),
+        };
+        let member = format!("@Override\n{} {{\n{}\n}}", signature, indent_lines(&body, "    "));
+        let new_text = indent_lines(&member, &self.indentation);
+        return CompletionItem {
+            label,
+            kind: Some(CompletionItemKind::METHOD),
+            filter_text: Some(format!("{}{}", self.typed_before_name, name)),
+            insert_text_format: Some(if self.as_snippet { InsertTextFormat::SNIPPET } else { InsertTextFormat::PLAIN_TEXT }),
+            insert_text_mode: Some(InsertTextMode::AS_IS),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: self.range,
+                new_text: new_text.trim_start().to_string(),
+            })),
+            ..CompletionItem::default()
+        };
+    }
+}
+
+fn member_start(source: &str, position: Position, as_snippet: bool) -> Option<MemberStart> {
+    let line = source.lines().nth(position.line as usize).unwrap_or("");
+    let typed = line_index::prefix(line, position.character);
+    let typed_start = typed.len() - typed.trim_start().len();
+    let at_member_start = typed[typed_start..]
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == ' ' || c == '\t');
+    if !at_member_start {
+        return None;
+    }
+    let typed_member = &typed[typed_start..];
+    return Some(MemberStart {
+        indentation: line.chars().take_while(|c| *c == ' ' || *c == '\t').collect(),
+        range: Range {
+            start: Position {
+                line: position.line,
+                character: line_index::character(&line[..typed_start]),
+            },
+            end: position,
+        },
+        typed_before_name: match typed_member.rfind([' ', '\t']) {
+            Some(index) => typed_member[..=index].to_string(),
+            None => String::new(),
+        },
+        as_snippet,
+    });
+}
+
+/// `text` with the characters that mean something in a snippet escaped.
+pub fn escape_snippet(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('$', "\\$").replace('}', "\\}");
+}
+
+/// The names and parameter counts of the methods declared in `body_node`.
+fn declared_methods(body_node: Node, source: &str) -> Vec<(String, usize)> {
+    return hierarchy::member_method_declarations(body_node)
+        .into_iter()
+        .map(|method_node| hierarchy::method_signature(method_node, source))
+        .map(|signature| (signature.name, signature.parameter_types.len()))
+        .collect();
+}
+
+/// `written` without the packages qualifying the types it names: `List<String>` for
+/// `java.util.List<java.lang.String>`.
+fn unqualified(written: &str) -> String {
+    let mut unqualified = String::new();
+    let mut name_start = 0;
+    let mut chars = written.chars().peekable();
+    while let Some(c) = chars.next() {
+        let follows_name = unqualified[name_start..].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+        if c == '.' && follows_name && chars.peek() != Some(&'.') {
+            unqualified.truncate(name_start);
+            continue;
+        }
+        unqualified.push(c);
+        if !(c.is_alphanumeric() || c == '_' || c == '$') {
+            name_start = unqualified.len();
+        }
+    }
+    return unqualified;
+}
+
+fn enclosing_type<'a>(tree: &'a Tree, source: &str, position: Position) -> Option<EnclosingType<'a>> {
+    let point = LineIndex::new(source).point(position);
+    let mut node = tree.root_node().descendant_for_point_range(point, point)?;
+    loop {
+        if CODE_KINDS.contains(&node.kind()) && node.start_position() < point {
+            return None;
+        }
+        if node.kind() == "class_body" || node.kind() == "enum_body" {
+            break;
+        }
+        node = node.parent()?;
+    }
+    let body_node = node;
+    let owner_node = body_node.parent()?;
+    let name = match owner_node.kind() {
+        "object_creation_expression" => None,
+        "class_declaration" | "record_declaration" | "enum_declaration" => owner_node
+            .child_by_field_name("name")
+            .map(|name_node| name_node.utf8_text(source.as_bytes()).unwrap().to_string()),
+        // enum constants with a body are anonymous classes too
+        "enum_constant" => None,
+        _ => return None,
+    };
+    let type_parameters = match owner_node.child_by_field_name("type_parameters") {
+        Some(type_parameters_node) => hierarchy::type_parameter_names(type_parameters_node, source),
+        None => Vec::new(),
+    };
+    return Some(EnclosingType {
+        body_node,
+        name,
+        type_parameters,
+        is_enum: body_node.kind() == "enum_body" || owner_node.kind() == "enum_constant",
+    });
+}
+
+/// Record components followed by the non-static fields of `body_node`, skipping any declaration on
+/// `cursor_row`, where an unfinished member may parse as a field.
+fn instance_fields(body_node: Node, source: &str, cursor_row: usize) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let owner_node = body_node.parent();
+    if let Some(parameters_node) = owner_node
+        .filter(|n| n.kind() == "record_declaration")
+        .and_then(|n| n.child_by_field_name("parameters"))
+    {
+        for component_node in parameters_node.named_children(&mut parameters_node.walk()) {
+            if let (Some(type_node), Some(name_node)) = (component_node.child_by_field_name("type"), component_node.child_by_field_name("name")) {
+                fields.push(Field {
+                    name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    kind: field_kind(type_node, source, false),
+                });
+            }
+        }
+    }
+    let mut members: Vec<Node> = body_node.named_children(&mut body_node.walk()).collect();
+    if let Some(declarations_node) = members.iter().copied().find(|n| n.kind() == "enum_body_declarations") {
+        members = declarations_node.named_children(&mut declarations_node.walk()).collect();
+    }
+    for field_node in members {
+        if field_node.kind() != "field_declaration" || has_modifier(field_node, "static") {
+            continue;
+        }
+        if field_node.start_position().row <= cursor_row && cursor_row <= field_node.end_position().row {
+            continue;
+        }
+        let type_node = match field_node.child_by_field_name("type") {
+            Some(type_node) => type_node,
+            None => continue,
+        };
+        for declarator_node in field_node.children_by_field_name("declarator", &mut field_node.walk()) {
+            if let Some(name_node) = declarator_node.child_by_field_name("name") {
+                fields.push(Field {
+                    name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    kind: field_kind(type_node, source, declarator_node.child_by_field_name("dimensions").is_some()),
+                });
+            }
+        }
+    }
+    return fields;
+}
+
+fn field_kind(type_node: Node, source: &str, has_dimensions: bool) -> FieldKind {
+    if has_dimensions || type_node.kind() == "array_type" {
+        return FieldKind::Array;
+    }
+    return match type_node.kind() {
+        "integral_type" | "boolean_type" => FieldKind::Primitive,
+        "floating_point_type" => match type_node.utf8_text(source.as_bytes()).unwrap() {
+            "float" => FieldKind::FloatingPoint("Float"),
+            _ => FieldKind::FloatingPoint("Double"),
+        },
+        _ => FieldKind::Reference,
+    };
+}
+
+fn generic_type(name: &str, type_parameters: &[String]) -> String {
+    if type_parameters.is_empty() {
+        return name.to_string();
+    }
+    return format!("{}<{}>", name, type_parameters.join(", "));
+}
+
+fn to_string_body(enclosing_type: &EnclosingType, fields: &[Field]) -> String {
+    let prefix = match (&enclosing_type.name, enclosing_type.is_enum) {
+        (_, true) => "name() + \"".to_string(),
+        (Some(name), false) => format!("\"{}", name),
+        (None, false) => "getClass().getName() + \"".to_string(),
+    };
+    if fields.is_empty() {
+        if enclosing_type.is_enum {
+            return "return name();".to_string();
+        }
+        return format!("return {}{{}}\";", prefix);
+    }
+    let mut text = format!("return {}{{", prefix);
+    for (i, field) in fields.iter().enumerate() {
+        let separator = if i == 0 { "" } else { ", " };
+        let value = if field.kind == FieldKind::Array {
+            format!("Arrays.toString({})", field.name)
+        } else {
+            field.name.clone()
+        };
+        text.push_str(&format!("{}{}=\" + {} + \"", separator, field.name, value));
+    }
+    text.push_str("}\";");
+    return text;
+}
+
+fn equals_body(enclosing_type: &EnclosingType, fields: &[Field]) -> String {
+    let mut lines = vec![
+        "if (this == o) {".to_string(),
+        "    return true;".to_string(),
+        "}".to_string(),
+        "if (o == null || getClass() != o.getClass()) {".to_string(),
+        "    return false;".to_string(),
+        "}".to_string(),
+    ];
+    if fields.is_empty() {
+        lines.push("return true;".to_string());
+        return lines.join("\n");
+    }
+    let name = enclosing_type.name.clone().unwrap_or_default();
+    let wildcards = vec!["?".to_string(); enclosing_type.type_parameters.len()];
+    let cast_type = generic_type(&name, &wildcards);
+    lines.push(format!("{} other = ({}) o;", cast_type, cast_type));
+    let comparisons: Vec<String> = fields
+        .iter()
+        .map(|field| match field.kind {
+            FieldKind::Primitive => format!("{0} == other.{0}", field.name),
+            FieldKind::FloatingPoint(boxed) => format!("{1}.compare({0}, other.{0}) == 0", field.name, boxed),
+            FieldKind::Array => format!("Arrays.equals({0}, other.{0})", field.name),
+            FieldKind::Reference => format!("Objects.equals({0}, other.{0})", field.name),
+        })
+        .collect();
+    lines.push(format!("return {};", comparisons.join("\n        && ")));
+    return lines.join("\n");
+}
+
+fn hash_code_body(fields: &[Field]) -> String {
+    let hashed: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.kind != FieldKind::Array)
+        .map(|field| field.name.as_str())
+        .collect();
+    let arrays: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.kind == FieldKind::Array)
+        .map(|field| field.name.as_str())
+        .collect();
+    if arrays.is_empty() {
+        return format!("return Objects.hash({});", hashed.join(", "));
+    }
+    let mut lines = vec![format!("int result = Objects.hash({});", hashed.join(", "))];
+    for array in arrays {
+        lines.push(format!("result = 31 * result + Arrays.hashCode({});", array));
+    }
+    lines.push("return result;".to_string());
+    return lines.join("\n");
+}
+
+fn array_imports(fields: &[Field]) -> Vec<&'static str> {
+    if fields.iter().any(|field| field.kind == FieldKind::Array) {
+        return vec!["java.util.Arrays"];
+    }
+    return Vec::new();
+}
+
+fn equals_imports(fields: &[Field]) -> Vec<&'static str> {
+    let mut imports = array_imports(fields);
+    if fields.iter().any(|field| field.kind == FieldKind::Reference) {
+        imports.push("java.util.Objects");
+    }
+    return imports;
+}
+
+fn hash_code_imports(fields: &[Field]) -> Vec<&'static str> {
+    let mut imports = array_imports(fields);
+    imports.push("java.util.Objects");
+    return imports;
+}
+
+fn indent_lines(text: &str, indentation: &str) -> String {
+    return text
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indentation, line)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+}
+
+/// The edits importing those of `imports` the document doesn't import yet, `None` when there are
+/// none.
+fn import_edits(tree: &Tree, source: &str, imports: &[String]) -> Option<Vec<TextEdit>> {
+    let edits: Vec<TextEdit> = imports.iter().filter_map(|import| import_edit(tree, source, import)).collect();
+    return if edits.is_empty() { None } else { Some(edits) };
+}
+
+/// An edit importing `qualified_name` after the last import, or after the package declaration,
+/// unless the document already imports it or its whole package.
+fn import_edit(tree: &Tree, source: &str, qualified_name: &str) -> Option<TextEdit> {
+    let root = tree.root_node();
+    let package = &qualified_name[..qualified_name.rfind('.')?];
+    let mut anchor: Option<Node> = None;
+    for child in root.named_children(&mut root.walk()) {
+        match child.kind() {
+            "import_declaration" => {
+                let text = child.utf8_text(source.as_bytes()).unwrap();
+                let imported = text.trim_start_matches("import").trim_end_matches(';').trim();
+                if imported == qualified_name || imported == format!("{}.*", package) {
+                    return None;
+                }
+                anchor = Some(child);
+            }
+            "package_declaration" => anchor = Some(child),
+            _ => {}
+        }
+    }
+    let import = format!("import {};", qualified_name);
+    return Some(match anchor {
+        Some(anchor) => {
+            let position = LineIndex::new(source).position(anchor.end_position());
+            let separator = if anchor.kind() == "package_declaration" { "\n\n" } else { "\n" };
+            TextEdit {
+                range: Range { start: position, end: position },
+                new_text: format!("{}{}", separator, import),
+            }
+        }
+        None => {
+            let position = Position { line: 0, character: 0 };
+            TextEdit {
+                range: Range { start: position, end: position },
+                new_text: format!("{}\n\n", import),
+            }
+        }
+    });
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use dashmap::DashMap;
+use log::info;
+use serde::Serialize;
+
+/// A syntax node kind the indexer met but has no rule for, in the context it was found in, e.g.
+/// the parent of an identifier or of a variable declarator.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnhandledNode {
+    pub context: &'static str,
+    pub kind: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnhandledNodeReport {
+    pub context: &'static str,
+    pub kind: String,
+    pub occurrences: usize,
+    pub documents: usize,
+}
+
+/// Counts of the node kinds the indexer skipped, kept per document so re-parsing a document
+/// replaces its counts instead of inflating them.
+#[derive(Debug, Default)]
+pub struct UnhandledNodes {
+    by_document: DashMap<String, HashMap<UnhandledNode, usize>>,
+}
+
+impl UnhandledNodes {
+    /// Records what indexing `uri` skipped, logging each kind the first time any document has it.
+    pub fn replace(&self, uri: &str, counts: HashMap<UnhandledNode, usize>) {
+        let new_nodes: Vec<&UnhandledNode> = counts
+            .keys()
+            .filter(|node| !self.by_document.iter().any(|entry| entry.value().contains_key(*node)))
+            .collect();
+        for node in new_nodes {
+            info!("indexer skips {} under {} (first seen in {})", node.kind, node.context, uri);
+        }
+        self.by_document.insert(uri.to_string(), counts);
+    }
+
+    pub fn remove(&self, uri: &str) {
+        self.by_document.remove(uri);
+    }
+
+    /// Skipped kinds across all documents, most frequent first.
+    pub fn report(&self) -> Vec<UnhandledNodeReport> {
+        let mut totals: HashMap<UnhandledNode, (usize, usize)> = HashMap::new();
+        for entry in self.by_document.iter() {
+            for (node, count) in entry.value() {
+                let total = totals.entry(node.clone()).or_default();
+                total.0 += count;
+                total.1 += 1;
+            }
+        }
+        let mut report: Vec<UnhandledNodeReport> = totals
+            .into_iter()
+            .map(|(node, (occurrences, documents))| UnhandledNodeReport {
+                context: node.context,
+                kind: node.kind,
+                occurrences,
+                documents,
+            })
+            .collect();
+        report.sort_by(|a, b| {
+            b.occurrences
+                .cmp(&a.occurrences)
+                .then_with(|| a.context.cmp(b.context))
+                .then_with(|| a.kind.cmp(&b.kind))
+        });
+        return report;
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use log::error;
+use tree_sitter::{Node, Query, QueryCursor, Tree};
+
+/// The names the indexer records, one pattern for each kind of declaration, capturing the
+/// declared name. Identifiers no other pattern captures are captured as `@identifier`, so that
+/// those under declarations the indexer has no rule for are counted.
+const DECLARED_NAMES_QUERY: &str = r#"
+(class_declaration name: (identifier) @class_name)
+(enum_declaration name: (identifier) @class_name)
+(annotation_type_declaration name: (identifier) @class_name)
+(annotation_type_element_declaration name: (identifier) @element_name)
+(enum_constant name: (identifier) @enum_constant)
+(type_parameter (type_identifier) @type_parameter)
+(variable_declarator name: (identifier) @variable)
+(method_declaration name: (identifier) @method_name)
+(formal_parameter name: (identifier) @parameter)
+(labeled_statement (identifier) @label)
+(lambda_expression parameters: (identifier) @lambda_parameter)
+(inferred_parameters (identifier) @lambda_parameter)
+(instanceof_expression name: (identifier) @pattern_binding)
+(identifier) @identifier
+"#;
+
+/// The parents of identifiers that aren't declared names but are known not to need a rule:
+/// annotations, labels named by `break` and `continue`, and the other identifiers of the
+/// declarations above, like the initializer of a variable.
+const UNDECLARING_PARENTS: [&str; 14] = [
+    "annotation",
+    "marker_annotation",
+    "element_value_pair",
+    "break_statement",
+    "continue_statement",
+    "class_declaration",
+    "enum_declaration",
+    "annotation_type_declaration",
+    "enum_constant",
+    "variable_declarator",
+    "method_declaration",
+    "formal_parameter",
+    "labeled_statement",
+    "inferred_parameters",
+];
+
+/// What a name captured by [`DECLARED_NAMES_QUERY`] declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Declared {
+    ClassName,
+    ElementName,
+    EnumConstant,
+    TypeParameter,
+    Variable,
+    MethodName,
+    Parameter,
+    Label,
+    LambdaParameter,
+    PatternBinding,
+}
+
+impl Declared {
+    fn from_capture_name(capture_name: &str) -> Option<Declared> {
+        return match capture_name {
+            "class_name" => Some(Declared::ClassName),
+            "element_name" => Some(Declared::ElementName),
+            "enum_constant" => Some(Declared::EnumConstant),
+            "type_parameter" => Some(Declared::TypeParameter),
+            "variable" => Some(Declared::Variable),
+            "method_name" => Some(Declared::MethodName),
+            "parameter" => Some(Declared::Parameter),
+            "label" => Some(Declared::Label),
+            "lambda_parameter" => Some(Declared::LambdaParameter),
+            "pattern_binding" => Some(Declared::PatternBinding),
+            _ => None,
+        };
+    }
+}
+
+/// [`DECLARED_NAMES_QUERY`] compiled, with what each of its captures declares.
+struct DeclaredNamesQuery {
+    query: Query,
+    declared: Vec<Option<Declared>>,
+}
+
+/// The query compiled the first time a document is scanned. `None` when the grammar doesn't
+/// accept it.
+fn declared_names_query() -> Option<&'static DeclaredNamesQuery> {
+    static QUERY: OnceLock<Option<DeclaredNamesQuery>> = OnceLock::new();
+    let query = QUERY.get_or_init(|| {
+        let query = match Query::new(tree_sitter_java::language(), DECLARED_NAMES_QUERY) {
+            Ok(query) => query,
+            Err(query_error) => {
+                error!("unable to compile the declared names query: {}", query_error);
+                return None;
+            }
+        };
+        let declared = query.capture_names().iter().map(|capture_name| Declared::from_capture_name(capture_name)).collect();
+        return Some(DeclaredNamesQuery { query, declared });
+    });
+    return query.as_ref();
+}
+
+/// The names declared in `tree`, in the order they appear in `text`, with what they declare,
+/// along with the other identifiers, which declare nothing and come with `None`.
+pub fn declared_names<'tree>(tree: &'tree Tree, text: &str) -> Vec<(Node<'tree>, Option<Declared>)> {
+    let query = match declared_names_query() {
+        Some(query) => query,
+        None => return Vec::new(),
+    };
+    let mut names: Vec<(Node<'tree>, Option<Declared>)> = Vec::new();
+    // an identifier a declaration pattern captures is also captured as `@identifier`
+    let mut positions: HashMap<usize, usize> = HashMap::new();
+    let mut cursor = QueryCursor::new();
+    for (query_match, capture_index) in cursor.captures(&query.query, tree.root_node(), text.as_bytes()) {
+        let capture = query_match.captures[capture_index];
+        let declared = query.declared[capture.index as usize];
+        match positions.get(&capture.node.id()) {
+            Some(&position) => {
+                if declared.is_some() {
+                    names[position].1 = declared;
+                }
+            }
+            None => {
+                positions.insert(capture.node.id(), names.len());
+                names.push((capture.node, declared));
+            }
+        }
+    }
+    return names;
+}
+
+/// Whether an identifier under `parent_kind` that isn't a declared name is left out on purpose.
+pub fn is_undeclaring_parent(parent_kind: &str) -> bool {
+    return UNDECLARING_PARENTS.contains(&parent_kind);
+}
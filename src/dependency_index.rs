@@ -0,0 +1,459 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+use crate::class_file::{self, ClassFile, Member};
+use crate::diagnostics;
+use crate::hierarchy::{self, FieldSignature, MethodSignature, TypeDeclaration};
+use crate::imports::ResolvedClass;
+use crate::jar::Jar;
+use crate::modifiers::{Modifiers, Visibility};
+use crate::package_trie::PackageTrie;
+use crate::Backend;
+
+/// Jars are read and parsed off the async runtime this many at a time.
+const BATCH_SIZE: usize = 8;
+
+/// A class read from a dependency jar, or from an archive of sources like the JDK's `src.zip`.
+/// Its supertypes are fully qualified, so they can be looked up without the imports of the class.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyClass {
+    pub jar: PathBuf,
+    pub binary_name: String,
+    pub source_file: Option<String>,
+    // the entry of the source file in `jar`, when the class was read from sources
+    pub source_entry: Option<String>,
+    pub declaration: TypeDeclaration,
+}
+
+impl DependencyClass {
+    /// The URI of the class file or source file in the jar, like
+    /// `jar:file:///lib/a.jar!/p/A.class`.
+    pub fn uri(&self) -> String {
+        let jar_uri = Url::from_file_path(&self.jar).map(|uri| uri.to_string()).unwrap_or_default();
+        return match &self.source_entry {
+            Some(source_entry) => format!("jar:{}!/{}", jar_uri, source_entry),
+            None => format!("jar:{}!/{}.class", jar_uri, self.binary_name),
+        };
+    }
+}
+
+/// The public and protected classes, fields and methods of the jars on project classpaths, by
+/// fully qualified class name. A jar is read again only when it changes.
+#[derive(Debug, Default)]
+pub struct DependencyIndex {
+    // the providers of each class, in classpath order
+    classes: DashMap<String, Vec<Arc<DependencyClass>>>,
+    // jar -> when it was last modified as indexed, and the classes read from it
+    jars: DashMap<PathBuf, (Option<SystemTime>, Vec<String>)>,
+    // the packages of the top-level classes
+    packages: RwLock<PackageTrie>,
+}
+
+impl DependencyIndex {
+    pub fn insert_jar(&self, jar: &Path, modified: Option<SystemTime>, classes: Vec<DependencyClass>) {
+        self.remove_jar(jar);
+        let mut class_names = Vec::new();
+        let mut packages = self.packages.write().unwrap_or_else(PoisonError::into_inner);
+        for class in classes {
+            if let Some((package, simple_name)) = top_level_class(&class.binary_name) {
+                packages.insert(&package, simple_name);
+            }
+            let class_name = class_file::qualified_name(&class.binary_name);
+            self.classes.entry(class_name.clone()).or_default().push(Arc::new(class));
+            class_names.push(class_name);
+        }
+        self.jars.insert(jar.to_path_buf(), (modified, class_names));
+    }
+
+    pub fn remove_jar(&self, jar: &Path) {
+        let (_, class_names) = match self.jars.remove(jar) {
+            Some((_, indexed)) => indexed,
+            None => return,
+        };
+        let mut packages = self.packages.write().unwrap_or_else(PoisonError::into_inner);
+        for class_name in class_names {
+            if let Some(mut providers) = self.classes.get_mut(&class_name) {
+                for class in providers.iter().filter(|class| class.jar == jar) {
+                    if let Some((package, simple_name)) = top_level_class(&class.binary_name) {
+                        packages.remove(&package, simple_name);
+                    }
+                }
+                providers.retain(|class| class.jar != jar);
+            }
+            self.classes.remove_if(&class_name, |_, providers| providers.is_empty());
+        }
+    }
+
+    /// The packages of the classes in the jars, with their top-level classes.
+    pub fn packages(&self) -> RwLockReadGuard<'_, PackageTrie> {
+        return self.packages.read().unwrap_or_else(PoisonError::into_inner);
+    }
+
+    pub fn is_current(&self, jar: &Path, modified: Option<SystemTime>) -> bool {
+        return self.jars.get(jar).map(|indexed| indexed.0 == modified).unwrap_or(false);
+    }
+
+    /// The class named `class_name` in the first jar of `classpath` providing it, or in any jar
+    /// without one.
+    pub fn class(&self, class_name: &str, classpath: Option<&[PathBuf]>) -> Option<Arc<DependencyClass>> {
+        let providers = self.classes.get(class_name)?;
+        return match classpath {
+            Some(classpath) => classpath
+                .iter()
+                .find_map(|jar| providers.iter().find(|class| class.jar == *jar))
+                .cloned(),
+            None => providers.first().cloned(),
+        };
+    }
+
+    pub fn class_count(&self) -> usize {
+        return self.classes.len();
+    }
+}
+
+impl Backend {
+    /// Indexes the jars on the classpath of any project, or that the `project.classpath` setting
+    /// adds, reading only those that are new or changed, and forgets the jars no project has
+    /// anymore. The JDK's sources stay.
+    pub async fn index_dependencies(&self) {
+        let jdk_sources = self.jdk_sources();
+        let mut jars: Vec<PathBuf> = Vec::new();
+        let mut classpath = self.config.read().unwrap_or_else(PoisonError::into_inner).project.classpath.clone();
+        for project in self.projects.read().unwrap_or_else(PoisonError::into_inner).iter() {
+            classpath.extend(project.classpath.iter().cloned());
+        }
+        for entry in classpath {
+            if entry.extension().map(|extension| extension == "jar").unwrap_or(false) && !jars.contains(&entry) {
+                jars.push(entry);
+            }
+        }
+        let removed: Vec<PathBuf> = self
+            .dependency_index
+            .jars
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|jar| !jars.contains(jar) && Some(jar) != jdk_sources.as_ref())
+            .collect();
+        self.class_registry.set_classpath(jars.clone());
+        let mut affected_uris = Vec::new();
+        for jar in removed {
+            self.dependency_index.remove_jar(&jar);
+            affected_uris.extend(self.class_registry.remove_jar(&jar));
+        }
+        let changed: Vec<(PathBuf, Option<SystemTime>)> = jars
+            .into_iter()
+            .map(|jar| {
+                let modified = fs::metadata(&jar).and_then(|metadata| metadata.modified()).ok();
+                return (jar, modified);
+            })
+            .filter(|(jar, modified)| !self.dependency_index.is_current(jar, *modified))
+            .collect();
+        for batch in changed.chunks(BATCH_SIZE) {
+            let batch = batch.to_vec();
+            let read = tokio::task::spawn_blocking(move || {
+                return batch
+                    .into_iter()
+                    .map(|(jar, modified)| {
+                        let classes = read_jar(&jar);
+                        return (jar, modified, classes);
+                    })
+                    .collect::<Vec<_>>();
+            })
+            .await
+            .unwrap_or_default();
+            for (jar, modified, classes) in read {
+                match classes {
+                    Ok(classes) => {
+                        let class_names = classes.iter().map(|class| class_file::qualified_name(&class.binary_name)).collect();
+                        affected_uris.extend(self.class_registry.register_jar(&jar, class_names));
+                        self.dependency_index.insert_jar(&jar, modified, classes);
+                    }
+                    Err(error) => info!("unable to read {:?}: {}", jar, error),
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+        // the classpath of a project may have changed along with its jars
+        self.revisions.declarations_changed();
+        info!("{} classes indexed from dependencies", self.dependency_index.class_count());
+        affected_uris.sort();
+        affected_uris.dedup();
+        self.publish_affected_diagnostics(affected_uris).await;
+        self.publish_classpath_diagnostics().await;
+    }
+
+    /// Publishes, for the build file of each project, the classes its jars provide more than once.
+    async fn publish_classpath_diagnostics(&self) {
+        let projects = self.projects.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let enabled = self.config.read().unwrap_or_else(PoisonError::into_inner).features.diagnostics;
+        for project in projects {
+            let build_file = match Url::from_file_path(&project.build_file) {
+                Ok(build_file) => build_file,
+                Err(_) => continue,
+            };
+            // with diagnostics turned off, publishing none clears those published before
+            let diagnostics = match enabled {
+                true => diagnostics::shadowed_class_diagnostics(&self.class_registry.shadowed_classes(&project.classpath)),
+                false => Vec::new(),
+            };
+            self.client.publish_diagnostics(build_file, diagnostics, None).await;
+        }
+    }
+
+    /// The jars on the classpath of the project the document at `uri` is in, which is the one
+    /// whose build file is nearest above it. `None` when it's in none.
+    pub fn document_classpath(&self, uri: &str) -> Option<Vec<PathBuf>> {
+        let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+        return self
+            .projects
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|project| project.build_file.parent().map(|directory| path.starts_with(directory)).unwrap_or(false))
+            .max_by_key(|project| project.build_file.components().count())
+            .map(|project| project.classpath.clone());
+    }
+
+    /// Finds the type `name` refers to in the document at `uri` through its imports and package,
+    /// among the workspace's sources and the classes of the document's dependencies. Names that
+    /// resolve to neither are looked up the way `hierarchy::locate_type_declaration` does, leaving
+    /// out what the document's module can't read.
+    pub fn locate_type(&self, uri: &str, name: &str) -> Option<(String, TypeDeclaration)> {
+        match self.resolve_class(uri, name) {
+            Some(ResolvedClass::Source(source_uri, _)) => {
+                let simple_name = name.rsplit('.').next().unwrap_or(name);
+                let declaration = self
+                    .type_declaration_map
+                    .get(&source_uri)
+                    .and_then(|declarations| declarations.iter().find(|declaration| declaration.name == simple_name).cloned());
+                if let Some(declaration) = declaration {
+                    return Some((source_uri, declaration));
+                }
+            }
+            Some(ResolvedClass::Dependency(class)) => return Some((class.uri(), class.declaration.clone())),
+            None => {}
+        }
+        let is_accessible = |target_uri: &str| self.can_access_document(uri, target_uri);
+        return hierarchy::locate_accessible_type_declaration(&self.type_declaration_map, uri, name, is_accessible);
+    }
+
+    /// The class of the document's dependencies that `name` refers to, by qualified name, or by
+    /// simple name through the document's imports, its own package and `java.lang`. A nested
+    /// class's name may also start with the simple name of its outer class, like `Map.Entry`.
+    pub fn dependency_class(&self, uri: &str, name: &str) -> Option<Arc<DependencyClass>> {
+        if self.dependency_index.classes.is_empty() {
+            return None;
+        }
+        let classpath = self.dependency_classpath(uri);
+        return self
+            .class_name_candidates(uri, name)
+            .iter()
+            .find_map(|class_name| self.accessible_dependency_class(uri, class_name, classpath.as_deref()));
+    }
+
+    /// The jars the document's dependency classes are looked up in, the JDK's sources first.
+    /// `None` when the document is in no project, which makes any jar do.
+    pub fn dependency_classpath(&self, uri: &str) -> Option<Vec<PathBuf>> {
+        let mut classpath = self.document_classpath(uri);
+        if let (Some(classpath), Some(jdk_sources)) = (classpath.as_mut(), self.jdk_sources()) {
+            classpath.insert(0, jdk_sources);
+        }
+        return classpath;
+    }
+
+    /// The dependency class of the fully qualified `class_name` on `classpath`, if the document's
+    /// module can read it.
+    pub fn accessible_dependency_class(&self, uri: &str, class_name: &str, classpath: Option<&[PathBuf]>) -> Option<Arc<DependencyClass>> {
+        return self
+            .dependency_index
+            .class(class_name, classpath)
+            .filter(|class| self.can_access_class(uri, class));
+    }
+}
+
+/// The package and simple name of the class with `binary_name`, unless it's nested or not a
+/// class at all, like `module-info`.
+fn top_level_class(binary_name: &str) -> Option<(String, &str)> {
+    let (package, simple_name) = match binary_name.rsplit_once('/') {
+        Some((package, simple_name)) => (package.replace('/', "."), simple_name),
+        None => (String::new(), binary_name),
+    };
+    if simple_name.contains(['$', '-']) {
+        return None;
+    }
+    return Some((package, simple_name));
+}
+
+/// The public and protected classes of the jar at `path`. Classes of other Java versions in
+/// multi-release jars, anonymous and local classes are left out.
+fn read_jar(path: &Path) -> io::Result<Vec<DependencyClass>> {
+    let jar = Jar::open(path)?;
+    let mut classes = Vec::new();
+    for entry in &jar.entries {
+        let binary_name = match entry.name.strip_suffix(".class") {
+            Some(binary_name) => binary_name,
+            None => continue,
+        };
+        if binary_name.starts_with("META-INF/") || binary_name.ends_with("module-info") || binary_name.ends_with("package-info") {
+            continue;
+        }
+        let is_anonymous_or_local = binary_name
+            .split('$')
+            .skip(1)
+            .any(|segment| segment.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(true));
+        if is_anonymous_or_local {
+            continue;
+        }
+        let class_file = match jar.read(entry).and_then(|data| class_file::parse(&data)) {
+            Some(class_file) => class_file,
+            None => continue,
+        };
+        let access_flags = class_file.inner_access_flags.unwrap_or(class_file.access_flags);
+        if access_flags & (class_file::ACC_PUBLIC | class_file::ACC_PROTECTED) == 0 || access_flags & class_file::ACC_SYNTHETIC != 0 {
+            continue;
+        }
+        classes.push(DependencyClass {
+            jar: path.to_path_buf(),
+            binary_name: class_file.name.clone(),
+            source_file: class_file.source_file.clone(),
+            source_entry: None,
+            declaration: type_declaration(&class_file, access_flags),
+        });
+    }
+    return Ok(classes);
+}
+
+fn type_declaration(class_file: &ClassFile, access_flags: u16) -> TypeDeclaration {
+    let kind = if access_flags & class_file::ACC_ANNOTATION != 0 {
+        "annotation_type_declaration"
+    } else if access_flags & class_file::ACC_INTERFACE != 0 {
+        "interface_declaration"
+    } else if access_flags & class_file::ACC_ENUM != 0 {
+        "enum_declaration"
+    } else if class_file.super_class.as_deref() == Some("java/lang/Record") {
+        "record_declaration"
+    } else {
+        "class_declaration"
+    };
+    let is_interface = kind == "interface_declaration" || kind == "annotation_type_declaration";
+    // the superclass of enums and records, and of every interface, says nothing new
+    let superclass = class_file
+        .super_class
+        .as_ref()
+        .filter(|superclass| !matches!(superclass.as_str(), "java/lang/Object" | "java/lang/Enum" | "java/lang/Record"));
+    let supertypes = superclass
+        .into_iter()
+        .chain(class_file.interfaces.iter())
+        .map(|supertype| class_file::qualified_name(supertype))
+        .collect();
+    let is_visible = |member: &Member| {
+        member.access_flags & (class_file::ACC_PUBLIC | class_file::ACC_PROTECTED) != 0
+            && member.access_flags & class_file::ACC_SYNTHETIC == 0
+    };
+    let is_inner = class_file.inner_access_flags.map(|flags| flags & class_file::ACC_STATIC == 0).unwrap_or(false);
+    let constructors = class_file
+        .methods
+        .iter()
+        .filter(|method| is_visible(method) && method.name == "<init>")
+        .filter_map(|method| {
+            let (mut parameter_types, _) = class_file::method_types(&method.descriptor)?;
+            // the name and ordinal of enum constants, and the enclosing instance of inner
+            // classes, are passed without being declared
+            let hidden = if kind == "enum_declaration" { 2 } else if is_inner { 1 } else { 0 };
+            parameter_types.drain(..hidden.min(parameter_types.len()));
+            return Some(MethodSignature {
+                name: class_file::simple_name(&class_file.name).to_string(),
+                parameter_types,
+                parameter_names: Vec::new(),
+                return_type: String::new(),
+                type_parameters: method.signature.as_deref().map(class_file::type_parameters).unwrap_or_default(),
+                modifiers: modifiers(method.access_flags),
+            });
+        })
+        .collect();
+    let mut methods: Vec<MethodSignature> = class_file
+        .methods
+        .iter()
+        .filter(|method| is_visible(method) && method.access_flags & class_file::ACC_BRIDGE == 0 && !method.name.starts_with('<'))
+        .filter_map(|method| {
+            let (parameter_types, return_type) = class_file::method_types(&method.descriptor)?;
+            let mut modifiers = modifiers(method.access_flags);
+            modifiers.is_default = is_interface && !modifiers.is_abstract && !modifiers.is_static;
+            return Some(MethodSignature {
+                name: method.name.clone(),
+                parameter_types,
+                parameter_names: Vec::new(),
+                return_type,
+                type_parameters: method.signature.as_deref().map(class_file::type_parameters).unwrap_or_default(),
+                modifiers,
+            });
+        })
+        .collect();
+    if kind == "enum_declaration" {
+        // those inherited from `java.lang.Enum`, which isn't kept as a supertype
+        for method in hierarchy::implicit_enum_methods(class_file::simple_name(&class_file.name)) {
+            if !methods.iter().any(|m| m.name == method.name && m.parameter_types == method.parameter_types) {
+                methods.push(method);
+            }
+        }
+    }
+    let fields = class_file
+        .fields
+        .iter()
+        .filter(|field| is_visible(field))
+        .filter_map(|field| {
+            let (field_type, _) = class_file::descriptor_type(&field.descriptor)?;
+            return Some(FieldSignature {
+                name: field.name.clone(),
+                field_type,
+                modifiers: modifiers(field.access_flags),
+            });
+        })
+        .collect();
+    return TypeDeclaration {
+        name: class_file::simple_name(&class_file.name).to_string(),
+        kind,
+        // the flag methods take for synchronized means something else for classes
+        modifiers: Modifiers {
+            is_synchronized: false,
+            is_sealed: !class_file.permitted_subclasses.is_empty(),
+            ..modifiers(access_flags)
+        },
+        type_parameters: class_file.signature.as_deref().map(class_file::type_parameters).unwrap_or_default(),
+        supertypes,
+        permitted_subtypes: class_file.permitted_subclasses.iter().map(|subclass| class_file::qualified_name(subclass)).collect(),
+        methods,
+        constructors,
+        fields,
+    };
+}
+
+fn modifiers(access_flags: u16) -> Modifiers {
+    let visibility = if access_flags & class_file::ACC_PUBLIC != 0 {
+        Visibility::Public
+    } else if access_flags & class_file::ACC_PROTECTED != 0 {
+        Visibility::Protected
+    } else if access_flags & class_file::ACC_PRIVATE != 0 {
+        Visibility::Private
+    } else {
+        Visibility::Package
+    };
+    return Modifiers {
+        visibility,
+        is_static: access_flags & class_file::ACC_STATIC != 0,
+        is_final: access_flags & class_file::ACC_FINAL != 0,
+        is_abstract: access_flags & class_file::ACC_ABSTRACT != 0,
+        is_synchronized: access_flags & class_file::ACC_SYNCHRONIZED != 0,
+        is_default: false,
+        is_sealed: false,
+        is_non_sealed: false,
+    };
+}
@@ -0,0 +1,437 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use dashmap::DashMap;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::classpath::{self, ClassOrigin, ClassRegistry};
+use crate::hierarchy::{self, TypeDeclaration};
+use crate::imports::Imports;
+use crate::line_index::LineIndex;
+use crate::references::package_name;
+use crate::source_set::SourceSet;
+use crate::Backend;
+
+pub const SOURCE: &str = "javals";
+
+pub const MISSING_OVERRIDE: &str = "missing-override";
+
+pub const TEST_TYPE_IN_MAIN: &str = "test-type-in-main";
+
+pub const DUPLICATE_CLASS: &str = "duplicate-class";
+
+pub const UNRESOLVED_METHOD: &str = "unresolved-method";
+
+pub const AMBIGUOUS_TYPE: &str = "ambiguous-type";
+
+pub const UNPERMITTED_SUBTYPE: &str = "unpermitted-subtype";
+
+pub const UNDEFINED_LABEL: &str = "undefined-label";
+
+/// Reports methods that override a supertype method without carrying `@Override`.
+pub fn missing_override_diagnostics(
+    uri: &str,
+    tree: &Tree,
+    source: &str,
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        let declaration = match hierarchy::type_declaration(node, source) {
+            Some(declaration) => declaration,
+            None => continue,
+        };
+        let body_node = match node.child_by_field_name("body") {
+            Some(body_node) => body_node,
+            None => continue,
+        };
+        for method_node in hierarchy::member_method_declarations(body_node) {
+            if has_annotation(method_node, source, "Override")
+                || has_modifier(method_node, "static")
+                || has_modifier(method_node, "private")
+            {
+                continue;
+            }
+            let name_node = match method_node.child_by_field_name("name") {
+                Some(name_node) => name_node,
+                None => continue,
+            };
+            let signature = hierarchy::method_signature(method_node, source);
+            let supertype_name = match hierarchy::find_overridden_method(type_declaration_map, uri, &declaration, &signature) {
+                Some(supertype_name) => supertype_name,
+                None => continue,
+            };
+            diagnostics.push(Diagnostic {
+                range: lines.range(name_node.start_position(), name_node.end_position()),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String(MISSING_OVERRIDE.to_string())),
+                source: Some(SOURCE.to_string()),
+                message: format!(
+                    "Method '{}' overrides a method of '{}' but is missing @Override",
+                    signature.name, supertype_name
+                ),
+                ..Diagnostic::default()
+            });
+        }
+    }
+    return diagnostics;
+}
+
+/// The simple names of the types declared in test sources.
+pub fn test_type_names(type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for entry in type_declaration_map.iter() {
+        if SourceSet::of(entry.key()) == SourceSet::Test {
+            names.extend(entry.value().iter().map(|declaration| declaration.name.clone()));
+        }
+    }
+    return names;
+}
+
+/// Reports type references in main sources that only resolve to types declared in test sources,
+/// whose names are `test_type_names`.
+pub fn test_type_reference_diagnostics(
+    uri: &str,
+    tree: &Tree,
+    source: &str,
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    test_type_names: &HashSet<String>,
+) -> Vec<Diagnostic> {
+    if SourceSet::of(uri) != SourceSet::Main {
+        return Vec::new();
+    }
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "type_identifier" {
+            continue;
+        }
+        let name = node.utf8_text(source.as_bytes()).unwrap();
+        if !test_type_names.contains(name) || hierarchy::find_type_declaration(type_declaration_map, uri, name).is_some() {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: lines.range(node.start_position(), node.end_position()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(TEST_TYPE_IN_MAIN.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!("Type '{}' is declared in test sources and is not visible from main sources", name),
+            ..Diagnostic::default()
+        });
+    }
+    return diagnostics;
+}
+
+/// Reports classes whose fully qualified name is also provided elsewhere, by another source or a
+/// jar of `classpath`, naming the provider that resolution will use.
+pub fn duplicate_class_diagnostics(
+    uri: &str,
+    tree: &Tree,
+    source: &str,
+    class_registry: &ClassRegistry,
+    classpath: Option<&[PathBuf]>,
+) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for (class_name, name_node) in classpath::declared_class_names(tree, source) {
+        let providers = class_registry.providers_on(&class_name, classpath);
+        if providers.len() < 2 {
+            continue;
+        }
+        let mut related_information = Vec::new();
+        let mut provider_names = Vec::new();
+        for provider in &providers {
+            let provider_url = match provider {
+                ClassOrigin::Source(provider_uri) => {
+                    provider_names.push(provider_uri.clone());
+                    if provider_uri == uri {
+                        continue;
+                    }
+                    Url::parse(provider_uri).ok()
+                }
+                ClassOrigin::Jar(jar) => {
+                    provider_names.push(jar.display().to_string());
+                    Url::from_file_path(jar).ok()
+                }
+            };
+            if let Some(provider_url) = provider_url {
+                related_information.push(DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: provider_url,
+                        range: Range::default(),
+                    },
+                    message: format!("'{}' is also declared here", class_name),
+                });
+            }
+        }
+        let winner = if providers[0] == ClassOrigin::Source(uri.to_string()) {
+            "this declaration".to_string()
+        } else {
+            provider_names[0].clone()
+        };
+        diagnostics.push(Diagnostic {
+            range: lines.range(name_node.start_position(), name_node.end_position()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(DUPLICATE_CLASS.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!(
+                "Class '{}' is provided {} times: {}. Resolution uses {}",
+                class_name,
+                providers.len(),
+                provider_names.join(", "),
+                winner
+            ),
+            related_information: Some(related_information),
+            ..Diagnostic::default()
+        });
+    }
+    return diagnostics;
+}
+
+/// Reports, for the build file of a project, the classes of each jar on its classpath that an
+/// earlier jar provides too, which resolution uses instead.
+pub fn shadowed_class_diagnostics(shadowed: &[(PathBuf, PathBuf, Vec<String>)]) -> Vec<Diagnostic> {
+    return shadowed
+        .iter()
+        .map(|(jar, winner, class_names)| Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(DUPLICATE_CLASS.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!(
+                "{} classes of {}, like '{}', are also provided by {}, which comes first on the classpath and is used instead",
+                class_names.len(),
+                jar.display(),
+                class_names[0],
+                winner.display()
+            ),
+            ..Diagnostic::default()
+        })
+        .collect();
+}
+
+/// Reports invocations of methods that the receiver's class, and every one of its supertypes,
+/// is known not to declare. Receivers whose class or supertypes aren't indexed are left alone.
+pub fn unresolved_method_diagnostics(backend: &Backend, uri: &str, tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "method_invocation" {
+            continue;
+        }
+        let name_node = match node.child_by_field_name("name") {
+            Some(name_node) => name_node,
+            None => continue,
+        };
+        if node.child_by_field_name("object").map(|n| n.kind()) == Some("super") {
+            continue;
+        }
+        let receiver_type = match backend.invocation_receiver_type(uri, node, source) {
+            Some(receiver_type) => receiver_type,
+            None => continue,
+        };
+        let declaration = match backend.locate_type(uri, &receiver_type) {
+            Some((declaring_uri, declaration)) if !declaring_uri.starts_with("jar:") => declaration,
+            _ => continue,
+        };
+        if declaration.kind != "class_declaration" && declaration.kind != "interface_declaration" {
+            continue;
+        }
+        let method_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        if backend.declares_method(uri, &receiver_type, method_name) != Some(false) {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: lines.range(name_node.start_position(), name_node.end_position()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(UNRESOLVED_METHOD.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!("The method '{}' is undefined for the type '{}'", method_name, receiver_type),
+            ..Diagnostic::default()
+        });
+    }
+    return diagnostics;
+}
+
+/// Reports type names that classes of several packages imported on demand answer to, when no
+/// single-type import or class of the document's own package decides between them.
+pub fn ambiguous_type_diagnostics(backend: &Backend, uri: &str, tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    let imports = Imports::of(tree, source);
+    if !imports.has_on_demand() {
+        return Vec::new();
+    }
+    let package = package_name(tree, source).unwrap_or_default();
+    let declared = classpath::declared_class_names(tree, source);
+    let declared_names: Vec<&str> = declared.iter().map(|(class_name, _)| class_name.as_str()).collect();
+    let mut type_parameters: Vec<&str> = Vec::new();
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "type_identifier" {
+            continue;
+        }
+        let name = node.utf8_text(source.as_bytes()).unwrap();
+        match node.parent() {
+            Some(parent_node) if parent_node.kind() == "type_parameter" => {
+                type_parameters.push(name);
+                continue;
+            }
+            // only the outermost name of `Outer.Inner` is looked up through the imports
+            Some(parent_node) if parent_node.kind() == "scoped_type_identifier" && parent_node.named_child(0) != Some(node) => continue,
+            _ => {}
+        }
+        if type_parameters.contains(&name) {
+            continue;
+        }
+        let candidates = imports.scoped_candidates(name, &package, &declared_names);
+        let ambiguous = backend.ambiguous_candidates(uri, &candidates);
+        if ambiguous.is_empty() {
+            continue;
+        }
+        let class_names: Vec<String> = ambiguous.iter().map(|class_name| format!("'{}'", class_name)).collect();
+        diagnostics.push(Diagnostic {
+            range: lines.range(node.start_position(), node.end_position()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(AMBIGUOUS_TYPE.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!("The type '{}' is ambiguous between {}", name, class_names.join(", ")),
+            ..Diagnostic::default()
+        });
+    }
+    return diagnostics;
+}
+
+/// Reports types extending or implementing a sealed type that doesn't permit them to.
+pub fn unpermitted_subtype_diagnostics(backend: &Backend, uri: &str, tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if !hierarchy::is_type_declaration(node.kind()) {
+            continue;
+        }
+        let type_name = match node.child_by_field_name("name") {
+            Some(name_node) => name_node.utf8_text(source.as_bytes()).unwrap(),
+            None => continue,
+        };
+        for supertype_node in hierarchy::supertype_nodes(node) {
+            let raw_type_node = match supertype_node.kind() {
+                "generic_type" => supertype_node.named_child(0).unwrap_or(supertype_node),
+                _ => supertype_node,
+            };
+            let supertype_name = raw_type_node.utf8_text(source.as_bytes()).unwrap();
+            let (declaring_uri, declaration) = match backend.locate_type(uri, supertype_name) {
+                Some(found) if found.1.modifiers.is_sealed => found,
+                _ => continue,
+            };
+            if backend.permits_subtype(uri, &declaring_uri, &declaration, type_name) {
+                continue;
+            }
+            let is_interface = declaration.kind == "interface_declaration";
+            let relation = match is_interface && node.kind() != "interface_declaration" {
+                true => "implement",
+                false => "extend",
+            };
+            diagnostics.push(Diagnostic {
+                range: lines.range(supertype_node.start_position(), supertype_node.end_position()),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(UNPERMITTED_SUBTYPE.to_string())),
+                source: Some(SOURCE.to_string()),
+                message: format!(
+                    "The type '{}' isn't permitted to {} the sealed {} '{}'",
+                    type_name,
+                    relation,
+                    if is_interface { "interface" } else { "class" },
+                    declaration.name,
+                ),
+                ..Diagnostic::default()
+            });
+        }
+    }
+    return diagnostics;
+}
+
+/// Reports `break` and `continue` statements naming a label no statement around them has. Labels
+/// don't reach into the bodies of methods, lambdas and classes nested in the labeled statement.
+pub fn undefined_label_diagnostics(tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "break_statement" && node.kind() != "continue_statement" {
+            continue;
+        }
+        let label_node = match node.named_children(&mut node.walk()).find(|n| n.kind() == "identifier") {
+            Some(label_node) => label_node,
+            None => continue,
+        };
+        let label = label_node.utf8_text(source.as_bytes()).unwrap();
+        let mut is_defined = false;
+        let mut current_node = node;
+        while let Some(parent_node) = current_node.parent() {
+            if matches!(parent_node.kind(), "method_declaration" | "constructor_declaration" | "lambda_expression" | "class_body") {
+                break;
+            }
+            let labeled_name = match parent_node.kind() {
+                "labeled_statement" => parent_node.named_child(0).map(|n| n.utf8_text(source.as_bytes()).unwrap()),
+                _ => None,
+            };
+            if labeled_name == Some(label) {
+                is_defined = true;
+                break;
+            }
+            current_node = parent_node;
+        }
+        if is_defined {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: lines.range(label_node.start_position(), label_node.end_position()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(UNDEFINED_LABEL.to_string())),
+            source: Some(SOURCE.to_string()),
+            message: format!("Undefined label '{}'", label),
+            ..Diagnostic::default()
+        });
+    }
+    return diagnostics;
+}
+
+pub fn has_modifier(declaration_node: Node, modifier: &str) -> bool {
+    let modifiers_node = match modifiers(declaration_node) {
+        Some(modifiers_node) => modifiers_node,
+        None => return false,
+    };
+    let mut cursor = modifiers_node.walk();
+    let found = modifiers_node.children(&mut cursor).any(|n| n.kind() == modifier);
+    return found;
+}
+
+/// Matches both the simple and the `java.lang`-qualified annotation name.
+pub fn has_annotation(declaration_node: Node, source: &str, annotation: &str) -> bool {
+    let modifiers_node = match modifiers(declaration_node) {
+        Some(modifiers_node) => modifiers_node,
+        None => return false,
+    };
+    let qualified = format!("java.lang.{}", annotation);
+    let mut cursor = modifiers_node.walk();
+    let found = modifiers_node.named_children(&mut cursor).any(|n| {
+        if n.kind() != "marker_annotation" && n.kind() != "annotation" {
+            return false;
+        }
+        match n.child_by_field_name("name") {
+            Some(name_node) => {
+                let name = name_node.utf8_text(source.as_bytes()).unwrap();
+                name == annotation || name == qualified
+            }
+            None => false,
+        }
+    });
+    return found;
+}
+
+fn modifiers(declaration_node: Node) -> Option<Node> {
+    let mut cursor = declaration_node.walk();
+    let modifiers_node = declaration_node.named_children(&mut cursor).find(|n| n.kind() == "modifiers");
+    return modifiers_node;
+}
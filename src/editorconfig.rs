@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError};
+
+use log::info;
+use tower_lsp::lsp_types::{FileEvent, Url};
+
+use crate::format::{self, FormatOptions};
+use crate::{glob, Backend};
+
+pub const FILE_NAME: &str = ".editorconfig";
+
+/// Numeric ranges like `{1..3}` expand to at most this many alternatives; larger ones never match.
+const MAX_RANGE_EXPANSION: i64 = 1000;
+
+/// One parsed `.editorconfig` file.
+#[derive(Debug, Default)]
+pub struct EditorConfigFile {
+    root: bool,
+    // glob and properties, in file order
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+/// The EditorConfig properties that apply to one document, as far as formatting and generated
+/// code go. Properties left out, or set to `unset`, are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorConfig {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+    pub end_of_line: Option<&'static str>,
+    pub charset: Option<String>,
+    pub max_line_length: Option<usize>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    fn from_properties(properties: &HashMap<String, String>) -> EditorConfig {
+        let get = |key: &str| properties.get(key).map(String::as_str).filter(|value| *value != "unset");
+        let tab_width = get("tab_width").and_then(|value| value.parse::<usize>().ok());
+        let indent_size = match get("indent_size") {
+            Some("tab") => tab_width,
+            Some(value) => value.parse::<usize>().ok(),
+            None => None,
+        };
+        return EditorConfig {
+            indent_style: match get("indent_style") {
+                Some("tab") => Some(IndentStyle::Tab),
+                Some("space") => Some(IndentStyle::Space),
+                _ => None,
+            },
+            indent_size: indent_size.filter(|size| *size > 0),
+            end_of_line: match get("end_of_line") {
+                Some("lf") => Some("\n"),
+                Some("crlf") => Some("\r\n"),
+                Some("cr") => Some("\r"),
+                _ => None,
+            },
+            charset: get("charset").map(str::to_string),
+            max_line_length: get("max_line_length").and_then(|value| value.parse::<usize>().ok()),
+            trim_trailing_whitespace: get("trim_trailing_whitespace").and_then(parse_bool),
+            insert_final_newline: get("insert_final_newline").and_then(parse_bool),
+        };
+    }
+
+    /// One level of indentation, when the configuration says enough to tell. An indent size on
+    /// its own means spaces.
+    pub fn indent_unit(&self) -> Option<String> {
+        return match (self.indent_style, self.indent_size) {
+            (Some(IndentStyle::Tab), _) => Some("\t".to_string()),
+            (_, Some(size)) => Some(" ".repeat(size)),
+            (Some(IndentStyle::Space), None) => None,
+            (None, None) => None,
+        };
+    }
+
+    /// Overrides the options a formatting request came with by the ones configured.
+    pub fn apply(&self, options: &mut FormatOptions) {
+        if let Some(indent_unit) = self.indent_unit() {
+            options.indent_unit = indent_unit;
+        }
+        if self.end_of_line.is_some() {
+            options.line_ending = self.end_of_line;
+        }
+        if let Some(charset) = &self.charset {
+            options.byte_order_mark = Some(charset == "utf-8-bom");
+        }
+        if let Some(trim_trailing_whitespace) = self.trim_trailing_whitespace {
+            options.trim_trailing_whitespace = trim_trailing_whitespace;
+        }
+        if let Some(insert_final_newline) = self.insert_final_newline {
+            options.insert_final_newline = insert_final_newline;
+        }
+    }
+}
+
+impl Backend {
+    /// The EditorConfig properties for the document at `uri`, merged from the `.editorconfig`
+    /// files in its directory and above, up to the first one marked `root = true`. Nearer files
+    /// and later sections win. Parsed files are cached per directory.
+    pub fn editor_config(&self, uri: &Url) -> EditorConfig {
+        let path = match uri.to_file_path() {
+            Ok(path) => path,
+            Err(_) => return EditorConfig::default(),
+        };
+        let mut files: Vec<(PathBuf, Arc<EditorConfigFile>)> = Vec::new();
+        for directory in path.ancestors().skip(1) {
+            if let Some(file) = self.editor_config_file(directory) {
+                let root = file.root;
+                files.push((directory.to_path_buf(), file));
+                if root {
+                    break;
+                }
+            }
+        }
+        let mut properties: HashMap<String, String> = HashMap::new();
+        for (directory, file) in files.iter().rev() {
+            let relative_path = match path.strip_prefix(directory) {
+                Ok(relative_path) => relative_path.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+            for (glob, section_properties) in &file.sections {
+                if !glob_matches(glob, &relative_path) {
+                    continue;
+                }
+                for (key, value) in section_properties {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        return EditorConfig::from_properties(&properties);
+    }
+
+    /// The line break text inserted into the document at `uri` should use: the configured one,
+    /// or else the one the document already uses.
+    pub fn line_ending(&self, uri: &Url) -> &'static str {
+        if let Some(end_of_line) = self.editor_config(uri).end_of_line {
+            return end_of_line;
+        }
+        return match self.document_map.get(uri.as_str()) {
+            Some(source) => format::document_line_ending(&source),
+            None => "\n",
+        };
+    }
+
+    fn editor_config_file(&self, directory: &Path) -> Option<Arc<EditorConfigFile>> {
+        if let Some(cached) = self.editor_config_cache.read().unwrap_or_else(PoisonError::into_inner).get(directory) {
+            return cached.clone();
+        }
+        let file = fs::read_to_string(directory.join(FILE_NAME))
+            .ok()
+            .map(|text| Arc::new(parse(&text)));
+        self.editor_config_cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(directory.to_path_buf(), file.clone());
+        return file;
+    }
+
+    /// Forgets the parsed `.editorconfig` files that changed on disk.
+    pub fn invalidate_editor_configs(&self, changes: &[FileEvent]) {
+        let mut cache = self.editor_config_cache.write().unwrap_or_else(PoisonError::into_inner);
+        for change in changes {
+            let path = match change.uri.to_file_path() {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            if path.file_name().map(|name| name == FILE_NAME).unwrap_or(false) {
+                if let Some(directory) = path.parent() {
+                    info!("editorconfig changed in {:?}", directory);
+                    cache.remove(directory);
+                }
+            }
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    return match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    };
+}
+
+/// Parses the INI-like format of `.editorconfig` files. Keys, and the values of the properties
+/// this server reads, are case-insensitive.
+fn parse(text: &str) -> EditorConfigFile {
+    let mut file = EditorConfigFile::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            file.sections.push((line[1..line.len() - 1].to_string(), Vec::new()));
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_lowercase()),
+            None => continue,
+        };
+        match file.sections.last_mut() {
+            Some((_, properties)) => properties.push((key, value)),
+            None if key == "root" => file.root = value == "true",
+            None => {}
+        }
+    }
+    return file;
+}
+
+/// Whether `path`, relative to the directory of the `.editorconfig` file, matches the section
+/// glob. Globs without a `/` match the file name in any directory.
+fn glob_matches(glob: &str, path: &str) -> bool {
+    let anchored = if glob.contains('/') {
+        glob.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", glob)
+    };
+    let path: Vec<char> = path.chars().collect();
+    return expand_braces(&anchored).iter().any(|pattern| {
+        let pattern: Vec<char> = pattern.chars().collect();
+        return glob::matches(&pattern, &path);
+    });
+}
+
+/// Expands `{a,b}` alternatives and `{1..3}` numeric ranges into one pattern each.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let open = match pattern.find('{') {
+        Some(open) => open,
+        None => return vec![pattern.to_string()],
+    };
+    let mut depth = 0;
+    let mut close = None;
+    let mut commas = Vec::new();
+    for (index, c) in pattern[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + index);
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(open + index),
+            _ => {}
+        }
+    }
+    let close = match close {
+        Some(close) => close,
+        None => return vec![pattern.to_string()],
+    };
+    let (prefix, inner, suffix) = (&pattern[..open], &pattern[open + 1..close], &pattern[close + 1..]);
+    let alternatives: Vec<String> = if !commas.is_empty() {
+        let mut alternatives = Vec::new();
+        let mut start = open + 1;
+        for comma in commas.iter().copied().chain(std::iter::once(close)) {
+            alternatives.push(pattern[start..comma].to_string());
+            start = comma + 1;
+        }
+        alternatives
+    } else if let Some((low, high)) = inner.split_once("..").and_then(|(low, high)| Some((low.parse::<i64>().ok()?, high.parse::<i64>().ok()?))) {
+        let (low, high) = (low.min(high), low.max(high));
+        if high - low >= MAX_RANGE_EXPANSION {
+            return Vec::new();
+        }
+        (low..=high).map(|n| n.to_string()).collect()
+    } else {
+        // a single word in braces stands for itself
+        vec![format!("{{{}}}", inner)]
+    };
+    let mut expanded = Vec::new();
+    for alternative in alternatives {
+        // the alternative may have braces of its own, and so may the rest of the pattern
+        for rest in expand_braces(&format!("{}{}", alternative, suffix)) {
+            expanded.push(format!("{}{}", prefix, rest));
+        }
+    }
+    return expanded;
+}
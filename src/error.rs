@@ -0,0 +1,99 @@
+use std::any::Any;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::task::Poll;
+
+use log::error;
+use tower_lsp::jsonrpc::{self, ErrorCode};
+use tracing::Instrument;
+
+use crate::logging;
+
+/// What goes wrong handling a request or notification, short of the answer just being empty.
+#[derive(Debug)]
+pub enum Error {
+    /// The document at the URI has no text or tree: it was never opened or indexed.
+    UnknownDocument(String),
+    /// The Java grammar couldn't be loaded, or the parser gave up on the document at the URI.
+    Parse(String),
+    /// Handling the method panicked, with the panic's message.
+    Panicked(&'static str, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::UnknownDocument(uri) => write!(f, "{} is not open", uri),
+            Error::Parse(uri) => write!(f, "unable to parse {}", uri),
+            Error::Panicked(method, message) => write!(f, "{} failed: {}", method, message),
+        };
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for jsonrpc::Error {
+    fn from(error: Error) -> jsonrpc::Error {
+        let code = match error {
+            Error::UnknownDocument(_) => ErrorCode::InvalidParams,
+            Error::Parse(_) | Error::Panicked(..) => ErrorCode::InternalError,
+        };
+        return jsonrpc::Error {
+            code,
+            message: error.to_string().into(),
+            data: None,
+        };
+    }
+}
+
+/// What a handler answers when handling the method panicked instead.
+pub trait Recover {
+    fn recover(error: Error) -> Self;
+}
+
+/// Notifications have no answer, so the failure is only logged.
+impl Recover for () {
+    fn recover(error: Error) {
+        error!("{}", error);
+    }
+}
+
+impl<T> Recover for jsonrpc::Result<T> {
+    fn recover(error: Error) -> jsonrpc::Result<T> {
+        error!("{}", error);
+        return Err(error.into());
+    }
+}
+
+/// Awaits `handler`, the handling of a `method` request or notification, in a span of its own,
+/// answering as [`Recover`] says if it panics rather than letting the panic take down the server.
+pub async fn guarded<F>(method: &'static str, handler: F) -> F::Output
+where
+    F: Future,
+    F::Output: Recover,
+{
+    let mut handler = pin!(handler.instrument(logging::request_span(method)));
+    let outcome = poll_fn(|cx| match panic::catch_unwind(AssertUnwindSafe(|| handler.as_mut().poll(cx))) {
+        Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(payload) => Poll::Ready(Err(payload)),
+    })
+    .await;
+    return match outcome {
+        Ok(output) => output,
+        Err(payload) => F::Output::recover(Error::Panicked(method, panic_message(payload.as_ref()))),
+    };
+}
+
+/// The message a panic was raised with, when it was raised with one.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    return "unknown panic".to_string();
+}
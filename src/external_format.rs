@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::line_index;
+
+/// How long an external formatter gets before it's killed and the built-in one used instead.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Past this many differing lines the diff gives up on finding the smallest edits and replaces
+/// everything between the common prefix and suffix.
+const MAX_DIFF_DISTANCE: usize = 2000;
+
+/// The command for running the google-java-format jar at `jar` on standard input with the
+/// `java` launcher at `java`.
+pub fn google_java_format_command(java: &Path, jar: &str) -> Vec<String> {
+    return vec![java.to_string_lossy().to_string(), "-jar".to_string(), jar.to_string(), "-".to_string()];
+}
+
+/// Runs `command`, writing `source` to its standard input, and returns what it writes to
+/// standard output. Failing to start, a non-zero exit and taking too long are errors.
+pub async fn run(command: &[String], source: &str) -> Result<String, String> {
+    let (program, args) = command.split_first().ok_or("empty command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| format!("unable to start {}: {}", program, error))?;
+    let mut stdin = child.stdin.take().ok_or("no stdin")?;
+    let input = source.to_string();
+    // written while the output is read, so a large document can't fill both pipes; dropping
+    // stdin afterwards signals the end of the document
+    let writer = tokio::spawn(async move { stdin.write_all(input.as_bytes()).await });
+    let output = tokio::time::timeout(TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("{} timed out after {:?}", program, TIMEOUT))?
+        .map_err(|error| error.to_string())?;
+    let _ = writer.await;
+    if !output.status.success() {
+        return Err(format!("{} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    return String::from_utf8(output.stdout).map_err(|error| error.to_string());
+}
+
+/// The edits turning `old` into `new`, one per run of changed lines, so the client keeps the
+/// cursor and markers on the lines that didn't change.
+pub fn line_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let prefix = old_lines.iter().zip(&new_lines).take_while(|(a, b)| a == b).count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old_lines[prefix..old_lines.len() - suffix];
+    let new_middle = &new_lines[prefix..new_lines.len() - suffix];
+    let hunks = diff(old_middle, new_middle).unwrap_or_else(|| vec![(0, old_middle.len(), 0, new_middle.len())]);
+    return hunks
+        .into_iter()
+        .filter(|(old_start, old_end, new_start, new_end)| old_start != old_end || new_start != new_end)
+        .map(|(old_start, old_end, new_start, new_end)| TextEdit {
+            range: Range {
+                start: line_start(&old_lines, prefix + old_start),
+                end: line_start(&old_lines, prefix + old_end),
+            },
+            new_text: new_middle[new_start..new_end].concat(),
+        })
+        .collect();
+}
+
+/// Where line `index` starts, or the end of the text when it's past the last line.
+fn line_start(lines: &[&str], index: usize) -> Position {
+    if index < lines.len() || lines.last().map(|line| line.ends_with('\n')).unwrap_or(true) {
+        return Position {
+            line: index as u32,
+            character: 0,
+        };
+    }
+    // the last line has no newline, so the text ends on it
+    let last = lines[lines.len() - 1];
+    return Position {
+        line: (lines.len() - 1) as u32,
+        character: line_index::character(last),
+    };
+}
+
+/// Myers' diff of two line sequences, as `(old_start, old_end, new_start, new_end)` ranges that
+/// differ, in order. `None` when they differ in more than `MAX_DIFF_DISTANCE` lines.
+fn diff(old: &[&str], new: &[&str]) -> Option<Vec<(usize, usize, usize, usize)>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    let mut furthest = vec![0isize; (2 * offset + 1) as usize];
+    // the furthest reaching x on each diagonal, after each number of differences
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut distance = None;
+    'search: for d in 0..=(n + m).min(MAX_DIFF_DISTANCE as isize) {
+        trace.push(furthest.clone());
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && furthest[index - 1] < furthest[index + 1]) {
+                furthest[index + 1]
+            } else {
+                furthest[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[index] = x;
+            if x >= n && y >= m {
+                distance = Some(d);
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+    let distance = distance?;
+
+    // walk back from the end, collecting the matching lines
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=distance).rev() {
+        let previous = &trace[d as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && previous[(k - 1 + offset) as usize] < previous[(k + 1 + offset) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = previous[(previous_k + offset) as usize];
+        let previous_y = previous_x - previous_k;
+        // the lines matched after the insertion or deletion this step made
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+
+    let mut hunks = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    for (old_match, new_match) in matches.into_iter().chain(std::iter::once((old.len(), new.len()))) {
+        if old_match > old_index || new_match > new_index {
+            hunks.push((old_index, old_match, new_index, new_match));
+        }
+        old_index = old_match + 1;
+        new_index = new_match + 1;
+    }
+    return Some(hunks);
+}
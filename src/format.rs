@@ -0,0 +1,952 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::PoisonError;
+
+use log::{debug, info};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Tree};
+
+use crate::line_index::{self, LineIndex};
+use crate::{external_format, Backend};
+
+/// Nodes whose contents are indented one level deeper than the line holding their opening brace.
+const CONTAINERS: [&str; 10] = [
+    "class_body",
+    "interface_body",
+    "enum_body",
+    "annotation_type_body",
+    "constructor_body",
+    "block",
+    "switch_block",
+    "array_initializer",
+    "element_value_array_initializer",
+    "module_body",
+];
+
+/// Containers whose opening brace belongs at the end of the line before it, as in `void f() {`.
+/// The rest stand alone, like nested blocks and instance initializers.
+const STANDALONE_CONTAINER_PARENTS: [&str; 6] = [
+    "block",
+    "class_body",
+    "constructor_body",
+    "switch_block_statement_group",
+    "labeled_statement",
+    "program",
+];
+
+/// Tokens the formatter never looks inside, since their text is content rather than layout.
+const ATOMIC_KINDS: [&str; 5] = [
+    "string_literal",
+    "character_literal",
+    "line_comment",
+    "block_comment",
+    "comment",
+];
+
+/// Parents in which an operator token is binary and gets a space on both sides.
+const BINARY_OPERATOR_PARENTS: [&str; 14] = [
+    "binary_expression",
+    "assignment_expression",
+    "ternary_expression",
+    "variable_declarator",
+    "lambda_expression",
+    "enhanced_for_statement",
+    "switch_rule",
+    "catch_type",
+    "type_bound",
+    "instanceof_expression",
+    "element_value_pair",
+    "resource",
+    "assert_statement",
+    "annotation_type_element_declaration",
+];
+
+const BINARY_OPERATORS: [&str; 36] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>=", ">>>=",
+    "==", "!=", "<", ">", "<=", ">=", "&&", "||",
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", ">>>",
+    "?", ":", "->", "instanceof", "default",
+];
+
+const KEYWORDS_BEFORE_PARENTHESIS: [&str; 7] = ["if", "for", "while", "switch", "catch", "synchronized", "try"];
+
+/// Levels added to a line that continues a statement or declaration started on an earlier line.
+const CONTINUATION_LEVELS: usize = 2;
+
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub indent_unit: String,
+    pub trim_trailing_whitespace: bool,
+    pub insert_final_newline: bool,
+    pub trim_final_newlines: bool,
+    /// The line break to use, or `None` to keep the document's own.
+    pub line_ending: Option<&'static str>,
+    /// Whether the document should start with a byte order mark, or `None` to leave it as it is.
+    pub byte_order_mark: Option<bool>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        return FormatOptions {
+            indent_unit: "    ".to_string(),
+            trim_trailing_whitespace: true,
+            insert_final_newline: true,
+            trim_final_newlines: true,
+            line_ending: None,
+            byte_order_mark: None,
+        };
+    }
+}
+
+impl From<&FormattingOptions> for FormatOptions {
+    /// The options a client sends along with a formatting request. The ones it leaves out keep
+    /// their default.
+    fn from(options: &FormattingOptions) -> FormatOptions {
+        let defaults = FormatOptions::default();
+        let indent_unit = if !options.insert_spaces {
+            "\t".to_string()
+        } else if options.tab_size > 0 {
+            " ".repeat(options.tab_size as usize)
+        } else {
+            defaults.indent_unit
+        };
+        return FormatOptions {
+            indent_unit,
+            trim_trailing_whitespace: options.trim_trailing_whitespace.unwrap_or(defaults.trim_trailing_whitespace),
+            insert_final_newline: options.insert_final_newline.unwrap_or(defaults.insert_final_newline),
+            trim_final_newlines: options.trim_final_newlines.unwrap_or(defaults.trim_final_newlines),
+            ..defaults
+        };
+    }
+}
+
+impl Backend {
+    pub async fn format_document(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        debug!("formatting {}", uri);
+        // copied, since an external formatter is awaited
+        let (tree, source_text, version) = match (self.parsed_document_map.get(uri.as_str()), self.document_map.get(uri.as_str())) {
+            (Some(tree), Some(source_text)) => (tree.clone(), source_text.clone(), self.document_version(uri.as_str())),
+            _ => return Ok(None),
+        };
+        let options = self.format_options(&uri, &params.options);
+        let edits = self.document_format_edits(&tree, &source_text, &options).await;
+        self.ensure_version(uri.as_str(), version)?;
+        return Ok(edits);
+    }
+
+    /// The edits formatting a whole document, by the external formatter when one is configured
+    /// and works, by [`format_edits`] otherwise.
+    pub async fn document_format_edits(&self, tree: &Tree, source: &str, options: &FormatOptions) -> Option<Vec<TextEdit>> {
+        let command = self.config.read().unwrap_or_else(PoisonError::into_inner).external_formatter.clone();
+        if let Some(command) = command {
+            match external_format::run(&command, source).await {
+                Ok(formatted) => return Some(external_format::line_edits(source, &formatted)),
+                Err(error) => {
+                    let message = format!("external formatter failed, using the built-in one: {}", error);
+                    info!("{}", message);
+                    self.client.log_message(MessageType::WARNING, message).await;
+                }
+            }
+        }
+        return format_edits(tree, source, options);
+    }
+
+    pub async fn format_range(&self, params: DocumentRangeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        debug!("range formatting {} {:?}", uri, params.range);
+        let tree = match self.parsed_document_map.get(uri.as_str()) {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+        let source_text = match self.document_map.get(uri.as_str()) {
+            Some(source_text) => source_text,
+            None => return Ok(None),
+        };
+        let options = self.format_options(&uri, &params.options);
+        return Ok(format_range_edits(&tree, &source_text, params.range, &options));
+    }
+
+    pub async fn format_on_type(&self, params: DocumentOnTypeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let position = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+        debug!("on type formatting {} {:?} {:?}", uri, position, params.ch);
+        let tree = match self.parsed_document_map.get(uri.as_str()) {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+        let source_text = match self.document_map.get(uri.as_str()) {
+            Some(source_text) => source_text,
+            None => return Ok(None),
+        };
+        let range = match typed_range(&tree, &source_text, position, &params.ch) {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let options = self.format_options(&uri, &params.options);
+        return Ok(format_range_edits(&tree, &source_text, range, &options));
+    }
+
+    /// The options a formatting request came with, overridden by the document's EditorConfig.
+    fn format_options(&self, uri: &Url, client_options: &FormattingOptions) -> FormatOptions {
+        let mut options = FormatOptions::from(client_options);
+        self.editor_config(uri).apply(&mut options);
+        return options;
+    }
+}
+
+/// The whitespace edits that lay out `tree` consistently: indentation from nesting, opening braces
+/// on the line of their declaration or statement, single spaces around binary operators and
+/// after commas, no spaces inside parentheses, at most one blank line in a row, and imports
+/// sorted into a static and a regular group. Only whitespace between tokens changes, apart from
+/// the import block, the leading whitespace of lines inside block comments, line breaks inside
+/// comments and text blocks, and the byte order mark. Documents with syntax errors are left
+/// alone, returning `None`.
+pub fn format_edits(tree: &Tree, source: &str, options: &FormatOptions) -> Option<Vec<TextEdit>> {
+    let mut edits = layout_edits(tree, source, options, None)?;
+    let start = Position { line: 0, character: 0 };
+    match options.byte_order_mark {
+        Some(true) if !source.starts_with(BYTE_ORDER_MARK) => edits.insert(
+            0,
+            TextEdit {
+                range: Range { start, end: start },
+                new_text: BYTE_ORDER_MARK.to_string(),
+            },
+        ),
+        Some(false) if source.starts_with(BYTE_ORDER_MARK) => edits.insert(
+            0,
+            TextEdit {
+                range: Range {
+                    start,
+                    end: Position {
+                        line: 0,
+                        character: line_index::character(&source[..BYTE_ORDER_MARK.len_utf8()]),
+                    },
+                },
+                new_text: String::new(),
+            },
+        ),
+        _ => {}
+    }
+    return Some(edits);
+}
+
+/// The edits of [`format_edits`] that fall on the lines of `range`, leaving every other byte of the
+/// document as it is. Only the innermost block around the range is laid out, indented relative to
+/// the line opening it, so a selection inside code indented differently still lines up with it,
+/// and syntax errors elsewhere in the document don't get in the way.
+pub fn format_range_edits(tree: &Tree, source: &str, range: Range, options: &FormatOptions) -> Option<Vec<TextEdit>> {
+    let mut last_line = range.end.line;
+    if range.end.character == 0 && last_line > range.start.line {
+        // a selection of whole lines ends at the start of the next one
+        last_line -= 1;
+    }
+    let first_line_text = source.lines().nth(range.start.line as usize).unwrap_or("");
+    let scope = LocalScope::around_lines(tree, source, range.start.line as usize, last_line as usize);
+    let edits = layout_edits(tree, source, options, scope.as_ref())?;
+    let region_start = Position {
+        line: range.start.line,
+        character: 0,
+    };
+    let region_end = Position {
+        line: last_line,
+        character: line_index::character(source.lines().nth(last_line as usize).unwrap_or("")),
+    };
+    let mut range_edits = Vec::new();
+    for edit in edits {
+        if edit.range.end < region_start || edit.range.start > region_end || edit.range.end > region_end {
+            continue;
+        }
+        if edit.range.start >= region_start {
+            range_edits.push(edit);
+            continue;
+        }
+        // the gap before the first line: only its indentation is part of the range
+        if edit.range.end.line == range.start.line && edit.new_text.contains(['\n', '\r']) {
+            let indentation = edit.new_text.rsplit(['\n', '\r']).next().unwrap_or("").to_string();
+            let current_indentation = line_index::prefix(first_line_text, edit.range.end.character);
+            if indentation == current_indentation {
+                continue;
+            }
+            range_edits.push(TextEdit {
+                range: Range {
+                    start: region_start,
+                    end: edit.range.end,
+                },
+                new_text: indentation,
+            });
+        }
+    }
+    return Some(range_edits);
+}
+
+/// The lines to reformat after typing `ch` just before `position`: the block a `}` closes, the
+/// statement or member a `;` ends, or the line a newline ends.
+fn typed_range(tree: &Tree, source: &str, position: Position, ch: &str) -> Option<Range> {
+    let lines = |first: usize, end: Position| Range {
+        start: Position {
+            line: first as u32,
+            character: 0,
+        },
+        end,
+    };
+    if ch == "\n" {
+        let line = position.line.checked_sub(1)?;
+        return Some(lines(line as usize, Position { line, character: 0 }));
+    }
+    let typed_end = LineIndex::new(source).point(position);
+    let point = tree_sitter::Point::new(typed_end.row, typed_end.column.checked_sub(1)?);
+    let leaf = tree.root_node().descendant_for_point_range(point, point)?;
+    // the character may have been typed inside a string or comment
+    if leaf.kind() != ch {
+        return None;
+    }
+    let parent = leaf.parent()?;
+    return match ch {
+        "}" if CONTAINERS.contains(&parent.kind()) => Some(lines(parent.start_position().row, position)),
+        ";" => {
+            let mut statement = parent;
+            while let Some(next) = statement.parent() {
+                if next.kind() == "program" || CONTAINERS.contains(&next.kind()) {
+                    break;
+                }
+                statement = next;
+            }
+            Some(lines(statement.start_position().row, position))
+        }
+        _ => None,
+    };
+}
+
+/// A block laid out on its own: its contents are indented relative to the indentation the line of
+/// its opening brace actually has.
+struct LocalScope<'a> {
+    container: Node<'a>,
+    indentation: String,
+}
+
+impl<'a> LocalScope<'a> {
+    /// The innermost block opening before `first_line` and closing on or after `last_line`.
+    fn around_lines(tree: &'a Tree, source: &str, first_line: usize, last_line: usize) -> Option<LocalScope<'a>> {
+        let line_text = source.lines().nth(first_line).unwrap_or("");
+        let point = tree_sitter::Point::new(first_line, line_text.len() - line_text.trim_start().len());
+        let node = tree.root_node().descendant_for_point_range(point, point)?;
+        let container = std::iter::successors(Some(node), |node| node.parent()).find(|node| {
+            CONTAINERS.contains(&node.kind()) && node.start_position().row < first_line && node.end_position().row >= last_line
+        })?;
+        let indentation = source
+            .lines()
+            .nth(container.start_position().row)
+            .unwrap_or("")
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        return Some(LocalScope { container, indentation });
+    }
+}
+
+/// The edits laying out the whole document, or only the contents of `scope`. The opening brace of
+/// the scope is taken to be at level zero, and nothing outside it is looked at or changed.
+fn layout_edits(tree: &Tree, source: &str, options: &FormatOptions, scope: Option<&LocalScope>) -> Option<Vec<TextEdit>> {
+    let root = tree.root_node();
+    let layout_root = scope.map(|scope| scope.container).unwrap_or(root);
+    if layout_root.has_error() {
+        return None;
+    }
+    let mut leaves = Vec::new();
+    collect_leaves(layout_root, &mut leaves);
+    if leaves.is_empty() {
+        return Some(Vec::new());
+    }
+    let leaf_indexes: HashMap<usize, usize> = leaves.iter().enumerate().map(|(i, leaf)| (leaf.id(), i)).collect();
+    let lines = LineIndex::new(source);
+    let line_ending = options.line_ending.unwrap_or_else(|| document_line_ending(source));
+    let imports = if scope.is_none() { ImportBlock::find(root, source, line_ending, None) } else { None };
+    let base_indentation = scope.map(|scope| scope.indentation.as_str()).unwrap_or("");
+    let indent_unit = &options.indent_unit;
+
+    let mut edits = Vec::new();
+    // for each leaf, the level of the formatted line it ends up on, not counting continuation
+    let mut line_levels: Vec<usize> = Vec::with_capacity(leaves.len());
+    for (i, leaf) in leaves.iter().enumerate() {
+        if i == 0 && scope.is_some() {
+            // the opening brace of the scope, whose line stays as it is
+            line_levels.push(0);
+            continue;
+        }
+        let previous = if i > 0 { Some(leaves[i - 1]) } else { None };
+        let gap_start = previous.map(|p| p.end_byte()).unwrap_or(0);
+        let gap = &source[gap_start..leaf.start_byte()];
+        let in_imports = imports
+            .as_ref()
+            .map(|imports| leaf.start_byte() > imports.start_byte && leaf.start_byte() < imports.end_byte)
+            .unwrap_or(false);
+
+        let joins_brace = previous.map(|p| !is_comment(p)).unwrap_or(false) && is_header_brace(*leaf);
+        let starts_line = gap.contains('\n') && !joins_brace;
+        let (level, continuation) = if starts_line {
+            line_level(*leaf, previous, &leaf_indexes, &line_levels)
+        } else {
+            (line_levels.last().copied().unwrap_or(0), 0)
+        };
+        line_levels.push(level);
+        let indentation = format!("{}{}", base_indentation, indent_unit.repeat(level + continuation));
+
+        if in_imports || !gap.chars().all(char::is_whitespace) {
+            continue;
+        }
+        let new_gap = match previous {
+            None => String::new(),
+            Some(previous) if imports.as_ref().map(|imports| imports.borders(previous, *leaf)).unwrap_or(false) => {
+                // one blank line around the import block
+                line_ending.repeat(2)
+            }
+            Some(_) if joins_brace => " ".to_string(),
+            Some(_) if starts_line => {
+                // the whitespace ending the previous line and any blank ones, unless it's trimmed
+                let newlines = gap.matches('\n').count().min(2);
+                let line_ends: Vec<&str> = gap
+                    .split('\n')
+                    .take(newlines)
+                    .map(|line_end| if options.trim_trailing_whitespace { "" } else { line_end.trim_end_matches('\r') })
+                    .collect();
+                format!("{}{}{}", line_ends.join(line_ending), line_ending, indentation)
+            }
+            Some(previous) => match spacing(previous, *leaf) {
+                Some(spacing) => spacing.to_string(),
+                // spaces aligning a trailing comment are deliberate
+                None if is_comment(*leaf) || gap.is_empty() => gap.to_string(),
+                None => " ".to_string(),
+            },
+        };
+        if new_gap != gap {
+            edits.push(TextEdit {
+                range: Range {
+                    start: previous.map(|p| lines.position(p.end_position())).unwrap_or(Position { line: 0, character: 0 }),
+                    end: lines.position(leaf.start_position()),
+                },
+                new_text: new_gap,
+            });
+        }
+        if starts_line && leaf.kind() == "block_comment" {
+            edits.extend(block_comment_edits(*leaf, source, indentation.len(), &indentation));
+        }
+        if is_comment(*leaf) || is_text_block(*leaf, source) {
+            let trim = options.trim_trailing_whitespace && is_comment(*leaf);
+            edits.extend(line_end_edits(*leaf, source, &lines, trim, line_ending));
+        }
+    }
+
+    let last = leaves[leaves.len() - 1];
+    let trailing = &source[last.end_byte()..];
+    let new_trailing = final_newlines(trailing, options, line_ending);
+    if scope.is_none() && trailing != new_trailing {
+        edits.push(TextEdit {
+            range: Range {
+                start: lines.position(last.end_position()),
+                end: lines.position_at(source.len()),
+            },
+            new_text: new_trailing,
+        });
+    }
+    if let Some(imports) = imports {
+        if imports.new_text != source[imports.start_byte..imports.end_byte] {
+            edits.push(TextEdit {
+                range: lines.range(imports.start_position, imports.end_position),
+                new_text: imports.new_text,
+            });
+        }
+    }
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    return Some(edits);
+}
+
+fn collect_leaves<'a>(node: Node<'a>, leaves: &mut Vec<Node<'a>>) {
+    if node.child_count() == 0 || ATOMIC_KINDS.contains(&node.kind()) {
+        if node.end_byte() > node.start_byte() {
+            leaves.push(node);
+        }
+        return;
+    }
+    for child in node.children(&mut node.walk()) {
+        collect_leaves(child, leaves);
+    }
+}
+
+fn is_comment(node: Node) -> bool {
+    return node.kind().ends_with("comment");
+}
+
+/// Whether `node` is a text block, the string literal in triple quotes that may span lines.
+fn is_text_block(node: Node, source: &str) -> bool {
+    return node.kind() == "string_literal" && source[node.start_byte()..].starts_with("\"\"\"");
+}
+
+fn is_header_brace(leaf: Node) -> bool {
+    if leaf.kind() != "{" {
+        return false;
+    }
+    let container = match leaf.parent() {
+        Some(container) => container,
+        None => return false,
+    };
+    if !CONTAINERS.contains(&container.kind()) || container.kind().ends_with("array_initializer") {
+        return false;
+    }
+    return match container.parent() {
+        Some(owner) => !STANDALONE_CONTAINER_PARENTS.contains(&owner.kind()),
+        None => false,
+    };
+}
+
+/// The indentation level of a line starting with `leaf`: one deeper than the line opening the
+/// innermost enclosing container, and separately the continuation levels added when the line
+/// doesn't start the statement or member it belongs to.
+fn line_level(leaf: Node, previous: Option<Node>, leaf_indexes: &HashMap<usize, usize>, line_levels: &[usize]) -> (usize, usize) {
+    let opening_level = |container: Node| -> usize {
+        return container
+            .child(0)
+            .and_then(|brace| leaf_indexes.get(&brace.id()))
+            .and_then(|i| line_levels.get(*i))
+            .copied()
+            .unwrap_or(0);
+    };
+    let mut path = vec![leaf];
+    let mut base = 0;
+    let mut anchor = leaf;
+    let mut current = leaf;
+    while let Some(parent) = current.parent() {
+        if parent.kind() == "program" {
+            anchor = current;
+            break;
+        }
+        if CONTAINERS.contains(&parent.kind()) && parent.child(0) != Some(current) {
+            if parent.child(parent.child_count() - 1) == Some(current) && current.kind() == "}" {
+                return (opening_level(parent), 0);
+            }
+            base = opening_level(parent) + 1;
+            anchor = current;
+            break;
+        }
+        path.push(parent);
+        current = parent;
+        anchor = parent;
+    }
+    // statements under a `case ...:` label sit one level deeper than the label
+    if anchor.kind() == "switch_block_statement_group" || anchor.kind() == "enum_body_declarations" {
+        let position = path.iter().position(|node| *node == anchor).unwrap_or(0);
+        if position > 0 {
+            let member = path[position - 1];
+            if anchor.kind() == "switch_block_statement_group" && member.kind() != "switch_label" && member.kind() != ":" {
+                base += 1;
+            }
+            anchor = member;
+        }
+    }
+    let continues = anchor.start_position().row < leaf.start_position().row;
+    // `} else`, `})`, annotations and labels on their own line don't make the next line a continuation
+    let follows_brace_or_prefix = match previous {
+        Some(previous) => {
+            previous.kind() == "}"
+                || ends_annotation(previous)
+                || (previous.kind() == ":" && previous.parent().map(|p| p.kind()) == Some("labeled_statement"))
+        }
+        None => false,
+    };
+    if continues && !follows_brace_or_prefix {
+        return (base, CONTINUATION_LEVELS);
+    }
+    return (base, 0);
+}
+
+fn ends_annotation(leaf: Node) -> bool {
+    let mut current = leaf;
+    while let Some(parent) = current.parent() {
+        if parent.end_byte() != leaf.end_byte() {
+            return false;
+        }
+        if parent.kind() == "annotation" || parent.kind() == "marker_annotation" {
+            return true;
+        }
+        current = parent;
+    }
+    return false;
+}
+
+/// The spacing between two tokens on the same line, or `None` when no rule applies, which keeps
+/// them adjacent or separated by a single space as they were.
+fn spacing(left: Node, right: Node) -> Option<&'static str> {
+    if is_comment(left) || is_comment(right) {
+        return None;
+    }
+    let left_kind = left.kind();
+    let right_kind = right.kind();
+    let parent_kind = |node: Node| node.parent().map(|p| p.kind()).unwrap_or("");
+    let is_binary_operator = |node: Node| BINARY_OPERATORS.contains(&node.kind()) && BINARY_OPERATOR_PARENTS.contains(&parent_kind(node));
+    let is_type_angle = |node: Node| (node.kind() == "<" || node.kind() == ">") && (parent_kind(node) == "type_arguments" || parent_kind(node) == "type_parameters");
+
+    if right_kind == ";" || right_kind == "," {
+        return Some("");
+    }
+    if left_kind == "," {
+        return Some(" ");
+    }
+    if left_kind == ";" {
+        return Some(if right_kind == ")" { "" } else { " " });
+    }
+    if left_kind == "(" || right_kind == ")" || left_kind == "[" || right_kind == "]" || right_kind == "[" {
+        return Some("");
+    }
+    if left_kind == "." || right_kind == "." || left_kind == "::" || right_kind == "::" || left_kind == "@" {
+        return Some("");
+    }
+    if right_kind == "(" {
+        if KEYWORDS_BEFORE_PARENTHESIS.contains(&left_kind) {
+            return Some(" ");
+        }
+        let names_callee = left.is_named() || left_kind == ">";
+        if names_callee && ["argument_list", "formal_parameters", "annotation_argument_list"].contains(&parent_kind(right)) {
+            return Some("");
+        }
+        return None;
+    }
+    // `List<String>`, but `static <T> T max()`
+    let opens_type_parameters = right_kind == "<" && parent_kind(right) == "type_parameters";
+    if (is_type_angle(right) && !opens_type_parameters) || (left_kind == "<" && is_type_angle(left)) {
+        return Some("");
+    }
+    if right_kind == "{" {
+        if left_kind == "{" {
+            return None;
+        }
+        return Some(" ");
+    }
+    if left_kind == "}" && ["else", "catch", "finally", "while"].contains(&right_kind) {
+        return Some(" ");
+    }
+    if (left_kind == "!" || left_kind == "~" || left_kind == "-" || left_kind == "+") && parent_kind(left) == "unary_expression" {
+        return Some("");
+    }
+    if (left_kind == "++" || left_kind == "--" || right_kind == "++" || right_kind == "--")
+        && (parent_kind(left) == "update_expression" || parent_kind(right) == "update_expression")
+    {
+        return Some("");
+    }
+    if is_binary_operator(left) || is_binary_operator(right) {
+        return Some(" ");
+    }
+    return None;
+}
+
+/// Edits moving the inner lines of a multi-line block comment along with its first line, so the
+/// `*` of a Javadoc comment stays aligned.
+fn block_comment_edits(comment: Node, source: &str, new_column: usize, new_indentation: &str) -> Vec<TextEdit> {
+    let old_column = comment.start_position().column;
+    if old_column == new_column {
+        return Vec::new();
+    }
+    let mut edits = Vec::new();
+    let text = &source[comment.start_byte()..comment.end_byte()];
+    for (offset, line) in text.lines().enumerate().skip(1) {
+        let leading = line.len() - line.trim_start().len();
+        // blank lines have nothing to align, only trailing whitespace
+        if line.trim().is_empty() || leading < old_column || !line[..leading].chars().all(|c| c == ' ' || c == '\t') {
+            continue;
+        }
+        let row = (comment.start_position().row + offset) as u32;
+        edits.push(TextEdit {
+            range: Range {
+                start: Position { line: row, character: 0 },
+                end: Position {
+                    line: row,
+                    character: old_column as u32,
+                },
+            },
+            new_text: new_indentation.to_string(),
+        });
+    }
+    return edits;
+}
+
+/// Edits giving each line inside a comment or text block `line_ending`, and removing the
+/// whitespace at the end of its lines when `trim` is set.
+fn line_end_edits(leaf: Node, source: &str, source_lines: &LineIndex, trim: bool, line_ending: &str) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+    let text = &source[leaf.start_byte()..leaf.end_byte()];
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut line_start = leaf.start_byte();
+    for (offset, line) in lines.iter().enumerate() {
+        let is_last = offset == lines.len() - 1;
+        let content = line.trim_end_matches('\r');
+        let kept = if trim { content.trim_end() } else { content };
+        let old_line_end = format!("{}{}", &line[kept.len()..], if is_last { "" } else { "\n" });
+        let new_line_end = if is_last { "" } else { line_ending };
+        let kept_end = line_start + kept.len();
+        line_start += line.len() + 1;
+        if old_line_end == new_line_end {
+            continue;
+        }
+        edits.push(TextEdit {
+            range: Range {
+                start: source_lines.position_at(kept_end),
+                end: source_lines.position_at(line_start.min(leaf.end_byte())),
+            },
+            new_text: new_line_end.to_string(),
+        });
+    }
+    return edits;
+}
+
+/// What should follow the last token of the document: its newlines, trimmed down to one and
+/// added when missing as the options say, with the whitespace on the lines they end removed
+/// when trailing whitespace is trimmed.
+fn final_newlines(trailing: &str, options: &FormatOptions, line_ending: &str) -> String {
+    let mut line_ends: Vec<&str> = trailing.split('\n').map(|line_end| line_end.trim_end_matches('\r')).collect();
+    // the text after the last newline is an unterminated last line
+    let last_line = line_ends.pop().unwrap_or("");
+    if options.trim_final_newlines {
+        line_ends.truncate(1);
+    }
+    if options.insert_final_newline && line_ends.is_empty() {
+        line_ends.push("");
+    }
+    let mut new_trailing = String::new();
+    for line_end in line_ends {
+        if !options.trim_trailing_whitespace {
+            new_trailing.push_str(line_end);
+        }
+        new_trailing.push_str(line_ending);
+    }
+    if !options.trim_trailing_whitespace {
+        new_trailing.push_str(last_line);
+    }
+    return new_trailing;
+}
+
+/// The line break the document uses first, `\n` when it has none.
+pub fn document_line_ending(source: &str) -> &'static str {
+    return match source.find(['\n', '\r']) {
+        Some(index) if source[index..].starts_with("\r\n") => "\r\n",
+        Some(index) if source[index..].starts_with('\r') => "\r",
+        _ => "\n",
+    };
+}
+
+/// The edit sorting the import block as formatting does, and dropping the single-type imports of
+/// names the document never mentions. On-demand imports are kept, since what they bring in isn't
+/// known. `None` when nothing changes.
+pub fn organize_imports_edit(tree: &Tree, source: &str) -> Option<TextEdit> {
+    let root = tree.root_node();
+    if root.has_error() {
+        return None;
+    }
+    let used_names = used_names(root, source);
+    let imports = ImportBlock::find(root, source, document_line_ending(source), Some(&used_names))?;
+    let lines = LineIndex::new(source);
+    let (mut end_byte, mut end) = (imports.end_byte, lines.position(imports.end_position));
+    if imports.new_text.is_empty() {
+        // without any imports left, the blank lines after them go too
+        let next = root
+            .named_children(&mut root.walk())
+            .find(|node| node.start_byte() >= imports.end_byte);
+        (end_byte, end) = match next {
+            Some(next) => (next.start_byte(), lines.position(next.start_position())),
+            None => (source.len(), lines.position_at(source.len())),
+        };
+    }
+    if imports.new_text == source[imports.start_byte..end_byte] {
+        return None;
+    }
+    return Some(TextEdit {
+        range: Range {
+            start: lines.position(imports.start_position),
+            end,
+        },
+        new_text: imports.new_text,
+    });
+}
+
+/// The identifiers used outside the package and import declarations, along with the words of
+/// comments, so a type only mentioned in Javadoc counts as used.
+fn used_names<'a>(root: Node, source: &'a str) -> HashSet<&'a str> {
+    let mut names = HashSet::new();
+    for node in tree_sitter_traversal::traverse(root.walk(), tree_sitter_traversal::Order::Pre) {
+        if is_comment(node) {
+            let text = &source[node.start_byte()..node.end_byte()];
+            names.extend(text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')));
+            continue;
+        }
+        if node.kind() != "identifier" && node.kind() != "type_identifier" {
+            continue;
+        }
+        let in_header = std::iter::successors(node.parent(), |n| n.parent())
+            .any(|n| n.kind() == "import_declaration" || n.kind() == "package_declaration");
+        if !in_header {
+            names.insert(&source[node.start_byte()..node.end_byte()]);
+        }
+    }
+    return names;
+}
+
+/// The contiguous import declarations at the top of a file and their sorted replacement: static
+/// imports first, then the rest, each group alphabetical and free of duplicates.
+struct ImportBlock {
+    start_byte: usize,
+    end_byte: usize,
+    start_position: tree_sitter::Point,
+    end_position: tree_sitter::Point,
+    new_text: String,
+}
+
+impl ImportBlock {
+    fn borders(&self, previous: Node, leaf: Node) -> bool {
+        return leaf.start_byte() == self.start_byte || previous.end_byte() == self.end_byte;
+    }
+
+    /// With `used_names`, single-type imports of other names are left out of the replacement.
+    fn find(root: Node, source: &str, line_ending: &str, used_names: Option<&HashSet<&str>>) -> Option<ImportBlock> {
+        let children: Vec<Node> = root.named_children(&mut root.walk()).collect();
+        let first_index = children.iter().position(|n| n.kind() == "import_declaration")?;
+        let last_index = children.iter().rposition(|n| n.kind() == "import_declaration")?;
+        let imports = &children[first_index..=last_index];
+        // a comment among the imports would lose its place when sorting
+        if imports.iter().any(|n| n.kind() != "import_declaration") {
+            return None;
+        }
+        let first = imports[0];
+        let last = imports[imports.len() - 1];
+        let mut static_imports: Vec<String> = Vec::new();
+        let mut regular_imports: Vec<String> = Vec::new();
+        for import in imports {
+            let mut leaves = Vec::new();
+            collect_leaves(*import, &mut leaves);
+            let is_static = leaves.iter().any(|leaf| leaf.kind() == "static");
+            let path: String = leaves
+                .iter()
+                .filter(|leaf| !["import", "static", ";"].contains(&leaf.kind()))
+                .map(|leaf| &source[leaf.start_byte()..leaf.end_byte()])
+                .collect();
+            let simple_name = path.rsplit('.').next().unwrap_or("");
+            if simple_name != "*" && used_names.map(|names| !names.contains(simple_name)).unwrap_or(false) {
+                continue;
+            }
+            if is_static {
+                static_imports.push(format!("import static {};", path));
+            } else {
+                regular_imports.push(format!("import {};", path));
+            }
+        }
+        let mut groups = Vec::new();
+        for mut group in [static_imports, regular_imports] {
+            if group.is_empty() {
+                continue;
+            }
+            group.sort();
+            group.dedup();
+            groups.push(group.join(line_ending));
+        }
+        return Some(ImportBlock {
+            start_byte: first.start_byte(),
+            end_byte: last.end_byte(),
+            start_position: first.start_position(),
+            end_position: last.end_position(),
+            new_text: groups.join(&line_ending.repeat(2)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, TextEdit};
+    use tree_sitter::{Parser, Tree};
+
+    use super::{format_edits, format_range_edits, typed_range, FormatOptions};
+    use crate::line_index::LineIndex;
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        return parser.parse(source, None).unwrap();
+    }
+
+    /// `source` with `edits`, which are made to it and don't overlap, applied.
+    fn apply(source: &str, edits: &[TextEdit]) -> String {
+        let lines = LineIndex::new(source);
+        let mut offsets: Vec<(usize, usize, &str)> = edits
+            .iter()
+            .map(|edit| (lines.offset(edit.range.start), lines.offset(edit.range.end), edit.new_text.as_str()))
+            .collect();
+        offsets.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+        let mut formatted = source.to_string();
+        for (start, end, new_text) in offsets {
+            formatted.replace_range(start..end, new_text);
+        }
+        return formatted;
+    }
+
+    fn format(source: &str) -> String {
+        let edits = format_edits(&parse(source), source, &FormatOptions::default()).unwrap();
+        return apply(source, &edits);
+    }
+
+    #[test]
+    fn lays_out_a_document() {
+        let source = "class A{\nint a=1 ;\n  void f( int b )\n{\nif(b>a){\nreturn;}\n\n\n\n}\n}";
+        // only the whitespace between tokens changes, so no line is broken
+        assert_eq!(
+            format(source),
+            "class A {\n    int a = 1;\n    void f(int b) {\n        if (b > a) {\n            return; }\n\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn leaves_formatted_documents_alone() {
+        let source = "class A {\n    void f() {\n        g(1, 2);\n    }\n}\n";
+        assert_eq!(format_edits(&parse(source), source, &FormatOptions::default()), Some(Vec::new()));
+    }
+
+    #[test]
+    fn leaves_documents_with_syntax_errors_alone() {
+        let source = "class A {\n void f( {\n}\n";
+        assert_eq!(format_edits(&parse(source), source, &FormatOptions::default()), None);
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let source = "import b.B;\r\nimport a.A;\r\nclass A{\r\n/**\r\n * doc \r\n */\r\nvoid f(){\r\n\r\n\r\nreturn;\r\n}\r\n}";
+        let formatted = format(source);
+        assert_eq!(
+            formatted,
+            "import a.A;\r\nimport b.B;\r\n\r\nclass A {\r\n    /**\r\n     * doc\r\n     */\r\n    void f() {\r\n\r\n        return;\r\n    }\r\n}\r\n"
+        );
+        assert_eq!(formatted.matches('\n').count(), formatted.matches("\r\n").count());
+    }
+
+    #[test]
+    fn formats_only_the_lines_of_a_range() {
+        let source = "class A {\nint a ;\n      void f() {\n  g( );\n      }\n}\n";
+        let range = Range {
+            start: Position::new(3, 0),
+            end: Position::new(4, 0),
+        };
+        let edits = format_range_edits(&parse(source), source, range, &FormatOptions::default()).unwrap();
+        // indented relative to the line opening the block, which stays as it is
+        assert_eq!(apply(source, &edits), "class A {\nint a ;\n      void f() {\n          g();\n      }\n}\n");
+    }
+
+    #[test]
+    fn formats_the_block_a_typed_brace_closes() {
+        let source = "class A {\n    void f() {\n  if (b) {\n  g( );\n  }\n    }\n}\n";
+        let tree = parse(source);
+        let position = Position::new(4, 3);
+        let range = typed_range(&tree, source, position, "}").unwrap();
+        assert_eq!(range.start, Position::new(2, 0));
+        let edits = format_range_edits(&tree, source, range, &FormatOptions::default()).unwrap();
+        assert_eq!(apply(source, &edits), "class A {\n    void f() {\n        if (b) {\n            g();\n        }\n    }\n}\n");
+        // a brace inside a string closes nothing
+        let source = "class A {\n    String s = \"}\";\n}\n";
+        assert_eq!(typed_range(&parse(source), source, Position::new(1, 17), "}"), None);
+    }
+}
@@ -0,0 +1,150 @@
+/// Matches `*` (within a path segment), `**` (across segments), `?`, `[...]` classes and
+/// backslash escapes.
+pub fn matches(pattern: &[char], path: &[char]) -> bool {
+    let mut matcher = Matcher {
+        pattern,
+        path,
+        memo: vec![None; (pattern.len() + 1) * (path.len() + 1)],
+    };
+    return matcher.matches(0, 0);
+}
+
+/// Matches the rest of a pattern against the rest of a path. Whether they match from a pair of
+/// positions is remembered, so that patterns with many stars don't backtrack exponentially.
+struct Matcher<'a> {
+    pattern: &'a [char],
+    path: &'a [char],
+    memo: Vec<Option<bool>>,
+}
+
+impl Matcher<'_> {
+    fn matches(&mut self, pattern_index: usize, path_index: usize) -> bool {
+        let key = pattern_index * (self.path.len() + 1) + path_index;
+        if let Some(matched) = self.memo[key] {
+            return matched;
+        }
+        let matched = self.matches_at(pattern_index, path_index);
+        self.memo[key] = Some(matched);
+        return matched;
+    }
+
+    fn matches_at(&mut self, pattern_index: usize, path_index: usize) -> bool {
+        let (pattern, path) = (self.pattern, self.path);
+        let c = match pattern.get(pattern_index) {
+            Some(c) => *c,
+            None => return path_index == path.len(),
+        };
+        let rest = pattern_index + 1;
+        match c {
+            '*' if pattern.get(rest) == Some(&'*') => {
+                let rest = rest + 1;
+                // `**/` also matches no directory at all
+                if pattern.get(rest) == Some(&'/') && self.matches(rest + 1, path_index) {
+                    return true;
+                }
+                return (path_index..=path.len()).any(|skipped| self.matches(rest, skipped));
+            }
+            '*' => {
+                for skipped in path_index..=path.len() {
+                    if self.matches(rest, skipped) {
+                        return true;
+                    }
+                    if skipped < path.len() && path[skipped] == '/' {
+                        break;
+                    }
+                }
+                return false;
+            }
+            '?' => return path_index < path.len() && path[path_index] != '/' && self.matches(rest, path_index + 1),
+            '[' => {
+                let close = match pattern[rest..].iter().position(|c| *c == ']') {
+                    Some(close) => rest + close,
+                    None => return path.get(path_index) == Some(&'[') && self.matches(rest, path_index + 1),
+                };
+                let class = &pattern[rest..close];
+                let first = match path.get(path_index) {
+                    Some(first) if *first != '/' => *first,
+                    _ => return false,
+                };
+                let (negated, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut in_class = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        in_class |= class[i] <= first && first <= class[i + 2];
+                        i += 3;
+                    } else {
+                        in_class |= class[i] == first;
+                        i += 1;
+                    }
+                }
+                return in_class != negated && self.matches(close + 1, path_index + 1);
+            }
+            '\\' if rest < pattern.len() => {
+                return path.get(path_index) == Some(&pattern[rest]) && self.matches(rest + 1, path_index + 1);
+            }
+            c => return path.get(path_index) == Some(&c) && self.matches(rest, path_index + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob_matches(pattern: &str, path: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let path: Vec<char> = path.chars().collect();
+        return matches(&pattern, &path);
+    }
+
+    #[test]
+    fn matches_a_star_within_a_segment() {
+        assert!(glob_matches("*.java", "Main.java"));
+        assert!(glob_matches("src/*.java", "src/Main.java"));
+        assert!(!glob_matches("*.java", "src/Main.java"));
+        assert!(!glob_matches("*.java", "Main.kt"));
+    }
+
+    #[test]
+    fn matches_a_double_star_across_segments() {
+        assert!(glob_matches("**/*.java", "Main.java"));
+        assert!(glob_matches("**/*.java", "src/main/java/Main.java"));
+        assert!(glob_matches("src/**/Main.java", "src/Main.java"));
+        assert!(glob_matches("src/**/Main.java", "src/main/java/Main.java"));
+        assert!(glob_matches("build/**", "build/classes/Main.class"));
+        assert!(!glob_matches("src/**/Main.java", "test/Main.java"));
+    }
+
+    #[test]
+    fn matches_single_characters_and_classes() {
+        assert!(glob_matches("Main?.java", "Main2.java"));
+        assert!(!glob_matches("a?b", "a/b"));
+        assert!(glob_matches("[a-c]x", "bx"));
+        assert!(!glob_matches("[a-c]x", "dx"));
+        assert!(glob_matches("[!a-c]x", "dx"));
+        assert!(glob_matches("[^a-c]x", "dx"));
+        assert!(!glob_matches("[!a-c]x", "ax"));
+        assert!(glob_matches("[x", "[x"));
+    }
+
+    #[test]
+    fn matches_escaped_characters_literally() {
+        assert!(glob_matches("\\*.java", "*.java"));
+        assert!(!glob_matches("\\*.java", "Main.java"));
+        assert!(glob_matches("a\\[b]", "a[b]"));
+    }
+
+    #[test]
+    fn matches_many_stars_without_backtracking_exponentially() {
+        let pattern = "*a".repeat(30) + "b";
+        let path = "a".repeat(60);
+        assert!(!glob_matches(&pattern, &path));
+        let pattern = "**/".repeat(20) + "x";
+        let path = "a/".repeat(40) + "y";
+        assert!(!glob_matches(&pattern, &path));
+    }
+}
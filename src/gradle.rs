@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::PoisonError;
+use std::time::{Duration, SystemTime};
+
+use log::info;
+use tokio::process::Command;
+
+use crate::maven;
+use crate::project::{self, Project};
+use crate::Backend;
+
+/// The files that make a directory the root of a Gradle build.
+pub const SETTINGS_FILES: [&str; 2] = ["settings.gradle", "settings.gradle.kts"];
+/// The files of each Gradle project.
+pub const BUILD_FILES: [&str; 2] = ["build.gradle", "build.gradle.kts"];
+/// Other files whose changes can change what a build resolves.
+pub const PROPERTY_FILES: [&str; 2] = ["gradle.properties", "libs.versions.toml"];
+
+/// Resolving the first time may download the whole classpath, so Gradle gets a while.
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Prints the source directories and the resolved compile classpaths of every project of a
+/// build, each line prefixed so the output of the build itself can be told apart.
+const INIT_SCRIPT: &str = r#"allprojects {
+    tasks.register("javalsClasspath") {
+        doLast {
+            def sourceSets = project.extensions.findByName("sourceSets")
+            if (sourceSets == null) {
+                return
+            }
+            println "javals-project " + project.buildFile
+            sourceSets.findByName("main")?.java?.srcDirs?.each { println "javals-source " + it }
+            sourceSets.findByName("test")?.java?.srcDirs?.each { println "javals-test-source " + it }
+            ["compileClasspath", "testCompileClasspath"].each { name ->
+                def configuration = project.configurations.findByName(name)
+                if (configuration != null && configuration.canBeResolved) {
+                    def resolved = configuration.resolvedConfiguration.lenientConfiguration
+                    resolved.files.each { println "javals-classpath " + it }
+                    resolved.unresolvedModuleDependencies.each { println "javals-unresolved " + it.selector }
+                }
+            }
+        }
+    }
+}
+"#;
+
+const OUTPUT_PREFIX: &str = "javals-";
+
+/// What a Gradle build resolved to, and the build files it was resolved from, so it's resolved
+/// again only when one of them changes.
+#[derive(Debug, Clone)]
+pub struct GradleBuild {
+    stamp: Vec<(PathBuf, Option<SystemTime>)>,
+    projects: Vec<Project>,
+}
+
+impl Backend {
+    /// The Gradle projects of the builds under `roots`. A build is resolved by running its
+    /// wrapper, or `gradle`, with an init script printing its classpaths; when that fails the
+    /// dependencies its build files declare are looked up in the Gradle and Maven caches instead.
+    /// Results are cached until a build file changes.
+    pub async fn gradle_projects(&self, roots: &[PathBuf]) -> Vec<Project> {
+        let build_roots = build_roots(roots);
+        let mut projects = Vec::new();
+        let mut builds = HashMap::new();
+        for build_root in build_roots {
+            let stamp = stamp(&build_root);
+            let cached = self.gradle_builds.read().unwrap_or_else(PoisonError::into_inner).get(&build_root).cloned();
+            let build = match cached {
+                Some(build) if build.stamp == stamp => build,
+                _ => {
+                    let build_projects = match resolve(&build_root).await {
+                        Ok(build_projects) => build_projects,
+                        Err(error) => {
+                            info!("unable to resolve the Gradle build at {:?}, reading its build files instead: {}", build_root, error);
+                            declared_projects(&build_root)
+                        }
+                    };
+                    GradleBuild {
+                        stamp,
+                        projects: build_projects,
+                    }
+                }
+            };
+            projects.extend(build.projects.iter().cloned());
+            builds.insert(build_root, build);
+        }
+        *self.gradle_builds.write().unwrap_or_else(PoisonError::into_inner) = builds;
+        return projects;
+    }
+}
+
+/// The directories with a settings file, and those with a build file that no settings file
+/// above it includes.
+fn build_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let file_names: Vec<&str> = SETTINGS_FILES.iter().chain(BUILD_FILES.iter()).copied().collect();
+    let files = project::find_files(roots, &file_names);
+    let directory = |file: &PathBuf| file.parent().unwrap_or(Path::new("")).to_path_buf();
+    let settings_roots: Vec<PathBuf> = files
+        .iter()
+        .filter(|file| SETTINGS_FILES.iter().any(|name| file.ends_with(name)))
+        .map(directory)
+        .collect();
+    let mut build_roots = settings_roots.clone();
+    for file in files.iter().filter(|file| BUILD_FILES.iter().any(|name| file.ends_with(name))) {
+        let build_directory = directory(file);
+        if !settings_roots.iter().any(|settings_root| build_directory.starts_with(settings_root)) {
+            build_roots.push(build_directory);
+        }
+    }
+    build_roots.sort();
+    build_roots.dedup();
+    return build_roots;
+}
+
+/// The modification times of the files under `build_root` that decide what it resolves to.
+fn stamp(build_root: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let file_names: Vec<&str> = SETTINGS_FILES.iter().chain(BUILD_FILES.iter()).chain(PROPERTY_FILES.iter()).copied().collect();
+    return project::find_files(&[build_root.to_path_buf()], &file_names)
+        .into_iter()
+        .map(|file| {
+            let modified = fs::metadata(&file).and_then(|metadata| metadata.modified()).ok();
+            return (file, modified);
+        })
+        .collect();
+}
+
+/// Runs the build at `build_root` with the init script and reads the projects from its output.
+async fn resolve(build_root: &Path) -> Result<Vec<Project>, String> {
+    let wrapper = build_root.join(if cfg!(windows) { "gradlew.bat" } else { "gradlew" });
+    let program = if wrapper.is_file() { wrapper } else { PathBuf::from("gradle") };
+    let init_script = env::temp_dir().join("javals-classpath.gradle");
+    fs::write(&init_script, INIT_SCRIPT).map_err(|error| error.to_string())?;
+    info!("resolving the Gradle build at {:?} with {:?}", build_root, program);
+    let child = Command::new(&program)
+        .args(["--init-script", &init_script.to_string_lossy(), "--quiet", "--console=plain", "javalsClasspath"])
+        .current_dir(build_root)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| format!("unable to start {:?}: {}", program, error))?;
+    let output = tokio::time::timeout(TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("{:?} timed out after {:?}", program, TIMEOUT))?
+        .map_err(|error| error.to_string())?;
+    if !output.status.success() {
+        return Err(format!("{:?} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    return Ok(parse_output(&String::from_utf8_lossy(&output.stdout)));
+}
+
+fn parse_output(output: &str) -> Vec<Project> {
+    let mut projects: Vec<Project> = Vec::new();
+    for line in output.lines() {
+        let (kind, value) = match line.strip_prefix(OUTPUT_PREFIX).and_then(|line| line.split_once(' ')) {
+            Some((kind, value)) => (kind, value.trim()),
+            None => continue,
+        };
+        if kind == "project" {
+            projects.push(Project {
+                build_file: PathBuf::from(value),
+                source_directories: Vec::new(),
+                test_source_directories: Vec::new(),
+                classpath: Vec::new(),
+                unresolved_dependencies: Vec::new(),
+            });
+            continue;
+        }
+        let project = match projects.last_mut() {
+            Some(project) => project,
+            None => continue,
+        };
+        let entries = match kind {
+            "source" => &mut project.source_directories,
+            "test-source" => &mut project.test_source_directories,
+            "classpath" => &mut project.classpath,
+            "unresolved" => {
+                project.unresolved_dependencies.push(value.to_string());
+                continue;
+            }
+            _ => continue,
+        };
+        let path = PathBuf::from(value);
+        if !entries.contains(&path) {
+            entries.push(path);
+        }
+    }
+    return projects;
+}
+
+/// The projects of the build at `build_root` as its build files declare them, with the
+/// dependencies written out as `group:artifact:version` found in the Gradle or Maven caches.
+/// Transitive dependencies, and ones declared any other way, are missing.
+fn declared_projects(build_root: &Path) -> Vec<Project> {
+    let gradle_cache = gradle_cache();
+    let maven_repository = maven::local_repository();
+    let mut projects = Vec::new();
+    for build_file in project::find_files(&[build_root.to_path_buf()], &BUILD_FILES) {
+        let directory = build_file.parent().unwrap_or(Path::new("")).to_path_buf();
+        let text = fs::read_to_string(&build_file).unwrap_or_default();
+        let mut classpath = Vec::new();
+        let mut unresolved_dependencies = Vec::new();
+        for coordinates in declared_dependencies(&text) {
+            match find_jar(&gradle_cache, &maven_repository, &coordinates) {
+                Some(jar) => classpath.push(jar),
+                None => unresolved_dependencies.push(coordinates),
+            }
+        }
+        projects.push(Project {
+            build_file,
+            source_directories: vec![directory.join("src/main/java")],
+            test_source_directories: vec![directory.join("src/test/java")],
+            classpath,
+            unresolved_dependencies,
+        });
+    }
+    return projects;
+}
+
+/// The `group:artifact:version` string literals on the lines of `text` that look like
+/// dependency declarations, such as `implementation "org.slf4j:slf4j-api:2.0.9"`.
+fn declared_dependencies(text: &str) -> Vec<String> {
+    let mut dependencies = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let configuration: String = line.chars().take_while(|c| c.is_alphanumeric()).collect();
+        if !configuration.ends_with("mplementation") && !configuration.ends_with("ompileOnly") && !configuration.ends_with("pi") {
+            continue;
+        }
+        for literal in line.split(['"', '\'']).skip(1).step_by(2) {
+            let parts: Vec<&str> = literal.split(':').collect();
+            let is_coordinates = (3..=4).contains(&parts.len())
+                && parts
+                    .iter()
+                    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || "._-".contains(c)));
+            if is_coordinates && !dependencies.contains(&literal.to_string()) {
+                dependencies.push(literal.to_string());
+            }
+        }
+    }
+    return dependencies;
+}
+
+/// Where Gradle keeps the dependencies it downloaded.
+fn gradle_cache() -> PathBuf {
+    let gradle_home = env::var_os("GRADLE_USER_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| maven::home_directory().join(".gradle"));
+    return gradle_home.join("caches").join("modules-2").join("files-2.1");
+}
+
+/// The jar of `coordinates` in the Gradle cache, which keeps each file in a directory named after
+/// its hash, or in the local Maven repository.
+fn find_jar(gradle_cache: &Path, maven_repository: &Path, coordinates: &str) -> Option<PathBuf> {
+    let parts: Vec<&str> = coordinates.split(':').collect();
+    let (group_id, artifact_id, version) = (parts[0], parts[1], parts[2]);
+    let file_name = match parts.get(3) {
+        Some(classifier) => format!("{}-{}-{}.jar", artifact_id, version, classifier),
+        None => format!("{}-{}.jar", artifact_id, version),
+    };
+    let gradle_directory = gradle_cache.join(group_id).join(artifact_id).join(version);
+    if let Ok(entries) = fs::read_dir(&gradle_directory) {
+        for entry in entries.flatten() {
+            let jar = entry.path().join(&file_name);
+            if jar.is_file() {
+                return Some(jar);
+            }
+        }
+    }
+    let mut maven_jar = maven_repository.to_path_buf();
+    maven_jar.extend(group_id.split('.'));
+    maven_jar.push(artifact_id);
+    maven_jar.push(version);
+    maven_jar.push(&file_name);
+    if maven_jar.is_file() {
+        return Some(maven_jar);
+    }
+    return None;
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use tower_lsp::lsp_types::*;
+
+use crate::cancellation::Cancellation;
+use crate::latency::{self, Phase};
+use crate::line_index;
+use crate::search::{fuzzy_score, is_identifier_byte};
+use crate::{source_set, Backend};
+
+/// Words that can precede a parenthesis without the identifier before it being a method name.
+const NON_DECLARING_WORDS: [&str; 7] = ["new", "return", "throw", "else", "case", "yield", "assert"];
+
+const CONTROL_KEYWORDS: [&str; 8] = ["if", "for", "while", "switch", "catch", "synchronized", "super", "this"];
+
+const MODIFIERS: [&str; 8] = ["public", "protected", "private", "static", "final", "abstract", "transient", "volatile"];
+
+/// Caps a single text search, which may otherwise read every file for short queries.
+const MAX_HEURISTIC_RESULTS: usize = 500;
+
+/// Shown as the container of heuristic workspace symbols so clients can tell them apart.
+const HEURISTIC_CONTAINER_NAME: &str = "(heuristic match)";
+
+/// A declaration found by scanning text rather than by parsing, so it may be wrong.
+#[derive(Debug, Clone)]
+pub struct HeuristicDeclaration {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: u32,
+    pub start_character: u32,
+    pub end_character: u32,
+}
+
+impl HeuristicDeclaration {
+    pub fn range(&self) -> Range {
+        return Range {
+            start: Position {
+                line: self.line,
+                character: self.start_character,
+            },
+            end: Position {
+                line: self.line,
+                character: self.end_character,
+            },
+        };
+    }
+}
+
+/// Lines that look like type, method, constructor or field declarations. Comments and string
+/// contents are not recognized, so this over-reports; callers mark the results as heuristic.
+pub fn declarations(text: &str) -> Vec<HeuristicDeclaration> {
+    let mut result = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("//") || trimmed.starts_with('*') || trimmed.starts_with("/*") || trimmed.starts_with("import ") {
+            continue;
+        }
+        let words = words(line);
+        let starts_with_modifier = words.first().map(|(_, word)| MODIFIERS.contains(word)).unwrap_or(false);
+        for (i, (start, word)) in words.iter().enumerate() {
+            let before = line[..*start].trim_end();
+            let after = line[start + word.len()..].trim_start();
+            let previous_word = if i > 0 && before.ends_with(words[i - 1].1) {
+                Some(words[i - 1].1)
+            } else {
+                None
+            };
+            let kind = match previous_word {
+                Some("class") | Some("record") => Some(SymbolKind::CLASS),
+                Some("interface") => Some(SymbolKind::INTERFACE),
+                Some("enum") => Some(SymbolKind::ENUM),
+                _ => member_kind(word, before, after, previous_word, starts_with_modifier),
+            };
+            if let Some(kind) = kind {
+                result.push(HeuristicDeclaration {
+                    name: word.to_string(),
+                    kind,
+                    line: line_number as u32,
+                    start_character: line_index::character(&line[..*start]),
+                    end_character: line_index::character(&line[..start + word.len()]),
+                });
+            }
+        }
+    }
+    return result;
+}
+
+/// The kind of member `word` declares, judging by the text around it: a type (or `>`/`]`) before
+/// and a parenthesis after make a method, a modifier and `=`/`;` after make a field.
+fn member_kind(word: &str, before: &str, after: &str, previous_word: Option<&str>, starts_with_modifier: bool) -> Option<SymbolKind> {
+    if CONTROL_KEYWORDS.contains(&word) || word.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let follows_type = before.ends_with(|c: char| (c.is_ascii() && is_identifier_byte(c as u8)) || c == '>' || c == ']');
+    if !follows_type || previous_word.map(|w| NON_DECLARING_WORDS.contains(&w)).unwrap_or(false) {
+        return None;
+    }
+    if after.starts_with('(') {
+        if previous_word.map(|w| MODIFIERS.contains(&w)).unwrap_or(false) {
+            return Some(SymbolKind::CONSTRUCTOR);
+        }
+        return Some(SymbolKind::METHOD);
+    }
+    let ends_declarator = after.starts_with(';') || after.starts_with(',') || (after.starts_with('=') && !after.starts_with("=="));
+    if starts_with_modifier && ends_declarator {
+        return Some(SymbolKind::FIELD);
+    }
+    return None;
+}
+
+/// The identifier-like words of `line` with their byte offsets.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut result = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in line.char_indices() {
+        let is_word_char = c.is_ascii() && is_identifier_byte(c as u8);
+        match (start, is_word_char) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                result.push((s, &line[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        result.push((s, &line[s..]));
+    }
+    return result;
+}
+
+/// `paths` as owned paths, so the search index they're borrowed from can be let go of while
+/// they're searched.
+fn owned_paths(paths: Vec<&Path>) -> Vec<PathBuf> {
+    return paths.into_iter().map(Path::to_path_buf).collect();
+}
+
+/// Declarations in `paths` whose name satisfies `matches`, up to `limit` of them. Checks for
+/// cancellation between files.
+async fn search_declarations(
+    paths: Vec<PathBuf>,
+    limit: usize,
+    matches: impl Fn(&str) -> bool,
+    cancellation: &mut Cancellation,
+) -> Vec<(Url, HeuristicDeclaration)> {
+    let mut results = Vec::new();
+    for path in paths {
+        cancellation.checkpoint().await;
+        let uri = match Url::from_file_path(&path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        // reading the file would hold up the other requests, so it's done on a blocking thread
+        let scanning = tokio::task::spawn_blocking(move || {
+            let start = Instant::now();
+            let found = fs::read_to_string(&path).ok().map(|text| declarations(&text));
+            return (start.elapsed(), found);
+        });
+        let found = match scanning.await {
+            Ok((elapsed, found)) => {
+                latency::record_phase(Phase::IndexQuery, elapsed);
+                found
+            }
+            Err(join_error) if join_error.is_panic() => std::panic::resume_unwind(join_error.into_panic()),
+            // the runtime is shutting down
+            Err(_) => return results,
+        };
+        let found = match found {
+            Some(found) => found,
+            None => continue,
+        };
+        for declaration in found {
+            if matches(&declaration.name) {
+                results.push((uri.clone(), declaration));
+                if results.len() >= limit {
+                    return results;
+                }
+            }
+        }
+    }
+    return results;
+}
+
+impl Backend {
+    /// Whether navigation may fall back to text search, which is the case until every workspace
+    /// file has been parsed into the index.
+    pub fn heuristics_enabled(&self) -> bool {
+        let text_search_fallback = self.config.read().unwrap_or_else(PoisonError::into_inner).features.text_search_fallback;
+        return text_search_fallback && !self.index_ready.load(Ordering::Acquire);
+    }
+
+    /// Text-search candidates for the declaration of `name` visible from `uri`, restricted to
+    /// `kinds` when it's not empty. Returns nothing once the index is ready.
+    pub async fn heuristic_definitions(&self, uri: &Url, name: &str, kinds: &[SymbolKind], cancellation: &mut Cancellation) -> Vec<Location> {
+        if !self.heuristics_enabled() {
+            return Vec::new();
+        }
+        let candidates = owned_paths(self.search_index.read().unwrap_or_else(PoisonError::into_inner).candidate_files(name));
+        return search_declarations(candidates, MAX_HEURISTIC_RESULTS, |candidate| candidate == name, cancellation)
+            .await
+            .into_iter()
+            .filter(|(target_uri, declaration)| {
+                source_set::is_visible(uri.as_str(), target_uri.as_str()) && (kinds.is_empty() || kinds.contains(&declaration.kind))
+            })
+            .map(|(target_uri, declaration)| Location {
+                uri: target_uri,
+                range: declaration.range(),
+            })
+            .collect();
+    }
+
+    /// Text-search matches for a fuzzy workspace symbol query in files that aren't indexed yet.
+    /// The container name marks them as heuristic, since they come from text rather than a parse.
+    pub async fn heuristic_workspace_symbols(&self, query: &str, cancellation: &mut Cancellation) -> Vec<SymbolInformation> {
+        if !self.heuristics_enabled() {
+            return Vec::new();
+        }
+        let matches = |candidate: &str| fuzzy_score(query, candidate).is_some();
+        let candidates = owned_paths(self.search_index.read().unwrap_or_else(PoisonError::into_inner).files_declaring(matches));
+        return search_declarations(candidates, MAX_HEURISTIC_RESULTS, matches, cancellation)
+            .await
+            .into_iter()
+            .filter(|(target_uri, _)| !self.type_declaration_map.contains_key(target_uri.as_str()))
+            .map(|(target_uri, declaration)| {
+                #[allow(deprecated)]
+                SymbolInformation {
+                    name: declaration.name.clone(),
+                    kind: declaration.kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location {
+                        uri: target_uri,
+                        range: declaration.range(),
+                    },
+                    container_name: Some(HEURISTIC_CONTAINER_NAME.to_string()),
+                }
+            })
+            .collect();
+    }
+}
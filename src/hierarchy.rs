@@ -0,0 +1,555 @@
+use std::collections::HashSet;
+
+use dashmap::DashMap;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use tree_sitter::{Node, Tree};
+
+use crate::latency::{self, Phase};
+use crate::modifiers::{Modifiers, Visibility};
+use crate::source_set;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodSignature {
+    pub name: String,
+    pub parameter_types: Vec<String>, // erased
+    #[serde(default)]
+    pub parameter_names: Vec<String>, // empty where they aren't known
+    pub return_type: String, // as written, empty for constructors
+    pub type_parameters: Vec<String>,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSignature {
+    pub name: String,
+    pub field_type: String, // as written
+    pub modifiers: Modifiers,
+}
+
+/// The node kind of a type declaration. Being an alias keeps serde from taking the field as
+/// borrowed from what it's deserialized from.
+pub type DeclarationKind = &'static str;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypeDeclaration {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_kind")]
+    pub kind: DeclarationKind,
+    pub modifiers: Modifiers,
+    pub type_parameters: Vec<String>,
+    pub supertypes: Vec<String>, // erased simple names of superclass and interfaces, qualified ones for dependencies
+    pub permitted_subtypes: Vec<String>, // named by a `permits` clause, like `supertypes`
+    pub methods: Vec<MethodSignature>,
+    pub constructors: Vec<MethodSignature>, // named after the type, none when it only has the default one
+    pub fields: Vec<FieldSignature>, // including enum constants and record components
+}
+
+/// The node kinds a `TypeDeclaration` can be of.
+const DECLARATION_KINDS: [DeclarationKind; 5] = [
+    "class_declaration",
+    "interface_declaration",
+    "enum_declaration",
+    "record_declaration",
+    "annotation_type_declaration",
+];
+
+/// Reads a declaration kind back as the static string it was written from.
+fn deserialize_kind<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<DeclarationKind, D::Error> {
+    let kind = String::deserialize(deserializer)?;
+    return DECLARATION_KINDS
+        .iter()
+        .find(|declaration_kind| **declaration_kind == kind)
+        .copied()
+        .ok_or_else(|| de::Error::custom(format!("unknown declaration kind {}", kind)));
+}
+
+/// Collects every class, interface, enum and record declared in the tree, including nested
+/// ones, along with their direct supertypes and method signatures.
+pub fn collect_type_declarations(tree: &Tree, source: &str) -> Vec<TypeDeclaration> {
+    return type_declaration_nodes(tree).into_iter().filter_map(|node| type_declaration(node, source)).collect();
+}
+
+/// The nodes of the declarations `collect_type_declarations` collects, in the same order.
+pub fn type_declaration_nodes(tree: &Tree) -> Vec<Node<'_>> {
+    return tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre)
+        .filter(|node| is_type_declaration(node.kind()) && node.child_by_field_name("name").is_some())
+        .collect();
+}
+
+/// The types a type declaration names as its superclass and interfaces.
+pub fn supertype_nodes(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut supertype_nodes = Vec::new();
+    for child in node.named_children(&mut node.walk()) {
+        match child.kind() {
+            "superclass" => {
+                supertype_nodes.extend(child.named_children(&mut child.walk()));
+            }
+            "super_interfaces" | "extends_interfaces" => {
+                for type_list_node in child.named_children(&mut child.walk()) {
+                    supertype_nodes.extend(type_list_node.named_children(&mut type_list_node.walk()));
+                }
+            }
+            _ => {}
+        };
+    }
+    return supertype_nodes;
+}
+
+pub fn type_declaration(node: Node, source: &str) -> Option<TypeDeclaration> {
+    if !is_type_declaration(node.kind()) {
+        return None;
+    }
+    let name = node.child_by_field_name("name")?.utf8_text(source.as_bytes()).unwrap().to_string();
+    let type_parameters = match node.child_by_field_name("type_parameters") {
+        Some(type_parameters_node) => type_parameter_names(type_parameters_node, source),
+        None => Vec::new(),
+    };
+    let supertypes: Vec<String> = supertype_nodes(node).into_iter().map(|type_node| erased_simple_name(type_node, source)).collect();
+    let permitted_subtypes: Vec<String> = match node.child_by_field_name("permits").and_then(|permits_node| permits_node.named_child(0)) {
+        Some(type_list_node) => type_list_node
+            .named_children(&mut type_list_node.walk())
+            .map(|type_node| erased_simple_name(type_node, source))
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut methods: Vec<MethodSignature> = match node.child_by_field_name("body") {
+        Some(body_node) => member_method_declarations(body_node)
+            .into_iter()
+            .map(|method_node| method_signature(method_node, source))
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut constructors: Vec<MethodSignature> = match node.child_by_field_name("body") {
+        Some(body_node) => member_constructor_declarations(body_node)
+            .into_iter()
+            .map(|constructor_node| method_signature(constructor_node, source))
+            .collect(),
+        None => Vec::new(),
+    };
+    let fields = member_fields(node, source);
+    if node.kind() == "record_declaration" {
+        // the canonical constructor takes the components, declared in full, compactly or not at all
+        let component_types: Vec<String> = match node.child_by_field_name("parameters") {
+            Some(parameters_node) => parameters_node
+                .named_children(&mut parameters_node.walk())
+                .filter_map(|parameter_node| parameter_node.child_by_field_name("type"))
+                .map(|type_node| erased_type(type_node, source))
+                .collect(),
+            None => Vec::new(),
+        };
+        for constructor in constructors.iter_mut().filter(|c| c.parameter_types.is_empty()) {
+            constructor.parameter_types = component_types.clone();
+        }
+        if !constructors.iter().any(|c| c.parameter_types == component_types) {
+            constructors.push(MethodSignature {
+                name: name.clone(),
+                parameter_types: component_types,
+                parameter_names: Vec::new(),
+                return_type: String::new(),
+                type_parameters: Vec::new(),
+                modifiers: Modifiers {
+                    visibility: Visibility::Public,
+                    ..Modifiers::default()
+                },
+            });
+        }
+        // every component gets a public accessor unless the record declares one itself
+        for field in fields.iter().filter(|f| !f.modifiers.is_static) {
+            if !methods.iter().any(|m| m.name == field.name && m.parameter_types.is_empty()) {
+                methods.push(MethodSignature {
+                    name: field.name.clone(),
+                    parameter_types: Vec::new(),
+                    parameter_names: Vec::new(),
+                    return_type: field.field_type.clone(),
+                    type_parameters: Vec::new(),
+                    modifiers: Modifiers {
+                        visibility: Visibility::Public,
+                        ..Modifiers::default()
+                    },
+                });
+            }
+        }
+    }
+    if node.kind() == "enum_declaration" {
+        for method in implicit_enum_methods(&name) {
+            if !methods.iter().any(|m| m.name == method.name && m.parameter_types == method.parameter_types) {
+                methods.push(method);
+            }
+        }
+    }
+    return Some(TypeDeclaration {
+        name,
+        kind: node.kind(),
+        modifiers: Modifiers::of(node),
+        type_parameters,
+        supertypes,
+        permitted_subtypes,
+        methods,
+        constructors,
+        fields,
+    });
+}
+
+impl TypeDeclaration {
+    /// Whether `method`, one of the type's methods, is a member of the types extending or
+    /// implementing it too. Static methods of interfaces aren't, and are only called through the
+    /// interface's name.
+    pub fn is_inherited(&self, method: &MethodSignature) -> bool {
+        let is_interface = self.kind == "interface_declaration" || self.kind == "annotation_type_declaration";
+        return !(is_interface && method.modifiers.is_static);
+    }
+}
+
+/// The methods of the enum `enum_name` it doesn't declare: `values()` and `valueOf(String)`, and
+/// the public ones of `java.lang.Enum`, whose type argument is the enum itself.
+pub fn implicit_enum_methods(enum_name: &str) -> Vec<MethodSignature> {
+    let method = |name: &str, parameter_types: &[&str], return_type: String, is_static: bool| MethodSignature {
+        name: name.to_string(),
+        parameter_types: parameter_types.iter().map(|t| t.to_string()).collect(),
+        parameter_names: Vec::new(),
+        return_type,
+        type_parameters: Vec::new(),
+        modifiers: Modifiers {
+            visibility: Visibility::Public,
+            is_static,
+            is_final: !is_static,
+            ..Modifiers::default()
+        },
+    };
+    return vec![
+        method("values", &[], format!("{}[]", enum_name), true),
+        method("valueOf", &["String"], enum_name.to_string(), true),
+        method("name", &[], "String".to_string(), false),
+        method("ordinal", &[], "int".to_string(), false),
+        method("compareTo", &[enum_name], "int".to_string(), false),
+        method("getDeclaringClass", &[], format!("Class<{}>", enum_name), false),
+    ];
+}
+
+pub fn is_type_declaration(kind: &str) -> bool {
+    return matches!(
+        kind,
+        "class_declaration" | "interface_declaration" | "enum_declaration" | "record_declaration" | "annotation_type_declaration"
+    );
+}
+
+/// Returns the method declarations that sit directly in a type body, the elements of annotation
+/// types included. Enum bodies keep their members one level down, in `enum_body_declarations`.
+pub fn member_method_declarations(body_node: Node) -> Vec<Node> {
+    return member_declarations(body_node, &["method_declaration", "annotation_type_element_declaration"]);
+}
+
+/// Returns the constructor declarations, compact ones included, that sit directly in a type body.
+pub fn member_constructor_declarations(body_node: Node) -> Vec<Node> {
+    return member_declarations(body_node, &["constructor_declaration", "compact_constructor_declaration"]);
+}
+
+fn member_declarations<'a>(body_node: Node<'a>, kinds: &[&str]) -> Vec<Node<'a>> {
+    let mut declarations = Vec::new();
+    for child in body_node.named_children(&mut body_node.walk()) {
+        match child.kind() {
+            "enum_body_declarations" => {
+                for member in child.named_children(&mut child.walk()) {
+                    if kinds.contains(&member.kind()) {
+                        declarations.push(member);
+                    }
+                }
+            }
+            kind if kinds.contains(&kind) => declarations.push(child),
+            _ => {}
+        };
+    }
+    return declarations;
+}
+
+pub fn method_signature(method_node: Node, source: &str) -> MethodSignature {
+    let name = method_node
+        .child_by_field_name("name")
+        .map(|n| n.utf8_text(source.as_bytes()).unwrap().to_string())
+        .unwrap_or_default();
+    let mut parameter_types = Vec::new();
+    let mut parameter_names = Vec::new();
+    if let Some(params_node) = method_node.child_by_field_name("parameters") {
+        for param_node in params_node.named_children(&mut params_node.walk()) {
+            let name_node = match param_node.kind() {
+                "formal_parameter" => {
+                    if let Some(type_node) = param_node.child_by_field_name("type") {
+                        parameter_types.push(erased_type(type_node, source));
+                    }
+                    param_node.child_by_field_name("name")
+                }
+                "spread_parameter" => {
+                    let type_node = param_node
+                        .named_children(&mut param_node.walk())
+                        .find(|n| n.kind() != "modifiers" && n.kind() != "variable_declarator");
+                    if let Some(type_node) = type_node {
+                        parameter_types.push(format!("{}[]", erased_type(type_node, source)));
+                    }
+                    param_node
+                        .named_children(&mut param_node.walk())
+                        .find(|n| n.kind() == "variable_declarator")
+                        .and_then(|declarator_node| declarator_node.child_by_field_name("name"))
+                }
+                _ => continue,
+            };
+            if let Some(name_node) = name_node {
+                parameter_names.push(name_node.utf8_text(source.as_bytes()).unwrap().to_string());
+            }
+        }
+    }
+    // a parameter missing its type or name leaves them unmatched, and the names unusable
+    if parameter_names.len() != parameter_types.len() {
+        parameter_names.clear();
+    }
+    let type_parameters = match method_node.child_by_field_name("type_parameters") {
+        Some(type_parameters_node) => type_parameter_names(type_parameters_node, source),
+        None => Vec::new(),
+    };
+    let return_type = method_node
+        .child_by_field_name("type")
+        .map(|type_node| type_node.utf8_text(source.as_bytes()).unwrap().to_string())
+        .unwrap_or_default();
+    return MethodSignature {
+        name,
+        parameter_types,
+        parameter_names,
+        return_type,
+        type_parameters,
+        modifiers: Modifiers::of(method_node),
+    };
+}
+
+/// The fields of a type declaration: those declared in its body, interface constants, enum
+/// constants and record components.
+pub fn member_fields(declaration_node: Node, source: &str) -> Vec<FieldSignature> {
+    let mut fields = Vec::new();
+    let type_name = declaration_node
+        .child_by_field_name("name")
+        .map(|n| n.utf8_text(source.as_bytes()).unwrap().to_string())
+        .unwrap_or_default();
+    if let Some(parameters_node) = declaration_node.child_by_field_name("parameters") {
+        for parameter_node in parameters_node.named_children(&mut parameters_node.walk()) {
+            let (Some(type_node), Some(name_node)) =
+                (parameter_node.child_by_field_name("type"), parameter_node.child_by_field_name("name"))
+            else {
+                continue;
+            };
+            fields.push(FieldSignature {
+                name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                field_type: type_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                modifiers: Modifiers::of(parameter_node),
+            });
+        }
+    }
+    let body_node = match declaration_node.child_by_field_name("body") {
+        Some(body_node) => body_node,
+        None => return fields,
+    };
+    let mut members: Vec<Node> = Vec::new();
+    for child in body_node.named_children(&mut body_node.walk()) {
+        if child.kind() == "enum_body_declarations" {
+            members.extend(child.named_children(&mut child.walk()));
+        } else {
+            members.push(child);
+        }
+    }
+    for member in members {
+        match member.kind() {
+            "enum_constant" => {
+                if let Some(name_node) = member.child_by_field_name("name") {
+                    // constants are implicitly public, static and final
+                    fields.push(FieldSignature {
+                        name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                        field_type: type_name.clone(),
+                        modifiers: Modifiers {
+                            visibility: Visibility::Public,
+                            is_static: true,
+                            is_final: true,
+                            ..Modifiers::of(member)
+                        },
+                    });
+                }
+            }
+            "field_declaration" | "constant_declaration" => {
+                let field_type = match member.child_by_field_name("type") {
+                    Some(type_node) => type_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    None => continue,
+                };
+                let mut cursor = member.walk();
+                for declarator_node in member.children_by_field_name("declarator", &mut cursor) {
+                    if let Some(name_node) = declarator_node.child_by_field_name("name") {
+                        fields.push(FieldSignature {
+                            name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                            field_type: field_type.clone(),
+                            modifiers: Modifiers::of(member),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        };
+    }
+    return fields;
+}
+
+pub fn type_parameter_names(type_parameters_node: Node, source: &str) -> Vec<String> {
+    return type_parameters_node
+        .named_children(&mut type_parameters_node.walk())
+        .filter_map(|type_parameter_node| {
+            type_parameter_node
+                .named_children(&mut type_parameter_node.walk())
+                .find(|n| n.kind() == "type_identifier" || n.kind() == "identifier")
+                .map(|n| n.utf8_text(source.as_bytes()).unwrap().to_string())
+        })
+        .collect();
+}
+
+/// Type text with generic arguments and package qualifiers removed, e.g. `java.util.List<String>[]`
+/// becomes `List[]`.
+pub fn erased_type(type_node: Node, source: &str) -> String {
+    match type_node.kind() {
+        "array_type" => {
+            let element = match type_node.child_by_field_name("element") {
+                Some(element_node) => erased_type(element_node, source),
+                None => String::new(),
+            };
+            let dimensions = type_node
+                .child_by_field_name("dimensions")
+                .map(|n| n.utf8_text(source.as_bytes()).unwrap().chars().filter(|c| *c == '[' || *c == ']').collect::<String>())
+                .unwrap_or_default();
+            return format!("{}{}", element, dimensions);
+        }
+        _ => erased_simple_name(type_node, source),
+    }
+}
+
+fn erased_simple_name(type_node: Node, source: &str) -> String {
+    match type_node.kind() {
+        "generic_type" => match type_node.named_child(0) {
+            Some(raw_type_node) => erased_simple_name(raw_type_node, source),
+            None => String::new(),
+        },
+        "scoped_type_identifier" => {
+            let last = type_node.named_child(type_node.named_child_count().saturating_sub(1));
+            match last {
+                Some(last_node) => erased_simple_name(last_node, source),
+                None => String::new(),
+            }
+        }
+        _ => type_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+    }
+}
+
+/// Methods every type inherits from `java.lang.Object` that can be overridden.
+const OBJECT_METHODS: [(&str, &[&str]); 5] = [
+    ("toString", &[]),
+    ("equals", &["Object"]),
+    ("hashCode", &[]),
+    ("clone", &[]),
+    ("finalize", &[]),
+];
+
+/// Walks the supertypes of `declaration` and returns the name of the nearest type declaring a
+/// method that `signature` overrides. Types are looked up by simple name across every indexed
+/// document visible from `uri`; supertypes that aren't indexed are skipped, apart from `Object`.
+pub fn find_overridden_method(
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    uri: &str,
+    declaration: &TypeDeclaration,
+    signature: &MethodSignature,
+) -> Option<String> {
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = declaration.supertypes.clone();
+    visited.insert(declaration.name.clone());
+    while let Some(supertype_name) = pending.pop() {
+        if !visited.insert(supertype_name.clone()) {
+            continue;
+        }
+        let supertype = match find_type_declaration(type_declaration_map, uri, &supertype_name) {
+            Some(supertype) => supertype,
+            None => continue,
+        };
+        let overridden = supertype
+            .methods
+            .iter()
+            .any(|method| overrides(signature, method, &supertype.type_parameters));
+        if overridden {
+            return Some(supertype.name);
+        }
+        pending.extend(supertype.supertypes);
+    }
+    let overrides_object_method = OBJECT_METHODS.iter().any(|(name, parameter_types)| {
+        *name == signature.name
+            && parameter_types.len() == signature.parameter_types.len()
+            && parameter_types.iter().zip(signature.parameter_types.iter()).all(|(a, b)| a == b)
+    });
+    if overrides_object_method {
+        return Some("Object".to_string());
+    }
+    return None;
+}
+
+/// Finds a type by simple name among the documents whose source set is visible from `uri`. When
+/// several documents declare it, the one with the lowest URI wins, matching `ClassOrigin` order.
+pub fn find_type_declaration(
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    uri: &str,
+    name: &str,
+) -> Option<TypeDeclaration> {
+    return locate_type_declaration(type_declaration_map, uri, name).map(|(_, declaration)| declaration);
+}
+
+/// Like `find_type_declaration`, along with the URI of the document declaring the type.
+pub fn locate_type_declaration(
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    uri: &str,
+    name: &str,
+) -> Option<(String, TypeDeclaration)> {
+    return locate_accessible_type_declaration(type_declaration_map, uri, name, |_| true);
+}
+
+/// Like `locate_type_declaration`, only among the documents `is_accessible` accepts the URI of.
+pub fn locate_accessible_type_declaration(
+    type_declaration_map: &DashMap<String, Vec<TypeDeclaration>>,
+    uri: &str,
+    name: &str,
+    is_accessible: impl Fn(&str) -> bool,
+) -> Option<(String, TypeDeclaration)> {
+    return latency::phase(Phase::IndexQuery, || {
+        let mut found: Option<(String, TypeDeclaration)> = None;
+        for entry in type_declaration_map.iter() {
+            if !source_set::is_visible(uri, entry.key()) {
+                continue;
+            }
+            if let Some((found_uri, _)) = &found {
+                if found_uri <= entry.key() {
+                    continue;
+                }
+            }
+            if let Some(declaration) = entry.value().iter().find(|d| d.name == name) {
+                if is_accessible(entry.key()) {
+                    found = Some((entry.key().clone(), declaration.clone()));
+                }
+            }
+        }
+        return found;
+    });
+}
+
+/// Compares erased parameter types. A parameter typed by one of the supertype's (or the
+/// method's own) type parameters matches anything, since the subtype may have substituted it.
+fn overrides(method: &MethodSignature, candidate: &MethodSignature, supertype_parameters: &[String]) -> bool {
+    if method.name != candidate.name || method.parameter_types.len() != candidate.parameter_types.len() {
+        return false;
+    }
+    return method
+        .parameter_types
+        .iter()
+        .zip(candidate.parameter_types.iter())
+        .all(|(parameter_type, candidate_type)| {
+            let candidate_element = candidate_type.trim_end_matches("[]");
+            parameter_type == candidate_type
+                || supertype_parameters.iter().any(|p| p == candidate_element)
+                || candidate.type_parameters.iter().any(|p| p == candidate_element)
+        });
+}
@@ -0,0 +1,117 @@
+use log::debug;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
+
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+/// Kinds of the expressions hovering shows the type of.
+const EXPRESSION_KINDS: [&str; 20] = [
+    "identifier",
+    "this",
+    "field_access",
+    "method_invocation",
+    "object_creation_expression",
+    "array_access",
+    "cast_expression",
+    "parenthesized_expression",
+    "switch_expression",
+    "decimal_integer_literal",
+    "hex_integer_literal",
+    "octal_integer_literal",
+    "binary_integer_literal",
+    "decimal_floating_point_literal",
+    "hex_floating_point_literal",
+    "true",
+    "false",
+    "character_literal",
+    "string_literal",
+    "array_creation_expression",
+];
+
+impl Backend {
+    /// Handles `textDocument/hover` over an expression, showing its type, fully qualified and with
+    /// the type arguments it's declared with, like `java.util.List<java.lang.String>`. A name
+    /// after a dot stands for the whole access or call it ends.
+    pub async fn expression_hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        debug!("hover {} {:?}", uri, position);
+        let tree = match self.parsed_document_map.get(uri.as_str()) {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+        let source_text = match self.document_map.get(uri.as_str()) {
+            Some(source_text) => source_text,
+            None => return Ok(None),
+        };
+        let lines = LineIndex::new(&source_text);
+        let point = lines.point(position);
+        let mut node = match tree.root_node().named_descendant_for_point_range(point, point) {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        // the parts of a literal, like the escapes in a string, stand for the literal
+        if !EXPRESSION_KINDS.contains(&node.kind()) {
+            node = match node.parent() {
+                Some(parent) if EXPRESSION_KINDS.contains(&parent.kind()) => parent,
+                _ => return Ok(None),
+            };
+        }
+        let expression_node = match node.parent() {
+            Some(parent) if is_member_name(parent, node) => parent,
+            _ => node,
+        };
+        let expression_type = match self.qualified_expression_type(uri.as_str(), expression_node, &source_text) {
+            Some(expression_type) => expression_type,
+            None => return Ok(None),
+        };
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```java\n{}\n```", expression_type),
+            }),
+            range: Some(lines.range(expression_node.start_position(), expression_node.end_position())),
+        }));
+    }
+
+    /// The type of `expression_node` the way a declaration would write it out in full: with its
+    /// class fully qualified when it's found, and its type arguments.
+    fn qualified_expression_type(&self, uri: &str, expression_node: Node, source: &str) -> Option<String> {
+        let expression_type = match expression_node.kind() {
+            "array_creation_expression" => {
+                let type_node = expression_node.child_by_field_name("type")?;
+                // `[length]` is one dimension, whatever the length, and `[][]` two
+                let dimensions = expression_node
+                    .children(&mut expression_node.walk())
+                    .map(|n| match n.kind() {
+                        "dimensions_expr" => 1,
+                        "dimensions" => n.utf8_text(source.as_bytes()).unwrap().matches('[').count(),
+                        _ => 0,
+                    })
+                    .sum::<usize>();
+                format!("{}{}", type_node.utf8_text(source.as_bytes()).unwrap(), "[]".repeat(dimensions))
+            }
+            _ => self.expression_type(uri, expression_node, source)?,
+        };
+        let element_type = expression_type.trim_end_matches("[]");
+        let dimensions = &expression_type[element_type.len()..];
+        let qualified = self.qualify_type(uri, element_type);
+        let type_arguments = self.type_arguments(uri, expression_node, source);
+        if type_arguments.is_empty() {
+            return Some(format!("{}{}", qualified, dimensions));
+        }
+        return Some(format!("{}<{}>{}", qualified, type_arguments.join(", "), dimensions));
+    }
+}
+
+/// Whether `name_node` is the name of the method `parent_node` calls or the field it accesses.
+fn is_member_name(parent_node: Node, name_node: Node) -> bool {
+    let field_name = match parent_node.kind() {
+        "method_invocation" => "name",
+        "field_access" => "field",
+        _ => return false,
+    };
+    return parent_node.child_by_field_name(field_name) == Some(name_node);
+}
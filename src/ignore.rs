@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError};
+
+use crate::{glob, search, Backend};
+
+const GITIGNORE: &str = ".gitignore";
+
+#[derive(Debug)]
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    directory_only: bool,
+}
+
+/// The patterns of a `.gitignore` file, or the configured exclusions, which take the same
+/// syntax. Patterns are relative to `base`.
+#[derive(Debug)]
+pub struct IgnoreFile {
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreFile {
+    pub fn new<'a>(base: &Path, lines: impl IntoIterator<Item = &'a str>) -> IgnoreFile {
+        return IgnoreFile {
+            base: base.to_path_buf(),
+            patterns: lines.into_iter().filter_map(parse_pattern).collect(),
+        };
+    }
+
+    fn read(directory: &Path) -> Option<IgnoreFile> {
+        let text = fs::read_to_string(directory.join(GITIGNORE)).ok()?;
+        return Some(IgnoreFile::new(directory, text.lines()));
+    }
+
+    /// `Some(true)` when the last pattern matching `path` ignores it, `Some(false)` when it's a
+    /// negation and `None` when none match.
+    fn matches(&self, path: &Path, is_directory: bool) -> Option<bool> {
+        let relative_path = path.strip_prefix(&self.base).ok()?;
+        let relative_path: Vec<char> = relative_path.to_string_lossy().replace('\\', "/").chars().collect();
+        return self
+            .patterns
+            .iter()
+            .rev()
+            .find(|pattern| (is_directory || !pattern.directory_only) && glob::matches(&pattern.glob, &relative_path))
+            .map(|pattern| !pattern.negated);
+    }
+}
+
+/// Parses one line of a `.gitignore` file. Patterns without a `/` but at the end match names at
+/// any depth; the others are anchored to the directory of the file.
+fn parse_pattern(line: &str) -> Option<Pattern> {
+    let mut line = line.trim_start();
+    // trailing spaces don't count unless escaped
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (directory_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    let glob = if line.contains('/') {
+        line.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", line)
+    };
+    return Some(Pattern {
+        glob: glob.chars().collect(),
+        negated,
+        directory_only,
+    });
+}
+
+/// The ignore rules in effect in one directory while walking a workspace root: the configured
+/// exclusions, which always win, and the `.gitignore` files from the repository root down, the
+/// nearest taking precedence.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    excludes: Arc<IgnoreFile>,
+    files: Vec<Arc<IgnoreFile>>,
+}
+
+impl IgnoreRules {
+    /// The rules at workspace `root`, including the `.gitignore` files of the repository above it,
+    /// if it's inside one.
+    pub fn at_root(root: &Path, excludes: &[String]) -> IgnoreRules {
+        let mut rules = IgnoreRules {
+            excludes: Arc::new(IgnoreFile::new(root, excludes.iter().map(String::as_str))),
+            files: Vec::new(),
+        };
+        let repository_root = root.ancestors().find(|directory| directory.join(".git").exists());
+        if let Some(repository_root) = repository_root {
+            let mut parents: Vec<&Path> = root
+                .ancestors()
+                .skip(1)
+                .take_while(|directory| directory.starts_with(repository_root))
+                .collect();
+            parents.reverse();
+            for parent in parents {
+                rules = rules.enter(parent);
+            }
+        }
+        return rules.enter(root);
+    }
+
+    /// The rules in `directory`, a subdirectory of the one these are for.
+    pub fn enter(&self, directory: &Path) -> IgnoreRules {
+        let mut rules = self.clone();
+        if let Some(file) = IgnoreFile::read(directory) {
+            rules.files.push(Arc::new(file));
+        }
+        return rules;
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_directory: bool) -> bool {
+        if self.excludes.matches(path, is_directory) == Some(true) {
+            return true;
+        }
+        return self
+            .files
+            .iter()
+            .rev()
+            .find_map(|file| file.matches(path, is_directory))
+            .unwrap_or(false);
+    }
+}
+
+impl Backend {
+    /// Whether indexing leaves out the file at `path`: it's outside every workspace root, or it
+    /// or a directory it's in is ignored or excluded, unless that's in a generated sources
+    /// directory. The rules of each directory are loaded into `directory_rules` once, for the
+    /// paths checked together to share.
+    pub fn is_excluded(&self, path: &Path, directory_rules: &mut HashMap<PathBuf, IgnoreRules>) -> bool {
+        let root = match self
+            .workspace_roots
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+        {
+            Some(root) => root.clone(),
+            None => return true,
+        };
+        let excludes = self.config.read().unwrap_or_else(PoisonError::into_inner).index_excludes.clone();
+        let generated_directories = self.config.read().unwrap_or_else(PoisonError::into_inner).generated_source_directories.clone();
+        let directories: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|directory| *directory != root)
+            .collect();
+        if directories.iter().any(|directory| search::is_generated_source_directory(directory, &generated_directories)) {
+            return false;
+        }
+        let mut rules = directory_rules
+            .entry(root.clone())
+            .or_insert_with(|| IgnoreRules::at_root(&root, &excludes))
+            .clone();
+        for directory in directories.into_iter().rev() {
+            if search::is_skipped_directory(directory) || rules.is_ignored(directory, true) {
+                return true;
+            }
+            let entered = directory_rules
+                .entry(directory.to_path_buf())
+                .or_insert_with(|| rules.enter(directory))
+                .clone();
+            rules = entered;
+        }
+        return rules.is_ignored(path, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore_file(lines: &[&str]) -> IgnoreFile {
+        return IgnoreFile::new(Path::new("/repo"), lines.iter().copied());
+    }
+
+    #[test]
+    fn matches_names_without_a_slash_at_any_depth() {
+        let file = ignore_file(&["*.class"]);
+        assert_eq!(file.matches(Path::new("/repo/Main.class"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/a/b/Main.class"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/Main.java"), false), None);
+    }
+
+    #[test]
+    fn anchors_patterns_with_a_slash_to_the_directory() {
+        let file = ignore_file(&["/build", "src/generated"]);
+        assert_eq!(file.matches(Path::new("/repo/build"), true), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/module/build"), true), None);
+        assert_eq!(file.matches(Path::new("/repo/src/generated"), true), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/module/src/generated"), true), None);
+    }
+
+    #[test]
+    fn matches_directory_only_patterns_against_directories() {
+        let file = ignore_file(&["out/"]);
+        assert_eq!(file.matches(Path::new("/repo/out"), true), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/a/out"), true), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/out"), false), None);
+    }
+
+    #[test]
+    fn lets_the_last_matching_pattern_decide() {
+        let file = ignore_file(&["*.java", "!Keep.java", "# a comment", ""]);
+        assert_eq!(file.matches(Path::new("/repo/Main.java"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/Keep.java"), false), Some(false));
+        let file = ignore_file(&["!Keep.java", "*.java"]);
+        assert_eq!(file.matches(Path::new("/repo/Keep.java"), false), Some(true));
+    }
+
+    #[test]
+    fn matches_double_stars_in_patterns() {
+        let file = ignore_file(&["**/target/**", "docs/**/*.html"]);
+        assert_eq!(file.matches(Path::new("/repo/target/Main.class"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/a/target/b/Main.class"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/docs/index.html"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/docs/api/index.html"), false), Some(true));
+        assert_eq!(file.matches(Path::new("/repo/index.html"), false), None);
+    }
+
+    #[test]
+    fn lets_excludes_win_and_nearer_files_take_precedence() {
+        let rules = IgnoreRules {
+            excludes: Arc::new(ignore_file(&["*.tmp"])),
+            files: vec![
+                Arc::new(ignore_file(&["*.log", "!Keep.tmp"])),
+                Arc::new(IgnoreFile::new(Path::new("/repo/app"), ["!debug.log"])),
+            ],
+        };
+        assert!(rules.is_ignored(Path::new("/repo/app/Keep.tmp"), false));
+        assert!(rules.is_ignored(Path::new("/repo/server.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/app/debug.log"), false));
+        assert!(rules.is_ignored(Path::new("/repo/app/trace.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/app/Main.java"), false));
+    }
+}
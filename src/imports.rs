@@ -0,0 +1,355 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::{Node, Tree};
+
+use crate::class_contents;
+use crate::class_file;
+use crate::classpath::{self, ClassOrigin};
+use crate::dependency_index::DependencyClass;
+use crate::hierarchy::TypeDeclaration;
+use crate::line_index::LineIndex;
+use crate::modifiers::Visibility;
+use crate::references::package_name;
+use crate::source_set;
+use crate::Backend;
+
+/// Where a candidate for a type name comes from. The declaration order is the order of
+/// precedence: a class of the file shadows a single-type import, which shadows the classes of the
+/// package, which shadow on-demand imports. `java.lang` is imported on demand implicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ImportScope {
+    Qualified, // the name is written fully qualified
+    File,
+    SingleType,
+    Package,
+    OnDemand,
+}
+
+/// A class a type name resolves to.
+#[derive(Debug, Clone)]
+pub enum ResolvedClass {
+    Source(String, String), // document uri, fully qualified name
+    Dependency(Arc<DependencyClass>),
+}
+
+impl ResolvedClass {
+    pub fn qualified_name(&self) -> String {
+        return match self {
+            ResolvedClass::Source(_, class_name) => class_name.clone(),
+            ResolvedClass::Dependency(class) => class_file::qualified_name(&class.binary_name),
+        };
+    }
+}
+
+/// The type imports of a compilation unit.
+pub struct Imports {
+    single_type: Vec<String>,
+    on_demand: Vec<String>, // packages
+    single_static: Vec<String>, // qualified class name and member name
+    static_on_demand: Vec<String>, // classes
+}
+
+impl Imports {
+    pub fn of(tree: &Tree, source: &str) -> Imports {
+        let mut imports = Imports {
+            single_type: Vec::new(),
+            on_demand: Vec::new(),
+            single_static: Vec::new(),
+            static_on_demand: Vec::new(),
+        };
+        let root = tree.root_node();
+        for import_node in root.named_children(&mut root.walk()).filter(|node| node.kind() == "import_declaration") {
+            let text = import_node.utf8_text(source.as_bytes()).unwrap();
+            let imported = text.trim_start_matches("import").trim().trim_end_matches(';').trim();
+            if let Some(imported) = imported.strip_prefix("static ") {
+                match imported.trim().strip_suffix(".*") {
+                    Some(class_name) => imports.static_on_demand.push(class_name.to_string()),
+                    None => imports.single_static.push(imported.trim().to_string()),
+                }
+                continue;
+            }
+            match imported.strip_suffix(".*") {
+                Some(package) => imports.on_demand.push(package.to_string()),
+                None => imports.single_type.push(imported.to_string()),
+            }
+        }
+        return imports;
+    }
+
+    /// The classes whose static member `member_name` the compilation unit may refer to without
+    /// qualifying it, those importing the member by name first.
+    pub fn static_candidates(&self, member_name: &str) -> Vec<String> {
+        let suffix = format!(".{}", member_name);
+        let mut candidates: Vec<String> = self
+            .single_static
+            .iter()
+            .filter_map(|imported| imported.strip_suffix(&suffix))
+            .map(|class_name| class_name.to_string())
+            .collect();
+        candidates.extend(self.static_on_demand.iter().cloned());
+        return candidates;
+    }
+
+    /// Whether the class `class_name`, fully qualified, of `class_package` may be referred to by
+    /// its simple name without another import: it's imported by name or on demand, or it's in
+    /// `java.lang`.
+    pub fn is_imported(&self, class_package: &str, class_name: &str) -> bool {
+        return class_package == "java.lang"
+            || self.single_type.iter().any(|imported| imported == class_name)
+            || self.on_demand.iter().any(|imported_package| imported_package == class_package);
+    }
+
+    /// Whether a single-type import already takes the simple name of `class_name` for another
+    /// class.
+    pub fn imports_other(&self, class_name: &str) -> bool {
+        let simple_name = class_name.rsplit('.').next().unwrap_or(class_name);
+        let suffix = format!(".{}", simple_name);
+        return self
+            .single_type
+            .iter()
+            .any(|imported| imported.ends_with(&suffix) && imported != class_name);
+    }
+
+    /// Whether the compilation unit imports any package on demand.
+    pub fn has_on_demand(&self) -> bool {
+        return !self.on_demand.is_empty();
+    }
+
+    /// The fully qualified names a type name written in a file of `package` may stand for, the
+    /// most specific first: a class nested in the file, an import, the package, `java.lang`.
+    pub fn candidates(&self, written: &str, package: &str, declared_names: &[&str]) -> Vec<String> {
+        return self
+            .scoped_candidates(written, package, declared_names)
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect();
+    }
+
+    /// Like `candidates`, along with the scope each comes from.
+    pub fn scoped_candidates(&self, written: &str, package: &str, declared_names: &[&str]) -> Vec<(ImportScope, String)> {
+        let (head, rest) = match written.split_once('.') {
+            Some((head, rest)) => (head, format!(".{}", rest)),
+            None => (written, String::new()),
+        };
+        if head.starts_with(|c: char| c.is_lowercase()) {
+            return vec![(ImportScope::Qualified, written.to_string())];
+        }
+        let suffix = format!(".{}", head);
+        let mut candidates: Vec<(ImportScope, String)> = declared_names
+            .iter()
+            .filter(|declared_name| **declared_name == head || declared_name.ends_with(&suffix))
+            .map(|declared_name| (ImportScope::File, declared_name.to_string()))
+            .collect();
+        candidates.extend(
+            self.single_type
+                .iter()
+                .filter(|imported| imported.ends_with(&suffix))
+                .map(|imported| (ImportScope::SingleType, imported.clone())),
+        );
+        match package.is_empty() {
+            true => candidates.push((ImportScope::Package, head.to_string())),
+            false => candidates.push((ImportScope::Package, format!("{}.{}", package, head))),
+        }
+        candidates.extend(
+            self.on_demand
+                .iter()
+                .map(|imported_package| (ImportScope::OnDemand, format!("{}.{}", imported_package, head))),
+        );
+        candidates.push((ImportScope::OnDemand, format!("java.lang.{}", head)));
+        return candidates
+            .into_iter()
+            .map(|(scope, candidate)| (scope, format!("{}{}", candidate, rest)))
+            .collect();
+    }
+}
+
+impl Backend {
+    /// The fully qualified names the type name `written` may stand for in the document at `uri`,
+    /// the most specific first. A name starting with a lowercase package is taken as qualified.
+    pub fn class_name_candidates(&self, uri: &str, written: &str) -> Vec<String> {
+        return self
+            .scoped_class_name_candidates(uri, written)
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect();
+    }
+
+    /// Like `class_name_candidates`, along with the scope each comes from.
+    pub fn scoped_class_name_candidates(&self, uri: &str, written: &str) -> Vec<(ImportScope, String)> {
+        let (tree, source) = match self.document_source(uri) {
+            Some(document) => document,
+            None => return vec![(ImportScope::Qualified, written.to_string())],
+        };
+        let package = package_name(&tree, &source).unwrap_or_default();
+        let declared = classpath::declared_class_names(&tree, &source);
+        let declared_names: Vec<&str> = declared.iter().map(|(class_name, _)| class_name.as_str()).collect();
+        return Imports::of(&tree, &source).scoped_candidates(written, &package, &declared_names);
+    }
+
+    /// The class the type name `written` refers to in the document at `uri`: the first candidate
+    /// that a workspace document visible from it declares, or else one of its dependencies.
+    pub fn resolve_class(&self, uri: &str, written: &str) -> Option<ResolvedClass> {
+        let candidates = self.scoped_class_name_candidates(uri, written);
+        return self.resolve_candidates(uri, &candidates);
+    }
+
+    /// The class the first of `candidates` that exists stands for. On-demand imports only make
+    /// public classes available.
+    fn resolve_candidates(&self, uri: &str, candidates: &[(ImportScope, String)]) -> Option<ResolvedClass> {
+        let classpath = self.dependency_classpath(uri);
+        return candidates
+            .iter()
+            .find_map(|(scope, class_name)| self.resolve_candidate(uri, *scope, class_name, classpath.as_deref()));
+    }
+
+    /// The classes on-demand imports make `candidates` ambiguous between, when no more specific
+    /// import or class of the package decides and more than one of them exists. Empty otherwise.
+    pub fn ambiguous_candidates(&self, uri: &str, candidates: &[(ImportScope, String)]) -> Vec<String> {
+        let classpath = self.dependency_classpath(uri);
+        let (on_demand, specific): (Vec<_>, Vec<_>) = candidates.iter().partition(|(scope, _)| *scope == ImportScope::OnDemand);
+        let is_decided = specific
+            .iter()
+            .any(|(scope, class_name)| self.resolve_candidate(uri, *scope, class_name, classpath.as_deref()).is_some());
+        if is_decided {
+            return Vec::new();
+        }
+        let mut found: Vec<String> = on_demand
+            .iter()
+            .filter(|(scope, class_name)| self.resolve_candidate(uri, *scope, class_name, classpath.as_deref()).is_some())
+            .map(|(_, class_name)| class_name.clone())
+            .collect();
+        found.dedup();
+        if found.len() < 2 {
+            return Vec::new();
+        }
+        return found;
+    }
+
+    /// The class `class_name`, fully qualified, when it can be referred to from the document at
+    /// `uri` coming from `scope`: it's visible from there, and public unless it's in the
+    /// document's package.
+    pub fn available_class(&self, uri: &str, scope: ImportScope, class_name: &str, classpath: Option<&[PathBuf]>) -> Option<ResolvedClass> {
+        return self.resolve_candidate(uri, scope, class_name, classpath);
+    }
+
+    fn resolve_candidate(&self, uri: &str, scope: ImportScope, class_name: &str, classpath: Option<&[PathBuf]>) -> Option<ResolvedClass> {
+        let provider = self.class_registry.providers(class_name).into_iter().find_map(|origin| {
+            // classes of jars are resolved through the document's classpath below
+            let source_uri = match origin {
+                ClassOrigin::Source(source_uri) => source_uri,
+                ClassOrigin::Jar(_) => return None,
+            };
+            let is_visible = source_uri == uri
+                || (source_set::is_visible(uri, &source_uri) && self.can_access_document(uri, &source_uri));
+            let is_imported = scope != ImportScope::OnDemand || self.is_public_source_class(&source_uri, class_name);
+            return if is_visible && is_imported { Some(source_uri) } else { None };
+        });
+        if let Some(source_uri) = provider {
+            return Some(ResolvedClass::Source(source_uri, class_name.to_string()));
+        }
+        return self
+            .accessible_dependency_class(uri, class_name, classpath)
+            .map(ResolvedClass::Dependency);
+    }
+
+    fn is_public_source_class(&self, source_uri: &str, class_name: &str) -> bool {
+        let simple_name = class_name.rsplit('.').next().unwrap_or(class_name);
+        return self
+            .type_declaration_map
+            .get(source_uri)
+            .map(|declarations| {
+                declarations
+                    .iter()
+                    .any(|declaration| declaration.name == simple_name && declaration.modifiers.visibility == Visibility::Public)
+            })
+            .unwrap_or(false);
+    }
+
+    /// The class a static import makes the field or method `member_name` available from in the
+    /// document at `uri`, by fully qualified name, along with its declaration.
+    pub fn static_import_owner(&self, uri: &str, member_name: &str, is_method: bool) -> Option<(String, TypeDeclaration)> {
+        let candidates = {
+            let (tree, source) = self.document_source(uri)?;
+            Imports::of(&tree, &source).static_candidates(member_name)
+        };
+        for class_name in candidates {
+            let (_, declaration) = match self.locate_type(uri, &class_name) {
+                Some(found) => found,
+                None => continue,
+            };
+            let declares = match is_method {
+                true => declaration
+                    .methods
+                    .iter()
+                    .any(|method| method.name == member_name && method.modifiers.is_static),
+                false => declaration
+                    .fields
+                    .iter()
+                    .any(|field| field.name == member_name && field.modifiers.is_static),
+            };
+            if declares {
+                return Some((class_name, declaration));
+            }
+        }
+        return None;
+    }
+
+    /// The declaration of the statically imported field or method an unqualified name refers
+    /// to, in the workspace or a dependency.
+    pub fn static_import_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let parent_node = name_node.parent()?;
+        let is_method = parent_node.kind() == "method_invocation"
+            && parent_node.child_by_field_name("name") == Some(name_node)
+            && parent_node.child_by_field_name("object").is_none();
+        let is_qualified = match parent_node.kind() {
+            "field_access" => parent_node.child_by_field_name("field") == Some(name_node),
+            "method_invocation" => parent_node.child_by_field_name("name") == Some(name_node) && !is_method,
+            _ => false,
+        };
+        if name_node.kind() != "identifier" || is_qualified {
+            return None;
+        }
+        let member_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        let (class_name, _) = self.static_import_owner(uri, member_name, is_method)?;
+        return match self.resolve_class(uri, &class_name)? {
+            ResolvedClass::Source(source_uri, class_name) => self.source_class_location(&source_uri, &class_name, Some(member_name)),
+            ResolvedClass::Dependency(class) => class_contents::dependency_location(&class, Some(member_name)),
+        };
+    }
+
+    /// The declaration of the workspace class the type identifier `name_node` refers to, resolved
+    /// through the imports and package of the document at `uri`. Includes the qualifier of a
+    /// scoped type, like `Outer.Inner` or `com.example.Foo`, up to the name.
+    pub fn source_class_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let written = match name_node.parent() {
+            Some(parent_node) if parent_node.kind() == "scoped_type_identifier" => &source[parent_node.start_byte()..name_node.end_byte()],
+            _ => name_node.utf8_text(source.as_bytes()).unwrap(),
+        };
+        return match self.resolve_class(uri, written)? {
+            ResolvedClass::Source(source_uri, class_name) => self.source_class_location(&source_uri, &class_name, None),
+            ResolvedClass::Dependency(_) => None,
+        };
+    }
+
+    /// Where the workspace document at `source_uri` declares the class `class_name`, or its
+    /// member `member_name`.
+    pub fn source_class_location(&self, source_uri: &str, class_name: &str, member_name: Option<&str>) -> Option<Location> {
+        let (tree, text) = self.document_source(source_uri)?;
+        let (_, type_name_node) = classpath::declared_class_names(&tree, &text)
+            .into_iter()
+            .find(|(declared_name, _)| *declared_name == class_name)?;
+        let name_node = match member_name {
+            Some(member_name) => {
+                let body_node = type_name_node.parent()?.child_by_field_name("body")?;
+                class_contents::member_name_node(body_node, member_name, &text)?
+            }
+            None => type_name_node,
+        };
+        return Some(Location {
+            uri: Url::parse(source_uri).ok()?,
+            range: LineIndex::new(&text).range(name_node.start_position(), name_node.end_position()),
+        });
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, Url};
+use tree_sitter::{Point, Tree};
+
+use crate::coverage::UnhandledNode;
+use crate::hierarchy::TypeDeclaration;
+use crate::lombok::LombokAnnotations;
+use crate::maven;
+use crate::modifiers::Modifiers;
+use crate::modules::ModuleDeclaration;
+use crate::references;
+use crate::{ScannedDocument, ScopeKey, TokenLocation, TokenType};
+
+/// Caches written by other versions of the server are ignored, since their indexer may find
+/// other things in the same text.
+const CACHE_FORMAT: &str = env!("CARGO_PKG_VERSION");
+
+/// The parameters of the 64-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The contexts the indexer counts unhandled nodes in, which the cache keeps as text.
+const UNHANDLED_CONTEXTS: [&str; 3] = ["identifier", "variable_declarator", "formal_parameter"];
+
+/// What scanning the files of a workspace found, kept between sessions so that at startup the
+/// files that haven't changed since are neither read nor parsed again. The members Lombok
+/// generates are kept with the rest, so the cache only holds for the annotations it was written
+/// with.
+#[derive(Default, Serialize, Deserialize)]
+pub struct WorkspaceCache {
+    format: String,
+    lombok_annotations: LombokAnnotations,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+/// What scanning a file found, by the size, modification time and hash of the text it was found
+/// in.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedFile {
+    len: u64,
+    modified: Option<SystemTime>,
+    hash: u64,
+    package: String,
+    // the bindings of record patterns are typed from the tree, which isn't cached
+    has_record_patterns: bool,
+    symbols: HashMap<String, Vec<CachedLocation>>,
+    unhandled: Vec<(String, String, usize)>,
+    type_declarations: Vec<TypeDeclaration>,
+    module_declaration: Option<ModuleDeclaration>,
+    class_names: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedLocation {
+    start_position: (usize, usize),
+    end_position: (usize, usize),
+    range: Range,
+    token_type: TokenType,
+    scope: ScopeKey,
+    modifiers: Modifiers,
+}
+
+impl WorkspaceCache {
+    /// The cache of the workspace with `roots`, empty when there's none yet or it was written
+    /// with other `lombok_annotations`.
+    pub fn read(roots: &[PathBuf], lombok_annotations: &LombokAnnotations) -> WorkspaceCache {
+        let cache_file = cache_file(roots);
+        let cache = fs::read(&cache_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<WorkspaceCache>(&data).ok())
+            .filter(|cache| cache.format == CACHE_FORMAT && cache.lombok_annotations == *lombok_annotations);
+        return match cache {
+            Some(cache) => {
+                info!("{} workspace files in {:?}", cache.files.len(), cache_file);
+                cache
+            }
+            None => WorkspaceCache::default(),
+        };
+    }
+
+    /// Replaces the cache of the workspace with `roots` with `files`, scanned with
+    /// `lombok_annotations`.
+    pub fn write(roots: &[PathBuf], lombok_annotations: LombokAnnotations, files: HashMap<PathBuf, CachedFile>) {
+        let cache = WorkspaceCache {
+            format: CACHE_FORMAT.to_string(),
+            lombok_annotations,
+            files,
+        };
+        write_json(&cache_file(roots), &cache);
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&CachedFile> {
+        return self.files.get(path);
+    }
+}
+
+impl CachedFile {
+    /// What `scanned` found in `text`, parsed as `tree`, read from a file with `metadata`.
+    pub fn new(metadata: &Metadata, text: &str, tree: &Tree, scanned: &ScannedDocument) -> CachedFile {
+        let mut symbols = HashMap::new();
+        for (name, locations) in &scanned.symbols {
+            let mut cached_locations = Vec::new();
+            for location in locations {
+                cached_locations.push(CachedLocation {
+                    start_position: (location.start_position.row, location.start_position.column),
+                    end_position: (location.end_position.row, location.end_position.column),
+                    range: location.range,
+                    token_type: location.token_type.clone(),
+                    scope: location.scope,
+                    modifiers: location.modifiers,
+                });
+            }
+            symbols.insert(name.clone(), cached_locations);
+        }
+        return CachedFile {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            hash: content_hash(text),
+            package: references::package_name(tree, text).unwrap_or_default(),
+            has_record_patterns: !scanned.record_patterns.is_empty(),
+            symbols,
+            unhandled: scanned
+                .unhandled
+                .iter()
+                .map(|(node, count)| (node.context.to_string(), node.kind.clone(), *count))
+                .collect(),
+            type_declarations: scanned.type_declarations.clone(),
+            module_declaration: scanned.module_declaration.clone(),
+            class_names: scanned.class_names.clone(),
+        };
+    }
+
+    /// Whether the file has the size and modification time it had when it was cached, and so
+    /// the same text, without reading it.
+    pub fn is_unchanged(&self, metadata: &Metadata) -> bool {
+        return self.modified.is_some() && self.len == metadata.len() && self.modified == metadata.modified().ok();
+    }
+
+    /// Whether `text` is the text the file was cached with.
+    pub fn has_text(&self, text: &str) -> bool {
+        return self.hash == content_hash(text);
+    }
+
+    /// The same entry, for the file with the same text now having `metadata`.
+    pub fn touched(&self, metadata: &Metadata) -> CachedFile {
+        return CachedFile {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            ..self.clone()
+        };
+    }
+
+    /// The package the file declares, empty for the unnamed package.
+    pub fn package(&self) -> &str {
+        return &self.package;
+    }
+
+    /// What scanning the unchanged file at `uri` would find, without its text or tree. `None`
+    /// when it has record patterns, since typing their bindings takes its tree.
+    pub fn restore(&self, uri: &Url) -> Option<ScannedDocument> {
+        if self.has_record_patterns {
+            return None;
+        }
+        let mut symbols = HashMap::new();
+        for (name, cached_locations) in &self.symbols {
+            let mut locations = Vec::new();
+            for location in cached_locations {
+                locations.push(TokenLocation {
+                    uri: uri.to_string(),
+                    start_position: Point::new(location.start_position.0, location.start_position.1),
+                    end_position: Point::new(location.end_position.0, location.end_position.1),
+                    range: location.range,
+                    token_type: location.token_type.clone(),
+                    scope: location.scope,
+                    modifiers: location.modifiers,
+                });
+            }
+            symbols.insert(name.clone(), locations);
+        }
+        let unhandled = self
+            .unhandled
+            .iter()
+            .filter_map(|(context, kind, count)| {
+                let context = UNHANDLED_CONTEXTS.into_iter().find(|known| *known == context.as_str())?;
+                return Some((UnhandledNode { context, kind: kind.clone() }, *count));
+            })
+            .collect();
+        return Some(ScannedDocument {
+            symbols,
+            unhandled,
+            record_patterns: Vec::new(),
+            type_declarations: self.type_declarations.clone(),
+            module_declaration: self.module_declaration.clone(),
+            class_names: self.class_names.clone(),
+        });
+    }
+}
+
+/// The directory the server keeps its caches in.
+pub fn cache_directory() -> PathBuf {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| maven::home_directory().join(".cache"));
+    return cache_home.join("javals");
+}
+
+/// Writes `value` to `cache_file` as JSON, logging rather than failing when it can't.
+pub fn write_json<T: Serialize>(cache_file: &Path, value: &T) {
+    let written = serde_json::to_vec(value).map_err(io::Error::from).and_then(|data| {
+        fs::create_dir_all(cache_file.parent().unwrap_or(Path::new("")))?;
+        return fs::write(cache_file, data);
+    });
+    if let Err(error) = written {
+        info!("unable to write {:?}: {}", cache_file, error);
+    }
+}
+
+/// Where the cache of the workspace with `roots` is, named after a hash of the roots.
+fn cache_file(roots: &[PathBuf]) -> PathBuf {
+    let mut roots = roots.to_vec();
+    roots.sort();
+    let mut bytes = Vec::new();
+    for root in &roots {
+        bytes.extend_from_slice(root.as_os_str().as_encoded_bytes());
+        bytes.push(0);
+    }
+    return cache_directory().join(format!("workspace-{:016x}.json", stable_hash(&bytes)));
+}
+
+fn content_hash(text: &str) -> u64 {
+    return stable_hash(text.as_bytes());
+}
+
+/// Hashes `bytes` with FNV-1a, which unlike the hasher of the standard library is specified, so
+/// the hashes kept on disk stay the same across Rust releases.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_document;
+
+    fn cached_file(text: &str) -> (Url, ScannedDocument, CachedFile) {
+        let uri = Url::parse("file:///repo/src/p/C.java").unwrap();
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let scanned = scan_document(&uri, text, &tree);
+        // any file will do for the size and modification time
+        let metadata = fs::metadata(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let cached = CachedFile::new(&metadata, text, &tree, &scanned);
+        return (uri, scanned, cached);
+    }
+
+    #[test]
+    fn restores_what_scanning_found_without_the_tree() {
+        let text = "package p; class C { int f; void m(int a) { int b = a; } }";
+        let (uri, scanned, cached) = cached_file(text);
+        assert!(cached.has_text(text));
+        assert!(!cached.has_text("package p; class C {}"));
+        assert_eq!(cached.package(), "p");
+        let restored = cached.restore(&uri).unwrap();
+        let scopes = |document: &ScannedDocument, name: &str| -> Vec<ScopeKey> {
+            return document.symbols[name].iter().map(|location| location.scope).collect();
+        };
+        for name in ["C", "f", "m", "a", "b"] {
+            assert_eq!(scopes(&restored, name), scopes(&scanned, name));
+        }
+    }
+
+    #[test]
+    fn leaves_files_with_record_patterns_to_be_parsed() {
+        let text = "class C { record P(int x) {} boolean m(Object o) { return o instanceof P(var x); } }";
+        let (uri, _, cached) = cached_file(text);
+        assert!(cached.restore(&uri).is_none());
+    }
+
+    #[test]
+    fn hashes_like_the_fnv_1a_reference() {
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(stable_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,306 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// An entry of a jar's central directory.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    method: u16,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    local_header_offset: u64,
+}
+
+/// A jar, or any zip archive. Only its central directory is read up front; entries are read from
+/// the file when asked for.
+pub struct Jar {
+    file: Mutex<File>,
+    pub entries: Vec<Entry>,
+}
+
+impl Jar {
+    pub fn open(path: &Path) -> io::Result<Jar> {
+        let mut file = File::open(path)?;
+        let entries = central_directory(&mut file)?.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a zip archive"))?;
+        return Ok(Jar {
+            file: Mutex::new(file),
+            entries,
+        });
+    }
+
+    /// The uncompressed contents of `entry`. `None` when it's damaged or compressed with something
+    /// other than deflate.
+    pub fn read(&self, entry: &Entry) -> Option<Vec<u8>> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        let header = read_at(&mut file, entry.local_header_offset, 30).ok()?;
+        if u32_at(&header, 0)? != LOCAL_FILE_HEADER {
+            return None;
+        }
+        let data_offset = 30 + u16_at(&header, 26)? as u64 + u16_at(&header, 28)? as u64;
+        let compressed_size = usize::try_from(entry.compressed_size).ok()?;
+        let compressed = read_at(&mut file, entry.local_header_offset.checked_add(data_offset)?, compressed_size).ok()?;
+        return match entry.method {
+            STORED => Some(compressed),
+            DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, entry.uncompressed_size as usize).ok(),
+            _ => None,
+        };
+    }
+}
+
+/// The `length` bytes of `file` at `offset`.
+fn read_at(file: &mut File, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.take(length as u64).read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    return Ok(data);
+}
+
+/// The entries listed in the central directory at the end of `file`. `None` when it isn't a
+/// zip archive.
+fn central_directory(file: &mut File) -> io::Result<Option<Vec<Entry>>> {
+    let file_length = file.metadata()?.len();
+    // the end record is followed by a comment of at most 64 KiB, and preceded by the zip64 locator
+    let tail_length = file_length.min(20 + 22 + 0xFFFF);
+    let tail = read_at(file, file_length - tail_length, tail_length as usize)?;
+    let mut directory = match end_record(&tail) {
+        Some(directory) => directory,
+        None => return Ok(None),
+    };
+    if let Some(zip64_end_offset) = directory.zip64_end_offset {
+        let zip64_end = read_at(file, zip64_end_offset, 56)?;
+        if u32_at(&zip64_end, 0) == Some(ZIP64_END_OF_CENTRAL_DIRECTORY) {
+            directory.entry_count = u64_at(&zip64_end, 32).unwrap_or_default();
+            directory.length = u64_at(&zip64_end, 40).unwrap_or_default();
+            directory.offset = u64_at(&zip64_end, 48).unwrap_or_default();
+        }
+    }
+    let is_in_file = directory.offset.checked_add(directory.length).map(|end| end <= file_length).unwrap_or(false);
+    if !is_in_file {
+        return Ok(None);
+    }
+    let data = read_at(file, directory.offset, directory.length as usize)?;
+    return Ok(directory_entries(&data, directory.entry_count));
+}
+
+/// Where the central directory is, as the end records say.
+struct DirectoryLocation {
+    entry_count: u64,
+    length: u64,
+    offset: u64,
+    // where the zip64 end record with the real values is, when the archive has one
+    zip64_end_offset: Option<u64>,
+}
+
+/// The location of the central directory from the end record in `tail`, the end of the archive.
+fn end_record(tail: &[u8]) -> Option<DirectoryLocation> {
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|offset| u32_at(tail, *offset) == Some(END_OF_CENTRAL_DIRECTORY))?;
+    let zip64_end_offset = match end >= 20 && u32_at(tail, end - 20) == Some(ZIP64_LOCATOR) {
+        true => Some(u64_at(tail, end - 12)?),
+        false => None,
+    };
+    return Some(DirectoryLocation {
+        entry_count: u16_at(tail, end + 10)? as u64,
+        length: u32_at(tail, end + 12)? as u64,
+        offset: u32_at(tail, end + 16)? as u64,
+        zip64_end_offset,
+    });
+}
+
+/// The `entry_count` entries of the central directory `data`.
+fn directory_entries(data: &[u8], entry_count: u64) -> Option<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for _ in 0..entry_count {
+        if u32_at(data, offset)? != CENTRAL_DIRECTORY_HEADER {
+            return None;
+        }
+        let name_length = u16_at(data, offset + 28)? as usize;
+        let extra_length = u16_at(data, offset + 30)? as usize;
+        let comment_length = u16_at(data, offset + 32)? as usize;
+        let name = data.get(offset + 46..offset + 46 + name_length)?;
+        let extra = data.get(offset + 46 + name_length..offset + 46 + name_length + extra_length)?;
+        let mut entry = Entry {
+            name: String::from_utf8_lossy(name).to_string(),
+            method: u16_at(data, offset + 10)?,
+            compressed_size: u32_at(data, offset + 20)? as u64,
+            uncompressed_size: u32_at(data, offset + 24)? as u64,
+            local_header_offset: u32_at(data, offset + 42)? as u64,
+        };
+        read_zip64_extra(extra, &mut entry);
+        entries.push(entry);
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+    return Some(entries);
+}
+
+/// Takes the sizes and offset too large for the central directory header from the zip64 extra
+/// field, which has them in that order, only for the ones the header marks as such.
+fn read_zip64_extra(extra: &[u8], entry: &mut Entry) {
+    let mut offset = 0;
+    while let (Some(id), Some(size)) = (u16_at(extra, offset), u16_at(extra, offset + 2)) {
+        if id == ZIP64_EXTRA_FIELD {
+            let mut field_offset = offset + 4;
+            for value in [&mut entry.uncompressed_size, &mut entry.compressed_size, &mut entry.local_header_offset] {
+                if *value != 0xFFFF_FFFF {
+                    continue;
+                }
+                match u64_at(extra, field_offset) {
+                    Some(wide) => *value = wide,
+                    None => return,
+                }
+                field_offset += 8;
+            }
+            return;
+        }
+        offset += 4 + size as usize;
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    return Some(u16::from_le_bytes([bytes[0], bytes[1]]));
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    return Some(u32::from_le_bytes(bytes.try_into().ok()?));
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    return Some(u64::from_le_bytes(bytes.try_into().ok()?));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A zip archive of `entries`, deflated or stored, ending with `comment`. Checksums are left
+    /// out, since reading doesn't check them.
+    fn zip(entries: &[(&str, &[u8], bool)], comment: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for (name, contents, deflated) in entries {
+            let (method, stored) = match deflated {
+                true => (DEFLATED, miniz_oxide::deflate::compress_to_vec(contents, 6)),
+                false => (STORED, contents.to_vec()),
+            };
+            let local_header_offset = data.len() as u32;
+            data.extend(LOCAL_FILE_HEADER.to_le_bytes());
+            for value in [20, 0, method, 0, 0] {
+                data.extend(u16::to_le_bytes(value));
+            }
+            for value in [0, stored.len() as u32, contents.len() as u32] {
+                data.extend(value.to_le_bytes());
+            }
+            data.extend((name.len() as u16).to_le_bytes());
+            data.extend(0u16.to_le_bytes());
+            data.extend(name.as_bytes());
+            data.extend(&stored);
+
+            directory.extend(CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+            for value in [20, 20, 0, method, 0, 0] {
+                directory.extend(u16::to_le_bytes(value));
+            }
+            for value in [0, stored.len() as u32, contents.len() as u32] {
+                directory.extend(value.to_le_bytes());
+            }
+            for value in [name.len() as u16, 0, 0, 0, 0] {
+                directory.extend(value.to_le_bytes());
+            }
+            directory.extend(0u32.to_le_bytes());
+            directory.extend(local_header_offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = data.len() as u32;
+        data.extend(&directory);
+        data.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        for value in [0, 0, entries.len() as u16, entries.len() as u16] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend((directory.len() as u32).to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend((comment.len() as u16).to_le_bytes());
+        data.extend(comment);
+        return data;
+    }
+
+    fn write_fixture(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("javals-jar-{}-{}.jar", name, std::process::id()));
+        fs::write(&path, data).unwrap();
+        return path;
+    }
+
+    #[test]
+    fn reads_stored_and_deflated_entries() {
+        let source = "package p;\n\nclass A {}\n".repeat(20);
+        let path = write_fixture(
+            "entries",
+            &zip(
+                &[("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\n", false), ("p/A.java", source.as_bytes(), true)],
+                b"a comment",
+            ),
+        );
+        let jar = Jar::open(&path).unwrap();
+        let names: Vec<&str> = jar.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["META-INF/MANIFEST.MF", "p/A.java"]);
+        assert_eq!(jar.read(&jar.entries[0]).unwrap(), b"Manifest-Version: 1.0\n");
+        assert_eq!(jar.read(&jar.entries[1]).unwrap(), source.as_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_that_arent_archives() {
+        let path = write_fixture("text", b"not a zip archive");
+        assert_eq!(Jar::open(&path).err().map(|error| error.kind()), Some(io::ErrorKind::InvalidData));
+        fs::remove_file(&path).unwrap();
+        // a central directory past the end of the file
+        let mut data = zip(&[("A.class", b"", false)], b"");
+        let length = data.len();
+        data[length - 6..length - 2].copy_from_slice(&u32::MAX.to_le_bytes());
+        let path = write_fixture("truncated", &data);
+        assert!(Jar::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_sizes_and_offsets_from_the_zip64_extra_field() {
+        let mut extra = Vec::new();
+        extra.extend(ZIP64_EXTRA_FIELD.to_le_bytes());
+        extra.extend(16u16.to_le_bytes());
+        extra.extend(5_000_000_000u64.to_le_bytes());
+        extra.extend(6_000_000_000u64.to_le_bytes());
+        let mut entry = Entry {
+            name: "A.class".to_string(),
+            method: STORED,
+            compressed_size: 10,
+            uncompressed_size: 0xFFFF_FFFF,
+            local_header_offset: 0xFFFF_FFFF,
+        };
+        read_zip64_extra(&extra, &mut entry);
+        assert_eq!(entry.uncompressed_size, 5_000_000_000);
+        assert_eq!(entry.compressed_size, 10);
+        assert_eq!(entry.local_header_offset, 6_000_000_000);
+    }
+}
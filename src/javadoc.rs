@@ -0,0 +1,178 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::code_action;
+use crate::line_index;
+
+/// Block tags that go in the Javadoc of any declaration.
+const BLOCK_TAGS: [&str; 3] = ["see", "since", "deprecated"];
+
+/// Block tags that go in the Javadoc of types only.
+const TYPE_BLOCK_TAGS: [&str; 2] = ["author", "version"];
+
+/// Tags that go inside a sentence, between braces.
+const INLINE_TAGS: [&str; 7] = ["link", "linkplain", "code", "literal", "value", "inheritDoc", "index"];
+
+const TYPE_KINDS: [&str; 5] = [
+    "class_declaration",
+    "interface_declaration",
+    "enum_declaration",
+    "record_declaration",
+    "annotation_type_declaration",
+];
+
+/// Completions in a Javadoc comment: after `@` at the start of a line, the block tags that fit
+/// the declaration the comment documents, after `{@` the inline tags, and after `@param` the
+/// parameters and type parameters of the declaration that aren't documented yet.
+pub fn javadoc_items(tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+    let line = source.lines().nth(position.line as usize).unwrap_or("");
+    let typed = line_index::prefix(line, position.character);
+    let before_word = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+    let word_start = Point {
+        row: position.line as usize,
+        column: before_word.len(),
+    };
+    let comment_node = match javadoc_comment(tree, source, word_start) {
+        Some(comment_node) => comment_node,
+        None => return Vec::new(),
+    };
+    let range = |start: &str| Range {
+        start: Position {
+            line: position.line,
+            character: line_index::character(start),
+        },
+        end: position,
+    };
+    // the declaration is the one right after the comment, if any
+    let declaration_node = comment_node.next_named_sibling();
+    let signature_tags = match declaration_node {
+        Some(declaration_node) => code_action::javadoc_tags(declaration_node, source),
+        None => Vec::new(),
+    };
+    // what's being written on the cursor's line isn't written yet
+    let cursor_line = position.line as usize - comment_node.start_position().row;
+    let comment = comment_node
+        .utf8_text(source.as_bytes())
+        .unwrap()
+        .lines()
+        .enumerate()
+        .filter(|(index, _)| *index != cursor_line)
+        .map(|(_, comment_line)| comment_line)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let comment = comment.as_str();
+
+    if before_word.ends_with("{@") {
+        return INLINE_TAGS.iter().map(|tag| tag_item(tag, range(before_word))).collect();
+    }
+    if let Some(before_tag) = before_word.strip_suffix('@') {
+        // a block tag only starts a line of the comment
+        if !before_tag.trim_start().trim_start_matches("/**").trim_start_matches('*').trim().is_empty() {
+            return Vec::new();
+        }
+        let written_tags = written_block_tags(comment);
+        let mut tags: Vec<&str> = Vec::new();
+        for signature_tag in &signature_tags {
+            let tag = signature_tag[1..].split(' ').next().unwrap_or("");
+            // each parameter has its own `@param`, but there's one `@return`
+            let is_written = match tag {
+                "param" => undocumented_parameters(&signature_tags, comment).is_empty(),
+                "return" => written_tags.contains(&"return"),
+                _ => false,
+            };
+            if !is_written && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        let is_type = declaration_node.map(|n| TYPE_KINDS.contains(&n.kind())).unwrap_or(false);
+        if is_type {
+            tags.extend(TYPE_BLOCK_TAGS.iter().filter(|tag| !written_tags.contains(tag)));
+        }
+        // there may be many references, but one version to have been added in and deprecated
+        tags.extend(BLOCK_TAGS.iter().filter(|tag| **tag == "see" || !written_tags.contains(tag)));
+        return tags.into_iter().map(|tag| tag_item(tag, range(before_word))).collect();
+    }
+    // a type parameter is documented by its name in angle brackets
+    let (before_name, in_brackets) = match before_word.strip_suffix('<') {
+        Some(before_bracket) => (before_bracket, true),
+        None => (before_word, false),
+    };
+    let is_param_name = before_name.ends_with(char::is_whitespace) && before_name.trim_end().ends_with("@param");
+    if !is_param_name {
+        return Vec::new();
+    }
+    return undocumented_parameters(&signature_tags, comment)
+        .into_iter()
+        .filter(|parameter| !in_brackets || parameter.starts_with('<'))
+        .map(|parameter| {
+            let is_type_parameter = parameter.starts_with('<');
+            return CompletionItem {
+                label: parameter.to_string(),
+                kind: Some(if is_type_parameter { CompletionItemKind::TYPE_PARAMETER } else { CompletionItemKind::VARIABLE }),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range: range(before_name),
+                    new_text: parameter.to_string(),
+                })),
+                ..CompletionItem::default()
+            };
+        })
+        .collect();
+}
+
+/// The Javadoc comment `point` is inside of, past its opening `/**` and before its closing `*/`.
+fn javadoc_comment<'a>(tree: &'a Tree, source: &str, point: Point) -> Option<Node<'a>> {
+    let comment_node = tree.root_node().descendant_for_point_range(point, point)?;
+    if comment_node.kind() != "block_comment" {
+        return None;
+    }
+    let comment = comment_node.utf8_text(source.as_bytes()).unwrap();
+    let opening_end = Point {
+        row: comment_node.start_position().row,
+        column: comment_node.start_position().column + 3,
+    };
+    let closing_start = Point {
+        row: comment_node.end_position().row,
+        column: comment_node.end_position().column.saturating_sub(2),
+    };
+    if !comment.starts_with("/**") || point < opening_end || (comment.ends_with("*/") && point > closing_start) {
+        return None;
+    }
+    return Some(comment_node);
+}
+
+fn tag_item(tag: &str, range: Range) -> CompletionItem {
+    return CompletionItem {
+        label: format!("@{}", tag),
+        kind: Some(CompletionItemKind::KEYWORD),
+        filter_text: Some(tag.to_string()),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: tag.to_string(),
+        })),
+        ..CompletionItem::default()
+    };
+}
+
+/// The names of the block tags written at the start of the lines of `comment`.
+fn written_block_tags(comment: &str) -> Vec<&str> {
+    return comment
+        .lines()
+        .filter_map(|line| line.trim_start().trim_start_matches("/**").trim_start_matches('*').trim_start().strip_prefix('@'))
+        .map(|tagged| tagged.split(|c: char| !c.is_alphanumeric()).next().unwrap_or(""))
+        .collect();
+}
+
+/// The parameters, and type parameters like `<T>`, among the `@param` tags of `signature_tags`
+/// that `comment` doesn't document yet.
+fn undocumented_parameters<'a>(signature_tags: &'a [String], comment: &str) -> Vec<&'a str> {
+    let documented: Vec<&str> = comment
+        .split("@param")
+        .skip(1)
+        .filter_map(|documentation| documentation.split_whitespace().next())
+        .collect();
+    return signature_tags
+        .iter()
+        .filter_map(|tag| tag.strip_prefix("@param "))
+        .filter(|parameter| !documented.contains(parameter))
+        .collect();
+}
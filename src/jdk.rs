@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+use std::time::SystemTime;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::MessageType;
+use tree_sitter::{Node, Parser};
+
+use crate::classpath;
+use crate::dependency_index::DependencyClass;
+use crate::hierarchy;
+use crate::imports::Imports;
+use crate::index_cache;
+use crate::jar::Jar;
+use crate::literals;
+use crate::modules::{self, ModuleDeclaration, MODULE_INFO};
+use crate::Backend;
+
+const LAUNCHER: &str = if cfg!(windows) { "java.exe" } else { "java" };
+
+/// The top-level packages of the JDK whose sources are indexed.
+const INDEXED_PACKAGES: [&str; 2] = ["java/", "javax/"];
+
+/// How the JDK in use was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum JdkSource {
+    #[serde(rename = "java.home")]
+    Setting,
+    #[serde(rename = "JAVA_HOME")]
+    JavaHome,
+    #[serde(rename = "PATH")]
+    Path,
+}
+
+/// A JDK installation and the archive of its sources.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Jdk {
+    pub home: PathBuf,
+    // as its `release` file tells it, like `17.0.8`
+    pub version: Option<String>,
+    pub sources: Option<PathBuf>,
+    pub found_through: JdkSource,
+}
+
+impl Jdk {
+    /// The JDK installed at `home`. `None` when there's no `java` launcher in it.
+    pub fn at(home: &Path, found_through: JdkSource) -> Option<Jdk> {
+        if !launcher(home).is_file() {
+            return None;
+        }
+        let version = fs::read_to_string(home.join("release")).ok().and_then(|release| {
+            return release.lines().find_map(|line| {
+                let value = line.strip_prefix("JAVA_VERSION=")?;
+                return Some(value.trim().trim_matches('"').to_string());
+            });
+        });
+        // `lib/src.zip` since JDK 9, at the top before
+        let sources = [home.join("lib").join("src.zip"), home.join("src.zip")].into_iter().find(|sources| sources.is_file());
+        return Some(Jdk {
+            home: home.to_path_buf(),
+            version,
+            sources,
+            found_through,
+        });
+    }
+
+    /// The `java` launcher of the JDK.
+    pub fn launcher(&self) -> PathBuf {
+        return launcher(&self.home);
+    }
+}
+
+fn launcher(home: &Path) -> PathBuf {
+    return home.join("bin").join(LAUNCHER);
+}
+
+/// The JDK at `configured_home`, the `java.home` setting, or else the one in `JAVA_HOME`, or
+/// else the one the `java` on the `PATH` belongs to, following symbolic links.
+pub fn find_jdk(configured_home: Option<&Path>) -> Option<Jdk> {
+    if let Some(jdk) = configured_home.and_then(|home| Jdk::at(home, JdkSource::Setting)) {
+        return Some(jdk);
+    }
+    if let Some(jdk) = env::var_os("JAVA_HOME").and_then(|home| Jdk::at(Path::new(&home), JdkSource::JavaHome)) {
+        return Some(jdk);
+    }
+    let path = env::var_os("PATH")?;
+    for directory in env::split_paths(&path) {
+        let home = match fs::canonicalize(directory.join(LAUNCHER)) {
+            Ok(launcher) => match launcher.parent().and_then(Path::parent) {
+                Some(home) => home.to_path_buf(),
+                None => continue,
+            },
+            Err(_) => continue,
+        };
+        // the launcher of a Java 8 JDK may be the one of the runtime inside it
+        let home = match home.file_name() {
+            Some(name) if name == "jre" && home.parent().map(|parent| launcher(parent).is_file()).unwrap_or(false) => {
+                home.parent().unwrap().to_path_buf()
+            }
+            _ => home,
+        };
+        if let Some(jdk) = Jdk::at(&home, JdkSource::Path) {
+            return Some(jdk);
+        }
+    }
+    return None;
+}
+
+/// The classes read from a JDK's sources, kept between sessions since reading them takes a while
+/// and they never change.
+#[derive(Serialize, Deserialize)]
+struct JdkCache {
+    sources: PathBuf,
+    modified: Option<SystemTime>,
+    classes: Vec<DependencyClass>,
+    modules: Vec<ModuleDeclaration>,
+}
+
+impl Backend {
+    /// Indexes the `java.*` and `javax.*` classes of the JDK's `src.zip` alongside the classes of
+    /// dependency jars, so they resolve like any other library class, and the declarations of its
+    /// modules. Both are cached on disk by JDK version and read again only when the archive
+    /// changes.
+    pub async fn index_jdk(&self) {
+        let previous_sources = self.jdk_sources();
+        let jdk = self.select_jdk();
+        let configured_home = self.config.read().unwrap_or_else(PoisonError::into_inner).java_home.clone();
+        let ignored_setting = configured_home.filter(|_| jdk.as_ref().map(|jdk| jdk.found_through) != Some(JdkSource::Setting));
+        if let Some(configured_home) = ignored_setting {
+            let message = format!("java.home is set to {:?}, which has no JDK", configured_home);
+            self.client.log_message(MessageType::WARNING, message).await;
+        }
+        let jdk_sources = jdk.as_ref().and_then(|jdk| jdk.sources.clone());
+        if let Some(previous_sources) = previous_sources.filter(|previous_sources| Some(previous_sources) != jdk_sources.as_ref()) {
+            self.dependency_index.remove_jar(&previous_sources);
+            self.jdk_modules.clear();
+            self.revisions.declarations_changed();
+        }
+        let jdk = match jdk {
+            Some(jdk) => jdk,
+            None => return,
+        };
+        let sources = match &jdk.sources {
+            Some(sources) => sources.clone(),
+            None => {
+                info!("the JDK at {:?} comes without src.zip", jdk.home);
+                return;
+            }
+        };
+        let modified = fs::metadata(&sources).and_then(|metadata| metadata.modified()).ok();
+        if self.dependency_index.is_current(&sources, modified) {
+            return;
+        }
+        let read_sources = sources.clone();
+        let contents = tokio::task::spawn_blocking(move || {
+            let cache_file = jdk.version.as_deref().map(cache_file);
+            if let Some(cache) = cache_file.as_deref().and_then(read_cache) {
+                if cache.sources == read_sources && cache.modified == modified {
+                    info!("JDK classes read from the cache");
+                    return Ok((cache.classes, cache.modules));
+                }
+            }
+            let (classes, modules) = read_sources_archive(&read_sources)?;
+            if let Some(cache_file) = cache_file {
+                let cache = JdkCache {
+                    sources: read_sources,
+                    modified,
+                    classes,
+                    modules,
+                };
+                index_cache::write_json(&cache_file, &cache);
+                return Ok((cache.classes, cache.modules));
+            }
+            return Ok((classes, modules));
+        })
+        .await
+        .unwrap_or_else(|error| Err(io::Error::other(error.to_string())));
+        match contents {
+            Ok((classes, modules)) => {
+                info!("{} classes and {} modules indexed from {:?}", classes.len(), modules.len(), sources);
+                self.dependency_index.insert_jar(&sources, modified, classes);
+                self.jdk_modules.clear();
+                for module in modules {
+                    self.jdk_modules.insert(module.name.clone(), module);
+                }
+                self.revisions.declarations_changed();
+            }
+            Err(error) => info!("unable to read {:?}: {}", sources, error),
+        }
+    }
+
+    /// Finds the JDK to use, as [`find_jdk`] does with the `java.home` setting, and keeps it.
+    pub fn select_jdk(&self) -> Option<Jdk> {
+        let configured_home = self.config.read().unwrap_or_else(PoisonError::into_inner).java_home.clone();
+        let jdk = find_jdk(configured_home.as_deref());
+        match &jdk {
+            Some(jdk) => info!("using the JDK at {:?}, found through {:?}, version {:?}", jdk.home, jdk.found_through, jdk.version),
+            None => info!("no JDK found"),
+        }
+        *self.jdk.write().unwrap_or_else(PoisonError::into_inner) = jdk.clone();
+        return jdk;
+    }
+
+    /// The `java` launcher of the JDK the settings pick, or `java` to look up on the `PATH`
+    /// without one.
+    pub fn java_launcher(&self) -> PathBuf {
+        let configured_home = self.config.read().unwrap_or_else(PoisonError::into_inner).java_home.clone();
+        return find_jdk(configured_home.as_deref())
+            .map(|jdk| jdk.launcher())
+            .unwrap_or_else(|| PathBuf::from("java"));
+    }
+
+    /// The `src.zip` of the JDK in use, which is on every classpath ahead of the dependencies.
+    pub fn jdk_sources(&self) -> Option<PathBuf> {
+        return self.jdk.read().unwrap_or_else(PoisonError::into_inner).as_ref().and_then(|jdk| jdk.sources.clone());
+    }
+}
+
+/// Where the classes of a JDK of `version` are cached.
+fn cache_file(version: &str) -> PathBuf {
+    let version: String = version.chars().filter(|c| c.is_alphanumeric() || "._+-".contains(*c)).collect();
+    return index_cache::cache_directory().join(format!("jdk-{}.json", version));
+}
+
+fn read_cache(cache_file: &Path) -> Option<JdkCache> {
+    let data = fs::read(cache_file).ok()?;
+    return serde_json::from_slice(&data).ok();
+}
+
+/// The classes declared by the `java.*` and `javax.*` source files of a JDK's `src.zip`, which
+/// keeps them in a directory per module since JDK 9, and the declarations of those modules.
+/// Supertypes are qualified through the imports of each file once every class is known.
+fn read_sources_archive(sources: &Path) -> io::Result<(Vec<DependencyClass>, Vec<ModuleDeclaration>)> {
+    let archive = Jar::open(sources)?;
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_java::language())
+        .map_err(|error| io::Error::other(error.to_string()))?;
+    let mut classes = Vec::new();
+    let mut modules = Vec::new();
+    let mut supertype_candidates: Vec<Vec<Vec<String>>> = Vec::new();
+    for entry in &archive.entries {
+        let is_module_info = entry.name.split_once('/').map(|(_, path)| path == MODULE_INFO).unwrap_or(false);
+        if is_module_info {
+            let text = archive.read(entry).map(|data| String::from_utf8_lossy(&data).to_string());
+            let tree = text.as_ref().and_then(|text| parser.parse(literals::parseable_text(text).as_ref(), None));
+            if let Some(module) = text.zip(tree).and_then(|(text, tree)| modules::module_declaration(&tree, &text)) {
+                modules.push(module);
+            }
+            continue;
+        }
+        let path = match source_path(&entry.name) {
+            Some(path) => path,
+            None => continue,
+        };
+        let text = match archive.read(entry) {
+            Some(data) => String::from_utf8_lossy(&data).to_string(),
+            None => continue,
+        };
+        let tree = match parser.parse(literals::parseable_text(&text).as_ref(), None) {
+            Some(tree) => tree,
+            None => continue,
+        };
+        let (package_directory, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+        let package = package_directory.replace('/', ".");
+        let imports = Imports::of(&tree, &text);
+        let declared = classpath::declared_class_names(&tree, &text);
+        let declared_names: Vec<&str> = declared.iter().map(|(class_name, _)| class_name.as_str()).collect();
+        for (class_name, name_node) in &declared {
+            let declaration_node = match name_node.parent() {
+                Some(declaration_node) => declaration_node,
+                None => continue,
+            };
+            let declaration = match hierarchy::type_declaration(declaration_node, &text) {
+                Some(declaration) => declaration,
+                None => continue,
+            };
+            let nested_name = class_name.strip_prefix(&format!("{}.", package)).unwrap_or(class_name);
+            let candidates = written_supertypes(declaration_node, &text)
+                .iter()
+                .map(|written| imports.candidates(written, &package, &declared_names))
+                .collect();
+            classes.push(DependencyClass {
+                jar: sources.to_path_buf(),
+                binary_name: format!("{}/{}", package_directory, nested_name.replace('.', "$")),
+                source_file: Some(file_name.to_string()),
+                source_entry: Some(entry.name.clone()),
+                declaration,
+            });
+            supertype_candidates.push(candidates);
+        }
+    }
+    let known: HashSet<String> = classes.iter().map(|class| class.binary_name.replace(['/', '$'], ".")).collect();
+    for (class, candidates) in classes.iter_mut().zip(supertype_candidates) {
+        class.declaration.supertypes = candidates
+            .into_iter()
+            .filter_map(|candidates| {
+                let found = candidates.iter().find(|candidate| known.contains(*candidate));
+                return found.or(candidates.first()).cloned();
+            })
+            .filter(|supertype| !matches!(supertype.as_str(), "java.lang.Object" | "java.lang.Enum" | "java.lang.Record"))
+            .collect();
+    }
+    return Ok((classes, modules));
+}
+
+/// The path of a source entry of the indexed packages within its module, like
+/// `java/lang/String.java` for `java.base/java/lang/String.java`.
+fn source_path(entry_name: &str) -> Option<&str> {
+    let path = match INDEXED_PACKAGES.iter().any(|package| entry_name.starts_with(package)) {
+        true => entry_name,
+        false => entry_name.split_once('/')?.1,
+    };
+    if !INDEXED_PACKAGES.iter().any(|package| path.starts_with(package)) || !path.ends_with(".java") {
+        return None;
+    }
+    if path.ends_with("/package-info.java") || path.ends_with("/module-info.java") {
+        return None;
+    }
+    return Some(path);
+}
+
+/// The superclass and interfaces of a type declaration as written, without type arguments, like
+/// `AbstractMap` or `Map.Entry`.
+fn written_supertypes(declaration_node: Node, source: &str) -> Vec<String> {
+    let mut type_nodes = Vec::new();
+    for child in declaration_node.named_children(&mut declaration_node.walk()) {
+        match child.kind() {
+            "superclass" => type_nodes.extend(child.named_children(&mut child.walk())),
+            "super_interfaces" | "extends_interfaces" => {
+                for type_list_node in child.named_children(&mut child.walk()) {
+                    type_nodes.extend(type_list_node.named_children(&mut type_list_node.walk()));
+                }
+            }
+            _ => {}
+        }
+    }
+    return type_nodes
+        .into_iter()
+        .map(|type_node| match type_node.kind() {
+            "generic_type" => type_node.named_child(0).unwrap_or(type_node),
+            _ => type_node,
+        })
+        .map(|type_node| type_node.utf8_text(source.as_bytes()).unwrap().to_string())
+        .collect();
+}
@@ -0,0 +1,166 @@
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::line_index;
+use crate::ranking::Proximity;
+
+const PRIMITIVE_TYPES: [&str; 8] = ["boolean", "byte", "char", "short", "int", "long", "float", "double"];
+
+const MEMBER_KEYWORDS: [&str; 15] = [
+    "public",
+    "protected",
+    "private",
+    "static",
+    "final",
+    "abstract",
+    "synchronized",
+    "native",
+    "transient",
+    "volatile",
+    "class",
+    "interface",
+    "enum",
+    "record",
+    "void",
+];
+
+const STATEMENT_KEYWORDS: [&str; 17] = [
+    "if", "for", "while", "do", "switch", "try", "return", "throw", "break", "continue", "new", "var", "final", "assert",
+    "synchronized", "this", "super",
+];
+
+/// Kinds the cursor may be in without it being in code at all.
+const TEXT_KINDS: [&str; 4] = ["line_comment", "block_comment", "string_literal", "character_literal"];
+
+/// Where in the syntax a keyword being typed goes, which decides the keywords that fit there.
+#[derive(Debug)]
+enum KeywordContext<'a> {
+    /// between the name of the type declaration and its body
+    Header(Node<'a>),
+    /// where a member of a type body is declared
+    Member { in_interface: bool },
+    /// where a statement of a block starts
+    Statement,
+    /// where the type of a parameter, variable or cast goes
+    Type,
+}
+
+/// The Java keywords that fit where the cursor is: the clauses of a type declaration's header,
+/// modifiers and the like where a member is declared, those starting a statement in a block, and
+/// primitive types where a type goes. Modifiers already written before the cursor aren't offered
+/// again.
+pub fn keyword_items(tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+    let line = source.lines().nth(position.line as usize).unwrap_or("");
+    let typed = line_index::prefix(line, position.character);
+    let before_word = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+    if before_word.trim_end().ends_with(['.', '@']) || typed[before_word.len()..].starts_with(|c: char| c.is_ascii_digit()) {
+        return Vec::new();
+    }
+    let word_start = Point {
+        row: position.line as usize,
+        column: before_word.len(),
+    };
+    let keywords: Vec<&str> = match keyword_context(tree, word_start) {
+        Some(KeywordContext::Header(declaration_node)) => header_keywords(declaration_node, word_start),
+        Some(KeywordContext::Member { in_interface }) => {
+            let written: Vec<&str> = before_word.split_whitespace().collect();
+            let mut keywords: Vec<&str> = MEMBER_KEYWORDS.iter().copied().filter(|keyword| !written.contains(keyword)).collect();
+            if in_interface && !written.contains(&"default") {
+                keywords.push("default");
+            }
+            keywords.extend(PRIMITIVE_TYPES);
+            keywords
+        }
+        Some(KeywordContext::Statement) => STATEMENT_KEYWORDS.iter().chain(PRIMITIVE_TYPES.iter()).copied().collect(),
+        Some(KeywordContext::Type) => PRIMITIVE_TYPES.to_vec(),
+        None => return Vec::new(),
+    };
+    return keywords
+        .into_iter()
+        .map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            sort_text: Proximity::Keyword.key(),
+            ..CompletionItem::default()
+        })
+        .collect();
+}
+
+/// Whether `point` is in a comment or a literal, where what's typed isn't code.
+pub fn is_in_text(tree: &Tree, point: Point) -> bool {
+    let node = match tree.root_node().descendant_for_point_range(point, point) {
+        Some(node) => node,
+        None => return false,
+    };
+    return TEXT_KINDS.contains(&node.kind()) || node.parent().map(|n| TEXT_KINDS.contains(&n.kind())).unwrap_or(false);
+}
+
+/// Decides the context of a word starting at `word_start` from the innermost node that started
+/// before it, the word itself and error nodes aside, so that an unfinished word is placed by what
+/// encloses it rather than by what it was taken for.
+fn keyword_context(tree: &Tree, word_start: Point) -> Option<KeywordContext<'_>> {
+    if is_in_text(tree, word_start) {
+        return None;
+    }
+    let word_node = tree.root_node().descendant_for_point_range(word_start, word_start)?;
+    let mut node = word_node;
+    while node.kind() == "ERROR" || node.start_position() >= word_start {
+        node = node.parent()?;
+    }
+    return match node.kind() {
+        "class_declaration" | "interface_declaration" | "enum_declaration" | "record_declaration" => {
+            let body_node = node.child_by_field_name("body")?;
+            let name_node = node.child_by_field_name("name")?;
+            if name_node.end_position() <= word_start && word_start <= body_node.start_position() {
+                Some(KeywordContext::Header(node))
+            } else {
+                None
+            }
+        }
+        "class_body" | "enum_body_declarations" => Some(KeywordContext::Member { in_interface: false }),
+        "interface_body" => Some(KeywordContext::Member { in_interface: true }),
+        "block" | "constructor_body" | "switch_block_statement_group" => Some(KeywordContext::Statement),
+        "formal_parameters" => Some(KeywordContext::Type),
+        // a word after modifiers is taken for the type of a member or variable
+        "field_declaration" | "method_declaration" if node.child_by_field_name("type") == Some(word_node) => {
+            let in_interface = node.parent().map(|n| n.kind() == "interface_body").unwrap_or(false);
+            Some(KeywordContext::Member { in_interface })
+        }
+        "local_variable_declaration" | "formal_parameter" | "cast_expression" if node.child_by_field_name("type") == Some(word_node) => {
+            Some(KeywordContext::Type)
+        }
+        _ => None,
+    };
+}
+
+/// The clauses of the header of `declaration_node` that may follow what's written before
+/// `word_start`, in the order they're written in.
+fn header_keywords(declaration_node: Node, word_start: Point) -> Vec<&'static str> {
+    let clause = |field_name: &str| declaration_node.child_by_field_name(field_name);
+    let clauses: Vec<(&'static str, Option<Node>)> = match declaration_node.kind() {
+        "class_declaration" => vec![
+            ("extends", clause("superclass")),
+            ("implements", clause("interfaces")),
+            ("permits", clause("permits")),
+        ],
+        "interface_declaration" => {
+            let extends_node = declaration_node
+                .named_children(&mut declaration_node.walk())
+                .find(|n| n.kind() == "extends_interfaces");
+            vec![("extends", extends_node), ("permits", clause("permits"))]
+        }
+        _ => vec![("implements", clause("interfaces"))],
+    };
+    let written_before = clauses
+        .iter()
+        .rposition(|(_, clause_node)| clause_node.map(|n| n.end_position() <= word_start).unwrap_or(false));
+    let following = match written_before {
+        Some(index) => &clauses[index + 1..],
+        None => &clauses[..],
+    };
+    return following
+        .iter()
+        .filter(|(_, clause_node)| clause_node.is_none())
+        .map(|(keyword, _)| *keyword)
+        .collect();
+}
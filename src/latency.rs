@@ -0,0 +1,263 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::{self, Recover};
+
+/// Budgets, in milliseconds, for the requests and notifications that are timed. Interactive
+/// requests get less than ones a user expects to wait for.
+const DEFAULT_BUDGETS: [(&str, u64); 12] = [
+    ("textDocument/completion", 100),
+    ("completionItem/resolve", 50),
+    ("textDocument/hover", 50),
+    ("textDocument/definition", 100),
+    ("textDocument/references", 500),
+    ("textDocument/codeAction", 200),
+    ("textDocument/formatting", 200),
+    ("textDocument/rangeFormatting", 100),
+    ("textDocument/onTypeFormatting", 50),
+    ("textDocument/didOpen", 200),
+    ("textDocument/didChange", 200),
+    ("workspace/symbol", 200),
+];
+
+/// How many of the most recent over-budget requests are kept per method for the status report.
+const MAX_SLOW_REQUESTS: usize = 10;
+
+/// The parts of handling a request that are timed separately. Time spent outside of them counts
+/// as `other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    Resolve,
+    IndexQuery,
+}
+
+const PHASES: [Phase; 3] = [Phase::Parse, Phase::Resolve, Phase::IndexQuery];
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        return match self {
+            Phase::Parse => "parse",
+            Phase::Resolve => "resolve",
+            Phase::IndexQuery => "indexQuery",
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+struct PhaseTimes {
+    durations: [Duration; 3],
+    // set while a phase is being timed, so phases nested in it count towards the outer one
+    in_phase: bool,
+}
+
+tokio::task_local! {
+    static CURRENT_REQUEST: RefCell<PhaseTimes>;
+}
+
+/// Runs `f`, counting the time it takes towards `phase` of the request being timed, if any.
+pub fn phase<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let nested = CURRENT_REQUEST
+        .try_with(|times| std::mem::replace(&mut times.borrow_mut().in_phase, true))
+        .unwrap_or(true);
+    if nested {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let _ = CURRENT_REQUEST.try_with(|times| {
+        let mut times = times.borrow_mut();
+        times.durations[phase as usize] += elapsed;
+        times.in_phase = false;
+    });
+    return result;
+}
+
+/// Counts `elapsed`, spent on `phase` away from the request's task, like on a blocking thread,
+/// towards the request being timed, if any.
+pub fn record_phase(phase: Phase, elapsed: Duration) {
+    let _ = CURRENT_REQUEST.try_with(|times| times.borrow_mut().durations[phase as usize] += elapsed);
+}
+
+/// How long a timed request took, and how much of that each phase took.
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub elapsed: Duration,
+    durations: [Duration; 3],
+    // set when the request went over its budget
+    pub slow_request: Option<SlowRequest>,
+}
+
+impl Timing {
+    /// The time spent in each phase, and then the rest of it as `other`.
+    pub fn phase_durations(&self) -> Vec<(&'static str, Duration)> {
+        let other = self.elapsed.saturating_sub(self.durations.iter().sum());
+        return PHASES
+            .iter()
+            .map(|phase| (phase.name(), self.durations[*phase as usize]))
+            .chain(std::iter::once(("other", other)))
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowRequest {
+    pub elapsed_ms: f64,
+    pub budget_ms: u64,
+    /// The phase that took the most time: `parse`, `resolve`, `indexQuery` or `other`.
+    pub dominant_phase: &'static str,
+    pub phase_ms: HashMap<&'static str, f64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyReport {
+    pub method: &'static str,
+    pub budget_ms: Option<u64>,
+    pub count: usize,
+    pub over_budget: usize,
+    pub average_ms: f64,
+    pub max_ms: f64,
+    pub recent_slow_requests: Vec<SlowRequest>,
+}
+
+#[derive(Debug, Default)]
+struct MethodStats {
+    count: usize,
+    over_budget: usize,
+    total: Duration,
+    max: Duration,
+    recent_slow_requests: VecDeque<SlowRequest>,
+}
+
+/// Wall-clock latency per request method, measured against a budget per method. Requests over
+/// budget are logged with a breakdown by phase and kept for the `javals/indexStatus` report.
+#[derive(Debug)]
+pub struct LatencyTracker {
+    budgets: RwLock<HashMap<String, Duration>>,
+    stats: Mutex<HashMap<&'static str, MethodStats>>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> LatencyTracker {
+        let budgets = DEFAULT_BUDGETS
+            .iter()
+            .map(|(method, millis)| (method.to_string(), Duration::from_millis(*millis)))
+            .collect();
+        return LatencyTracker {
+            budgets: RwLock::new(budgets),
+            stats: Mutex::new(HashMap::new()),
+        };
+    }
+}
+
+impl LatencyTracker {
+    /// Awaits `request`, timing it as a `method` request along with the phases it goes through,
+    /// and returns the timing with its output. A request that panics is answered as [`Recover`]
+    /// says.
+    pub async fn timed<F>(&self, method: &'static str, request: F) -> (F::Output, Timing)
+    where
+        F: Future,
+        F::Output: Recover,
+    {
+        let start = Instant::now();
+        let (output, durations) = CURRENT_REQUEST
+            .scope(RefCell::new(PhaseTimes::default()), async {
+                let output = error::guarded(method, request).await;
+                return (output, CURRENT_REQUEST.with(|times| times.borrow().durations));
+            })
+            .await;
+        let mut timing = Timing {
+            elapsed: start.elapsed(),
+            durations,
+            slow_request: None,
+        };
+        timing.slow_request = self.record(method, &timing);
+        return (output, timing);
+    }
+
+    /// Counts a `method` request that took `timing`, returning how it went over its budget, if
+    /// it did.
+    fn record(&self, method: &'static str, timing: &Timing) -> Option<SlowRequest> {
+        let elapsed = timing.elapsed;
+        let budget = self.budgets.read().unwrap_or_else(PoisonError::into_inner).get(method).copied();
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let method_stats = stats.entry(method).or_default();
+        method_stats.count += 1;
+        method_stats.total += elapsed;
+        method_stats.max = method_stats.max.max(elapsed);
+        let budget = match budget {
+            Some(budget) if elapsed > budget => budget,
+            _ => return None,
+        };
+        let phase_durations = timing.phase_durations();
+        let phase_ms: HashMap<&'static str, f64> = phase_durations.iter().map(|(name, duration)| (*name, millis(*duration))).collect();
+        let dominant_phase = phase_durations
+            .into_iter()
+            .max_by_key(|(_, duration)| *duration)
+            .map(|(name, _)| name)
+            .unwrap_or("other");
+        let slow_request = SlowRequest {
+            elapsed_ms: millis(elapsed),
+            budget_ms: budget.as_millis() as u64,
+            dominant_phase,
+            phase_ms,
+        };
+        warn!(
+            "{} took {}ms, over its {}ms budget, mostly in {}: {:?}",
+            method, slow_request.elapsed_ms, slow_request.budget_ms, dominant_phase, slow_request.phase_ms
+        );
+        method_stats.over_budget += 1;
+        if method_stats.recent_slow_requests.len() == MAX_SLOW_REQUESTS {
+            method_stats.recent_slow_requests.pop_front();
+        }
+        method_stats.recent_slow_requests.push_back(slow_request.clone());
+        return Some(slow_request);
+    }
+
+    /// Replaces the budgets of the methods in `budgets`, a map from method to milliseconds.
+    /// Methods set to `null` are no longer held to a budget.
+    pub fn set_budgets(&self, budgets: &serde_json::Map<String, Value>) {
+        let mut current = self.budgets.write().unwrap_or_else(PoisonError::into_inner);
+        for (method, millis) in budgets {
+            match millis.as_u64() {
+                Some(millis) => current.insert(method.clone(), Duration::from_millis(millis)),
+                None => current.remove(method),
+            };
+        }
+    }
+
+    /// The timed methods, the most often over budget first.
+    pub fn report(&self) -> Vec<LatencyReport> {
+        let budgets = self.budgets.read().unwrap_or_else(PoisonError::into_inner);
+        let stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut report: Vec<LatencyReport> = stats
+            .iter()
+            .map(|(method, method_stats)| LatencyReport {
+                method,
+                budget_ms: budgets.get(*method).map(|budget| budget.as_millis() as u64),
+                count: method_stats.count,
+                over_budget: method_stats.over_budget,
+                average_ms: millis(method_stats.total / method_stats.count.max(1) as u32),
+                max_ms: millis(method_stats.max),
+                recent_slow_requests: method_stats.recent_slow_requests.iter().cloned().collect(),
+            })
+            .collect();
+        report.sort_by(|a, b| b.over_budget.cmp(&a.over_budget).then_with(|| a.method.cmp(b.method)));
+        return report;
+    }
+}
+
+/// Milliseconds to a hundredth, since most phases of a fast request take less than one.
+pub fn millis(duration: Duration) -> f64 {
+    return (duration.as_secs_f64() * 100_000.0).round() / 100.0;
+}
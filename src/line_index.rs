@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
+use tree_sitter::Point;
+
+/// What the characters of LSP positions count, as agreed with the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+/// The encoding of every position the server reads and writes. There's one client per process,
+/// which picks it once at initialization; until then positions are in UTF-16, the default.
+static ENCODING: AtomicU8 = AtomicU8::new(Encoding::Utf16 as u8);
+
+impl Encoding {
+    /// The encoding to use with a client offering `offered`, in its order of preference: the
+    /// first one known, or UTF-16, which every client supports, when none is.
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Encoding {
+        return offered
+            .unwrap_or_default()
+            .iter()
+            .find_map(|kind| match kind.as_str() {
+                "utf-8" => Some(Encoding::Utf8),
+                "utf-16" => Some(Encoding::Utf16),
+                "utf-32" => Some(Encoding::Utf32),
+                _ => None,
+            })
+            .unwrap_or(Encoding::Utf16);
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        return match self {
+            Encoding::Utf8 => PositionEncodingKind::UTF8,
+            Encoding::Utf16 => PositionEncodingKind::UTF16,
+            Encoding::Utf32 => PositionEncodingKind::UTF32,
+        };
+    }
+
+    pub fn set(self) {
+        ENCODING.store(self as u8, Ordering::Relaxed);
+    }
+
+    fn current() -> Encoding {
+        return match ENCODING.load(Ordering::Relaxed) {
+            0 => Encoding::Utf8,
+            1 => Encoding::Utf16,
+            _ => Encoding::Utf32,
+        };
+    }
+
+    /// How many code units of the encoding `c` takes.
+    fn units(self, c: char) -> usize {
+        return match self {
+            Encoding::Utf8 => c.len_utf8(),
+            Encoding::Utf16 => c.len_utf16(),
+            Encoding::Utf32 => 1,
+        };
+    }
+}
+
+/// Where the lines of a text start, for converting between tree-sitter points, whose columns
+/// count bytes, and LSP positions, whose characters count code units of the negotiated
+/// [`Encoding`].
+#[derive(Debug)]
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> LineIndex<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        return LineIndex { text, line_starts };
+    }
+
+    /// The text of line `row`, without its line break. Empty past the last line.
+    pub fn line(&self, row: usize) -> &'a str {
+        let line = self.line_with_return(row);
+        return line.strip_suffix('\r').unwrap_or(line);
+    }
+
+    /// The text of line `row` up to its `\n`, keeping the `\r` before it, which tree-sitter
+    /// counts as part of the line, so that the end of a node ending in one converts and back.
+    fn line_with_return(&self, row: usize) -> &'a str {
+        let start = match self.line_starts.get(row) {
+            Some(start) => *start,
+            None => return "",
+        };
+        let line = &self.text[start..];
+        return &line[..line.find('\n').unwrap_or(line.len())];
+    }
+
+    pub fn position(&self, point: Point) -> Position {
+        let line = self.line_with_return(point.row);
+        let mut column = point.column.min(line.len());
+        while !line.is_char_boundary(column) {
+            column -= 1;
+        }
+        return Position {
+            line: point.row as u32,
+            character: character(&line[..column]),
+        };
+    }
+
+    pub fn range(&self, start: Point, end: Point) -> Range {
+        return Range {
+            start: self.position(start),
+            end: self.position(end),
+        };
+    }
+
+    /// The point of `position`. Characters past the end of a line are at its end.
+    pub fn point(&self, position: Position) -> Point {
+        let line = self.line_with_return(position.line as usize);
+        return Point {
+            row: position.line as usize,
+            column: prefix(line, position.character).len(),
+        };
+    }
+
+    /// The byte offset of `position`. Positions past the end of a line are at its end, and those
+    /// past the last line at the end of the text.
+    pub fn offset(&self, position: Position) -> usize {
+        return match self.line_starts.get(position.line as usize) {
+            Some(start) => start + self.point(position).column,
+            None => self.text.len(),
+        };
+    }
+
+    /// The point of the byte `offset`.
+    pub fn point_at(&self, offset: usize) -> Point {
+        let row = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        return Point {
+            row,
+            column: offset - self.line_starts[row],
+        };
+    }
+
+    /// The position of the byte `offset`.
+    pub fn position_at(&self, offset: usize) -> Position {
+        return self.position(self.point_at(offset));
+    }
+}
+
+/// The character of the position right after `prefix`, the start of a line.
+pub fn character(prefix: &str) -> u32 {
+    let encoding = Encoding::current();
+    if encoding == Encoding::Utf8 || prefix.is_ascii() {
+        return prefix.len() as u32;
+    }
+    return prefix.chars().map(|c| encoding.units(c)).sum::<usize>() as u32;
+}
+
+/// The prefix of `line` up to `character`, all of it when the line is shorter. A character
+/// falling inside one that takes several code units is at its start.
+pub fn prefix(line: &str, character: u32) -> &str {
+    let encoding = Encoding::current();
+    if encoding == Encoding::Utf8 {
+        let mut end = (character as usize).min(line.len());
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        return &line[..end];
+    }
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        units += encoding.units(c);
+        if units > character as usize {
+            return &line[..index];
+        }
+    }
+    return line;
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, PositionEncodingKind};
+    use tree_sitter::Point;
+
+    use super::{character, prefix, Encoding, LineIndex};
+
+    // the encoding is never set in tests, so positions count UTF-16 code units
+
+    #[test]
+    fn converts_between_points_and_positions() {
+        let text = "a\n\u{e9}t\u{e9} \u{1f600}x\nlast";
+        let lines = LineIndex::new(text);
+        assert_eq!(lines.line(1), "\u{e9}t\u{e9} \u{1f600}x");
+        // é takes two bytes and one unit, 😀 four bytes and two units
+        assert_eq!(lines.position(Point::new(1, 5)), Position::new(1, 3));
+        assert_eq!(lines.position(Point::new(1, 10)), Position::new(1, 6));
+        assert_eq!(lines.point(Position::new(1, 6)), Point::new(1, 10));
+        // inside 😀 is at its start
+        assert_eq!(lines.point(Position::new(1, 5)), Point::new(1, 6));
+        assert_eq!(lines.point(Position::new(0, 40)), Point::new(0, 1));
+        assert_eq!(lines.offset(Position::new(2, 2)), text.len() - 2);
+        assert_eq!(lines.offset(Position::new(9, 0)), text.len());
+        assert_eq!(lines.point_at(text.len()), Point::new(2, 4));
+        assert_eq!(lines.position_at(2), Position::new(1, 0));
+    }
+
+    #[test]
+    fn keeps_carriage_returns_on_their_line() {
+        let text = "ab\r\ncd\r\n";
+        let lines = LineIndex::new(text);
+        assert_eq!(lines.line(0), "ab");
+        // tree-sitter ends a node ending in `\r` after it
+        assert_eq!(lines.position(Point::new(0, 3)), Position::new(0, 3));
+        assert_eq!(lines.point_at(4), Point::new(1, 0));
+        assert_eq!(lines.line(5), "");
+    }
+
+    #[test]
+    fn counts_utf16_code_units() {
+        assert_eq!(character("a\u{1f600}"), 3);
+        assert_eq!(prefix("a\u{1f600}b", 3), "a\u{1f600}");
+        assert_eq!(prefix("ab", 9), "ab");
+    }
+
+    #[test]
+    fn negotiates_the_first_known_encoding() {
+        let offered = [PositionEncodingKind::new("utf-7"), PositionEncodingKind::UTF32, PositionEncodingKind::UTF8];
+        assert_eq!(Encoding::negotiate(Some(&offered)), Encoding::Utf32);
+        assert_eq!(Encoding::negotiate(Some(&offered[..1])), Encoding::Utf16);
+        assert_eq!(Encoding::negotiate(None), Encoding::Utf16);
+    }
+}
@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+
+/// The text to give the parser in place of `text`, for the constructs the Java grammar gets
+/// wrong: quotes and line continuations in text blocks, repeated underscores in hex and binary
+/// literals, and unicode escapes like `\u0061` in identifiers. They're respelled as ones it
+/// parses the same way, keeping every byte offset and line, so the nodes of the tree still
+/// index into `text` and names, values and ranges come from what was written. `text` itself
+/// when there's nothing to respell.
+pub fn parseable_text(text: &str) -> Cow<'_, str> {
+    let bytes = text.as_bytes();
+    let mut respelled = bytes.to_vec();
+    let mut index = 0;
+    while index < bytes.len() {
+        let rest = &bytes[index..];
+        if rest.starts_with(b"//") {
+            index += rest.iter().position(|byte| *byte == b'\n').unwrap_or(rest.len());
+        } else if rest.starts_with(b"/*") {
+            index += rest[2..].windows(2).position(|window| window == b"*/").map(|end| end + 4).unwrap_or(rest.len());
+        } else if rest.starts_with(b"\"\"\"") {
+            index = text_block_end(bytes, index + 3, &mut respelled);
+        } else if rest[0] == b'"' || rest[0] == b'\'' {
+            index = quoted_end(bytes, index);
+        } else if rest[0] == b'\\' {
+            index += unicode_escape(&text[index..], &mut respelled[index..]);
+        } else if is_word_byte(rest[0]) {
+            let length = rest.iter().position(|byte| !is_word_byte(*byte)).unwrap_or(rest.len());
+            // the grammar takes a run of underscores only in decimal digits
+            if rest[0].is_ascii_digit() {
+                for byte in &mut respelled[index..index + length] {
+                    if *byte == b'_' {
+                        *byte = b'0';
+                    }
+                }
+            }
+            index += length;
+        } else {
+            index += 1;
+        }
+    }
+    if respelled == bytes {
+        return Cow::Borrowed(text);
+    }
+    // only ASCII bytes are replaced, and by whole characters
+    return Cow::Owned(String::from_utf8(respelled).unwrap());
+}
+
+/// The name an identifier written as `text` declares or refers to, with its unicode escapes
+/// decoded, so that `\u0061ge` and `age` are the same variable.
+pub fn identifier_name(text: &str) -> Cow<'_, str> {
+    if !text.contains('\\') {
+        return Cow::Borrowed(text);
+    }
+    let mut name = String::with_capacity(text.len());
+    let mut index = 0;
+    while index < text.len() {
+        match decode_unicode_escape(&text[index..]) {
+            Some((c, length)) => {
+                name.push(c);
+                index += length;
+            }
+            None => {
+                let c = text[index..].chars().next().unwrap();
+                name.push(c);
+                index += c.len_utf8();
+            }
+        }
+    }
+    return Cow::Owned(name);
+}
+
+/// Bytes that continue a word: an identifier, a keyword or a numeric literal.
+fn is_word_byte(byte: u8) -> bool {
+    return byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || !byte.is_ascii();
+}
+
+/// The character a unicode escape at the start of `text` stands for, and the escape's length.
+/// The `u` may be repeated, as in `\uuu0061`.
+fn decode_unicode_escape(text: &str) -> Option<(char, usize)> {
+    let rest = text.strip_prefix('\\')?;
+    let digits = rest.trim_start_matches('u');
+    if digits.len() == rest.len() {
+        return None;
+    }
+    let hex = digits.get(..4).filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))?;
+    let c = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+    return Some((c, text.len() - digits.len() + 4));
+}
+
+/// Respells a unicode escape at the start of `text` that stands for part of an identifier as
+/// that character padded with underscores, returning how far to go on. The grammar would
+/// otherwise take the backslash for an error.
+fn unicode_escape(text: &str, respelled: &mut [u8]) -> usize {
+    let (c, length) = match decode_unicode_escape(text) {
+        Some(escape) => escape,
+        None => return 1,
+    };
+    if c.is_alphanumeric() || c == '_' || c == '$' {
+        let encoded_length = c.encode_utf8(&mut respelled[..length]).len();
+        respelled[encoded_length..length].fill(b'_');
+    }
+    return length;
+}
+
+/// The offset past the string or character literal starting at `start`, or past the end of its
+/// line when it isn't closed.
+fn quoted_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'\n' => return index,
+            byte if byte == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    return bytes.len();
+}
+
+/// The offset past the text block whose content starts at `start`, respelling the quotes in it
+/// that don't close it and the backslashes that join lines, which the grammar doesn't allow.
+fn text_block_end(bytes: &[u8], start: usize, respelled: &mut [u8]) -> usize {
+    let mut index = start;
+    while index < bytes.len() {
+        if bytes[index..].starts_with(b"\"\"\"") {
+            return index + 3;
+        }
+        match bytes[index] {
+            b'\\' if matches!(bytes.get(index + 1), Some(b'\n' | b'\r')) => {
+                respelled[index] = b' ';
+                index += 1;
+            }
+            b'\\' => index += 2,
+            b'"' => {
+                respelled[index] = b'\'';
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+    return bytes.len();
+}
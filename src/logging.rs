@@ -0,0 +1,160 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tower_lsp::lsp_types::MessageType;
+use tower_lsp::Client;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Span, Subscriber};
+use tracing_log::{LogTracer, NormalizeEvent};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::{reload, Registry};
+
+/// The environment variable that sets the log level before the client's settings do, in the
+/// syntax of the `logging.level` setting.
+const LOG_ENV: &str = "JAVALS_LOG";
+
+const DEFAULT_LEVEL: &str = "info";
+
+/// The filter of what gets logged, which the `logging.level` setting replaces.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Where warnings and errors go to be sent to the client, once it's initialized.
+static CLIENT_MESSAGES: OnceLock<UnboundedSender<(MessageType, String)>> = OnceLock::new();
+
+static FORWARDED_TO_CLIENT: AtomicBool = AtomicBool::new(false);
+
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+/// Where the log goes. Never stdout, which carries the protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogDestination {
+    File(PathBuf),
+    Stderr,
+    None,
+}
+
+/// Logs to `destination` what `level` lets through, in the syntax of [`set_level`], or else
+/// what the `JAVALS_LOG` environment variable does. The directory of a log file is created
+/// along with it, and a log file that can't be created is replaced by stderr. The `log` records of the server and its dependencies are logged in the
+/// spans they're made in.
+pub fn init(destination: LogDestination, level: Option<&str>) {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level).unwrap_or_else(|error| {
+            eprintln!("ignoring --log-level {:?}: {}", level, error);
+            return EnvFilter::new(DEFAULT_LEVEL);
+        }),
+        None => EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL)),
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+    let writer = match destination {
+        LogDestination::File(path) => match create_log_file(&path) {
+            Ok(log_file) => Some(BoxMakeWriter::new(Mutex::new(log_file))),
+            Err(error) => {
+                eprintln!("unable to log to {:?}, logging to stderr instead: {}", path, error);
+                Some(BoxMakeWriter::new(io::stderr))
+            }
+        },
+        LogDestination::Stderr => Some(BoxMakeWriter::new(io::stderr)),
+        LogDestination::None => None,
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(writer.map(|writer| tracing_subscriber::fmt::layer().with_ansi(false).with_writer(writer)))
+        .with(ClientLog);
+    if tracing::subscriber::set_global_default(subscriber).is_err() || LogTracer::init().is_err() {
+        eprintln!("unable to set up logging");
+        return;
+    }
+    let _ = FILTER.set(handle);
+}
+
+fn create_log_file(path: &Path) -> io::Result<File> {
+    if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        fs::create_dir_all(directory)?;
+    }
+    return File::create(path);
+}
+
+/// The span a `method` request or notification is handled in, numbered so that the lines of
+/// requests handled at the same time can be told apart.
+pub fn request_span(method: &'static str) -> Span {
+    let id = NEXT_REQUEST.fetch_add(1, Ordering::Relaxed);
+    return tracing::info_span!("request", method, id);
+}
+
+/// Logs what `directives` let through from now on: a level like `debug`, or levels by module
+/// like `info,lsp::jdk=debug`.
+pub fn set_level(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|error| error.to_string())?;
+    return match FILTER.get() {
+        Some(handle) => handle.reload(filter).map_err(|error| error.to_string()),
+        None => Err("logging isn't set up".to_string()),
+    };
+}
+
+pub fn set_forwarded_to_client(forwarded: bool) {
+    FORWARDED_TO_CLIENT.store(forwarded, Ordering::Relaxed);
+}
+
+/// Sends the warnings and errors the server logs to `client` as `window/logMessage`, when the
+/// `logging.forwardToClient` setting asks for them. Must be called on the async runtime.
+pub fn forward_to(client: Client) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    if CLIENT_MESSAGES.set(sender).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        while let Some((message_type, message)) = receiver.recv().await {
+            client.log_message(message_type, message).await;
+        }
+    });
+}
+
+/// Hands the warnings and errors of the server itself to [`forward_to`]. Those of its
+/// dependencies, like failing to write to the client, stay in the log.
+struct ClientLog;
+
+impl<S: Subscriber> Layer<S> for ClientLog {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if !FORWARDED_TO_CLIENT.load(Ordering::Relaxed) {
+            return;
+        }
+        let sender = match CLIENT_MESSAGES.get() {
+            Some(sender) => sender,
+            None => return,
+        };
+        // records of the `log` crate come through tracing-log with its metadata
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let message_type = match *metadata.level() {
+            Level::ERROR => MessageType::ERROR,
+            Level::WARN => MessageType::WARNING,
+            _ => return,
+        };
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+        let mut message = Message::default();
+        event.record(&mut message);
+        let _ = sender.send((message_type, message.0));
+    }
+}
+
+/// The message of an event.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tree_sitter::{Node, Tree};
+
+use crate::hierarchy::{self, MethodSignature, TypeDeclaration};
+use crate::modifiers::{Modifiers, Visibility};
+
+/// The members Lombok generates for an annotation, named in the `lombok.annotations` setting by
+/// the strings of `GENERATED_MEMBER_NAMES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeneratedMembers {
+    Getters,
+    Setters,
+    Withers,
+    ToString,
+    EqualsAndHashCode,
+    NoArgsConstructor,
+    RequiredArgsConstructor,
+    AllArgsConstructor,
+    Builder,
+}
+
+const GENERATED_MEMBER_NAMES: [(&str, GeneratedMembers); 9] = [
+    ("getters", GeneratedMembers::Getters),
+    ("setters", GeneratedMembers::Setters),
+    ("withers", GeneratedMembers::Withers),
+    ("toString", GeneratedMembers::ToString),
+    ("equalsAndHashCode", GeneratedMembers::EqualsAndHashCode),
+    ("noArgsConstructor", GeneratedMembers::NoArgsConstructor),
+    ("requiredArgsConstructor", GeneratedMembers::RequiredArgsConstructor),
+    ("allArgsConstructor", GeneratedMembers::AllArgsConstructor),
+    ("builder", GeneratedMembers::Builder),
+];
+
+/// Which annotations make Lombok generate which members, by the annotations' simple names.
+/// Accessors are generated for the fields of an annotated class, or for an annotated field alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LombokAnnotations {
+    members_by_annotation: HashMap<String, Vec<GeneratedMembers>>,
+}
+
+impl Default for LombokAnnotations {
+    fn default() -> LombokAnnotations {
+        use GeneratedMembers::*;
+        let members_by_annotation = [
+            ("Getter", vec![Getters]),
+            ("Setter", vec![Setters]),
+            ("With", vec![Withers]),
+            ("ToString", vec![ToString]),
+            ("EqualsAndHashCode", vec![EqualsAndHashCode]),
+            ("NoArgsConstructor", vec![NoArgsConstructor]),
+            ("RequiredArgsConstructor", vec![RequiredArgsConstructor]),
+            ("AllArgsConstructor", vec![AllArgsConstructor]),
+            ("Data", vec![Getters, Setters, ToString, EqualsAndHashCode, RequiredArgsConstructor]),
+            ("Value", vec![Getters, ToString, EqualsAndHashCode, AllArgsConstructor]),
+            ("Builder", vec![Builder]),
+        ]
+        .into_iter()
+        .map(|(annotation, members)| (annotation.to_string(), members))
+        .collect();
+        return LombokAnnotations { members_by_annotation };
+    }
+}
+
+/// A field of a type Lombok generates members for.
+struct Field {
+    name: String,
+    field_type: String, // as written
+    erased_type: String,
+    modifiers: Modifiers,
+    is_initialized: bool,
+    members: Vec<(GeneratedMembers, Option<Visibility>)>, // generated by the field's own annotations
+}
+
+impl LombokAnnotations {
+    /// Sets the members the annotations named in `annotations` generate, leaving the others to
+    /// their defaults. Annotations mapped to an empty list generate nothing, and members of
+    /// unknown names are skipped.
+    pub fn configure(&mut self, annotations: &Map<String, Value>) {
+        for (annotation, members) in annotations {
+            let members = match members.as_array() {
+                Some(members) => members,
+                None => continue,
+            };
+            let members = members
+                .iter()
+                .filter_map(Value::as_str)
+                .filter_map(|name| GENERATED_MEMBER_NAMES.iter().find(|(member_name, _)| *member_name == name))
+                .map(|(_, generated)| *generated)
+                .collect();
+            let simple_name = annotation.rsplit('.').next().unwrap_or(annotation);
+            self.members_by_annotation.insert(simple_name.to_string(), members);
+        }
+    }
+
+    /// Adds the members Lombok generates for the types of the tree to their `declarations`, which
+    /// are in the order `hierarchy::collect_type_declarations` returns them. Builders are added
+    /// as types of their own. Members a type declares itself aren't generated again.
+    pub fn add_generated_members(&self, tree: &Tree, source: &str, declarations: &mut Vec<TypeDeclaration>) {
+        let mut builders = Vec::new();
+        for (node, declaration) in hierarchy::type_declaration_nodes(tree).into_iter().zip(declarations.iter_mut()) {
+            if node.kind() != "class_declaration" && node.kind() != "enum_declaration" {
+                continue;
+            }
+            let fields = self.fields(node, source);
+            let type_members = self.annotated_members(node, source);
+            if type_members.is_empty() && fields.iter().all(|field| field.members.is_empty()) {
+                continue;
+            }
+            let self_type = match declaration.type_parameters.is_empty() {
+                true => declaration.name.clone(),
+                false => format!("{}<{}>", declaration.name, declaration.type_parameters.join(", ")),
+            };
+            for field in &fields {
+                let class_members = type_members.iter().filter(|_| !field.modifiers.is_static);
+                for (generated, visibility) in class_members.chain(field.members.iter()) {
+                    if let Some(method) = accessor(*generated, field, &self_type, *visibility) {
+                        add_method(&mut declaration.methods, method);
+                    }
+                }
+            }
+            let has_constructors = !declaration.constructors.is_empty();
+            let instance_fields: Vec<&Field> = fields.iter().filter(|field| !field.modifiers.is_static).collect();
+            for (generated, visibility) in &type_members {
+                let Some(visibility) = visibility else {
+                    continue;
+                };
+                let parameter_fields: Vec<&Field> = match generated {
+                    GeneratedMembers::NoArgsConstructor => Vec::new(),
+                    GeneratedMembers::RequiredArgsConstructor => instance_fields
+                        .iter()
+                        .filter(|field| field.modifiers.is_final && !field.is_initialized)
+                        .copied()
+                        .collect(),
+                    GeneratedMembers::AllArgsConstructor => instance_fields
+                        .iter()
+                        .filter(|field| !(field.modifiers.is_final && field.is_initialized))
+                        .copied()
+                        .collect(),
+                    GeneratedMembers::ToString => {
+                        add_method(&mut declaration.methods, method("toString", Vec::new(), "String", *visibility));
+                        continue;
+                    }
+                    GeneratedMembers::EqualsAndHashCode => {
+                        add_method(&mut declaration.methods, method("equals", vec!["Object".to_string()], "boolean", *visibility));
+                        add_method(&mut declaration.methods, method("hashCode", Vec::new(), "int", *visibility));
+                        continue;
+                    }
+                    GeneratedMembers::Builder => {
+                        let builder_name = format!("{}Builder", declaration.name);
+                        let builder_type = self_type.replacen(&declaration.name, &builder_name, 1);
+                        let mut builder_method = method("builder", Vec::new(), &builder_type, *visibility);
+                        builder_method.modifiers.is_static = true;
+                        builder_method.type_parameters = declaration.type_parameters.clone();
+                        add_method(&mut declaration.methods, builder_method);
+                        let parameter_fields: Vec<&Field> = instance_fields
+                            .iter()
+                            .filter(|field| !(field.modifiers.is_final && field.is_initialized))
+                            .copied()
+                            .collect();
+                        builders.push(builder(declaration, &builder_name, &builder_type, &self_type, &parameter_fields));
+                        if has_constructors {
+                            continue;
+                        }
+                        // the builder calls a package-private constructor taking every field
+                        let constructor = method(&declaration.name, erased_types(&parameter_fields), "", Visibility::Package);
+                        add_constructor(&mut declaration.constructors, constructor);
+                        continue;
+                    }
+                    GeneratedMembers::Getters | GeneratedMembers::Setters | GeneratedMembers::Withers => continue,
+                };
+                let constructor = method(&declaration.name, erased_types(&parameter_fields), "", *visibility);
+                add_constructor(&mut declaration.constructors, constructor);
+            }
+        }
+        declarations.extend(builders);
+    }
+
+    /// The members the annotations of the type or field declaration generate, with the access
+    /// level they give them, `None` for `AccessLevel.NONE`.
+    fn annotated_members(&self, declaration_node: Node, source: &str) -> Vec<(GeneratedMembers, Option<Visibility>)> {
+        let mut members = Vec::new();
+        let modifiers_node = match declaration_node.named_children(&mut declaration_node.walk()).find(|n| n.kind() == "modifiers") {
+            Some(modifiers_node) => modifiers_node,
+            None => return members,
+        };
+        for annotation_node in modifiers_node.named_children(&mut modifiers_node.walk()) {
+            if annotation_node.kind() != "annotation" && annotation_node.kind() != "marker_annotation" {
+                continue;
+            }
+            let name = match annotation_node.child_by_field_name("name") {
+                Some(name_node) => name_node.utf8_text(source.as_bytes()).unwrap(),
+                None => continue,
+            };
+            let simple_name = name.rsplit('.').next().unwrap_or(name);
+            let generated = match self.members_by_annotation.get(simple_name) {
+                Some(generated) => generated,
+                None => continue,
+            };
+            let visibility = access_level(annotation_node, source);
+            members.extend(generated.iter().map(|generated| (*generated, visibility)));
+        }
+        return members;
+    }
+
+    /// The fields the body of the type declaration declares, with the accessors their own
+    /// annotations generate.
+    fn fields(&self, declaration_node: Node, source: &str) -> Vec<Field> {
+        let mut fields = Vec::new();
+        for field_node in field_declarations(declaration_node) {
+            let type_node = match field_node.child_by_field_name("type") {
+                Some(type_node) => type_node,
+                None => continue,
+            };
+            let members: Vec<(GeneratedMembers, Option<Visibility>)> = self
+                .annotated_members(field_node, source)
+                .into_iter()
+                .filter(|(generated, _)| matches!(generated, GeneratedMembers::Getters | GeneratedMembers::Setters | GeneratedMembers::Withers))
+                .collect();
+            for declarator_node in field_node.children_by_field_name("declarator", &mut field_node.walk()) {
+                let name_node = match declarator_node.child_by_field_name("name") {
+                    Some(name_node) => name_node,
+                    None => continue,
+                };
+                fields.push(Field {
+                    name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    field_type: type_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                    erased_type: hierarchy::erased_type(type_node, source),
+                    modifiers: Modifiers::of(field_node),
+                    is_initialized: declarator_node.child_by_field_name("value").is_some(),
+                    members: members.clone(),
+                });
+            }
+        }
+        return fields;
+    }
+}
+
+/// The field declarations of the body of a class or enum declaration.
+fn field_declarations(declaration_node: Node) -> Vec<Node> {
+    let mut field_nodes = Vec::new();
+    let body_node = match declaration_node.child_by_field_name("body") {
+        Some(body_node) => body_node,
+        None => return field_nodes,
+    };
+    for child in body_node.named_children(&mut body_node.walk()) {
+        match child.kind() {
+            "enum_body_declarations" => {
+                field_nodes.extend(child.named_children(&mut child.walk()).filter(|member| member.kind() == "field_declaration"));
+            }
+            "field_declaration" => field_nodes.push(child),
+            _ => {}
+        };
+    }
+    return field_nodes;
+}
+
+/// The field declared in the body of a type declaration that a generated getter, setter or
+/// wither named `method_name` accesses.
+pub fn accessed_field<'a>(body_node: Node<'a>, method_name: &str, source: &str) -> Option<Node<'a>> {
+    let declaration_node = body_node.parent()?;
+    for field_node in field_declarations(declaration_node) {
+        let field_type = match field_node.child_by_field_name("type") {
+            Some(type_node) => type_node.utf8_text(source.as_bytes()).unwrap(),
+            None => continue,
+        };
+        for declarator_node in field_node.children_by_field_name("declarator", &mut field_node.walk()) {
+            let name_node = match declarator_node.child_by_field_name("name") {
+                Some(name_node) => name_node,
+                None => continue,
+            };
+            let name = name_node.utf8_text(source.as_bytes()).unwrap();
+            let property = property_name(name, field_type);
+            if [getter_name(name, field_type), format!("set{}", property), format!("with{}", property)].contains(&method_name.to_string()) {
+                return Some(name_node);
+            }
+        }
+    }
+    return None;
+}
+
+/// The access level an annotation's `AccessLevel` argument gives, public without one and
+/// `None` for `AccessLevel.NONE`.
+fn access_level(annotation_node: Node, source: &str) -> Option<Visibility> {
+    let arguments_node = match annotation_node.child_by_field_name("arguments") {
+        Some(arguments_node) => arguments_node,
+        None => return Some(Visibility::Public),
+    };
+    for node in tree_sitter_traversal::traverse(arguments_node.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "identifier" {
+            continue;
+        }
+        match node.utf8_text(source.as_bytes()).unwrap() {
+            "PUBLIC" => return Some(Visibility::Public),
+            "PROTECTED" => return Some(Visibility::Protected),
+            "PACKAGE" | "MODULE" => return Some(Visibility::Package),
+            "PRIVATE" => return Some(Visibility::Private),
+            "NONE" => return None,
+            _ => {}
+        };
+    }
+    return Some(Visibility::Public);
+}
+
+/// The field's name the way accessors name it: capitalized, without the `is` of a boolean
+/// field named like `isActive`.
+fn property_name(field_name: &str, field_type: &str) -> String {
+    let name = match field_name.strip_prefix("is") {
+        Some(rest) if field_type == "boolean" && rest.starts_with(|c: char| c.is_uppercase()) => rest,
+        _ => field_name,
+    };
+    let mut chars = name.chars();
+    return match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    };
+}
+
+fn getter_name(field_name: &str, field_type: &str) -> String {
+    let prefix = if field_type == "boolean" { "is" } else { "get" };
+    return format!("{}{}", prefix, property_name(field_name, field_type));
+}
+
+/// The getter, setter or wither of the field, when it has one of that kind. Final fields have
+/// no setter and static fields no wither.
+fn accessor(generated: GeneratedMembers, field: &Field, self_type: &str, visibility: Option<Visibility>) -> Option<MethodSignature> {
+    let visibility = visibility?;
+    let property = property_name(&field.name, &field.field_type);
+    let mut accessor = match generated {
+        GeneratedMembers::Getters => method(&getter_name(&field.name, &field.field_type), Vec::new(), &field.field_type, visibility),
+        GeneratedMembers::Setters if !field.modifiers.is_final => {
+            method(&format!("set{}", property), vec![field.erased_type.clone()], "void", visibility)
+        }
+        GeneratedMembers::Withers if !field.modifiers.is_static => {
+            method(&format!("with{}", property), vec![field.erased_type.clone()], self_type, visibility)
+        }
+        _ => return None,
+    };
+    accessor.modifiers.is_static = field.modifiers.is_static;
+    return Some(accessor);
+}
+
+/// The builder class Lombok generates for a type, with a method setting each field and `build()`.
+fn builder(declaration: &TypeDeclaration, builder_name: &str, builder_type: &str, self_type: &str, fields: &[&Field]) -> TypeDeclaration {
+    let mut methods: Vec<MethodSignature> = fields
+        .iter()
+        .map(|field| method(&field.name, vec![field.erased_type.clone()], builder_type, Visibility::Public))
+        .collect();
+    methods.push(method("build", Vec::new(), self_type, Visibility::Public));
+    methods.push(method("toString", Vec::new(), "String", Visibility::Public));
+    return TypeDeclaration {
+        name: builder_name.to_string(),
+        kind: "class_declaration",
+        modifiers: Modifiers {
+            visibility: Visibility::Public,
+            is_static: true,
+            ..Modifiers::default()
+        },
+        type_parameters: declaration.type_parameters.clone(),
+        supertypes: Vec::new(),
+        permitted_subtypes: Vec::new(),
+        methods,
+        constructors: Vec::new(),
+        fields: Vec::new(),
+    };
+}
+
+fn method(name: &str, parameter_types: Vec<String>, return_type: &str, visibility: Visibility) -> MethodSignature {
+    return MethodSignature {
+        name: name.to_string(),
+        parameter_types,
+        parameter_names: Vec::new(),
+        return_type: return_type.to_string(),
+        type_parameters: Vec::new(),
+        modifiers: Modifiers {
+            visibility,
+            ..Modifiers::default()
+        },
+    };
+}
+
+fn erased_types(fields: &[&Field]) -> Vec<String> {
+    return fields.iter().map(|field| field.erased_type.clone()).collect();
+}
+
+fn add_method(methods: &mut Vec<MethodSignature>, method: MethodSignature) {
+    if !methods.iter().any(|m| m.name == method.name && m.parameter_types == method.parameter_types) {
+        methods.push(method);
+    }
+}
+
+fn add_constructor(constructors: &mut Vec<MethodSignature>, constructor: MethodSignature) {
+    if !constructors.iter().any(|c| c.parameter_types == constructor.parameter_types) {
+        constructors.push(constructor);
+    }
+}
@@ -1,72 +1,336 @@
+#![allow(clippy::needless_return)]
+
 use std::collections::HashMap;
-use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
-use log::{info, error};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
+use tower_lsp::lsp_types::request::{GotoImplementationParams, GotoImplementationResponse};
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use clap::Parser as _;
 use dashmap::DashMap;
-use tree_sitter::{Parser, Tree, Node, Query, Point};
+use tree_sitter::{Parser, Tree, Node, Point};
 
-#[derive(Debug)]
+mod annotations;
+mod cancellation;
+mod cli;
+mod class_contents;
+mod class_file;
+mod classpath;
+mod code_action;
+mod commands;
+mod completion;
+mod coverage;
+mod declared_names;
+mod dependency_index;
+mod diagnostics;
+mod editorconfig;
+mod error;
+mod external_format;
+mod format;
+mod glob;
+mod gradle;
+mod heuristic;
+mod hierarchy;
+mod hover;
+mod ignore;
+mod imports;
+mod index_cache;
+mod jar;
+mod javadoc;
+mod jdk;
+mod keywords;
+mod latency;
+mod line_index;
+mod literals;
+mod logging;
+mod lombok;
+mod maven;
+mod members;
+mod modifiers;
+mod modules;
+mod on_save;
+mod overloads;
+mod package_trie;
+mod pattern;
+mod postfix;
+mod progress;
+mod project;
+mod qualified_name;
+mod ranking;
+mod references;
+mod registration;
+mod resolve;
+mod retention;
+mod revisions;
+mod sealed;
+mod search;
+mod settings;
+mod source_set;
+mod status;
+mod symbol_index;
+mod symbols;
+mod syntax_tree;
+mod workspace_index;
+mod text_sync;
+mod trace;
+mod xml;
+
+use cancellation::Cancellation;
+use classpath::ClassRegistry;
+use coverage::{UnhandledNode, UnhandledNodes};
+use declared_names::Declared;
+use dependency_index::DependencyIndex;
+use editorconfig::EditorConfigFile;
+use error::Error;
+use gradle::GradleBuild;
+use hierarchy::TypeDeclaration;
+use jdk::Jdk;
+use latency::{LatencyTracker, Phase};
+use line_index::LineIndex;
+use modifiers::Modifiers;
+use modules::ModuleDeclaration;
+use lombok::LombokAnnotations;
+use pattern::RecordPattern;
+use progress::ProgressTokens;
+use project::Project;
+use ranking::RecentCompletions;
+use references::SearchScope;
+use registration::{OptionalProvider, ProviderRegistrations};
+use retention::RetainedTrees;
+use revisions::{Memo, Revisions};
+use search::TrigramIndex;
+use settings::Config;
+use source_set::SourceSet;
+use symbol_index::{SymbolIndex, SymbolTable};
+use text_sync::PendingChanges;
+use workspace_index::IndexingRun;
+
+/// How many messages are handled at once. Indexing the workspace after `initialized`, and again
+/// on a rebuild, holds a slot until it's done, so the 4 tower-lsp allows by default would leave
+/// few for the requests made in the meantime.
+const MAX_CONCURRENCY: usize = 16;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum TokenType {
     ClassName,
-    MemberVariable,
+    MemberVariable(Option<String>), // type
     MethodName(Vec<String>), // parameter types
     ParameterName(Option<String>), // type
-    LocalVariable(&TokenLocation), // type location
+    LocalVariable(Option<String>), // type
+    TypeParameter,
+    Label, // of a statement, named by `break` and `continue`
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct TokenLocation {
     uri: String,
     start_position: Point,
     end_position: Point,
+    // the same span in the positions clients use
+    range: Range,
     token_type: TokenType,
-    scope_id: usize,
+    scope: ScopeKey,
+    modifiers: Modifiers,
 }
 
+/// A scope node by its span and kind, which unlike its id stay the same when the text is parsed
+/// again, as a closed document's is once its tree was dropped.
+type ScopeKey = (usize, usize, u16);
+
 #[derive(Debug)]
 struct Backend {
     client: Client,
     // ast_map: DashMap<String, HashMap<String, ()>>,
     document_map: DashMap<String, String>,
     parsed_document_map: DashMap<String, Tree>,
-    token_location_map: DashMap<String, Vec<TokenLocation>>,
+    // the closed documents the two above keep, beyond which only what they declare is indexed
+    retained_trees: RetainedTrees,
+    // the version of each open document that the text, tree and index entries above are of
+    document_versions: DashMap<String, i32>,
+    symbol_index: SymbolIndex,
+    type_declaration_map: DashMap<String, Vec<TypeDeclaration>>,
+    // the declarations of the workspace's modules, by the uri of their module-info.java
+    module_declarations: DashMap<String, ModuleDeclaration>,
+    class_registry: ClassRegistry,
+    unhandled_nodes: UnhandledNodes,
+    workspace_roots: RwLock<Vec<PathBuf>>,
+    search_index: RwLock<TrigramIndex>,
+    // the build tool projects in the workspace, with their classpaths
+    projects: RwLock<Vec<Project>>,
+    // resolved Gradle builds by root directory
+    gradle_builds: RwLock<HashMap<PathBuf, GradleBuild>>,
+    dependency_index: DependencyIndex,
+    // the JDK in use, whose sources are indexed with the dependencies
+    jdk: RwLock<Option<Jdk>>,
+    // the declarations of the JDK's modules, by name
+    jdk_modules: DashMap<String, ModuleDeclaration>,
+    // set once every workspace file is parsed; until then navigation falls back to text search
+    index_ready: AtomicBool,
+    // set while workspace files are indexed in bulk
+    indexing: AtomicBool,
+    last_indexing: RwLock<Option<IndexingRun>>,
+    // whether the client lets us register a watcher for workspace/didChangeWatchedFiles
+    can_watch_files: AtomicBool,
+    can_report_progress: AtomicBool,
+    // the long operations whose progress is shown
+    progress_tokens: ProgressTokens,
+    // whether the client answers workspace/configuration
+    can_pull_configuration: AtomicBool,
+    // whether the client lets us register for workspace/didChangeConfiguration
+    can_watch_configuration: AtomicBool,
+    // whether the client fills in the additionalTextEdits of a completion item when resolving it
+    can_resolve_completion_edits: AtomicBool,
+    // whether the client takes completion items as snippets
+    can_complete_snippets: AtomicBool,
+    // the completion items accepted lately, which rank higher
+    recent_completions: RecentCompletions,
+    // the settings the providers read
+    config: RwLock<Config>,
+    provider_registrations: ProviderRegistrations,
+    // how much the client asked to be traced with $/logTrace
+    trace: RwLock<TraceValue>,
+    // parsed .editorconfig files by directory, None where there is none
+    editor_config_cache: RwLock<HashMap<PathBuf, Option<Arc<EditorConfigFile>>>>,
+    latency: LatencyTracker,
+    // the changes to open documents being applied, which requests wait for
+    pending_changes: PendingChanges,
+    revisions: Revisions,
+    memoized_diagnostics: Memo<Vec<Diagnostic>>,
     // semantic_token_map: DashMap<String, Vec<()>>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let mut roots: Vec<PathBuf> = params
+            .workspace_folders
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|folder| folder.uri.to_file_path().ok())
+            .collect();
+        if roots.is_empty() {
+            if let Some(root_path) = params.root_uri.and_then(|root_uri| root_uri.to_file_path().ok()) {
+                roots.push(root_path);
+            }
+        }
+        *self.workspace_roots.write().unwrap_or_else(PoisonError::into_inner) = roots;
+        let can_report_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.can_report_progress.store(can_report_progress, Ordering::Release);
+        let workspace_capabilities = params.capabilities.workspace.unwrap_or_default();
+        let can_watch_files = workspace_capabilities
+            .did_change_watched_files
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        self.can_watch_files.store(can_watch_files, Ordering::Release);
+        self.can_pull_configuration
+            .store(workspace_capabilities.configuration.unwrap_or(false), Ordering::Release);
+        let can_watch_configuration = workspace_capabilities
+            .did_change_configuration
+            .and_then(|configuration| configuration.dynamic_registration)
+            .unwrap_or(false);
+        self.can_watch_configuration.store(can_watch_configuration, Ordering::Release);
+        *self.trace.write().unwrap_or_else(PoisonError::into_inner) = params.trace.unwrap_or(TraceValue::Off);
+        let completion_item_capabilities = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref());
+        let can_resolve_completion_edits = completion_item_capabilities
+            .and_then(|completion_item| completion_item.resolve_support.as_ref())
+            .map(|resolve_support| resolve_support.properties.iter().any(|property| property == "additionalTextEdits"))
+            .unwrap_or(false);
+        self.can_resolve_completion_edits.store(can_resolve_completion_edits, Ordering::Release);
+        let can_complete_snippets = completion_item_capabilities
+            .and_then(|completion_item| completion_item.snippet_support)
+            .unwrap_or(false);
+        self.can_complete_snippets.store(can_complete_snippets, Ordering::Release);
+        self.provider_registrations.negotiate(params.capabilities.text_document.as_ref());
+        let is_static = |provider: OptionalProvider| self.provider_registrations.is_static(provider);
+        let position_encoding = line_index::Encoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        position_encoding.set();
+        if let Some(options) = &params.initialization_options {
+            self.apply_settings(options);
+        }
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
-                // position_encoding: (),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+                position_encoding: Some(position_encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    will_save_wait_until: Some(true),
+                    ..TextDocumentSyncOptions::default()
+                })),
                 // selection_range_provider: (),
-                // hover_provider: (),
-                // completion_provider: (),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions {
+                    // `:` for the second colon of a method reference
+                    trigger_characters: Some(vec![".".to_string(), "@".to_string(), ":".to_string()]),
+                    resolve_provider: Some(true),
+                    ..CompletionOptions::default()
+                }),
                 // signature_help_provider: (),
                 definition_provider: Some(OneOf::Left(true)),
                 // type_definition_provider: (),
-                // implementation_provider: (),
-                // references_provider: (),
-                // document_highlight_provider: (),
+                implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: is_static(OptionalProvider::DocumentHighlight).then_some(OneOf::Left(true)),
                 // document_symbol_provider: (),
-                // workspace_symbol_provider: (),
-                // code_action_provider: (),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                    code_action_kinds: Some(vec![
+                        CodeActionKind::QUICKFIX,
+                        CodeActionKind::REFACTOR,
+                        CodeActionKind::REFACTOR_REWRITE,
+                        CodeActionKind::REFACTOR_EXTRACT,
+                        CodeActionKind::REFACTOR_INLINE,
+                    ]),
+                    ..CodeActionOptions::default()
+                })),
                 // code_lens_provider: (),
-                // document_formatting_provider: (),
-                // document_range_formatting_provider: (),
-                // document_on_type_formatting_provider: (),
+                document_formatting_provider: is_static(OptionalProvider::Formatting).then_some(OneOf::Left(true)),
+                document_range_formatting_provider: is_static(OptionalProvider::RangeFormatting).then_some(OneOf::Left(true)),
+                document_on_type_formatting_provider: is_static(OptionalProvider::OnTypeFormatting).then(|| DocumentOnTypeFormattingOptions {
+                    first_trigger_character: registration::ON_TYPE_FORMATTING_TRIGGER.to_string(),
+                    more_trigger_character: Some(registration::ON_TYPE_FORMATTING_MORE_TRIGGERS.iter().map(|trigger| trigger.to_string()).collect()),
+                }),
                 // rename_provider: (),
                 // document_link_provider: (),
                 // color_provider: (),
                 // folding_range_provider: (),
                 // declaration_provider: (),
-                // execute_command_provider: (),
-                // workspace: (),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: commands::COMMANDS.iter().map(|command| command.to_string()).collect(),
+                    ..ExecuteCommandOptions::default()
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
                 // call_hierarchy_provider: (),
                 // semantic_tokens_provider: (),
                 // moniker_provider: (),
@@ -82,108 +346,256 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         info!("initialized");
+        logging::forward_to(self.client.clone());
         self.client
             .log_message(MessageType::INFO, "server initialized")
             .await;
+        // settings the client has for the server override its initialization options
+        if let Some(settings) = self.pull_settings().await {
+            self.apply_settings(&settings);
+        }
+        if self.can_watch_configuration.load(Ordering::Acquire) {
+            let registration = Registration {
+                id: "javals/configuration".to_string(),
+                method: "workspace/didChangeConfiguration".to_string(),
+                register_options: None,
+            };
+            if let Err(error) = self.client.register_capability(vec![registration]).await {
+                info!("unable to register for configuration changes: {:?}", error);
+            }
+        }
+        self.update_provider_registrations().await;
+        let search_indexing = async {
+            self.build_search_index().await;
+        };
+        error::guarded("initialized", search_indexing).await;
+        if self.can_watch_files.load(Ordering::Acquire) {
+            let generated_source_directories = self.config.read().unwrap_or_else(PoisonError::into_inner).generated_source_directories.clone();
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String("**/*.java".to_string()),
+                        kind: None,
+                    },
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!("**/{}", editorconfig::FILE_NAME)),
+                        kind: None,
+                    },
+                ]
+                .into_iter()
+                .chain(project::BUILD_FILE_NAMES.iter().map(|file_name| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{}", file_name)),
+                    kind: None,
+                }))
+                // a build regenerating sources often replaces their whole directory at once
+                .chain(generated_source_directories.iter().map(|directory| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{}", directory.trim_end_matches('/'))),
+                    kind: None,
+                }))
+                .collect(),
+            };
+            let registration = Registration {
+                id: "javals/watchedFiles".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(error) = self.client.register_capability(vec![registration]).await {
+                info!("unable to register file watcher: {:?}", error);
+            }
+        }
+        let indexing = async {
+            self.index_workspace(true).await;
+            self.load_projects().await;
+            self.index_jdk().await;
+        };
+        error::guarded("initialized", indexing).await;
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        info!("did_open");
+        debug!("did_open");
+        if params.text_document.language_id != "java" && !text_sync::is_java_file(&params.text_document.uri) {
+            info!("ignoring {}, a {} document", params.text_document.uri, params.text_document.language_id);
+            return;
+        }
         self.client
             .log_message(MessageType::INFO, "file opened")
             .await;
-        self.on_change(TextDocumentItem {
+        let change = self.on_change(TextDocumentItem {
             uri: params.text_document.uri,
             text: params.text_document.text,
             version: params.text_document.version,
-        })
-            .await;
+            // a stored tree, from an earlier open or from the file on disk, may not match the
+            // text the client opens it with, so it can't be reused
+            tree: None,
+        });
+        self.pending_changes.run(self.traced("textDocument/didOpen", change)).await;
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        info!("did_change");
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
-            .await;
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        debug!("did_change");
+        let uri = params.text_document.uri;
+        // a document in another language was ignored when it was opened
+        if self.document_version(uri.as_str()).is_none() && !text_sync::is_java_file(&uri) {
+            return;
+        }
+        let version = params.text_document.version;
+        let change = async { self.apply_content_changes(&uri, version, params.content_changes) };
+        self.pending_changes.run(error::guarded("textDocument/didChange", change)).await;
+        // the text is stored right away, and parsed once typing pauses
+        self.pending_changes.debounce().await;
+        let reparse = self.reparse_document(&uri, version);
+        self.pending_changes.run(self.traced("textDocument/didChange", reparse)).await;
+    }
+
+    async fn will_save_wait_until(&self, params: WillSaveTextDocumentParams) -> Result<Option<Vec<TextEdit>>> {
+        error::guarded("textDocument/willSaveWaitUntil", self.will_save_edits(params)).await
     }
 
     async fn did_save(&self, _: DidSaveTextDocumentParams) {
-        info!("did_save");
+        debug!("did_save");
         self.client
             .log_message(MessageType::INFO, "file saved")
             .await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
-        info!("did_close");
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        debug!("did_close");
+        let close = error::guarded("textDocument/didClose", self.close_document(&params.text_document.uri));
+        self.pending_changes.run(close).await;
         self.client
             .log_message(MessageType::INFO, "file closed")
             .await;
     }
 
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        debug!("did_change_configuration {:?}", params.settings);
+        let change = async {
+            // clients that answer workspace/configuration may only say that something changed;
+            // others send the settings, usually under the server's section
+            let settings = match self.pull_settings().await {
+                Some(settings) => settings,
+                None => params.settings.get(settings::SECTION).unwrap_or(&params.settings).clone(),
+            };
+            self.apply_changed_settings(&settings).await;
+        };
+        error::guarded("workspace/didChangeConfiguration", change).await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        debug!("did_change_workspace_folders {:?}", params.event);
+        let change = async {
+            self.change_workspace_folders(params.event).await;
+            self.load_projects().await;
+        };
+        error::guarded("workspace/didChangeWorkspaceFolders", change).await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        debug!("did_change_watched_files {:?}", params.changes);
+        let update = async {
+            self.invalidate_editor_configs(&params.changes);
+            self.update_search_index(&params.changes);
+            self.update_workspace_index(&params.changes).await;
+            let build_file_changed = params
+                .changes
+                .iter()
+                .filter_map(|change| change.uri.to_file_path().ok())
+                .any(|path| project::is_build_file(&path));
+            if build_file_changed {
+                self.load_projects().await;
+            }
+            let generated_directories = self.config.read().unwrap_or_else(PoisonError::into_inner).generated_source_directories.clone();
+            let generated_sources_regenerated = params
+                .changes
+                .iter()
+                .filter_map(|change| change.uri.to_file_path().ok())
+                .any(|path| search::is_generated_source_directory(&path, &generated_directories));
+            if generated_sources_regenerated {
+                self.reindex_workspace().await;
+            }
+        };
+        error::guarded("workspace/didChangeWatchedFiles", update).await;
+    }
+
     async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        self.answer("textDocument/definition", self.definition(params)).await
+    }
+
+    async fn goto_implementation(&self, params: GotoImplementationParams) -> Result<Option<GotoImplementationResponse>> {
+        self.answer("textDocument/implementation", self.implementations(params)).await
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let position = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+        debug!("references {} {:?}", uri.to_string(), position);
+        let references = async {
+            let include_declaration = params.context.include_declaration;
+            let progress = self
+                .begin_progress("findReferences", "Finding references", params.work_done_progress_params.work_done_token)
+                .await;
+            let cancellation = &mut Cancellation::with_progress(progress);
+            Ok(self.find_references(&uri, position, SearchScope::Workspace, include_declaration, cancellation).await)
+        };
+        self.answer("textDocument/references", references).await
+    }
+
+    async fn document_highlight(&self, params: DocumentHighlightParams) -> Result<Option<Vec<DocumentHighlight>>> {
         let position = params.text_document_position_params.position;
         let uri = params.text_document_position_params.text_document.uri;
-        info!("goto_definition {} {:?}", uri.to_string(), position);
-        let tree = self.parsed_document_map.get(uri.as_str()).unwrap();
-        let source_text = self.document_map.get(uri.as_str()).unwrap();
-        let base_node = tree.root_node().named_descendant_for_point_range(
-            to_point(position),
-            to_point(position),
-        )
-            .expect(format!("Unable to find node at postion: {:?}", position).as_str());
-        if base_node.kind() != "identifier" {
+        debug!("document_highlight {} {:?}", uri.to_string(), position);
+        if !self.is_provider_enabled(OptionalProvider::DocumentHighlight) {
             return Ok(None);
         }
-        let token = base_node.utf8_text(source_text.as_bytes()).unwrap();
-        info!("found node = {:?}, {:?}", base_node, token);
-        let locations = self.token_location_map.get(token);
-        if locations.is_none() {
+        let highlights = async { Ok(self.document_highlights(&uri, position, &mut Cancellation::default()).await) };
+        self.answer("textDocument/documentHighlight", highlights).await
+    }
+
+    async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        debug!("symbol {:?}", params.query);
+        let symbols = async { Ok(Some(self.workspace_symbols(&params.query, &mut Cancellation::default()).await)) };
+        self.answer("workspace/symbol", symbols).await
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        self.answer("textDocument/codeAction", self.code_actions(params)).await
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        self.answer("textDocument/completion", self.completion_items(params)).await
+    }
+
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        self.answer("completionItem/resolve", self.resolve_completion_item(item)).await
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        self.answer("textDocument/hover", self.expression_hover(params)).await
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        if !self.is_provider_enabled(OptionalProvider::Formatting) {
             return Ok(None);
         }
-        // 
-        {
-            let parent_node = base_node.parent().unwrap();
-            match parent_node.kind() {
-                "field_access" => {
-                    let mut cursor = parent_node.walk();
-                    let identifier_nodes = parent_node.children(&mut cursor);
-                    for identifier_node in identifier_nodes {
-                        let identifier_token = identifier_node.utf8_text(source_text.as_bytes()).unwrap();
-                    }
-                }
-                _ => {}
-            };
+        self.answer("textDocument/formatting", self.format_document(params)).await
+    }
+
+    async fn range_formatting(&self, params: DocumentRangeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        if !self.is_provider_enabled(OptionalProvider::RangeFormatting) {
+            return Ok(None);
         }
-        let map = locations.unwrap().iter().fold(HashMap::new(), |mut map, loc| {
-            map.insert(loc.scope_id, (loc.start_position, loc.end_position));
-            return map;
-        });
-        let mut current_node = base_node;
-        loop {
-            let parent_node = match current_node.parent() {
-                Some(node) => node,
-                None => break,
-            };
-            match map.get(&parent_node.id()) {
-                Some((start_point, end_point)) => {
-                    return Ok(Some(GotoDefinitionResponse::Scalar(Location {
-                        uri,
-                        range: Range {
-                            start: to_position(*start_point),
-                            end: to_position(*end_point),
-                        },
-                    })));
-                }
-                None => {
-                    current_node = parent_node;
-                }
-            };
+        self.answer("textDocument/rangeFormatting", self.format_range(params)).await
+    }
+
+    async fn on_type_formatting(&self, params: DocumentOnTypeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        if !self.is_provider_enabled(OptionalProvider::OnTypeFormatting) {
+            return Ok(None);
         }
-        Ok(None)
+        self.answer("textDocument/onTypeFormatting", self.format_on_type(params)).await
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        self.execute(params).await
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -195,161 +607,638 @@ struct TextDocumentItem {
     uri: Url,
     text: String,
     version: i32,
+    // the stored tree, edited along with the text, for parsing to reuse
+    tree: Option<Tree>,
 }
 impl Backend {
+    async fn definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        debug!("goto_definition {} {:?}", uri.to_string(), position);
+        let unknown_document = || Error::UnknownDocument(uri.to_string());
+        // the search of the workspace text gives way between files, so it's left until the
+        // document is let go of
+        let (token, kinds, mut locations) = {
+            let tree = match self.parsed_document_map.get(uri.as_str()) {
+                Some(tree) => tree,
+                // documents in other languages have nothing to go to
+                None if !text_sync::is_java_file(&uri) => return Ok(None),
+                None => return Err(unknown_document().into()),
+            };
+            let source_text = self.document_map.get(uri.as_str()).ok_or_else(unknown_document)?;
+            let point = LineIndex::new(&source_text).point(position);
+            let base_node = match tree.root_node().named_descendant_for_point_range(point, point) {
+                Some(base_node) => base_node,
+                None => return Ok(None),
+            };
+            if let Some(location) = self.record_pattern_definition(&uri, &tree, &source_text, base_node) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if let Some(location) = self.module_definition(base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if let Some(location) = self.case_constant_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if let Some(location) = self.annotation_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if base_node.kind() != "identifier" && base_node.kind() != "type_identifier" {
+                return Ok(None);
+            }
+            let token = base_node.utf8_text(source_text.as_bytes()).unwrap_or_default();
+            debug!("found node = {:?}, {:?}", base_node, token);
+            // a segment of a dotted name, whose last segments may shadow local names
+            if let Some(location) = self.qualified_name_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            // a method invocation or a created class, through the overloads its arguments fit
+            let mut overload_locations = self.method_definitions(uri.as_str(), base_node, &source_text);
+            if overload_locations.is_empty() {
+                overload_locations = self.constructor_definitions(uri.as_str(), base_node, &source_text);
+            }
+            match overload_locations.len() {
+                0 => {}
+                1 => return Ok(Some(GotoDefinitionResponse::Scalar(overload_locations.remove(0)))),
+                _ => return Ok(Some(GotoDefinitionResponse::Array(overload_locations))),
+            }
+            // a field after a receiver, through the receiver's type
+            if let Some(location) = self.member_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            // a member of some other receiver than `this` is none of the names in scope here
+            let receiver_node = base_node.parent().and_then(|parent_node| match parent_node.kind() {
+                "field_access" if parent_node.child_by_field_name("field") == Some(base_node) => parent_node.child_by_field_name("object"),
+                "method_invocation" if parent_node.child_by_field_name("name") == Some(base_node) => parent_node.child_by_field_name("object"),
+                _ => None,
+            });
+            let declaration = match receiver_node {
+                Some(receiver_node) if receiver_node.kind() != "this" => None,
+                _ => self.find_declaration(uri.as_str(), base_node, token),
+            };
+            if let Some(location) = declaration {
+                return Ok(Some(GotoDefinitionResponse::Scalar(Location { uri, range: location.range })));
+            }
+            // declared in another document: found through the index, or until every file is
+            // indexed, guessed from the text of the workspace
+            let kinds = if base_node.kind() == "type_identifier" {
+                vec![SymbolKind::CLASS, SymbolKind::INTERFACE, SymbolKind::ENUM]
+            } else if base_node.parent().map(|n| n.kind()) == Some("method_invocation") {
+                vec![SymbolKind::METHOD]
+            } else {
+                Vec::new()
+            };
+            if let Some(location) = self.inherited_field_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if let Some(location) = self.static_import_definition(uri.as_str(), base_node, &source_text) {
+                return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+            }
+            if base_node.kind() == "type_identifier" {
+                if let Some(location) = self.source_class_definition(uri.as_str(), base_node, &source_text) {
+                    return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+                }
+            }
+            let locations = self.indexed_definitions(&uri, token, &kinds);
+            if locations.is_empty() {
+                if let Some(location) = self.dependency_definition(uri.as_str(), base_node, &source_text) {
+                    return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+                }
+            }
+            (token.to_string(), kinds, locations)
+        };
+        if locations.is_empty() {
+            locations = self.heuristic_definitions(&uri, &token, &kinds, &mut Cancellation::default()).await;
+        }
+        if locations.is_empty() {
+            return Ok(None);
+        }
+        debug!("goto_definition heuristic matches for {}: {:?}", token, locations);
+        return Ok(Some(GotoDefinitionResponse::Array(locations)));
+    }
+
+    async fn code_actions(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        debug!("code_action {} {:?}", uri.to_string(), params.range);
+        let style = self.editor_config(&uri);
+        let mut actions = {
+            let tree = match self.parsed_document_map.get(uri.as_str()) {
+                Some(tree) => tree,
+                None => return Ok(None),
+            };
+            let source_text = match self.document_map.get(uri.as_str()) {
+                Some(source_text) => source_text,
+                None => return Ok(None),
+            };
+            let mut actions = code_action::add_override_actions(&uri, &tree, &source_text, &params.context.diagnostics);
+            actions.extend(code_action::surround_with_try_catch_actions(&uri, &tree, &source_text, params.range, &self.type_declaration_map, &style));
+            actions.extend(code_action::declaration_actions(self, &uri, &tree, &source_text, params.range));
+            actions.extend(code_action::invert_if_actions(&uri, &tree, &source_text, params.range, &style));
+            actions.extend(code_action::javadoc_actions(&uri, &tree, &source_text, params.range));
+            actions.extend(code_action::local_variable_actions(self, &uri, &tree, &source_text, params.range));
+            actions
+        };
+        actions.extend(code_action::create_method_actions(self, &uri, &params.context.diagnostics));
+        code_action::use_line_endings(self, &mut actions);
+        Ok(Some(actions))
+    }
+
+    /// Resolves an identifier in the document at `uri` by walking up from `node` to the nearest
+    /// enclosing scope that declares `token`. Labels and other names don't hide each other.
+    fn find_declaration(&self, uri: &str, node: Node, token: &str) -> Option<TokenLocation> {
+        return latency::phase(Phase::Resolve, || {
+            let table = self.symbol_index.table(uri)?;
+            let token = literals::identifier_name(token);
+            let is_label = matches!(
+                node.parent().map(|n| n.kind()),
+                Some("labeled_statement" | "break_statement" | "continue_statement")
+            );
+            let map = table
+                .get(token.as_ref())?
+                .iter()
+                .filter(|loc| matches!(loc.token_type, TokenType::Label) == is_label)
+                .fold(HashMap::new(), |mut map, loc| {
+                    map.insert(loc.scope, loc);
+                    return map;
+                });
+            let mut current_node = node;
+            while let Some(parent_node) = current_node.parent() {
+                if let Some(location) = map.get(&scope_key(parent_node)) {
+                    return Some((*location).clone());
+                }
+                current_node = parent_node;
+            }
+            return None;
+        });
+    }
+
     async fn on_change(&self, params: TextDocumentItem) {
-        let mut parser = Parser::new();
-        parser.set_language(tree_sitter_java::language()).expect("Error loading Java grammar.");
-
-        let tree = match self.parsed_document_map.get(params.uri.as_str()) {
-            Some(r) => parser.parse(params.text.as_bytes(), Some(r.value())),
-            None => parser.parse(params.text.as_bytes(), None),
-        }.expect("Unable to walk tree");
-        let nodes: Vec<Node<'_>> = tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre).collect::<Vec<_>>();
-        for node in nodes {
-            info!("node = {}, {}, {}, {}, {}", node.id(), node.kind(), node.utf8_text(params.text.as_bytes()).unwrap(), node.start_position(), node.end_position());
-
-            if node.kind() != "identifier" {
+        let old_tree = params.tree;
+        let lombok_annotations = self.config.read().unwrap_or_else(PoisonError::into_inner).lombok_annotations.clone();
+        // parsing and scanning a large document would hold up every other request and
+        // notification, so they're done on a blocking thread, which hands the text back
+        let uri = params.uri.clone();
+        let text = params.text;
+        let parsing = tokio::task::spawn_blocking(move || {
+            let parsed = parse_document(&uri, &text, old_tree, &lombok_annotations);
+            return (text, parsed);
+        });
+        let (text, (elapsed, parsed)) = match parsing.await {
+            Ok(parsing) => parsing,
+            Err(join_error) if join_error.is_panic() => std::panic::resume_unwind(join_error.into_panic()),
+            // the runtime is shutting down
+            Err(_) => return,
+        };
+        latency::record_phase(Phase::Parse, elapsed);
+        let (tree, scanned) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                // the text is kept for the changes that follow to apply to, and the tree they'd
+                // no longer match is dropped, so the next change is parsed from scratch
+                warn!("{}", Error::Parse(params.uri.to_string()));
+                self.parsed_document_map.remove(params.uri.as_str());
+                self.document_map.insert(params.uri.to_string(), text);
+                self.document_versions.insert(params.uri.to_string(), params.version);
+                return;
+            }
+        };
+        let declarations = self.revisions.declarations();
+        let mut affected_uris = self.store_document(&params.uri, text, tree, scanned, Some(params.version));
+        self.publish_diagnostics(&params.uri).await;
+        if self.revisions.declarations() != declarations {
+            // any open document may depend on what changed, and those that don't keep their
+            // diagnostics as they are
+            affected_uris = self.document_versions.iter().map(|entry| entry.key().clone()).collect();
+        }
+        self.publish_affected_diagnostics(affected_uris).await;
+    }
+
+    /// Republishes the diagnostics of those of `affected_uris` that are open. Closed documents
+    /// get theirs when they're opened.
+    async fn publish_affected_diagnostics(&self, affected_uris: Vec<String>) {
+        for affected_uri in affected_uris {
+            if self.document_version(&affected_uri).is_none() {
                 continue;
             }
+            if let Ok(affected_uri) = Url::parse(&affected_uri) {
+                self.publish_diagnostics(&affected_uri).await;
+            }
+        }
+    }
 
-            let parent = node.parent().unwrap();
-            let token = node.utf8_text(params.text.as_bytes()).unwrap();
-            let (token_type, scope_id) = match parent.kind() {
-                "class_declaration" => {
-                    (TokenType::ClassName, parent.id())
+    /// Stores `text` and its `tree` as the document at `uri`, with what [`scan_document`] found
+    /// in them and the types of the bindings of its record patterns, which take the rest of the
+    /// index to work out. `version` is `None` for files indexed from disk rather than opened by
+    /// the client. Returns the other documents whose diagnostics the classes declared here
+    /// affect.
+    fn store_document(&self, uri: &Url, text: String, tree: Tree, mut scanned: ScannedDocument, version: Option<i32>) -> Vec<String> {
+        let lines = LineIndex::new(&text);
+        for pattern in &scanned.record_patterns {
+            for binding in &pattern.bindings {
+                let binding_type = self.binding_type(uri.as_str(), &tree, &text, pattern, binding);
+                // one declaration in each scope the binding is visible in
+                for scope in &pattern.scopes {
+                    let location = TokenLocation {
+                        uri: uri.to_string(),
+                        start_position: binding.start_position,
+                        end_position: binding.end_position,
+                        range: lines.range(binding.start_position, binding.end_position),
+                        token_type: TokenType::LocalVariable(binding_type.clone()),
+                        scope: *scope,
+                        modifiers: Modifiers::default(),
+                    };
+                    scanned.symbols.entry(binding.name.clone()).or_default().push(location);
                 }
-                "variable_declarator" => {
-                    let field_declaration_node = parent.parent().unwrap();
-                    match field_declaration_node.kind() {
-                        "field_declaration" => {
-                            let class_body_node = field_declaration_node.parent().unwrap();
-                            if class_body_node.kind() != "class_body" {
-                                panic!("expected class_body node, but got {}", class_body_node.kind());
-                            }
-                            (TokenType::MemberVariable, class_body_node.id())
-                        }
-                        "local_variable_declaration" => {
-                        }
-                        _ => {
-                            info!("unhandled variable_declarator branch {}", field_declaration_node.kind());
-                            continue;
-                        }
-                    }
+            }
+        }
+        let package = references::package_name(&tree, &text).unwrap_or_default();
+        let affected_uris = self.store_declarations(uri, scanned, &package);
+        self.document_map.insert(uri.to_string(), text);
+        self.parsed_document_map.insert(uri.to_string(), tree);
+        if let Some(version) = version {
+            self.document_versions.insert(uri.to_string(), version);
+        } else {
+            self.document_versions.remove(uri.as_str());
+        }
+        self.retain_tree(uri.as_str());
+        return affected_uris;
+    }
+
+    /// Stores what the workspace cache kept of the file at `uri`, which declares `package`,
+    /// without its text and tree, which are read from disk again when they're needed. Returns
+    /// the other documents whose diagnostics the classes declared here affect.
+    fn store_cached_document(&self, uri: &Url, scanned: ScannedDocument, package: &str) -> Vec<String> {
+        let affected_uris = self.store_declarations(uri, scanned, package);
+        self.document_map.remove(uri.as_str());
+        self.parsed_document_map.remove(uri.as_str());
+        self.retained_trees.forget(uri.as_str());
+        self.document_versions.remove(uri.as_str());
+        return affected_uris;
+    }
+
+    /// Stores what `scanned` found in the document at `uri`, which declares `package`, in the
+    /// indexes read across documents.
+    fn store_declarations(&self, uri: &Url, scanned: ScannedDocument, package: &str) -> Vec<String> {
+        let declarations = (&scanned.type_declarations, &scanned.module_declaration, &scanned.class_names);
+        self.revisions.document_changed(uri.as_str(), &declarations);
+        self.symbol_index.replace(uri.as_str(), scanned.symbols);
+        self.unhandled_nodes.replace(uri.as_str(), scanned.unhandled);
+        self.type_declaration_map.insert(uri.to_string(), scanned.type_declarations);
+        match scanned.module_declaration {
+            Some(module_declaration) => self.module_declarations.insert(uri.to_string(), module_declaration),
+            None => self.module_declarations.remove(uri.as_str()).map(|(_, module_declaration)| module_declaration),
+        };
+        return self.class_registry.register_source(uri.as_str(), package, scanned.class_names);
+    }
+
+    /// Publishes the diagnostics of the document at `uri`, tagged with the version they were
+    /// computed from. They're only computed and published again once the document, or the
+    /// declarations they may depend on, changed since they last were.
+    async fn publish_diagnostics(&self, uri: &Url) {
+        let inputs = self.revisions.inputs(uri.as_str());
+        if self.memoized_diagnostics.get(uri.as_str(), inputs).is_some() {
+            return;
+        }
+        let (diagnostics, version) = {
+            let tree = match self.parsed_document_map.get(uri.as_str()) {
+                Some(tree) => tree,
+                None => return,
+            };
+            let source_text = match self.document_map.get(uri.as_str()) {
+                Some(source_text) => source_text,
+                None => return,
+            };
+            let mut diagnostics = Vec::new();
+            // with diagnostics turned off, publishing none clears those published before
+            if self.config.read().unwrap_or_else(PoisonError::into_inner).features.diagnostics {
+                diagnostics.extend(diagnostics::missing_override_diagnostics(uri.as_str(), &tree, &source_text, &self.type_declaration_map));
+                if SourceSet::of(uri.as_str()) == SourceSet::Main {
+                    let test_type_names = diagnostics::test_type_names(&self.type_declaration_map);
+                    diagnostics.extend(diagnostics::test_type_reference_diagnostics(
+                        uri.as_str(),
+                        &tree,
+                        &source_text,
+                        &self.type_declaration_map,
+                        &test_type_names,
+                    ));
                 }
-                "method_declaration" => {
-                    let mut parameter_types: Vec<String> = Vec::new();
-                    let params_node = node.next_named_sibling().unwrap();
-                    if params_node.kind() == "formal_parameters" {
-                        for param_node in params_node.named_children(&mut params_node.walk()) {
-                            if param_node.kind() != "formal_parameter" {
-                                continue;
-                            }
-                            for param_child_node in param_node.named_children(&mut param_node.walk()) {
-                                match param_child_node.kind() {
-                                    "integral_type" | "type_identifier" => {
-                                        let parameter_type_token = param_child_node.utf8_text(params.text.as_bytes()).unwrap();
-                                        parameter_types.push(parameter_type_token.to_string());
-                                    }
-                                    _ => continue
-                                };
-                            }
-                        }
+                let classpath = self.document_classpath(uri.as_str());
+                diagnostics.extend(diagnostics::duplicate_class_diagnostics(uri.as_str(), &tree, &source_text, &self.class_registry, classpath.as_deref()));
+                diagnostics.extend(diagnostics::unresolved_method_diagnostics(self, uri.as_str(), &tree, &source_text));
+                diagnostics.extend(diagnostics::ambiguous_type_diagnostics(self, uri.as_str(), &tree, &source_text));
+                diagnostics.extend(diagnostics::unpermitted_subtype_diagnostics(self, uri.as_str(), &tree, &source_text));
+                diagnostics.extend(diagnostics::undefined_label_diagnostics(&tree, &source_text));
+            }
+            (diagnostics, self.document_version(uri.as_str()))
+        };
+        self.memoized_diagnostics.insert(uri.as_str(), inputs, diagnostics.clone());
+        self.client
+            .publish_diagnostics(uri.clone(), diagnostics, version)
+            .await;
+    }
+}
+
+/// What [`scan_document`] finds in a document, to be stored along with it.
+struct ScannedDocument {
+    symbols: SymbolTable,
+    unhandled: HashMap<UnhandledNode, usize>,
+    // their bindings are typed once the document is stored
+    record_patterns: Vec<RecordPattern>,
+    // with the members Lombok generates, once they're added
+    type_declarations: Vec<TypeDeclaration>,
+    module_declaration: Option<ModuleDeclaration>,
+    class_names: Vec<String>,
+}
+
+/// Parses `text`, reusing `old_tree` when it went through the same edits, and scans it. Also
+/// returns how long parsing took. No parse when the parser gives up on the text.
+fn parse_document(uri: &Url, text: &str, old_tree: Option<Tree>, lombok_annotations: &LombokAnnotations) -> (Duration, Option<(Tree, ScannedDocument)>) {
+    let mut parser = Parser::new();
+    if let Err(language_error) = parser.set_language(tree_sitter_java::language()) {
+        error!("unable to load the Java grammar: {}", language_error);
+    }
+    let parseable_text = literals::parseable_text(text);
+    let old_tree = old_tree.filter(|_| parseable_text == text);
+    let start = Instant::now();
+    let tree = parser.parse(parseable_text.as_bytes(), old_tree.as_ref());
+    let elapsed = start.elapsed();
+    let tree = match tree {
+        Some(tree) => tree,
+        None => return (elapsed, None),
+    };
+    let mut scanned = scan_document(uri, text, &tree);
+    lombok_annotations.add_generated_members(&tree, text, &mut scanned.type_declarations);
+    return (elapsed, Some((tree, scanned)));
+}
+
+/// Scans the document at `uri` for what it declares, from its `text` and `tree` alone, so that
+/// it can be done off the async runtime. The members Lombok generates are left for the caller
+/// to add to the type declarations, since which it generates is a setting.
+fn scan_document(uri: &Url, text: &str, tree: &Tree) -> ScannedDocument {
+    let mut unhandled: HashMap<UnhandledNode, usize> = HashMap::new();
+    let mut symbols: SymbolTable = HashMap::new();
+    let lines = LineIndex::new(text);
+    let record_patterns = pattern::record_patterns(tree, text);
+    let type_patterns = pattern::case_type_patterns(tree, text);
+    for (node, declared) in declared_names::declared_names(tree, text) {
+        if record_patterns.iter().any(|pattern| pattern.contains(node)) || type_patterns.iter().any(|pattern| pattern.contains(node)) {
+            // the bindings of record patterns and of case labels are added below, with the
+            // scopes of the patterns
+            continue;
+        }
+
+        let Some(parent) = node.parent() else { continue };
+        let declared = match declared {
+            Some(declared) => declared,
+            None => {
+                if !declared_names::is_undeclaring_parent(parent.kind()) {
+                    let node = UnhandledNode {
+                        context: "identifier",
+                        kind: parent.kind().to_string(),
+                    };
+                    *unhandled.entry(node).or_default() += 1;
+                }
+                continue;
+            }
+        };
+        let token = node.utf8_text(text.as_bytes()).unwrap();
+        // further scopes the name is visible in, besides the one of its declaration
+        let mut other_scopes = Vec::new();
+        let (token_type, scope) = match declared {
+            // visible throughout the class body, block or file declaring it, so that member and
+            // local classes nest in the scopes around them
+            Declared::ClassName => {
+                let Some(scope_node) = parent.parent() else { continue };
+                (TokenType::ClassName, scope_key(scope_node))
+            }
+            // annotation types and their elements, named where they're used
+            Declared::ElementName => (TokenType::MethodName(Vec::new()), scope_key(parent)),
+            Declared::EnumConstant => {
+                let Some(enum_body_node) = parent.parent() else { continue };
+                let enum_name = enum_body_node
+                    .parent() // enum_declaration
+                    .and_then(|n| n.child_by_field_name("name"))
+                    .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string());
+                (TokenType::MemberVariable(enum_name), scope_key(enum_body_node))
+            }
+            Declared::TypeParameter => {
+                let declaration_node = parent
+                    .parent() // type_parameters
+                    .and_then(|n| n.parent()); // the generic type, method or constructor
+                let Some(declaration_node) = declaration_node else { continue };
+                (TokenType::TypeParameter, scope_key(declaration_node))
+            }
+            Declared::Variable => {
+                let Some(field_declaration_node) = parent.parent() else { continue };
+                match field_declaration_node.kind() {
+                    "field_declaration" => {
+                        // a class or enum body, or the error an unfinished one, often of an
+                        // anonymous class, was recovered as
+                        let class_body_node = match field_declaration_node.parent() {
+                            // the fields of an enum follow its constants
+                            Some(n) if n.kind() == "enum_body_declarations" => n.parent(),
+                            n => n,
+                        };
+                        let Some(class_body_node) = class_body_node else { continue };
+                        let field_type = field_declaration_node
+                            .child_by_field_name("type")
+                            .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string());
+                        (TokenType::MemberVariable(field_type), scope_key(class_body_node))
+                    }
+                    "local_variable_declaration" => {
+                        let local_type = field_declaration_node
+                            .child_by_field_name("type")
+                            .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string());
+                        let Some(block_node) = field_declaration_node.parent() else { continue };
+                        (TokenType::LocalVariable(local_type), scope_key(block_node))
+                    }
+                    _ => {
+                        let node = UnhandledNode {
+                            context: "variable_declarator",
+                            kind: field_declaration_node.kind().to_string(),
+                        };
+                        *unhandled.entry(node).or_default() += 1;
+                        continue;
                     }
-                    (TokenType::MethodName(parameter_types), parent.id())
                 }
-                "formal_parameter" => {
-                    let parameter_type: Option<String> = parent.named_children(&mut parent.walk())
-                        .into_iter()
-                        .find_map(|n| {
-                            match n.kind() {
+            }
+            Declared::MethodName => {
+                let mut parameter_types: Vec<String> = Vec::new();
+                let params_node = node.next_named_sibling();
+                if let Some(params_node) = params_node.filter(|n| n.kind() == "formal_parameters") {
+                    for param_node in params_node.named_children(&mut params_node.walk()) {
+                        if param_node.kind() != "formal_parameter" {
+                            continue;
+                        }
+                        for param_child_node in param_node.named_children(&mut param_node.walk()) {
+                            match param_child_node.kind() {
                                 "integral_type" | "type_identifier" => {
-                                    Some(n.utf8_text(params.text.as_bytes()).unwrap().to_string())
+                                    let parameter_type_token = param_child_node.utf8_text(text.as_bytes()).unwrap();
+                                    parameter_types.push(parameter_type_token.to_string());
                                 }
-                                _ => None
-                            }
-                        });
-                    let method_declaration_node = parent
-                        .parent() // formal_parameters
-                        .unwrap()
-                        .parent() // method_declaration
-                        .unwrap();
-                    if method_declaration_node.kind() != "method_declaration" {
-                        panic!("expected method_declaration node, but got {}", method_declaration_node.kind());
+                                _ => continue
+                            };
+                        }
                     }
-                    (TokenType::ParameterName(parameter_type), method_declaration_node.id())
-                },
-                _ => {
-                    info!("unhandled branch {}", parent.kind());
-                    continue;
                 }
-            };
-            let location = TokenLocation {
-                uri: params.uri.to_string(),
-                start_position: node.start_position(),
-                end_position: node.end_position(),
-                token_type,
-                scope_id,
-            };
-            if !self.token_location_map.contains_key(token) {
-                self.token_location_map.insert(token.to_string(), Vec::new());
+                (TokenType::MethodName(parameter_types), scope_key(parent))
             }
-            self.token_location_map.get_mut(token).unwrap().push(location);
-        }
-        self.document_map.insert(params.uri.to_string(), params.text);
-        self.parsed_document_map.insert(params.uri.to_string(), tree);
-        info!("map {:#?}", self.token_location_map);
+            Declared::Parameter => {
+                let parameter_type = parent
+                    .child_by_field_name("type")
+                    .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string());
+                let declaration_node = parent
+                    .parent() // formal_parameters
+                    .and_then(|n| n.parent()); // method_declaration, constructor_declaration, lambda_expression or record_declaration
+                match declaration_node {
+                    Some(n) if matches!(n.kind(), "method_declaration" | "constructor_declaration" | "lambda_expression") => {
+                        (TokenType::ParameterName(parameter_type), scope_key(n))
+                    }
+                    // record components are the record's fields
+                    Some(n) if n.kind() == "record_declaration" => (TokenType::MemberVariable(parameter_type), scope_key(n)),
+                    _ => {
+                        // parameters recovered from an unfinished declaration
+                        let node = UnhandledNode {
+                            context: "formal_parameter",
+                            kind: declaration_node.map(|n| n.kind()).unwrap_or("none").to_string(),
+                        };
+                        *unhandled.entry(node).or_default() += 1;
+                        continue;
+                    }
+                }
+            },
+            // the labels `break` and `continue` name are resolved like variables
+            Declared::Label => (TokenType::Label, scope_key(parent)),
+            // the parameters of lambdas, which take their types from where they're passed
+            Declared::LambdaParameter => {
+                let lambda_node = match parent.kind() {
+                    "inferred_parameters" => parent.parent(),
+                    _ => Some(parent),
+                };
+                let Some(lambda_node) = lambda_node else { continue };
+                (TokenType::ParameterName(None), scope_key(lambda_node))
+            }
+            // the binding of a type pattern like `obj instanceof String s`
+            Declared::PatternBinding => {
+                let binding_type = parent
+                    .child_by_field_name("right")
+                    .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string());
+                let scopes: Vec<ScopeKey> = pattern::binding_scopes(parent).into_iter().map(scope_key).collect();
+                let scope = match scopes.first() {
+                    Some(scope) => *scope,
+                    None => continue,
+                };
+                other_scopes.extend_from_slice(&scopes[1..]);
+                (TokenType::LocalVariable(binding_type), scope)
+            }
+        };
+        // variables share the modifiers of the declaration listing them
+        let declaration_node = if parent.kind() == "variable_declarator" { parent.parent().unwrap_or(parent) } else { parent };
+        let location = TokenLocation {
+            uri: uri.to_string(),
+            start_position: node.start_position(),
+            end_position: node.end_position(),
+            range: lines.range(node.start_position(), node.end_position()),
+            token_type,
+            scope,
+            modifiers: Modifiers::of(declaration_node),
+        };
+        let other_locations: Vec<TokenLocation> = other_scopes
+            .into_iter()
+            .map(|scope| TokenLocation { scope, ..location.clone() })
+            .collect();
+        let locations = symbols.entry(literals::identifier_name(token).into_owned()).or_default();
+        locations.push(location);
+        locations.extend(other_locations);
     }
-}
-
-// fn main() {
-//     let code = r#"
-//     class Test {
-//         int double(int x) {
-//             return x * 2;
-//         }
-//     }
-//     "#;
-//     let mut parser = tree_sitter::Parser::new();
-//     parser.set_language(tree_sitter_java::language()).expect("Error loading Java grammar.");
-//     let tree = parser.parse(code, None).unwrap();
-//     let nodes = tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre).collect::<Vec<_>>();
-//     for node in nodes {
-//         println!("node = {:?}", node);
-//     }
-// }
-
-fn to_position(point: Point) -> Position {
-    return Position {
-        line: point.row as u32,
-        character: point.column as u32,
+    for pattern in &type_patterns {
+        let location = TokenLocation {
+            uri: uri.to_string(),
+            start_position: pattern.binding.start_position,
+            end_position: pattern.binding.end_position,
+            range: lines.range(pattern.binding.start_position, pattern.binding.end_position),
+            token_type: TokenType::LocalVariable(pattern.binding.declared_type.clone()),
+            scope: pattern.scope,
+            modifiers: Modifiers::default(),
+        };
+        symbols.entry(pattern.binding.name.clone()).or_default().push(location);
+    }
+    let class_names = classpath::declared_class_names(tree, text)
+        .into_iter()
+        .map(|(class_name, _)| class_name)
+        .collect();
+    return ScannedDocument {
+        symbols,
+        unhandled,
+        record_patterns,
+        type_declarations: hierarchy::collect_type_declarations(tree, text),
+        module_declaration: modules::module_declaration(tree, text),
+        class_names,
     };
 }
 
-fn to_point(position: Position) -> Point {
-    return Point {
-        row: position.line as usize,
-        column: position.character as usize,
-    };
+fn scope_key(node: Node) -> ScopeKey {
+    return (node.start_byte(), node.end_byte(), node.kind_id());
 }
 
 #[tokio::main]
 async fn main() {
-    let log_file = Box::new(File::create("log.txt").unwrap());
-    env_logger::Builder::new()
-        .filter(None, log::LevelFilter::Info)
-        .target(env_logger::Target::Pipe(log_file))
-        .init();
+    let args = cli::Args::parse();
+    logging::init(args.log_destination(), args.log_level.as_deref());
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| Backend {
+    let (service, socket) = LspService::build(|client| Backend {
         client,
         document_map: DashMap::new(),
         parsed_document_map: DashMap::new(),
-        token_location_map: DashMap::new(),
+        retained_trees: RetainedTrees::default(),
+        document_versions: DashMap::new(),
+        symbol_index: SymbolIndex::default(),
+        type_declaration_map: DashMap::new(),
+        module_declarations: DashMap::new(),
+        class_registry: ClassRegistry::default(),
+        unhandled_nodes: UnhandledNodes::default(),
+        workspace_roots: RwLock::new(Vec::new()),
+        search_index: RwLock::new(TrigramIndex::default()),
+        projects: RwLock::new(Vec::new()),
+        gradle_builds: RwLock::new(HashMap::new()),
+        dependency_index: DependencyIndex::default(),
+        jdk: RwLock::new(None),
+        jdk_modules: DashMap::new(),
+        index_ready: AtomicBool::new(false),
+        indexing: AtomicBool::new(false),
+        last_indexing: RwLock::new(None),
+        can_watch_files: AtomicBool::new(false),
+        can_report_progress: AtomicBool::new(false),
+        progress_tokens: ProgressTokens::default(),
+        can_pull_configuration: AtomicBool::new(false),
+        can_watch_configuration: AtomicBool::new(false),
+        can_resolve_completion_edits: AtomicBool::new(false),
+        can_complete_snippets: AtomicBool::new(false),
+        recent_completions: RecentCompletions::default(),
+        config: RwLock::new(Config::default()),
+        provider_registrations: ProviderRegistrations::default(),
+        trace: RwLock::new(TraceValue::Off),
+        editor_config_cache: RwLock::new(HashMap::new()),
+        latency: LatencyTracker::default(),
+        pending_changes: PendingChanges::default(),
+        revisions: Revisions::default(),
+        memoized_diagnostics: Memo::default(),
         // semantic_token_map: DashMap::new(),
-    });
-    Server::new(stdin, stdout, socket).serve(service).await;
+    })
+        .custom_method("javals/findReferences", Backend::find_scoped_references)
+        .custom_method("javals/textSearch", Backend::text_search)
+        .custom_method("javals/indexStatus", Backend::index_status)
+        .custom_method("javals/classFileContents", Backend::class_file_contents)
+        .custom_method("javals/syntaxTree", Backend::syntax_tree)
+        .custom_method("javals/reindexWorkspace", Backend::rebuild_workspace_index_notification)
+        .custom_method("window/workDoneProgress/cancel", Backend::cancel_progress)
+        .custom_method("$/setTrace", Backend::set_trace)
+        .finish();
+    Server::new(stdin, stdout, socket)
+        .concurrency_level(MAX_CONCURRENCY)
+        .serve(service)
+        .await;
 }
 
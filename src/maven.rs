@@ -0,0 +1,593 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::project::{self, Project};
+use crate::xml::{self, Element};
+
+pub const POM: &str = "pom.xml";
+
+/// Parents and imported boms nested deeper than this are taken for a cycle.
+const MAX_DEPTH: usize = 16;
+
+/// Resolution stops after this many dependencies, however many more there are.
+const MAX_DEPENDENCIES: usize = 5000;
+
+/// A dependency as a pom declares it, or as `dependencyManagement` manages it.
+#[derive(Debug, Clone, Default)]
+struct Dependency {
+    group_id: String,
+    artifact_id: String,
+    version: Option<String>,
+    dependency_type: Option<String>,
+    classifier: Option<String>,
+    scope: Option<String>,
+    optional: bool,
+    system_path: Option<String>,
+    // group and artifact ids, either of which may be `*`
+    exclusions: Vec<(String, String)>,
+}
+
+impl Dependency {
+    fn key(&self) -> (String, String) {
+        return (self.group_id.clone(), self.artifact_id.clone());
+    }
+
+    fn coordinates(&self) -> String {
+        return format!("{}:{}:{}", self.group_id, self.artifact_id, self.version.as_deref().unwrap_or("?"));
+    }
+
+    fn classifier(&self) -> Option<&str> {
+        if self.dependency_type.as_deref() == Some("test-jar") {
+            return Some("tests");
+        }
+        return self.classifier.as_deref();
+    }
+
+    fn excludes(&self, dependency: &Dependency) -> bool {
+        return self.exclusions.iter().any(|(group_id, artifact_id)| {
+            (group_id == "*" || *group_id == dependency.group_id) && (artifact_id == "*" || *artifact_id == dependency.artifact_id)
+        });
+    }
+}
+
+/// A pom with its parents merged in and properties substituted.
+#[derive(Debug, Clone, Default)]
+struct Model {
+    directory: PathBuf,
+    group_id: Option<String>,
+    artifact_id: Option<String>,
+    version: Option<String>,
+    packaging: Option<String>,
+    properties: HashMap<String, String>,
+    // nearest declaration first
+    managed_dependencies: Vec<Dependency>,
+    dependencies: Vec<Dependency>,
+    source_directory: Option<String>,
+    test_source_directory: Option<String>,
+}
+
+impl Model {
+    fn key(&self) -> Option<(String, String)> {
+        return Some((self.group_id.clone()?, self.artifact_id.clone()?));
+    }
+
+    fn managed(&self, dependency: &Dependency) -> Option<&Dependency> {
+        return self
+            .managed_dependencies
+            .iter()
+            .find(|managed| managed.key() == dependency.key() && managed.classifier() == dependency.classifier());
+    }
+
+    /// Fills in what `dependencyManagement` manages for `dependency` and it leaves out.
+    fn manage(&self, mut dependency: Dependency) -> Dependency {
+        if let Some(managed) = self.managed(&dependency) {
+            if dependency.version.is_none() {
+                dependency.version = managed.version.clone();
+            }
+            if dependency.scope.is_none() {
+                dependency.scope = managed.scope.clone();
+            }
+            dependency.exclusions.extend(managed.exclusions.iter().cloned());
+        }
+        return dependency;
+    }
+
+    /// Substitutes `${...}` references to properties and to the project's own coordinates in
+    /// `text`. Unknown references are kept as they are.
+    fn interpolate(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for _ in 0..MAX_DEPTH {
+            let start = match text.find("${") {
+                Some(start) => start,
+                None => break,
+            };
+            let end = match text[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let name = &text[start + 2..end];
+            let value = match name.strip_prefix("project.").or_else(|| name.strip_prefix("pom.")) {
+                Some("groupId") => self.group_id.clone(),
+                Some("artifactId") => self.artifact_id.clone(),
+                Some("version") => self.version.clone(),
+                Some("basedir") => Some(self.directory.to_string_lossy().to_string()),
+                _ if name == "basedir" => Some(self.directory.to_string_lossy().to_string()),
+                _ => match name.strip_prefix("env.") {
+                    Some(variable) => env::var(variable).ok(),
+                    None => self.properties.get(name).cloned(),
+                },
+            };
+            match value {
+                Some(value) => text.replace_range(start..end + 1, &value),
+                // leave it, but look for references after it
+                None => return format!("{}{}", &text[..end + 1], self.interpolate(&text[end + 1..])),
+            }
+        }
+        return text;
+    }
+
+    fn interpolate_dependency(&self, dependency: &mut Dependency) {
+        for field in [&mut dependency.group_id, &mut dependency.artifact_id] {
+            *field = self.interpolate(field);
+        }
+        for field in [
+            &mut dependency.version,
+            &mut dependency.dependency_type,
+            &mut dependency.classifier,
+            &mut dependency.scope,
+            &mut dependency.system_path,
+        ] {
+            if let Some(value) = field.as_mut() {
+                *value = self.interpolate(value);
+            }
+        }
+    }
+}
+
+/// Reads poms from the workspace and from the local repository, remembering the ones it read.
+struct Loader {
+    repository: PathBuf,
+    // poms of the workspace by group and artifact id, so dependencies between them resolve to
+    // their sources
+    reactor: HashMap<(String, String), Model>,
+    repository_models: HashMap<String, Option<Model>>,
+}
+
+impl Loader {
+    /// The model of the pom at `path`, with its parents merged in.
+    fn model(&mut self, path: &Path, depth: usize) -> Option<Model> {
+        if depth > MAX_DEPTH {
+            info!("parents of {:?} nest too deep", path);
+            return None;
+        }
+        let text = fs::read_to_string(path).ok()?;
+        let element = match xml::parse(&text) {
+            Some(element) => element,
+            None => {
+                info!("unable to parse {:?}", path);
+                return None;
+            }
+        };
+        let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let parent = match element.child("parent") {
+            Some(parent) => self.parent_model(&directory, parent, depth),
+            None => None,
+        };
+        let mut model = parent.clone().unwrap_or_default();
+        model.directory = directory;
+        model.artifact_id = element.child_text("artifactId");
+        model.packaging = element.child_text("packaging");
+        if let Some(group_id) = element.child_text("groupId") {
+            model.group_id = Some(group_id);
+        }
+        if let Some(version) = element.child_text("version") {
+            model.version = Some(version);
+        }
+        if let Some(properties) = element.child("properties") {
+            for property in &properties.children {
+                model.properties.insert(property.name.clone(), property.text.trim().to_string());
+            }
+        }
+        if let Some(parent) = &parent {
+            for (name, value) in [("project.parent.groupId", &parent.group_id), ("project.parent.version", &parent.version)] {
+                if let Some(value) = value {
+                    model.properties.insert(name.to_string(), value.clone());
+                }
+            }
+        }
+        let managed = element.descendant(&["dependencyManagement", "dependencies"]);
+        let mut managed_dependencies = dependencies(managed);
+        managed_dependencies.append(&mut model.managed_dependencies);
+        model.managed_dependencies = managed_dependencies;
+        for dependency in dependencies(element.child("dependencies")) {
+            model.dependencies.retain(|inherited| inherited.key() != dependency.key());
+            model.dependencies.push(dependency);
+        }
+        if let Some(build) = element.child("build") {
+            if let Some(source_directory) = build.child_text("sourceDirectory") {
+                model.source_directory = Some(source_directory);
+            }
+            if let Some(test_source_directory) = build.child_text("testSourceDirectory") {
+                model.test_source_directory = Some(test_source_directory);
+            }
+        }
+        return Some(model);
+    }
+
+    /// The model of the parent `parent` refers to: the pom at its relative path, `../pom.xml`
+    /// unless it says otherwise, when that is the one, or else the one in the repository.
+    fn parent_model(&mut self, directory: &Path, parent: &Element, depth: usize) -> Option<Model> {
+        let group_id = parent.child_text("groupId")?;
+        let artifact_id = parent.child_text("artifactId")?;
+        let version = parent.child_text("version")?;
+        let relative_path = match parent.child("relativePath") {
+            Some(relative_path) => relative_path.text.trim().to_string(),
+            None => format!("../{}", POM),
+        };
+        if !relative_path.is_empty() {
+            let mut path = directory.join(&relative_path);
+            if path.is_dir() {
+                path = path.join(POM);
+            }
+            if path.is_file() {
+                let model = self.model(&path, depth + 1);
+                if let Some(model) = model {
+                    if model.artifact_id.as_deref() == Some(artifact_id.as_str()) {
+                        return Some(model);
+                    }
+                }
+            }
+        }
+        return self.repository_model(&group_id, &artifact_id, &version, depth + 1);
+    }
+
+    /// The interpolated model of the pom the local repository has for these coordinates.
+    fn repository_model(&mut self, group_id: &str, artifact_id: &str, version: &str, depth: usize) -> Option<Model> {
+        let coordinates = format!("{}:{}:{}", group_id, artifact_id, version);
+        if let Some(model) = self.repository_models.get(&coordinates) {
+            return model.clone();
+        }
+        // a placeholder, so a cycle ends here
+        self.repository_models.insert(coordinates.clone(), None);
+        let path = self.artifact_path(group_id, artifact_id, version, None, "pom");
+        let model = self.model(&path, depth).map(|model| self.effective(model, depth));
+        self.repository_models.insert(coordinates, model.clone());
+        return model;
+    }
+
+    /// `model` with properties substituted and the dependency management of imported boms added.
+    fn effective(&mut self, mut model: Model, depth: usize) -> Model {
+        let interpolated = model.clone();
+        for dependency in model.managed_dependencies.iter_mut().chain(model.dependencies.iter_mut()) {
+            interpolated.interpolate_dependency(dependency);
+        }
+        for field in [&mut model.source_directory, &mut model.test_source_directory] {
+            if let Some(value) = field.as_mut() {
+                *value = interpolated.interpolate(value);
+            }
+        }
+        let imports: Vec<Dependency> = model
+            .managed_dependencies
+            .iter()
+            .filter(|managed| managed.scope.as_deref() == Some("import") && managed.dependency_type.as_deref() == Some("pom"))
+            .cloned()
+            .collect();
+        for import in imports {
+            let version = match &import.version {
+                Some(version) => version.clone(),
+                None => continue,
+            };
+            if let Some(bom) = self.repository_model(&import.group_id, &import.artifact_id, &version, depth + 1) {
+                model.managed_dependencies.extend(bom.managed_dependencies);
+            }
+        }
+        return model;
+    }
+
+    fn artifact_path(&self, group_id: &str, artifact_id: &str, version: &str, classifier: Option<&str>, extension: &str) -> PathBuf {
+        let file_name = match classifier {
+            Some(classifier) => format!("{}-{}-{}.{}", artifact_id, version, classifier, extension),
+            None => format!("{}-{}.{}", artifact_id, version, extension),
+        };
+        let mut path = self.repository.clone();
+        path.extend(group_id.split('.'));
+        path.push(artifact_id);
+        path.push(version);
+        path.push(file_name);
+        return path;
+    }
+
+    /// The jars `model` compiles and runs its tests against, nearest declaration winning like in
+    /// Maven, and the coordinates of the dependencies whose jars aren't in the repository. The
+    /// other projects of the workspace contribute their dependencies rather than a jar.
+    fn classpath(&mut self, model: &Model) -> (Vec<PathBuf>, Vec<String>) {
+        let mut jars = Vec::new();
+        let mut unresolved = Vec::new();
+        let mut seen: HashSet<(String, String, Option<String>)> = HashSet::new();
+        let mut pending: VecDeque<Dependency> = model.dependencies.iter().map(|dependency| model.manage(dependency.clone())).collect();
+        while let Some(dependency) = pending.pop_front() {
+            if seen.len() >= MAX_DEPENDENCIES {
+                info!("more than {} dependencies for {:?}", MAX_DEPENDENCIES, model.directory);
+                break;
+            }
+            let (group_id, artifact_id) = dependency.key();
+            if !seen.insert((group_id.clone(), artifact_id.clone(), dependency.classifier().map(str::to_string))) {
+                continue;
+            }
+            let dependency_model = match self.reactor.get(&dependency.key()) {
+                Some(reactor_model) => Some(reactor_model.clone()),
+                None => {
+                    if dependency.scope.as_deref() == Some("system") {
+                        match &dependency.system_path {
+                            Some(system_path) if Path::new(system_path).is_file() => jars.push(PathBuf::from(system_path)),
+                            _ => unresolved.push(dependency.coordinates()),
+                        }
+                        continue;
+                    }
+                    let version = match &dependency.version {
+                        Some(version) if !version.contains("${") && !version.starts_with('[') && !version.starts_with('(') => version.clone(),
+                        _ => {
+                            unresolved.push(dependency.coordinates());
+                            continue;
+                        }
+                    };
+                    if dependency.dependency_type.as_deref() != Some("pom") {
+                        let jar = self.artifact_path(&group_id, &artifact_id, &version, dependency.classifier(), "jar");
+                        if jar.is_file() {
+                            jars.push(jar);
+                        } else {
+                            unresolved.push(dependency.coordinates());
+                        }
+                    }
+                    self.repository_model(&group_id, &artifact_id, &version, 0)
+                }
+            };
+            let dependency_model = match dependency_model {
+                Some(dependency_model) => dependency_model,
+                None => continue,
+            };
+            for transitive in &dependency_model.dependencies {
+                let transitive = dependency_model.manage(transitive.clone());
+                let transitive_scope = transitive.scope.as_deref().unwrap_or("compile");
+                if transitive.optional || !matches!(transitive_scope, "compile" | "runtime") || dependency.excludes(&transitive) {
+                    continue;
+                }
+                // the project's dependency management also decides the versions of transitive
+                // dependencies
+                let mut transitive = match model.managed(&transitive) {
+                    Some(managed) => Dependency {
+                        version: managed.version.clone().or(transitive.version),
+                        ..transitive
+                    },
+                    None => transitive,
+                };
+                transitive.exclusions.extend(dependency.exclusions.iter().cloned());
+                pending.push_back(transitive);
+            }
+        }
+        return (jars, unresolved);
+    }
+}
+
+/// The Maven projects under `roots`: every pom there, except those that only aggregate or
+/// parent others.
+pub fn projects(roots: &[PathBuf]) -> Vec<Project> {
+    let mut loader = Loader {
+        repository: local_repository(),
+        reactor: HashMap::new(),
+        repository_models: HashMap::new(),
+    };
+    let mut models = Vec::new();
+    for pom in project::find_files(roots, &[POM]) {
+        let model = match loader.model(&pom, 0) {
+            Some(model) => loader.effective(model, 0),
+            None => continue,
+        };
+        if let Some(key) = model.key() {
+            loader.reactor.insert(key, model.clone());
+        }
+        models.push((pom, model));
+    }
+    let mut projects = Vec::new();
+    for (pom, model) in models {
+        if model.packaging.as_deref() == Some("pom") {
+            continue;
+        }
+        let (classpath, unresolved_dependencies) = loader.classpath(&model);
+        let source_directory = |configured: &Option<String>, default: &str| -> Vec<PathBuf> {
+            return vec![model.directory.join(configured.as_deref().unwrap_or(default))];
+        };
+        projects.push(Project {
+            source_directories: source_directory(&model.source_directory, "src/main/java"),
+            test_source_directories: source_directory(&model.test_source_directory, "src/test/java"),
+            build_file: pom,
+            classpath,
+            unresolved_dependencies,
+        });
+    }
+    return projects;
+}
+
+/// The dependencies listed in a `dependencies` element.
+fn dependencies(element: Option<&Element>) -> Vec<Dependency> {
+    let element = match element {
+        Some(element) => element,
+        None => return Vec::new(),
+    };
+    return element
+        .children("dependency")
+        .filter_map(|dependency| {
+            let group_id = dependency.child_text("groupId")?;
+            let artifact_id = dependency.child_text("artifactId")?;
+            let exclusions = dependency
+                .child("exclusions")
+                .map(|exclusions| {
+                    exclusions
+                        .children("exclusion")
+                        .filter_map(|exclusion| Some((exclusion.child_text("groupId")?, exclusion.child_text("artifactId")?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            return Some(Dependency {
+                group_id,
+                artifact_id,
+                version: dependency.child_text("version"),
+                dependency_type: dependency.child_text("type"),
+                classifier: dependency.child_text("classifier"),
+                scope: dependency.child_text("scope"),
+                optional: dependency.child_text("optional").as_deref() == Some("true"),
+                system_path: dependency.child_text("systemPath"),
+                exclusions,
+            });
+        })
+        .collect();
+}
+
+/// The local repository `~/.m2/settings.xml` configures, or else `~/.m2/repository`.
+pub fn local_repository() -> PathBuf {
+    let home = home_directory();
+    let configured = fs::read_to_string(home.join(".m2").join("settings.xml"))
+        .ok()
+        .and_then(|text| xml::parse(&text))
+        .and_then(|settings| settings.child_text("localRepository"));
+    return match configured {
+        Some(configured) => PathBuf::from(configured.replace("${user.home}", &home.to_string_lossy())),
+        None => home.join(".m2").join("repository"),
+    };
+}
+
+pub fn home_directory() -> PathBuf {
+    return env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from).unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for the files of the test `name`.
+    fn fixture_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("javals-maven-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        return directory;
+    }
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn loader(repository: &Path) -> Loader {
+        return Loader {
+            repository: repository.to_path_buf(),
+            reactor: HashMap::new(),
+            repository_models: HashMap::new(),
+        };
+    }
+
+    /// Puts the pom of `artifact_id` with `dependencies`, and its jar, in the repository.
+    fn install(loader: &Loader, artifact_id: &str, dependencies: &str) {
+        let pom = format!(
+            "<project><groupId>g</groupId><artifactId>{}</artifactId><version>1.0</version><dependencies>{}</dependencies></project>",
+            artifact_id, dependencies
+        );
+        write(&loader.artifact_path("g", artifact_id, "1.0", None, "pom"), &pom);
+        write(&loader.artifact_path("g", artifact_id, "1.0", None, "jar"), "");
+    }
+
+    fn dependency(artifact_id: &str, extra: &str) -> String {
+        return format!("<dependency><groupId>g</groupId><artifactId>{}</artifactId><version>1.0</version>{}</dependency>", artifact_id, extra);
+    }
+
+    #[test]
+    fn interpolates_properties_and_coordinates() {
+        let model = Model {
+            group_id: Some("g".to_string()),
+            properties: HashMap::from([("lib.version".to_string(), "2.0".to_string())]),
+            ..Model::default()
+        };
+        assert_eq!(
+            model.interpolate("${lib.version}-${project.groupId}-${unknown}-${pom.groupId}"),
+            "2.0-g-${unknown}-g"
+        );
+    }
+
+    #[test]
+    fn inherits_from_the_parent_and_its_dependency_management() {
+        let directory = fixture_directory("parent");
+        write(
+            &directory.join(POM),
+            "<project>
+                <groupId>g</groupId><artifactId>parent</artifactId><version>1.0</version><packaging>pom</packaging>
+                <properties><lib.version>2.0</lib.version></properties>
+                <dependencyManagement><dependencies>
+                    <dependency><groupId>g</groupId><artifactId>lib</artifactId><version>${lib.version}</version><scope>provided</scope></dependency>
+                </dependencies></dependencyManagement>
+            </project>",
+        );
+        write(
+            &directory.join("app").join(POM),
+            "<project>
+                <parent><groupId>g</groupId><artifactId>parent</artifactId><version>1.0</version></parent>
+                <artifactId>app</artifactId>
+                <dependencies><dependency><groupId>g</groupId><artifactId>lib</artifactId></dependency></dependencies>
+                <build><sourceDirectory>${project.artifactId}/java</sourceDirectory></build>
+            </project>",
+        );
+        let mut loader = loader(&directory.join("repository"));
+        let model = loader.model(&directory.join("app").join(POM), 0).unwrap();
+        let model = loader.effective(model, 0);
+        assert_eq!(model.key(), Some(("g".to_string(), "app".to_string())));
+        assert_eq!(model.version.as_deref(), Some("1.0"));
+        assert_eq!(model.packaging, None);
+        assert_eq!(model.source_directory.as_deref(), Some("app/java"));
+        let lib = model.manage(model.dependencies[0].clone());
+        assert_eq!(lib.version.as_deref(), Some("2.0"));
+        assert_eq!(lib.scope.as_deref(), Some("provided"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn resolves_transitive_dependencies() {
+        let directory = fixture_directory("transitive");
+        let mut loader = loader(&directory);
+        install(
+            &loader,
+            "a",
+            &[
+                dependency("b", ""),
+                dependency("optional", "<optional>true</optional>"),
+                dependency("tested", "<scope>test</scope>"),
+                dependency("excluded", ""),
+            ]
+            .concat(),
+        );
+        for artifact_id in ["b", "optional", "tested", "excluded"] {
+            install(&loader, artifact_id, "");
+        }
+        let exclusions = "<exclusions><exclusion><groupId>g</groupId><artifactId>excluded</artifactId></exclusion></exclusions>";
+        write(
+            &directory.join("app").join(POM),
+            &format!(
+                "<project><groupId>g</groupId><artifactId>app</artifactId><version>1.0</version><dependencies>{}{}</dependencies></project>",
+                dependency("a", exclusions),
+                dependency("missing", "")
+            ),
+        );
+        let model = loader.model(&directory.join("app").join(POM), 0).unwrap();
+        let (jars, unresolved) = loader.classpath(&model);
+        assert_eq!(
+            jars,
+            vec![
+                loader.artifact_path("g", "a", "1.0", None, "jar"),
+                loader.artifact_path("g", "b", "1.0", None, "jar"),
+            ]
+        );
+        assert_eq!(unresolved, vec!["g:missing:1.0".to_string()]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
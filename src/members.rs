@@ -0,0 +1,484 @@
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::{Node, Parser};
+
+use crate::class_contents;
+use crate::classpath;
+use crate::hierarchy::{self, MethodSignature, TypeDeclaration};
+use crate::line_index::LineIndex;
+use crate::literals;
+use crate::lombok;
+use crate::modifiers::Visibility;
+use crate::resolve::{self, erase};
+use crate::Backend;
+
+/// A type and the supertypes it extends and implements, directly or not.
+pub struct TypeHierarchy {
+    // the types found, the type itself first and the others depth first in declaration order,
+    // each with the URI of the document declaring it
+    pub types: Vec<(String, TypeDeclaration)>,
+    // the names of the supertypes that couldn't be found
+    pub missing: Vec<String>,
+}
+
+/// Primitive types, which name no class to look members up in.
+const PRIMITIVE_TYPES: [&str; 9] = ["boolean", "byte", "char", "short", "int", "long", "float", "double", "void"];
+
+/// `written` with the type variable it names replaced by the matching one of `type_arguments`,
+/// which stand for `type_parameters`. `None` when it names none of them, or isn't given one.
+pub fn substituted_type(written: &str, type_parameters: &[String], type_arguments: &[String]) -> Option<String> {
+    let erased = erase(written);
+    let (base, dimensions) = erased.split_at(erased.find('[').unwrap_or(erased.len()));
+    let index = type_parameters.iter().position(|type_parameter| type_parameter == base)?;
+    return Some(format!("{}{}", type_arguments.get(index)?, dimensions));
+}
+
+impl Backend {
+    /// `type_name` and its supertypes. Supertypes of workspace types are resolved in their
+    /// declaring document.
+    pub fn type_hierarchy(&self, uri: &str, type_name: &str) -> TypeHierarchy {
+        let mut hierarchy = TypeHierarchy {
+            types: Vec::new(),
+            missing: Vec::new(),
+        };
+        let mut pending = vec![(uri.to_string(), type_name.to_string())];
+        while let Some((context_uri, type_name)) = pending.pop() {
+            let (declaring_uri, declaration) = match self.locate_type(&context_uri, &type_name) {
+                Some(found) => found,
+                None => {
+                    hierarchy.missing.push(type_name);
+                    continue;
+                }
+            };
+            let is_visited = hierarchy
+                .types
+                .iter()
+                .any(|(visited_uri, visited)| *visited_uri == declaring_uri && visited.name == declaration.name);
+            if is_visited {
+                continue;
+            }
+            let supertype_context = self.type_context(uri, &declaring_uri);
+            pending.extend(declaration.supertypes.iter().rev().map(|supertype| (supertype_context.clone(), supertype.clone())));
+            hierarchy.types.push((declaring_uri, declaration));
+        }
+        return hierarchy;
+    }
+
+    /// The type that declares the field or method `member_name` of `type_name`, itself or one of
+    /// its supertypes, with the URI of the document declaring it.
+    pub fn member_declaration(&self, uri: &str, type_name: &str, member_name: &str, is_method: bool) -> Option<(String, TypeDeclaration)> {
+        return self
+            .type_hierarchy(uri, type_name)
+            .types
+            .into_iter()
+            .enumerate()
+            .find(|(index, (_, declaration))| match is_method {
+                true => declaration
+                    .methods
+                    .iter()
+                    .any(|method| method.name == member_name && (*index == 0 || declaration.is_inherited(method))),
+                false => declaration.fields.iter().any(|field| field.name == member_name),
+            })
+            .map(|(_, found)| found);
+    }
+
+    /// The type arguments `type_name` passes on to its supertype `supertype_name`, given it has
+    /// `type_arguments`, found through the supertypes each type writes out. Empty when it isn't
+    /// a supertype.
+    pub fn supertype_arguments(&self, uri: &str, type_name: &str, type_arguments: &[String], supertype_name: &str) -> Vec<String> {
+        let mut visited: Vec<(String, String)> = Vec::new();
+        let mut pending = vec![(uri.to_string(), type_name.to_string(), type_arguments.to_vec())];
+        while let Some((context_uri, type_name, type_arguments)) = pending.pop() {
+            let (declaring_uri, declaration) = match self.locate_type(&context_uri, &type_name) {
+                Some(found) => found,
+                None => continue,
+            };
+            if declaration.name == erase(supertype_name) {
+                return type_arguments;
+            }
+            let key = (declaring_uri.clone(), declaration.name.clone());
+            if visited.contains(&key) {
+                continue;
+            }
+            visited.push(key);
+            let written_supertypes = self
+                .read_type_declaration(&declaring_uri, &declaration.name, |declaration_node, text| {
+                    let supertype_nodes = hierarchy::supertype_nodes(declaration_node);
+                    return Some(supertype_nodes.iter().map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string()).collect::<Vec<String>>());
+                })
+                .unwrap_or_default();
+            if written_supertypes.len() != declaration.supertypes.len() {
+                continue;
+            }
+            let supertype_context = self.type_context(uri, &declaring_uri);
+            // the indexed names are the ones to look supertypes up by, being qualified for dependencies
+            for (written, supertype) in written_supertypes.iter().zip(&declaration.supertypes).rev() {
+                let supertype_arguments = resolve::written_type_arguments(written)
+                    .iter()
+                    .map(|type_argument| match substituted_type(type_argument, &declaration.type_parameters, &type_arguments) {
+                        Some(substituted) => substituted,
+                        None => self.qualify_type(&supertype_context, type_argument),
+                    })
+                    .collect();
+                pending.push((supertype_context.clone(), supertype.clone(), supertype_arguments));
+            }
+        }
+        return Vec::new();
+    }
+
+    /// The type of the field `field_name` of `type_name`, qualified where the document declaring
+    /// the field resolves it. A field typed by a type parameter of `type_name` itself has the
+    /// type of the matching one of `type_arguments`.
+    pub fn field_type(&self, uri: &str, type_name: &str, type_arguments: &[String], field_name: &str) -> Option<String> {
+        let (declaring_uri, declaration) = self.member_declaration(uri, type_name, field_name, false)?;
+        let written = &declaration.fields.iter().find(|field| field.name == field_name)?.field_type;
+        if declaration.name == erase(type_name) {
+            if let Some(substituted) = substituted_type(written, &declaration.type_parameters, type_arguments) {
+                return Some(substituted);
+            }
+        }
+        return Some(self.qualify_type(&self.type_context(uri, &declaring_uri), written));
+    }
+
+    /// The erased type `written` in the document at `context_uri` stands for, fully qualified when
+    /// it names a class found from there. Primitive and array types are only erased.
+    pub fn qualify_type(&self, context_uri: &str, written: &str) -> String {
+        let erased = erase(written);
+        if erased.is_empty() || erased.ends_with(']') || PRIMITIVE_TYPES.contains(&erased.as_str()) {
+            return erased;
+        }
+        // a qualified name needs no import to resolve
+        let written_name = written.split('<').next().unwrap_or(written).trim();
+        if written_name.contains('.') {
+            if let Some(class) = self.resolve_class(context_uri, written_name) {
+                return class.qualified_name();
+            }
+        }
+        return match self.resolve_class(context_uri, &erased) {
+            Some(class) => class.qualified_name(),
+            None => erased,
+        };
+    }
+
+    /// The document names written in `declaring_uri` are resolved in: the declaring document when
+    /// it's in the workspace. Dependencies aren't parsed, so their names are looked up from `uri`.
+    pub fn type_context(&self, uri: &str, declaring_uri: &str) -> String {
+        return match declaring_uri.starts_with("jar:") {
+            true => uri.to_string(),
+            false => declaring_uri.to_string(),
+        };
+    }
+
+    /// The declaration of the field `name_node` names after a receiver, as in
+    /// `person.address.city` or `this.name`, found through the type of the receiver and its
+    /// supertypes.
+    pub fn member_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let parent_node = name_node.parent()?;
+        if parent_node.kind() != "field_access" || parent_node.child_by_field_name("field") != Some(name_node) {
+            return None;
+        }
+        let object_node = parent_node.child_by_field_name("object")?;
+        let field_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        // `this` is bound by the innermost class around it, anonymous ones included, whose own
+        // fields come before those it inherits
+        let this_class_node = match object_node.kind() {
+            "this" => resolve::enclosing_class_node(object_node),
+            _ => resolve::qualified_this_node(object_node, source),
+        };
+        if let Some(class_node) = this_class_node {
+            let body_node = match class_node.kind() {
+                "class_body" => Some(class_node),
+                _ => class_node.child_by_field_name("body"),
+            };
+            if let Some(field_node) = body_node.and_then(|body_node| class_contents::member_name_node(body_node, field_name, source)) {
+                return Some(Location {
+                    uri: Url::parse(uri).ok()?,
+                    range: LineIndex::new(source).range(field_node.start_position(), field_node.end_position()),
+                });
+            }
+        }
+        let receiver_type = self.expression_type(uri, object_node, source)?;
+        let (declaring_uri, declaration) = self.member_declaration(uri, &receiver_type, field_name, false)?;
+        return self.type_member_location(&declaring_uri, &declaration.name, field_name, None);
+    }
+
+    /// The enum constant a `case` label names, found through the type of the switch's selector.
+    pub fn case_constant_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        if name_node.kind() != "identifier" {
+            return None;
+        }
+        // the grammar takes `case RED -> {}` for a lambda, and some labels for errors
+        let mut label_node = name_node.parent()?;
+        while label_node.kind() == "lambda_expression" || label_node.kind() == "ERROR" {
+            if label_node.kind() == "lambda_expression" && label_node.child_by_field_name("parameters") != Some(name_node) {
+                return None;
+            }
+            label_node = label_node.parent()?;
+        }
+        if label_node.kind() != "switch_label" {
+            return None;
+        }
+        let switch_node = label_node
+            .parent() // switch_rule or switch_block_statement_group
+            .and_then(|n| n.parent()) // switch_block
+            .and_then(|n| n.parent())?;
+        let selector_node = switch_node.child_by_field_name("condition")?.named_child(0)?;
+        let selector_type = self.expression_type(uri, selector_node, source)?;
+        let (declaring_uri, declaration) = self.locate_type(uri, &selector_type)?;
+        let constant_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        if declaration.kind != "enum_declaration" || !declaration.fields.iter().any(|field| field.name == constant_name) {
+            return None;
+        }
+        return self.type_member_location(&declaring_uri, &declaration.name, constant_name, None);
+    }
+
+    /// The type declaring the field an unqualified name refers to, with the URI of its document,
+    /// when the class around the name, or one enclosing that, inherits the field.
+    pub fn inherited_field(&self, uri: &str, name_node: Node, source: &str) -> Option<(String, TypeDeclaration)> {
+        let parent_node = name_node.parent()?;
+        let is_member_name = match parent_node.kind() {
+            "field_access" => parent_node.child_by_field_name("field") == Some(name_node),
+            "method_invocation" => parent_node.child_by_field_name("name") == Some(name_node),
+            _ => false,
+        };
+        if name_node.kind() != "identifier" || is_member_name {
+            return None;
+        }
+        let field_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        let mut class_node = resolve::enclosing_class_node(name_node);
+        while let Some(current_node) = class_node {
+            if let Some(class_name) = resolve::class_name(current_node, source) {
+                if let Some(found) = self.member_declaration(uri, &class_name, field_name, false) {
+                    return Some(found);
+                }
+            }
+            class_node = resolve::enclosing_class_node(current_node);
+        }
+        return None;
+    }
+
+    /// The declaration of the field an unqualified name refers to when it's inherited.
+    pub fn inherited_field_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let (declaring_uri, declaration) = self.inherited_field(uri, name_node, source)?;
+        let field_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        return self.type_member_location(&declaring_uri, &declaration.name, field_name, None);
+    }
+
+    /// Where the type named `type_name` in the document at `declaring_uri`, a workspace file or
+    /// an entry of a jar, declares `member_name`, the overload taking `parameter_types` when
+    /// given and found. Accessors Lombok generates are taken to be declared by their field, and
+    /// other members that aren't written out, like the `values()` of an enum, by the type's name.
+    pub fn type_member_location(
+        &self,
+        declaring_uri: &str,
+        type_name: &str,
+        member_name: &str,
+        parameter_types: Option<&[String]>,
+    ) -> Option<Location> {
+        return self.type_node_location(declaring_uri, type_name, |declaration_node, text| {
+            let body_node = declaration_node.child_by_field_name("body")?;
+            let overload_node = parameter_types.and_then(|parameter_types| {
+                hierarchy::member_method_declarations(body_node).into_iter().find(|method_node| {
+                    let signature = hierarchy::method_signature(*method_node, text);
+                    signature.name == member_name && signature.parameter_types == parameter_types
+                })
+            });
+            return match overload_node.and_then(|method_node| method_node.child_by_field_name("name")) {
+                Some(name_node) => Some(name_node),
+                None => class_contents::member_name_node(body_node, member_name, text)
+                    .or_else(|| lombok::accessed_field(body_node, member_name, text))
+                    .or_else(|| declaration_node.child_by_field_name("name")),
+            };
+        });
+    }
+
+    /// Where the type named `type_name` in the document at `declaring_uri` declares the
+    /// constructor taking `parameter_types`. Constructors that aren't written out, like the
+    /// canonical one of a record, are taken to be declared by the type's name.
+    pub fn constructor_location(&self, declaring_uri: &str, type_name: &str, parameter_types: &[String]) -> Option<Location> {
+        return self.type_node_location(declaring_uri, type_name, |declaration_node, text| {
+            let constructor_nodes = match declaration_node.child_by_field_name("body") {
+                Some(body_node) => hierarchy::member_constructor_declarations(body_node),
+                None => Vec::new(),
+            };
+            let constructor_node = constructor_nodes
+                .iter()
+                .find(|constructor_node| hierarchy::method_signature(**constructor_node, text).parameter_types == parameter_types)
+                .or_else(|| constructor_nodes.iter().find(|constructor_node| constructor_node.kind() == "compact_constructor_declaration"));
+            return match constructor_node {
+                Some(constructor_node) => constructor_node.child_by_field_name("name"),
+                None => declaration_node.child_by_field_name("name"),
+            };
+        });
+    }
+
+    /// Where the type named `type_name` in the document at `declaring_uri` is declared.
+    pub fn type_location(&self, declaring_uri: &str, type_name: &str) -> Option<Location> {
+        return self.type_node_location(declaring_uri, type_name, |declaration_node, _| declaration_node.child_by_field_name("name"));
+    }
+
+    /// The types of the parameters of `method`, a method or constructor of the type named
+    /// `type_name` in the document at `declaring_uri`, as written rather than erased.
+    pub fn written_parameter_types(&self, declaring_uri: &str, type_name: &str, method: &MethodSignature) -> Option<Vec<String>> {
+        return self.read_type_declaration(declaring_uri, type_name, |declaration_node, text| {
+            let body_node = declaration_node.child_by_field_name("body")?;
+            let mut method_nodes = hierarchy::member_method_declarations(body_node);
+            method_nodes.extend(hierarchy::member_constructor_declarations(body_node));
+            let method_node = method_nodes.into_iter().find(|method_node| {
+                let signature = hierarchy::method_signature(*method_node, text);
+                signature.name == method.name && signature.parameter_types == method.parameter_types
+            })?;
+            let parameters_node = method_node.child_by_field_name("parameters")?;
+            let parameter_types = parameters_node
+                .named_children(&mut parameters_node.walk())
+                .filter_map(|parameter_node| match parameter_node.kind() {
+                    "formal_parameter" => parameter_node.child_by_field_name("type"),
+                    "spread_parameter" => parameter_node
+                        .named_children(&mut parameter_node.walk())
+                        .find(|n| n.kind() != "modifiers" && n.kind() != "variable_declarator"),
+                    _ => None,
+                })
+                .map(|type_node| type_node.utf8_text(text.as_bytes()).unwrap().to_string())
+                .collect();
+            return Some(parameter_types);
+        });
+    }
+
+    /// The Javadoc of the member `member_name` of the type named `type_name` in the document at
+    /// `declaring_uri`, the overload taking `parameter_types` when given, without the comment's
+    /// delimiters and the asterisks starting its lines.
+    pub fn member_javadoc(&self, declaring_uri: &str, type_name: &str, member_name: &str, parameter_types: Option<&[String]>) -> Option<String> {
+        return self.read_type_declaration(declaring_uri, type_name, |declaration_node, text| {
+            let body_node = declaration_node.child_by_field_name("body")?;
+            let overload_node = parameter_types.and_then(|parameter_types| {
+                hierarchy::member_method_declarations(body_node).into_iter().find(|method_node| {
+                    let signature = hierarchy::method_signature(*method_node, text);
+                    signature.name == member_name && signature.parameter_types == parameter_types
+                })
+            });
+            let member_node = match overload_node {
+                Some(method_node) => method_node,
+                None => {
+                    let name_node = class_contents::member_name_node(body_node, member_name, text)?;
+                    // the declarator of a field is under a declaration it may share with others
+                    match name_node.parent()? {
+                        declarator_node if declarator_node.kind() == "variable_declarator" => declarator_node.parent()?,
+                        member_node => member_node,
+                    }
+                }
+            };
+            let comment_node = member_node.prev_named_sibling().filter(|n| n.kind() == "block_comment")?;
+            let comment = comment_node.utf8_text(text.as_bytes()).unwrap();
+            return javadoc_text(comment);
+        });
+    }
+
+    /// The defaults of the elements of the annotation type named `type_name` in the document at
+    /// `declaring_uri`, by element name, as written.
+    pub fn element_defaults(&self, declaring_uri: &str, type_name: &str) -> Vec<(String, String)> {
+        return self
+            .read_type_declaration(declaring_uri, type_name, |declaration_node, text| {
+                let body_node = declaration_node.child_by_field_name("body")?;
+                let defaults = body_node
+                    .named_children(&mut body_node.walk())
+                    .filter(|n| n.kind() == "annotation_type_element_declaration")
+                    .filter_map(|element_node| {
+                        let name = element_node.child_by_field_name("name")?.utf8_text(text.as_bytes()).unwrap();
+                        let value = element_node.child_by_field_name("value")?.utf8_text(text.as_bytes()).unwrap();
+                        return Some((name.to_string(), value.to_string()));
+                    })
+                    .collect::<Vec<(String, String)>>();
+                return Some(defaults);
+            })
+            .unwrap_or_default();
+    }
+
+    /// The single abstract method of the functional interface `type_name`, with the interface
+    /// declaring it and the URI of its document. `None` for classes, and for interfaces with
+    /// more or fewer abstract methods than one.
+    pub fn functional_method(&self, uri: &str, type_name: &str) -> Option<(String, TypeDeclaration, MethodSignature)> {
+        let hierarchy = self.type_hierarchy(uri, type_name);
+        if hierarchy.types.first()?.1.kind != "interface_declaration" {
+            return None;
+        }
+        let mut abstract_methods: Vec<(String, TypeDeclaration, MethodSignature)> = Vec::new();
+        for (declaring_uri, declaration) in &hierarchy.types {
+            for method in &declaration.methods {
+                let modifiers = &method.modifiers;
+                // interfaces may redeclare the public methods of `Object`, which classes implement
+                let is_abstract = !modifiers.is_static
+                    && !modifiers.is_default
+                    && modifiers.visibility != Visibility::Private
+                    && !resolve::OBJECT_METHOD_NAMES.contains(&method.name.as_str());
+                let is_overridden = abstract_methods
+                    .iter()
+                    .any(|(_, _, found)| found.name == method.name && found.parameter_types == method.parameter_types);
+                if is_abstract && !is_overridden {
+                    abstract_methods.push((declaring_uri.clone(), declaration.clone(), method.clone()));
+                }
+            }
+        }
+        if abstract_methods.len() != 1 {
+            return None;
+        }
+        return abstract_methods.pop();
+    }
+
+    /// The location of the node `find` picks from the declaration of the type named `type_name`
+    /// in the document at `declaring_uri`, a workspace file or an entry of a jar.
+    fn type_node_location<F>(&self, declaring_uri: &str, type_name: &str, find: F) -> Option<Location>
+    where
+        F: for<'t> Fn(Node<'t>, &str) -> Option<Node<'t>>,
+    {
+        let range = self.read_type_declaration(declaring_uri, type_name, |declaration_node, text| {
+            let node = find(declaration_node, text)?;
+            return Some(LineIndex::new(text).range(node.start_position(), node.end_position()));
+        })?;
+        return Some(Location {
+            uri: Url::parse(declaring_uri).ok()?,
+            range,
+        });
+    }
+
+    /// What `read` takes from the declaration of the type named `type_name` in the document at
+    /// `declaring_uri`, a workspace file or an entry of a jar, parsed again for the purpose.
+    fn read_type_declaration<T, F>(&self, declaring_uri: &str, type_name: &str, read: F) -> Option<T>
+    where
+        F: for<'t> Fn(Node<'t>, &str) -> Option<T>,
+    {
+        let text = match class_contents::split_jar_uri(declaring_uri) {
+            Some((jar_path, entry_name)) => class_contents::entry_contents(&jar_path, &entry_name)?,
+            None => self.document_source(declaring_uri)?.1,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).ok()?;
+        let tree = parser.parse(literals::parseable_text(&text).as_ref(), None)?;
+        let suffix = format!(".{}", type_name);
+        let declared_name_node = classpath::declared_class_names(&tree, &text)
+            .into_iter()
+            .find(|(declared_name, _)| declared_name == type_name || declared_name.ends_with(&suffix))
+            .map(|(_, type_name_node)| type_name_node);
+        // local classes have no name to address them by from elsewhere
+        let type_name_node = declared_name_node.or_else(|| {
+            tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre)
+                .filter(|node| hierarchy::is_type_declaration(node.kind()))
+                .filter_map(|node| node.child_by_field_name("name"))
+                .find(|name_node| name_node.utf8_text(text.as_bytes()).unwrap() == type_name)
+        })?;
+        return read(type_name_node.parent()?, &text);
+    }
+}
+
+/// The text of a Javadoc `comment`. `None` for other block comments.
+fn javadoc_text(comment: &str) -> Option<String> {
+    let content = comment.strip_prefix("/**")?.strip_suffix("*/")?;
+    let lines: Vec<&str> = content
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            return line.strip_prefix(' ').unwrap_or(line).trim_end();
+        })
+        .collect();
+    let text = lines.join("\n").trim().to_string();
+    return if text.is_empty() { None } else { Some(text) };
+}
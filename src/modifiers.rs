@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use crate::diagnostics::has_modifier;
+
+/// Java access levels, from the most to the least permissive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Visibility {
+    Public,
+    Protected,
+    Package,
+    Private,
+}
+
+impl Visibility {
+    pub fn keyword(&self) -> Option<&'static str> {
+        return match self {
+            Visibility::Public => Some("public"),
+            Visibility::Protected => Some("protected"),
+            Visibility::Package => None,
+            Visibility::Private => Some("private"),
+        };
+    }
+}
+
+/// The modifiers of a declaration, including the ones Java implies without them being written.
+/// Parameters and local variables have no access level and count as package-private.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub visibility: Visibility,
+    pub is_static: bool,
+    pub is_final: bool,
+    pub is_abstract: bool,
+    pub is_synchronized: bool,
+    pub is_default: bool,
+    pub is_sealed: bool,
+    pub is_non_sealed: bool,
+}
+
+impl Default for Modifiers {
+    fn default() -> Modifiers {
+        return Modifiers {
+            visibility: Visibility::Package,
+            is_static: false,
+            is_final: false,
+            is_abstract: false,
+            is_synchronized: false,
+            is_default: false,
+            is_sealed: false,
+            is_non_sealed: false,
+        };
+    }
+}
+
+impl Modifiers {
+    /// The modifiers of a type, method, constructor, field, enum constant, parameter, record
+    /// component or local variable declaration. Members of interfaces and annotation types are
+    /// public, their fields static and final and their methods without a body abstract. Enum
+    /// constants are public, static and final, record components private and final, records and
+    /// enums final, and nested enums, records and interfaces static.
+    pub fn of(declaration_node: Node) -> Modifiers {
+        let written = |modifier: &str| has_modifier(declaration_node, modifier);
+        let mut modifiers = Modifiers {
+            visibility: if written("public") {
+                Visibility::Public
+            } else if written("protected") {
+                Visibility::Protected
+            } else if written("private") {
+                Visibility::Private
+            } else {
+                Visibility::Package
+            },
+            is_static: written("static"),
+            is_final: written("final"),
+            is_abstract: written("abstract"),
+            is_synchronized: written("synchronized"),
+            is_default: written("default"),
+            is_sealed: written("sealed"),
+            is_non_sealed: written("non-sealed"),
+        };
+        let kind = declaration_node.kind();
+        let body_kind = declaration_node.parent().map(|n| n.kind()).unwrap_or("");
+        let in_interface = body_kind == "interface_body" || body_kind == "annotation_type_body";
+        if in_interface && modifiers.visibility == Visibility::Package {
+            modifiers.visibility = Visibility::Public;
+        }
+        match kind {
+            "constant_declaration" | "field_declaration" if in_interface => {
+                modifiers.is_static = true;
+                modifiers.is_final = true;
+            }
+            "method_declaration" | "annotation_type_element_declaration" if in_interface => {
+                let has_body = declaration_node.child_by_field_name("body").is_some();
+                modifiers.is_abstract |= !has_body && !modifiers.is_static && modifiers.visibility != Visibility::Private;
+            }
+            "enum_constant" => {
+                modifiers.visibility = Visibility::Public;
+                modifiers.is_static = true;
+                modifiers.is_final = true;
+            }
+            "formal_parameter" if is_record_component(declaration_node) => {
+                modifiers.visibility = Visibility::Private;
+                modifiers.is_final = true;
+            }
+            "interface_declaration" | "annotation_type_declaration" => {
+                modifiers.is_abstract = true;
+                modifiers.is_static |= is_nested(declaration_node);
+            }
+            "enum_declaration" | "record_declaration" => {
+                modifiers.is_final = true;
+                modifiers.is_static |= is_nested(declaration_node);
+            }
+            "class_declaration" if in_interface => modifiers.is_static = true,
+            _ => {}
+        };
+        return modifiers;
+    }
+
+    /// The modifier keywords in their customary order, the implied ones included.
+    pub fn keywords(&self) -> Vec<&'static str> {
+        let mut keywords: Vec<&'static str> = self.visibility.keyword().into_iter().collect();
+        for (present, keyword) in [
+            (self.is_abstract, "abstract"),
+            (self.is_default, "default"),
+            (self.is_static, "static"),
+            (self.is_final, "final"),
+            (self.is_sealed, "sealed"),
+            (self.is_non_sealed, "non-sealed"),
+            (self.is_synchronized, "synchronized"),
+        ] {
+            if present {
+                keywords.push(keyword);
+            }
+        }
+        return keywords;
+    }
+}
+
+fn is_record_component(parameter_node: Node) -> bool {
+    let declaration_kind = parameter_node.parent().and_then(|n| n.parent()).map(|n| n.kind());
+    return declaration_kind == Some("record_declaration");
+}
+
+/// Whether the type is declared inside another type or a block, rather than at the top level.
+fn is_nested(declaration_node: Node) -> bool {
+    return declaration_node.parent().map(|n| n.kind()) != Some("program");
+}
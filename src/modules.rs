@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::{Node, Parser, Tree};
+
+use crate::class_contents;
+use crate::dependency_index::DependencyClass;
+use crate::line_index::LineIndex;
+use crate::literals;
+use crate::Backend;
+
+/// The file a module is declared in, at the root of its sources.
+pub const MODULE_INFO: &str = "module-info.java";
+
+/// The module every module reads without requiring it.
+const BASE_MODULE: &str = "java.base";
+
+/// A `requires` directive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleRequirement {
+    pub module: String,
+    // whether modules reading this one read the required one as well
+    pub is_transitive: bool,
+}
+
+/// An `exports` directive, to every module or only to `modules`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleExport {
+    pub package: String,
+    pub modules: Vec<String>,
+}
+
+/// What a `module-info.java` declares about the packages a module shares and the modules it
+/// reads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleDeclaration {
+    pub name: String,
+    pub requires: Vec<ModuleRequirement>,
+    pub exports: Vec<ModuleExport>,
+}
+
+impl ModuleDeclaration {
+    /// Whether the module lets the module `reader` use the public types of `package`.
+    pub fn exports_to(&self, package: &str, reader: &str) -> bool {
+        return self
+            .exports
+            .iter()
+            .any(|export| export.package == package && (export.modules.is_empty() || export.modules.iter().any(|module| module == reader)));
+    }
+}
+
+/// The module declaration of a compilation unit, when it's a `module-info.java`.
+pub fn module_declaration(tree: &Tree, source: &str) -> Option<ModuleDeclaration> {
+    let declaration_node = declaration_node(tree)?;
+    let text = |node: Node| node.utf8_text(source.as_bytes()).unwrap().to_string();
+    let mut declaration = ModuleDeclaration {
+        name: text(declaration_node.child_by_field_name("name")?),
+        requires: Vec::new(),
+        exports: Vec::new(),
+    };
+    let body_node = declaration_node.child_by_field_name("body")?;
+    for directive_node in body_node.named_children(&mut body_node.walk()) {
+        match directive_node.kind() {
+            "requires_module_directive" => {
+                let module_node = match directive_node.child_by_field_name("module") {
+                    Some(module_node) => module_node,
+                    None => continue,
+                };
+                let is_transitive = directive_node
+                    .children_by_field_name("modifiers", &mut directive_node.walk())
+                    .any(|modifier_node| text(modifier_node) == "transitive");
+                declaration.requires.push(ModuleRequirement {
+                    module: text(module_node),
+                    is_transitive,
+                });
+            }
+            "exports_module_directive" => {
+                let package_node = match directive_node.child_by_field_name("package") {
+                    Some(package_node) => package_node,
+                    None => continue,
+                };
+                declaration.exports.push(ModuleExport {
+                    package: text(package_node),
+                    modules: directive_node
+                        .children_by_field_name("modules", &mut directive_node.walk())
+                        .map(text)
+                        .collect(),
+                });
+            }
+            _ => {}
+        }
+    }
+    return Some(declaration);
+}
+
+fn declaration_node(tree: &Tree) -> Option<Node<'_>> {
+    let root = tree.root_node();
+    return root.named_children(&mut root.walk()).find(|node| node.kind() == "module_declaration");
+}
+
+impl Backend {
+    /// The declaration of the module the document at `uri` belongs to: the `module-info.java`
+    /// nearest above it. `None` outside modular sources.
+    pub fn module_of(&self, uri: &str) -> Option<ModuleDeclaration> {
+        let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+        let mut nearest: Option<(PathBuf, ModuleDeclaration)> = None;
+        for entry in self.module_declarations.iter() {
+            let directory = match Url::parse(entry.key()).ok().and_then(|module_uri| module_uri.to_file_path().ok()) {
+                Some(module_path) => module_path.parent().map(PathBuf::from).unwrap_or_default(),
+                None => continue,
+            };
+            let is_nearer = nearest.as_ref().map(|(nearest_directory, _)| directory.starts_with(nearest_directory)).unwrap_or(true);
+            if path.starts_with(&directory) && is_nearer {
+                nearest = Some((directory, entry.value().clone()));
+            }
+        }
+        return nearest.map(|(_, declaration)| declaration);
+    }
+
+    /// The declaration of the module named `name`, in the workspace or the JDK.
+    fn module_named(&self, name: &str) -> Option<ModuleDeclaration> {
+        if let Some(entry) = self.module_declarations.iter().find(|entry| entry.value().name == name) {
+            return Some(entry.value().clone());
+        }
+        return self.jdk_modules.get(name).map(|declaration| declaration.clone());
+    }
+
+    /// Whether `reader` reads the module `module`: requires it, or requires a module requiring it
+    /// transitively.
+    fn reads(&self, reader: &ModuleDeclaration, module: &str) -> bool {
+        if module == reader.name || module == BASE_MODULE {
+            return true;
+        }
+        let mut pending: Vec<String> = reader.requires.iter().map(|requirement| requirement.module.clone()).collect();
+        let mut visited: Vec<String> = Vec::new();
+        while let Some(required) = pending.pop() {
+            if required == module {
+                return true;
+            }
+            if visited.contains(&required) {
+                continue;
+            }
+            if let Some(declaration) = self.module_named(&required) {
+                pending.extend(
+                    declaration
+                        .requires
+                        .iter()
+                        .filter(|requirement| requirement.is_transitive)
+                        .map(|requirement| requirement.module.clone()),
+                );
+            }
+            visited.push(required);
+        }
+        return false;
+    }
+
+    /// Whether code in the document at `uri` may use the public types of `package` in `module`
+    /// as far as modules go: the document's module has to read `module`, and `module` export the
+    /// package to it. Code outside modules, and code in the unnamed module, is not restricted.
+    pub fn can_access_package(&self, uri: &str, module: Option<&str>, package: &str) -> bool {
+        let (reader, module) = match (self.module_of(uri), module) {
+            (Some(reader), Some(module)) => (reader, module),
+            _ => return true,
+        };
+        if module == reader.name {
+            return true;
+        }
+        if !self.reads(&reader, module) {
+            return false;
+        }
+        return self.module_named(module).map(|declaration| declaration.exports_to(package, &reader.name)).unwrap_or(true);
+    }
+
+    /// Whether the document at `uri` may use the types declared in the document at `target_uri`.
+    pub fn can_access_document(&self, uri: &str, target_uri: &str) -> bool {
+        let module = match self.module_of(target_uri) {
+            Some(module) => module,
+            None => return true,
+        };
+        let package = self.package_of(target_uri).unwrap_or_default();
+        return self.can_access_package(uri, Some(&module.name), &package);
+    }
+
+    /// Whether the document at `uri` may use a dependency class. Only the JDK's classes are known
+    /// to be in modules; jars count as automatic modules everything reads.
+    pub fn can_access_class(&self, uri: &str, class: &DependencyClass) -> bool {
+        let module = match class.source_entry.as_deref().and_then(|source_entry| source_entry.split_once('/')) {
+            Some((module, _)) if self.jdk_modules.contains_key(module) => module,
+            _ => return true,
+        };
+        let package = class.binary_name.rsplit_once('/').map(|(package, _)| package.replace('/', ".")).unwrap_or_default();
+        return self.can_access_package(uri, Some(module), &package);
+    }
+
+    /// The declaration of the module a `requires` directive names, when `node` is in one.
+    pub fn module_definition(&self, node: Node, source: &str) -> Option<Location> {
+        let mut directive_node = node;
+        while directive_node.kind() != "requires_module_directive" {
+            directive_node = directive_node.parent()?;
+        }
+        let name = directive_node.child_by_field_name("module")?.utf8_text(source.as_bytes()).unwrap();
+        let workspace_uri = self
+            .module_declarations
+            .iter()
+            .find(|entry| entry.value().name == name)
+            .map(|entry| entry.key().clone());
+        let (uri, text) = match workspace_uri {
+            Some(uri) => {
+                let (_, text) = self.document_source(&uri)?;
+                (uri, text)
+            }
+            None if self.jdk_modules.contains_key(name) => {
+                let sources = self.jdk_sources()?;
+                let entry_name = format!("{}/{}", name, MODULE_INFO);
+                let text = class_contents::entry_contents(&sources, &entry_name)?;
+                (class_contents::jar_uri(&sources, &entry_name), text)
+            }
+            None => return None,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).ok()?;
+        let tree = parser.parse(literals::parseable_text(&text).as_ref(), None)?;
+        let name_node = declaration_node(&tree)?.child_by_field_name("name")?;
+        return Some(Location {
+            uri: Url::parse(&uri).ok()?,
+            range: LineIndex::new(&text).range(name_node.start_position(), name_node.end_position()),
+        });
+    }
+}
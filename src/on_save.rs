@@ -0,0 +1,91 @@
+use std::sync::PoisonError;
+
+use log::debug;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Parser, Tree};
+
+use crate::format::{self, FormatOptions};
+use crate::line_index::LineIndex;
+use crate::{external_format, literals, Backend};
+
+/// The edits `textDocument/willSaveWaitUntil` makes, each enabled separately in the `onSave`
+/// settings. All are off by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OnSaveActions {
+    pub organize_imports: bool,
+    pub format_document: bool,
+    pub trim_trailing_whitespace: bool,
+}
+
+impl Backend {
+    /// Handles `textDocument/willSaveWaitUntil`. The enabled actions run one after the other on
+    /// the text the previous one left, and the client gets the line edits from the document as
+    /// it is to the end result. Formatting uses the default options overridden by EditorConfig,
+    /// since the request carries none.
+    pub async fn will_save_edits(&self, params: WillSaveTextDocumentParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        debug!("will_save_wait_until {} {:?}", uri, params.reason);
+        self.pending_changes.settled().await;
+        let actions = self.config.read().unwrap_or_else(PoisonError::into_inner).on_save_actions;
+        let (source_text, version) = match self.document_map.get(uri.as_str()) {
+            Some(source_text) => (source_text.clone(), self.document_version(uri.as_str())),
+            None => return Ok(None),
+        };
+        let mut text = source_text.clone();
+        if actions.organize_imports {
+            if let Some(edit) = parse(&text).and_then(|tree| format::organize_imports_edit(&tree, &text)) {
+                text = apply_edits(&text, &[edit]);
+            }
+        }
+        if actions.format_document {
+            let mut options = FormatOptions::default();
+            self.editor_config(&uri).apply(&mut options);
+            if let Some(tree) = parse(&text) {
+                if let Some(edits) = self.document_format_edits(&tree, &text, &options).await {
+                    text = apply_edits(&text, &edits);
+                }
+            }
+        }
+        if actions.trim_trailing_whitespace {
+            text = trim_trailing_whitespace(&text);
+        }
+        self.ensure_version(uri.as_str(), version)?;
+        if text == source_text {
+            return Ok(None);
+        }
+        return Ok(Some(external_format::line_edits(&source_text, &text)));
+    }
+}
+
+fn parse(text: &str) -> Option<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_java::language()).ok()?;
+    return parser.parse(literals::parseable_text(text).as_ref(), None);
+}
+
+/// Applies edits to `text`. Edits must not overlap; those starting at the same place apply in
+/// order.
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let lines = LineIndex::new(text);
+    let mut ranges: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| (lines.offset(edit.range.start), lines.offset(edit.range.end), edit.new_text.as_str()))
+        .collect();
+    ranges.sort_by_key(|(start, _, _)| *start);
+    let mut result = text.to_string();
+    for (start, end, new_text) in ranges.into_iter().rev() {
+        result.replace_range(start..end, new_text);
+    }
+    return result;
+}
+
+fn trim_trailing_whitespace(text: &str) -> String {
+    return text
+        .split_inclusive('\n')
+        .map(|line| {
+            let content = line.trim_end_matches(['\n', '\r']);
+            return format!("{}{}", content.trim_end(), &line[content.len()..]);
+        })
+        .collect();
+}
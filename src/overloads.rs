@@ -0,0 +1,389 @@
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::Node;
+
+use crate::hierarchy::{self, MethodSignature, TypeDeclaration};
+use crate::line_index::LineIndex;
+use crate::members;
+use crate::resolve;
+use crate::Backend;
+
+/// Primitive types in the order they widen to, `char` aside.
+const NUMERIC_TYPES: [&str; 6] = ["byte", "short", "int", "long", "float", "double"];
+
+/// Primitive types with the classes boxing them.
+const BOXED_TYPES: [(&str, &str); 8] = [
+    ("boolean", "Boolean"),
+    ("byte", "Byte"),
+    ("char", "Character"),
+    ("short", "Short"),
+    ("int", "Integer"),
+    ("long", "Long"),
+    ("float", "Float"),
+    ("double", "Double"),
+];
+
+/// The type given to a `null` argument, which fits any parameter of a reference type.
+const NULL_TYPE: &str = "null";
+
+/// A method a type declares or inherits.
+#[derive(Debug, Clone)]
+pub struct Overload {
+    pub declaring_uri: String,
+    pub declaration: TypeDeclaration,
+    pub method: MethodSignature,
+}
+
+impl Backend {
+    /// Every overload of `method_name` that `type_name` declares or inherits. Overridden methods
+    /// of supertypes, and the static methods of interfaces it extends or implements, are left out.
+    pub fn method_overloads(&self, uri: &str, type_name: &str, method_name: &str) -> Vec<Overload> {
+        let mut overloads: Vec<Overload> = Vec::new();
+        for (index, (declaring_uri, declaration)) in self.type_hierarchy(uri, type_name).types.into_iter().enumerate() {
+            let is_member = |method: &MethodSignature| index == 0 || declaration.is_inherited(method);
+            for method in declaration.methods.iter().filter(|method| method.name == method_name && is_member(method)) {
+                if overloads.iter().any(|overload| overload.method.parameter_types == method.parameter_types) {
+                    continue;
+                }
+                overloads.push(Overload {
+                    declaring_uri: declaring_uri.clone(),
+                    declaration: declaration.clone(),
+                    method: method.clone(),
+                });
+            }
+        }
+        return overloads;
+    }
+
+    /// The types of the arguments of a method invocation or constructor call, `None` for those
+    /// that can't be inferred.
+    pub fn argument_types(&self, uri: &str, call_node: Node, source: &str) -> Vec<Option<String>> {
+        let arguments_node = match call_node.child_by_field_name("arguments") {
+            Some(arguments_node) => arguments_node,
+            None => return Vec::new(),
+        };
+        return arguments_node
+            .named_children(&mut arguments_node.walk())
+            .filter(|argument_node| argument_node.kind() != "comment" && argument_node.kind() != "ERROR")
+            .map(|argument_node| match argument_node.kind() {
+                "null_literal" => Some(NULL_TYPE.to_string()),
+                _ => self.expression_type(uri, argument_node, source),
+            })
+            .collect();
+    }
+
+    /// The indices of the `signatures` a call with `argument_types` fits best. Overloads taking as
+    /// many parameters as there are arguments come before variable arity ones, and then those
+    /// whose parameters match the arguments most closely. Every signature is returned when none
+    /// fits, and all the best ones when the arguments don't tell them apart.
+    pub fn best_overloads(&self, uri: &str, signatures: &[&MethodSignature], argument_types: &[Option<String>]) -> Vec<usize> {
+        let ranks: Vec<Option<(bool, usize)>> = signatures
+            .iter()
+            .map(|signature| self.overload_rank(uri, signature, argument_types))
+            .collect();
+        let best = match ranks.iter().flatten().max() {
+            Some(best) => *best,
+            None => return (0..signatures.len()).collect(),
+        };
+        return (0..signatures.len()).filter(|index| ranks[*index] == Some(best)).collect();
+    }
+
+    /// Whether `signature` takes the arguments without variable arity, and how closely its
+    /// parameters match them. `None` when it can't take them.
+    fn overload_rank(&self, uri: &str, signature: &MethodSignature, argument_types: &[Option<String>]) -> Option<(bool, usize)> {
+        let parameter_types = &signature.parameter_types;
+        let is_fixed_arity = parameter_types.len() == argument_types.len();
+        let element_type = match parameter_types.last().and_then(|last| last.strip_suffix("[]")) {
+            Some(element_type) if !is_fixed_arity && argument_types.len() + 1 >= parameter_types.len() => element_type,
+            _ if is_fixed_arity => "",
+            _ => return None,
+        };
+        let mut score = 0;
+        for (index, argument_type) in argument_types.iter().enumerate() {
+            let parameter_type = match is_fixed_arity || index + 1 < parameter_types.len() {
+                true => parameter_types[index].as_str(),
+                false => element_type,
+            };
+            score += self.argument_match(uri, parameter_type, argument_type.as_deref(), &signature.type_parameters)?;
+        }
+        return Some((is_fixed_arity, score));
+    }
+
+    /// How closely an argument of `argument_type` matches a parameter of `parameter_type`: 4 for
+    /// the same type, 3 for a subtype or a widened primitive, 2 for `Object`, 1 for boxing and type
+    /// variables and 0 when the argument's type isn't known. `None` when it doesn't fit.
+    fn argument_match(&self, uri: &str, parameter_type: &str, argument_type: Option<&str>, type_parameters: &[String]) -> Option<usize> {
+        let argument_type = match argument_type {
+            Some(argument_type) => argument_type.rsplit('.').next().unwrap_or(argument_type),
+            None => return Some(0),
+        };
+        let parameter_type = parameter_type.rsplit('.').next().unwrap_or(parameter_type);
+        if argument_type == parameter_type {
+            return Some(4);
+        }
+        if type_parameters.iter().any(|type_parameter| type_parameter == parameter_type) {
+            return Some(1);
+        }
+        let is_primitive = |type_name: &str| type_name == "char" || type_name == "boolean" || NUMERIC_TYPES.contains(&type_name);
+        let boxed = |type_name: &str| BOXED_TYPES.iter().find(|(primitive, _)| *primitive == type_name).map(|(_, boxed)| *boxed);
+        let unboxed = |type_name: &str| BOXED_TYPES.iter().find(|(_, boxed)| *boxed == type_name).map(|(primitive, _)| *primitive);
+        return match (is_primitive(argument_type), is_primitive(parameter_type)) {
+            (true, true) => widens(argument_type, parameter_type).then_some(3),
+            (true, false) => {
+                let boxed_type = boxed(argument_type)?;
+                let fits = boxed_type == parameter_type || matches!(parameter_type, "Object" | "Number" | "Comparable" | "Serializable");
+                fits.then_some(1)
+            }
+            (false, true) => {
+                let primitive_type = unboxed(argument_type)?;
+                (primitive_type == parameter_type || widens(primitive_type, parameter_type)).then_some(1)
+            }
+            (false, false) if parameter_type == "Object" => Some(2),
+            (false, false) if argument_type == NULL_TYPE => Some(3),
+            (false, false) if argument_type.ends_with(']') || parameter_type.ends_with(']') => None,
+            (false, false) => match self.is_subclass(uri, argument_type, parameter_type) {
+                Some(true) => Some(3),
+                Some(false) => None,
+                None => Some(0),
+            },
+        };
+    }
+
+    /// Whether the class `subtype` extends or implements `supertype`, `None` when some of its
+    /// supertypes can't be found.
+    fn is_subclass(&self, uri: &str, subtype: &str, supertype: &str) -> Option<bool> {
+        let hierarchy = self.type_hierarchy(uri, subtype);
+        let is_named = |type_name: &str| type_name.rsplit('.').next() == Some(supertype);
+        if hierarchy.types.iter().any(|(_, declaration)| is_named(&declaration.name)) || hierarchy.missing.iter().any(|name| is_named(name)) {
+            return Some(true);
+        }
+        return hierarchy.missing.is_empty().then_some(false);
+    }
+
+    /// The return type of the overload a method invocation calls, qualified where the document
+    /// declaring it resolves it. The first of several equally good overloads stands in for them.
+    pub fn invocation_type(&self, uri: &str, invocation_node: Node, source: &str) -> Option<String> {
+        let argument_types = self.argument_types(uri, invocation_node, source);
+        let (overload, receiver_arguments) = self.called_overload(uri, invocation_node, &argument_types, source)?;
+        let Overload { declaring_uri, declaration, method } = overload;
+        // a type parameter of the receiver's class stands for its type argument, and one of the
+        // method for the type of the argument passed as a parameter of that type
+        if let Some(substituted) = members::substituted_type(&method.return_type, &declaration.type_parameters, &receiver_arguments) {
+            return Some(substituted);
+        }
+        let inferred_arguments: Vec<String> = method
+            .type_parameters
+            .iter()
+            .map(|type_parameter| {
+                let index = method.parameter_types.iter().position(|parameter_type| parameter_type == type_parameter);
+                index.and_then(|index| argument_types.get(index).cloned().flatten()).unwrap_or_default()
+            })
+            .collect();
+        if let Some(substituted) = members::substituted_type(&method.return_type, &method.type_parameters, &inferred_arguments) {
+            return (!substituted.is_empty()).then_some(substituted);
+        }
+        return Some(self.qualify_type(&self.type_context(uri, &declaring_uri), &method.return_type));
+    }
+
+    /// The overload a method invocation passing arguments of `argument_types` calls, with the type
+    /// arguments its receiver gives the type declaring it. The first of several equally good
+    /// overloads stands in for them.
+    fn called_overload(&self, uri: &str, invocation_node: Node, argument_types: &[Option<String>], source: &str) -> Option<(Overload, Vec<String>)> {
+        let receiver_type = self.invocation_receiver_type(uri, invocation_node, source)?;
+        let method_name = invocation_node.child_by_field_name("name")?.utf8_text(source.as_bytes()).unwrap();
+        let overloads = self.method_overloads(uri, &receiver_type, method_name);
+        let signatures: Vec<&MethodSignature> = overloads.iter().map(|overload| &overload.method).collect();
+        let index = *self.best_overloads(uri, &signatures, argument_types).first()?;
+        let overload = overloads.into_iter().nth(index)?;
+        let receiver_arguments = match invocation_node.child_by_field_name("object") {
+            Some(object_node) => {
+                let object_arguments = self.type_arguments(uri, object_node, source);
+                self.supertype_arguments(uri, &receiver_type, &object_arguments, &overload.declaration.name)
+            }
+            None => Vec::new(),
+        };
+        return Some((overload, receiver_arguments));
+    }
+
+    /// The type of the parameter at `parameter_index` of a lambda that doesn't write its
+    /// parameters' types, from the single abstract method of the functional interface the method
+    /// or constructor it's passed to takes it as.
+    pub fn lambda_parameter_type(&self, uri: &str, lambda_node: Node, parameter_index: usize, source: &str) -> Option<String> {
+        let arguments_node = lambda_node.parent().filter(|n| n.kind() == "argument_list")?;
+        let argument_index = arguments_node
+            .named_children(&mut arguments_node.walk())
+            .filter(|argument_node| argument_node.kind() != "comment" && argument_node.kind() != "ERROR")
+            .position(|argument_node| argument_node == lambda_node)?;
+        let call_node = arguments_node.parent()?;
+        let argument_types = self.argument_types(uri, call_node, source);
+        let (Overload { declaring_uri, declaration, method }, type_arguments) = match call_node.kind() {
+            "method_invocation" => self.called_overload(uri, call_node, &argument_types, source)?,
+            "object_creation_expression" => {
+                let type_node = call_node.child_by_field_name("type")?;
+                let (declaring_uri, declaration) = self.locate_type(uri, &hierarchy::erased_type(type_node, source))?;
+                let signatures: Vec<&MethodSignature> = declaration.constructors.iter().collect();
+                let index = *self.best_overloads(uri, &signatures, &argument_types).first()?;
+                let method = declaration.constructors[index].clone();
+                let overload = Overload { declaring_uri, declaration, method };
+                (overload, self.type_arguments(uri, call_node, source))
+            }
+            _ => return None,
+        };
+        // a lambda consumes what the lower bound of a `? super` wildcard names
+        let written = self.written_parameter_types(&declaring_uri, &declaration.name, &method)?.get(argument_index)?.replace("? super ", "");
+        let context_uri = self.type_context(uri, &declaring_uri);
+        let interface_arguments: Vec<String> = resolve::written_type_arguments(&written)
+            .iter()
+            .map(|type_argument| {
+                if let Some(substituted) = members::substituted_type(type_argument, &declaration.type_parameters, &type_arguments) {
+                    return substituted;
+                }
+                // the method's own type parameters aren't inferred from other arguments
+                if method.type_parameters.contains(&resolve::erase(type_argument)) {
+                    return String::new();
+                }
+                return self.qualify_type(&context_uri, type_argument);
+            })
+            .collect();
+        let interface_type = self.qualify_type(&context_uri, &written);
+        let (method_uri, interface, abstract_method) = self.functional_method(uri, &interface_type)?;
+        let parameter_type = self.written_parameter_types(&method_uri, &interface.name, &abstract_method)?.get(parameter_index)?.clone();
+        if interface.name == resolve::erase(&interface_type) {
+            if let Some(substituted) = members::substituted_type(&parameter_type, &interface.type_parameters, &interface_arguments) {
+                return (!substituted.is_empty()).then_some(substituted);
+            }
+        }
+        return Some(self.qualify_type(&self.type_context(uri, &method_uri), &parameter_type));
+    }
+
+    /// The declarations of the overloads the method invocation `name_node` names may call, picked
+    /// by the arguments it passes. Unqualified calls look in the classes around them from the
+    /// innermost out, calls through `this` in the class it's bound to, and other calls in the
+    /// type of their receiver.
+    pub fn method_definitions(&self, uri: &str, name_node: Node, source: &str) -> Vec<Location> {
+        let invocation_node = match name_node.parent() {
+            Some(parent_node) if parent_node.kind() == "method_invocation" && parent_node.child_by_field_name("name") == Some(name_node) => {
+                parent_node
+            }
+            _ => return Vec::new(),
+        };
+        let method_name = name_node.utf8_text(source.as_bytes()).unwrap();
+        let argument_types = self.argument_types(uri, invocation_node, source);
+        let class_nodes: Vec<Node> = match invocation_node.child_by_field_name("object") {
+            None => std::iter::successors(resolve::enclosing_class_node(invocation_node), |class_node| {
+                resolve::enclosing_class_node(*class_node)
+            })
+            .collect(),
+            Some(object_node) if object_node.kind() == "this" => resolve::enclosing_class_node(object_node).into_iter().collect(),
+            Some(object_node) => resolve::qualified_this_node(object_node, source).into_iter().collect(),
+        };
+        for class_node in class_nodes {
+            let body_node = match class_node.kind() {
+                "class_body" => Some(class_node),
+                _ => class_node.child_by_field_name("body"),
+            };
+            let method_nodes: Vec<Node> = body_node
+                .map(hierarchy::member_method_declarations)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|method_node| {
+                    method_node
+                        .child_by_field_name("name")
+                        .map(|name_node| name_node.utf8_text(source.as_bytes()).unwrap() == method_name)
+                        .unwrap_or(false)
+                })
+                .collect();
+            if !method_nodes.is_empty() {
+                let signatures: Vec<MethodSignature> = method_nodes.iter().map(|method_node| hierarchy::method_signature(*method_node, source)).collect();
+                let signature_refs: Vec<&MethodSignature> = signatures.iter().collect();
+                return self
+                    .best_overloads(uri, &signature_refs, &argument_types)
+                    .into_iter()
+                    .filter_map(|index| node_location(uri, source, method_nodes[index].child_by_field_name("name")?))
+                    .collect();
+            }
+            let inherited = match resolve::class_name(class_node, source) {
+                Some(class_name) => self.overload_locations(uri, &class_name, method_name, &argument_types),
+                None => Vec::new(),
+            };
+            if !inherited.is_empty() {
+                return inherited;
+            }
+        }
+        return match self.invocation_receiver_type(uri, invocation_node, source) {
+            Some(receiver_type) => self.overload_locations(uri, &receiver_type, method_name, &argument_types),
+            None => Vec::new(),
+        };
+    }
+
+    /// The declarations of the constructors a `new` expression may call when `name_node` is the
+    /// name of the class it creates, picked by the arguments it passes, or the class itself when
+    /// it only has the default constructor.
+    pub fn constructor_definitions(&self, uri: &str, name_node: Node, source: &str) -> Vec<Location> {
+        let mut type_node = name_node;
+        while let Some(parent_node) = type_node.parent() {
+            let is_name = match parent_node.kind() {
+                "scoped_type_identifier" => parent_node.named_child(parent_node.named_child_count() - 1) == Some(type_node),
+                "generic_type" => parent_node.named_child(0) == Some(type_node),
+                _ => break,
+            };
+            if !is_name {
+                return Vec::new();
+            }
+            type_node = parent_node;
+        }
+        let creation_node = match type_node.parent() {
+            Some(parent_node) if parent_node.kind() == "object_creation_expression" && parent_node.child_by_field_name("type") == Some(type_node) => {
+                parent_node
+            }
+            _ => return Vec::new(),
+        };
+        let raw_type_node = match type_node.kind() {
+            "generic_type" => type_node.named_child(0).unwrap_or(type_node),
+            _ => type_node,
+        };
+        let (declaring_uri, declaration) = match self.locate_type(uri, raw_type_node.utf8_text(source.as_bytes()).unwrap()) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        if declaration.constructors.is_empty() {
+            return self.type_location(&declaring_uri, &declaration.name).into_iter().collect();
+        }
+        let signatures: Vec<&MethodSignature> = declaration.constructors.iter().collect();
+        return self
+            .best_overloads(uri, &signatures, &self.argument_types(uri, creation_node, source))
+            .into_iter()
+            .filter_map(|index| self.constructor_location(&declaring_uri, &declaration.name, &signatures[index].parameter_types))
+            .collect();
+    }
+
+    /// The declarations of the overloads of `method_name` that `type_name` has that fit the
+    /// arguments best.
+    fn overload_locations(&self, uri: &str, type_name: &str, method_name: &str, argument_types: &[Option<String>]) -> Vec<Location> {
+        let overloads = self.method_overloads(uri, type_name, method_name);
+        let signatures: Vec<&MethodSignature> = overloads.iter().map(|overload| &overload.method).collect();
+        return self
+            .best_overloads(uri, &signatures, argument_types)
+            .into_iter()
+            .filter_map(|index| {
+                let overload = &overloads[index];
+                self.type_member_location(&overload.declaring_uri, &overload.declaration.name, method_name, Some(&overload.method.parameter_types))
+            })
+            .collect();
+    }
+}
+
+/// Whether a value of the primitive type `from` converts to `to` by widening.
+fn widens(from: &str, to: &str) -> bool {
+    let from = if from == "char" { "short" } else { from };
+    return match (NUMERIC_TYPES.iter().position(|t| *t == from), NUMERIC_TYPES.iter().position(|t| *t == to)) {
+        (Some(from_index), Some(to_index)) => from_index < to_index,
+        _ => false,
+    };
+}
+
+fn node_location(uri: &str, source: &str, node: Node) -> Option<Location> {
+    return Some(Location {
+        uri: Url::parse(uri).ok()?,
+        range: LineIndex::new(source).range(node.start_position(), node.end_position()),
+    });
+}
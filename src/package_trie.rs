@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+/// Packages as a trie of their segments, with the top-level classes of each. A class is counted
+/// once for every document or jar providing it, and a package goes once it has neither classes
+/// nor subpackages left.
+#[derive(Debug, Default)]
+pub struct PackageTrie {
+    subpackages: BTreeMap<String, PackageTrie>,
+    classes: BTreeMap<String, usize>,
+}
+
+impl PackageTrie {
+    pub fn insert(&mut self, package: &str, class_name: &str) {
+        let mut node = self;
+        for segment in segments(package) {
+            node = node.subpackages.entry(segment.to_string()).or_default();
+        }
+        *node.classes.entry(class_name.to_string()).or_default() += 1;
+    }
+
+    pub fn remove(&mut self, package: &str, class_name: &str) {
+        let segments: Vec<&str> = segments(package).collect();
+        self.remove_from(&segments, class_name);
+    }
+
+    fn remove_from(&mut self, segments: &[&str], class_name: &str) {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => {
+                if let Some(count) = self.classes.get_mut(class_name) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.classes.remove(class_name);
+                    }
+                }
+                return;
+            }
+        };
+        if let Some(subpackage) = self.subpackages.get_mut(*segment) {
+            subpackage.remove_from(rest, class_name);
+            if subpackage.subpackages.is_empty() && subpackage.classes.is_empty() {
+                self.subpackages.remove(*segment);
+            }
+        }
+    }
+
+    fn package(&self, package: &str) -> Option<&PackageTrie> {
+        let mut node = self;
+        for segment in segments(package) {
+            node = node.subpackages.get(segment)?;
+        }
+        return Some(node);
+    }
+
+    /// The last segments of the packages directly in `package`, the empty one being the root.
+    pub fn subpackages(&self, package: &str) -> Vec<String> {
+        return match self.package(package) {
+            Some(node) => node.subpackages.keys().cloned().collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// The packages and simple names of the top-level classes, in any package, whose names
+    /// `is_match` accepts.
+    pub fn find_classes(&self, is_match: &dyn Fn(&str) -> bool) -> Vec<(String, String)> {
+        let mut found = Vec::new();
+        self.collect_classes("", is_match, &mut found);
+        return found;
+    }
+
+    fn collect_classes(&self, package: &str, is_match: &dyn Fn(&str) -> bool, found: &mut Vec<(String, String)>) {
+        for class_name in self.classes.keys().filter(|class_name| is_match(class_name)) {
+            found.push((package.to_string(), class_name.clone()));
+        }
+        for (segment, subpackage) in &self.subpackages {
+            let subpackage_name = if package.is_empty() { segment.clone() } else { format!("{}.{}", package, segment) };
+            subpackage.collect_classes(&subpackage_name, is_match, found);
+        }
+    }
+
+    /// The simple names of the top-level classes of `package`.
+    pub fn classes(&self, package: &str) -> Vec<String> {
+        return match self.package(package) {
+            Some(node) => node.classes.keys().cloned().collect(),
+            None => Vec::new(),
+        };
+    }
+}
+
+fn segments(package: &str) -> impl Iterator<Item = &str> {
+    return package.split('.').filter(|segment| !segment.is_empty());
+}
@@ -0,0 +1,464 @@
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::{Node, Point, Tree};
+
+use crate::line_index::LineIndex;
+use crate::{scope_key, Backend, ScopeKey};
+
+/// A record pattern like `Point(int x, var y)` in an `instanceof` or a `case` label.
+#[derive(Debug, Clone)]
+pub struct RecordPattern {
+    pub record_type: String, // as written
+    pub type_start: Point,
+    pub type_end: Point,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub bindings: Vec<PatternBinding>,
+    pub scopes: Vec<ScopeKey>, // the nodes the bindings are visible in
+}
+
+#[derive(Debug, Clone)]
+pub struct PatternBinding {
+    pub name: String,
+    pub declared_type: Option<String>, // None for `var`
+    pub component_index: usize,
+    pub start_position: Point,
+    pub end_position: Point,
+}
+
+impl RecordPattern {
+    pub fn contains(&self, node: Node) -> bool {
+        return self.start_byte <= node.start_byte() && node.end_byte() <= self.end_byte;
+    }
+}
+
+/// A type pattern like `String s` in a `case` label.
+#[derive(Debug, Clone)]
+pub struct TypePattern {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub binding: PatternBinding,
+    pub scope: ScopeKey, // the case the binding is visible in
+}
+
+impl TypePattern {
+    pub fn contains(&self, node: Node) -> bool {
+        return self.start_byte <= node.start_byte() && node.end_byte() <= self.end_byte;
+    }
+}
+
+/// Kinds whose bindings a pattern in an `instanceof` outside the condition of an `if` or a
+/// `while` is scoped to.
+const SCOPE_KINDS: [&str; 5] = ["block", "constructor_body", "switch_block_statement_group", "switch_rule", "class_body"];
+
+/// Statements after which the statements that follow don't run.
+const JUMP_KINDS: [&str; 5] = ["return_statement", "throw_statement", "break_statement", "continue_statement", "yield_statement"];
+
+/// Collects the record patterns in the tree, nested ones included, each with the bindings it
+/// declares directly.
+pub fn record_patterns(tree: &Tree, source: &str) -> Vec<RecordPattern> {
+    let mut patterns = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "record_pattern" {
+            continue;
+        }
+        // nested patterns share the scope of the outermost one
+        let mut outermost_node = node;
+        while let Some(parent_node) = outermost_node.parent().filter(|n| matches!(n.kind(), "record_pattern" | "record_pattern_body")) {
+            outermost_node = parent_node;
+        }
+        let scope_nodes = match outermost_node.parent() {
+            Some(parent_node) if parent_node.kind() == "instanceof_expression" => binding_scopes(parent_node),
+            // `case` labels wrap their pattern in a `pattern`
+            Some(parent_node) if parent_node.kind() == "pattern" => {
+                parent_node.parent().and_then(|label_node| label_node.parent()).into_iter().collect()
+            }
+            _ => Vec::new(),
+        };
+        if scope_nodes.is_empty() {
+            continue;
+        }
+        let scopes = scope_nodes.into_iter().map(scope_key).collect();
+        if let Some(pattern) = record_pattern(node, source, scopes) {
+            patterns.push(pattern);
+        }
+    }
+    return patterns;
+}
+
+/// The record pattern `pattern_node`, with the bindings of its components that aren't patterns
+/// themselves.
+fn record_pattern(pattern_node: Node, source: &str, scopes: Vec<ScopeKey>) -> Option<RecordPattern> {
+    let type_node = pattern_node.named_child(0)?;
+    let body_node = pattern_node.named_children(&mut pattern_node.walk()).find(|n| n.kind() == "record_pattern_body")?;
+    let mut bindings = Vec::new();
+    let component_nodes = body_node
+        .named_children(&mut body_node.walk())
+        .filter(|n| matches!(n.kind(), "record_pattern_component" | "record_pattern"))
+        .collect::<Vec<Node>>();
+    for (component_index, component_node) in component_nodes.into_iter().enumerate() {
+        // nested patterns have bindings of their own
+        if component_node.kind() != "record_pattern_component" {
+            continue;
+        }
+        // `_` matches the component without binding it
+        let name_node = match component_node.named_child(component_node.named_child_count().saturating_sub(1)) {
+            Some(name_node) if name_node.kind() == "identifier" => name_node,
+            _ => continue,
+        };
+        let written = component_node
+            .named_child(0)
+            .filter(|n| *n != name_node)
+            .map(|n| n.utf8_text(source.as_bytes()).unwrap());
+        bindings.push(PatternBinding {
+            name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+            declared_type: written.filter(|written| *written != "var").map(str::to_string),
+            component_index,
+            start_position: name_node.start_position(),
+            end_position: name_node.end_position(),
+        });
+    }
+    return Some(RecordPattern {
+        record_type: type_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+        type_start: type_node.start_position(),
+        type_end: type_node.end_position(),
+        start_byte: pattern_node.start_byte(),
+        end_byte: pattern_node.end_byte(),
+        bindings,
+        scopes,
+    });
+}
+
+/// Collects the type patterns of the `case` labels in the tree.
+pub fn case_type_patterns(tree: &Tree, source: &str) -> Vec<TypePattern> {
+    let mut patterns = Vec::new();
+    for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+        if node.kind() != "type_pattern" || node.parent().map(|n| n.kind()) != Some("pattern") {
+            continue;
+        }
+        let scope_node = match node.parent().and_then(|n| n.parent()).and_then(|label_node| label_node.parent()) {
+            Some(scope_node) => scope_node,
+            None => continue,
+        };
+        let (type_node, name_node) = match (node.named_child(0), node.named_child(1)) {
+            (Some(type_node), Some(name_node)) if name_node.kind() == "identifier" => (type_node, name_node),
+            _ => continue,
+        };
+        patterns.push(TypePattern {
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            binding: PatternBinding {
+                name: name_node.utf8_text(source.as_bytes()).unwrap().to_string(),
+                declared_type: Some(type_node.utf8_text(source.as_bytes()).unwrap().to_string()),
+                component_index: 0,
+                start_position: name_node.start_position(),
+                end_position: name_node.end_position(),
+            },
+            scope: scope_key(scope_node),
+        });
+    }
+    return patterns;
+}
+
+/// The pattern at `node` whose record type `node` names, if any.
+pub fn record_type_at<'a>(patterns: &'a [RecordPattern], node: Node) -> Option<&'a RecordPattern> {
+    return patterns.iter().find(|pattern| {
+        pattern.type_start <= node.start_position() && node.end_position() <= pattern.type_end
+    });
+}
+
+/// The declaration of the record named `record_type`, nested anywhere in the tree.
+pub fn record_declaration<'a>(tree: &'a Tree, source: &str, record_type: &str) -> Option<Node<'a>> {
+    let simple_name = simple_type_name(record_type);
+    return tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre).find(|node| {
+        node.kind() == "record_declaration"
+            && node.child_by_field_name("name").map(|n| n.utf8_text(source.as_bytes()).unwrap()) == Some(simple_name)
+    });
+}
+
+/// The types of the record's components, as written.
+pub fn component_types(record_node: Node, source: &str) -> Vec<String> {
+    let parameters_node = match record_node.child_by_field_name("parameters") {
+        Some(parameters_node) => parameters_node,
+        None => return Vec::new(),
+    };
+    return parameters_node
+        .named_children(&mut parameters_node.walk())
+        .filter(|n| n.kind() == "formal_parameter")
+        .filter_map(|n| n.child_by_field_name("type"))
+        .map(|n| n.utf8_text(source.as_bytes()).unwrap().to_string())
+        .collect();
+}
+
+/// `Point` for `Shape.Point<T>`.
+pub fn simple_type_name(written: &str) -> &str {
+    let erased = written.split('<').next().unwrap().trim();
+    return erased.rsplit('.').next().unwrap().trim();
+}
+
+/// The nodes the bindings of the pattern in `instanceof_node` are visible in. In the condition
+/// of an `if`, that's the condition and the branch taken when the test matched: the consequence,
+/// or for a negated test like `if (!(obj instanceof String s))` the alternative, and when the
+/// consequence can't complete, like `return;`, the statements after the `if` as well. A
+/// condition of a `while` binds them in its body. Elsewhere they're scoped to the enclosing
+/// block.
+pub fn binding_scopes(instanceof_node: Node) -> Vec<Node> {
+    let mut negated = false;
+    // whether the test matching follows from the whole condition being true or being false
+    let mut decides_condition = true;
+    let mut current_node = instanceof_node;
+    while let Some(parent_node) = current_node.parent() {
+        match parent_node.kind() {
+            "parenthesized_expression" => {}
+            "unary_expression" if parent_node.child_by_field_name("operator").map(|n| n.kind()) == Some("!") => negated = !negated,
+            // `a && b` being true means both are, and `a || b` being false means neither is
+            "binary_expression" => match parent_node.child_by_field_name("operator").map(|n| n.kind()) {
+                Some("&&") => decides_condition &= !negated,
+                Some("||") => decides_condition &= negated,
+                _ => break,
+            },
+            "condition" => return condition_scopes(parent_node, negated, decides_condition),
+            _ => break,
+        }
+        current_node = parent_node;
+    }
+    return enclosing_scope(instanceof_node).into_iter().collect();
+}
+
+/// The nodes a binding of `condition_node` is visible in, when the test declaring it matched
+/// if the condition is true, or if it's false when `negated`.
+fn condition_scopes(condition_node: Node, negated: bool, decides_condition: bool) -> Vec<Node> {
+    let mut scopes = vec![condition_node];
+    let statement_node = match condition_node.parent() {
+        Some(statement_node) if decides_condition => statement_node,
+        _ => return scopes,
+    };
+    let (matched_branch, other_branch) = match statement_node.kind() {
+        "if_statement" if negated => ("alternative", "consequence"),
+        "if_statement" => ("consequence", "alternative"),
+        "while_statement" if !negated => ("body", ""),
+        _ => return scopes,
+    };
+    scopes.extend(statement_node.child_by_field_name(matched_branch));
+    let completes = statement_node.child_by_field_name(other_branch).map(can_complete).unwrap_or(true);
+    if statement_node.kind() == "if_statement" && negated && !completes {
+        let mut next_node = statement_node.next_named_sibling();
+        while let Some(sibling_node) = next_node {
+            scopes.push(sibling_node);
+            next_node = sibling_node.next_named_sibling();
+        }
+    }
+    return scopes;
+}
+
+/// Whether running `statement_node` can go on to the statement after it, as far as a jump at its
+/// end tells.
+fn can_complete(statement_node: Node) -> bool {
+    return match statement_node.kind() {
+        kind if JUMP_KINDS.contains(&kind) => false,
+        "block" => {
+            let last_statement = statement_node
+                .named_children(&mut statement_node.walk())
+                .filter(|n| !n.kind().ends_with("comment"))
+                .last();
+            last_statement.map(can_complete).unwrap_or(true)
+        }
+        _ => true,
+    };
+}
+
+fn enclosing_scope(node: Node) -> Option<Node> {
+    let mut current_node = node.parent()?;
+    while !SCOPE_KINDS.contains(&current_node.kind()) {
+        current_node = current_node.parent()?;
+    }
+    return Some(current_node);
+}
+
+impl Backend {
+    /// The type of a pattern binding: the one written, or for `var` the type of the record
+    /// component it matches. The record is looked up in `tree` first, then in the other indexed
+    /// documents declaring a record by its name.
+    pub fn binding_type(&self, uri: &str, tree: &Tree, source: &str, pattern: &RecordPattern, binding: &PatternBinding) -> Option<String> {
+        if binding.declared_type.is_some() {
+            return binding.declared_type.clone();
+        }
+        if let Some(record_node) = record_declaration(tree, source, &pattern.record_type) {
+            return component_types(record_node, source).into_iter().nth(binding.component_index);
+        }
+        for other_uri in self.record_uris(&pattern.record_type) {
+            if other_uri == uri {
+                continue;
+            }
+            let (other_tree, other_source) = match self.document_source(&other_uri) {
+                Some(other_document) => other_document,
+                None => continue,
+            };
+            if let Some(record_node) = record_declaration(&other_tree, &other_source, &pattern.record_type) {
+                return component_types(record_node, &other_source).into_iter().nth(binding.component_index);
+            }
+        }
+        return None;
+    }
+
+    /// Where the record named by the record pattern type at `node` is declared, in `uri` or in
+    /// another indexed document.
+    pub fn record_pattern_definition(&self, uri: &Url, tree: &Tree, source: &str, node: Node) -> Option<Location> {
+        let patterns = record_patterns(tree, source);
+        let pattern = record_type_at(&patterns, node)?;
+        let name_range = |tree: &Tree, source: &str| {
+            let name_node = record_declaration(tree, source, &pattern.record_type)?.child_by_field_name("name")?;
+            return Some(LineIndex::new(source).range(name_node.start_position(), name_node.end_position()));
+        };
+        if let Some(range) = name_range(tree, source) {
+            return Some(Location { uri: uri.clone(), range });
+        }
+        for other_uri in self.record_uris(&pattern.record_type) {
+            if other_uri == uri.as_str() {
+                continue;
+            }
+            let (other_tree, other_source) = match self.document_source(&other_uri) {
+                Some(other_document) => other_document,
+                None => continue,
+            };
+            if let (Some(range), Ok(other_uri)) = (name_range(&other_tree, &other_source), Url::parse(&other_uri)) {
+                return Some(Location { uri: other_uri, range });
+            }
+        }
+        return None;
+    }
+
+    /// The indexed documents declaring a record named like `record_type`, in order of uri.
+    fn record_uris(&self, record_type: &str) -> Vec<String> {
+        let simple_name = simple_type_name(record_type);
+        let mut uris: Vec<String> = self
+            .type_declaration_map
+            .iter()
+            .filter(|entry| {
+                entry
+                    .value()
+                    .iter()
+                    .any(|declaration| declaration.kind == "record_declaration" && declaration.name == simple_name)
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        uris.sort();
+        return uris;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        return parser.parse(source, None).unwrap();
+    }
+
+    fn bindings(pattern: &RecordPattern) -> Vec<(&str, Option<&str>, usize)> {
+        return pattern
+            .bindings
+            .iter()
+            .map(|binding| (binding.name.as_str(), binding.declared_type.as_deref(), binding.component_index))
+            .collect();
+    }
+
+    #[test]
+    fn finds_the_bindings_of_record_patterns() {
+        let source = "class C { void m(Object o) { if (o instanceof Line(Point(var a, _), Box<String>(String s))) {} } }";
+        let tree = parse(source);
+        let patterns = record_patterns(&tree, source);
+        let types: Vec<&str> = patterns.iter().map(|pattern| pattern.record_type.as_str()).collect();
+        assert_eq!(types, vec!["Line", "Point", "Box<String>"]);
+        assert!(patterns[0].bindings.is_empty());
+        assert_eq!(bindings(&patterns[1]), vec![("a", None, 0)]);
+        assert_eq!(bindings(&patterns[2]), vec![("s", Some("String"), 0)]);
+        // nested patterns are scoped like the one they're in, to the condition and the consequence
+        let if_node = tree.root_node().descendant_for_byte_range(29, 31).unwrap().parent().unwrap();
+        let scopes = vec![
+            scope_key(if_node.child_by_field_name("condition").unwrap()),
+            scope_key(if_node.child_by_field_name("consequence").unwrap()),
+        ];
+        assert!(patterns.iter().all(|pattern| pattern.scopes == scopes));
+    }
+
+    #[test]
+    fn scopes_the_patterns_of_case_labels_to_their_case() {
+        let source = "class C { int m(Object o) { return switch (o) { case Point(int x, int y) -> x; case String s when s.isEmpty() -> 0; default -> 1; }; } }";
+        let tree = parse(source);
+        let record_pattern = &record_patterns(&tree, source)[0];
+        assert_eq!(record_pattern.record_type, "Point");
+        assert_eq!(bindings(record_pattern), vec![("x", Some("int"), 0), ("y", Some("int"), 1)]);
+        let type_patterns = case_type_patterns(&tree, source);
+        assert_eq!(type_patterns.len(), 1);
+        assert_eq!(type_patterns[0].binding.name, "s");
+        assert_eq!(type_patterns[0].binding.declared_type.as_deref(), Some("String"));
+        let rule_scopes: Vec<ScopeKey> = tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre)
+            .filter(|node| node.kind() == "switch_rule")
+            .map(scope_key)
+            .collect();
+        assert_eq!(record_pattern.scopes, vec![rule_scopes[0]]);
+        assert_eq!(type_patterns[0].scope, rule_scopes[1]);
+    }
+
+    #[test]
+    fn finds_the_record_type_a_node_names() {
+        let source = "class C { record Point(int x, String label) {} boolean m(Object o) { return o instanceof Point(var x, var label); } }";
+        let tree = parse(source);
+        let patterns = record_patterns(&tree, source);
+        let type_start = source.rfind("Point").unwrap();
+        let type_node = tree.root_node().descendant_for_byte_range(type_start, type_start + 5).unwrap();
+        assert!(record_type_at(&patterns, type_node).is_some());
+        assert!(record_type_at(&patterns, type_node.parent().unwrap().parent().unwrap()).is_none());
+        let record_node = record_declaration(&tree, source, "Shape.Point<T>").unwrap();
+        assert_eq!(component_types(record_node, source), vec!["int".to_string(), "String".to_string()]);
+    }
+
+    fn scope_texts<'a>(source: &'a str, test: &str) -> Vec<&'a str> {
+        let tree = parse(source);
+        let start = source.find(test).unwrap();
+        let mut node = tree.root_node().descendant_for_byte_range(start, start + test.len()).unwrap();
+        while node.kind() != "instanceof_expression" {
+            node = node.parent().unwrap();
+        }
+        return binding_scopes(node).iter().map(|n| n.utf8_text(source.as_bytes()).unwrap()).collect();
+    }
+
+    #[test]
+    fn scopes_instanceof_bindings_to_the_branch_their_test_decides() {
+        let source = "class C { void m(Object o) { if (o instanceof String s && s.isEmpty()) { a(s); } else { b(); } c(); } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["(o instanceof String s && s.isEmpty())", "{ a(s); }"]);
+
+        let source = "class C { void m(Object o) { if (!(o instanceof String s)) { a(); } else { b(s); } c(); } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["(!(o instanceof String s))", "{ b(s); }"]);
+
+        // neither branch knows the test matched when either side could have made it true
+        let source = "class C { void m(Object o) { if (o instanceof String s || o == null) { a(); } } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["(o instanceof String s || o == null)"]);
+    }
+
+    #[test]
+    fn scopes_negated_instanceof_bindings_to_the_statements_after_an_early_exit() {
+        let source = "class C { void m(Object o) { if (!(o instanceof String s)) { return; } a(s); b(s); } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["(!(o instanceof String s))", "a(s);", "b(s);"]);
+
+        // the statements after are reached without the test matching when the `if` can complete
+        let source = "class C { void m(Object o) { if (!(o instanceof String s)) { a(); } b(); } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["(!(o instanceof String s))"]);
+    }
+
+    #[test]
+    fn scopes_other_instanceof_bindings_to_the_enclosing_scope() {
+        let source = "class C { boolean m(Object o) { return o instanceof String s && s.isEmpty(); } }";
+        let scopes = scope_texts(source, "o instanceof String s");
+        assert_eq!(scopes, vec!["{ return o instanceof String s && s.isEmpty(); }"]);
+    }
+}
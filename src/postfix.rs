@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+
+use log::warn;
+use serde_json::{Map, Value};
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::line_index::{self, LineIndex};
+use crate::{code_action, completion, Backend};
+
+/// The templates there are before the settings change them. `$expr` stands for the expression
+/// the template is typed after, and `$name` for a name for its value, like `size` for
+/// `list.size()`.
+const DEFAULT_TEMPLATES: [(&str, &str); 9] = [
+    ("var", "var ${1:$name} = $expr;"),
+    ("if", "if ($expr) {\n    $0\n}"),
+    ("else", "if (!$expr) {\n    $0\n}"),
+    ("for", "for (var ${1:item} : $expr) {\n    $0\n}"),
+    ("while", "while ($expr) {\n    $0\n}"),
+    ("null", "if ($expr == null) {\n    $0\n}"),
+    ("nn", "if ($expr != null) {\n    $0\n}"),
+    ("return", "return $expr;"),
+    ("throw", "throw $expr;"),
+];
+
+/// The postfix templates completion offers after `expression.`, by name, in snippet syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostfixTemplates {
+    templates: BTreeMap<String, String>,
+}
+
+impl Default for PostfixTemplates {
+    fn default() -> PostfixTemplates {
+        return PostfixTemplates {
+            templates: DEFAULT_TEMPLATES
+                .iter()
+                .map(|(name, template)| (name.to_string(), template.to_string()))
+                .collect(),
+        };
+    }
+}
+
+impl PostfixTemplates {
+    /// Adds the templates named in `templates`, replacing those of the same names, and removes
+    /// those set to `null`.
+    pub fn configure(&mut self, templates: &Map<String, Value>) {
+        for (name, template) in templates {
+            match template {
+                Value::String(template) => {
+                    self.templates.insert(name.clone(), template.clone());
+                }
+                Value::Null => {
+                    self.templates.remove(name);
+                }
+                _ => warn!("ignoring postfix template {:?}, which isn't a string", name),
+            }
+        }
+    }
+}
+
+impl Backend {
+    /// The postfix templates, offered after an expression that starts a statement and a dot, like
+    /// `items.for`. Accepting one replaces the expression with the template filled in with it.
+    pub fn postfix_items(&self, tree: &Tree, source: &str, position: Position) -> Vec<CompletionItem> {
+        let line = source.lines().nth(position.line as usize).unwrap_or("");
+        let typed = line_index::prefix(line, position.character);
+        let before_name = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+        if !before_name.ends_with('.') {
+            return Vec::new();
+        }
+        let dot_point = Point {
+            row: position.line as usize,
+            column: before_name.len() - 1,
+        };
+        let dot_end = Point {
+            row: dot_point.row,
+            column: before_name.len(),
+        };
+        let dot_node = match tree.root_node().descendant_for_point_range(dot_point, dot_end) {
+            Some(dot_node) if dot_node.kind() == "." => dot_node,
+            _ => return Vec::new(),
+        };
+        let receiver_node = match completion::access_receiver(dot_node) {
+            Some(receiver_node) => receiver_node,
+            None => return Vec::new(),
+        };
+        if !starts_statement(receiver_node) {
+            return Vec::new();
+        }
+        let expression = receiver_node.utf8_text(source.as_bytes()).unwrap();
+        let name = code_action::suggested_name(receiver_node, source);
+        let lines = LineIndex::new(source);
+        let indentation: String = lines
+            .line(receiver_node.start_position().row)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let name_range = Range {
+            start: Position {
+                line: position.line,
+                character: line_index::character(before_name),
+            },
+            end: position,
+        };
+        // the expression and its dot make way for the template
+        let receiver_edit = TextEdit {
+            range: lines.range(receiver_node.start_position(), dot_end),
+            new_text: String::new(),
+        };
+        let as_snippets = self.can_complete_snippets.load(Ordering::Acquire);
+
+        let config = self.config.read().unwrap_or_else(PoisonError::into_inner);
+        return config
+            .postfix_templates
+            .templates
+            .iter()
+            .map(|(template_name, template)| {
+                let template = template.replace('\n', &format!("\n{}", indentation)).replace("$name", &name);
+                let plain = plain_text(&template).replace("$expr", expression);
+                let new_text = match as_snippets {
+                    true => template.replace("$expr", &completion::escape_snippet(expression)),
+                    false => plain.clone(),
+                };
+                return CompletionItem {
+                    label: template_name.clone(),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    detail: plain.lines().next().map(str::to_string),
+                    insert_text_format: Some(if as_snippets { InsertTextFormat::SNIPPET } else { InsertTextFormat::PLAIN_TEXT }),
+                    insert_text_mode: Some(InsertTextMode::AS_IS),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range: name_range,
+                        new_text,
+                    })),
+                    additional_text_edits: Some(vec![receiver_edit.clone()]),
+                    ..CompletionItem::default()
+                };
+            })
+            .collect();
+    }
+}
+
+/// Whether `expression_node` is where the statement it's in starts, which a template can only
+/// replace with a statement then.
+fn starts_statement(expression_node: Node) -> bool {
+    let mut node = expression_node;
+    while let Some(parent) = node.parent() {
+        if code_action::is_statement_container(parent.kind()) {
+            return node.start_byte() == expression_node.start_byte();
+        }
+        node = parent;
+    }
+    return false;
+}
+
+/// What a snippet inserts when its placeholders are left as they are, for clients that don't
+/// take snippets: placeholders become their default text, tab stops nothing, and escaped
+/// characters themselves.
+fn plain_text(snippet: &str) -> String {
+    let mut text = String::new();
+    let mut chars = snippet.chars().peekable();
+    let mut open_placeholders = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('$' | '}' | '\\')) => text.push(chars.next().unwrap()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                chars.next_if_eq(&':');
+                open_placeholders += 1;
+            }
+            '$' if chars.peek().map(char::is_ascii_digit).unwrap_or(false) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            }
+            '}' if open_placeholders > 0 => open_placeholders -= 1,
+            c => text.push(c),
+        }
+    }
+    return text;
+}
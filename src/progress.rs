@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use log::{debug, info};
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::*;
+use tower_lsp::Client;
+
+use crate::Backend;
+
+type CancelledFlags = Arc<Mutex<HashMap<NumberOrString, Arc<AtomicBool>>>>;
+
+/// The operations whose progress is shown, by token, with whether the user cancelled them.
+#[derive(Debug, Default)]
+pub struct ProgressTokens {
+    next: AtomicU64,
+    cancelled: CancelledFlags,
+}
+
+/// The progress of a long operation, shown by the client with a button to cancel it. The
+/// operation stops at its next chance once [`WorkDone::is_cancelled`]. Progress that isn't ended
+/// explicitly, because its request was cancelled say, ends when it's dropped.
+#[derive(Debug)]
+pub struct WorkDone {
+    client: Client,
+    token: NumberOrString,
+    cancelled: Arc<AtomicBool>,
+    tokens: CancelledFlags,
+    ended: bool,
+}
+
+impl WorkDone {
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Acquire);
+    }
+
+    pub async fn report(&self, message: String, percentage: usize) {
+        let report = WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(true),
+            message: Some(message),
+            percentage: Some(percentage.min(100) as u32),
+        });
+        send_progress(&self.client, self.token.clone(), report).await;
+    }
+
+    pub async fn end(mut self, message: String) {
+        self.ended = true;
+        let end = WorkDoneProgress::End(WorkDoneProgressEnd { message: Some(message) });
+        send_progress(&self.client, self.token.clone(), end).await;
+    }
+}
+
+impl Drop for WorkDone {
+    fn drop(&mut self) {
+        self.tokens.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.token);
+        if self.ended {
+            return;
+        }
+        let (client, token) = (self.client.clone(), self.token.clone());
+        tokio::spawn(async move {
+            send_progress(&client, token, WorkDoneProgress::End(WorkDoneProgressEnd { message: None })).await;
+        });
+    }
+}
+
+impl Backend {
+    /// Starts showing the progress of a long `operation`, titled `title`, when the client can
+    /// show it. The progress goes by `client_token` when the client sent one with its request, or
+    /// else by a token the server asks the client to create.
+    pub async fn begin_progress(&self, operation: &str, title: &str, client_token: Option<NumberOrString>) -> Option<WorkDone> {
+        let token = match client_token {
+            Some(client_token) => client_token,
+            None => {
+                if !self.can_report_progress.load(Ordering::Acquire) {
+                    return None;
+                }
+                let id = self.progress_tokens.next.fetch_add(1, Ordering::Relaxed);
+                let token = NumberOrString::String(format!("javals/{}/{}", operation, id));
+                let params = WorkDoneProgressCreateParams { token: token.clone() };
+                if let Err(error) = self.client.send_request::<WorkDoneProgressCreate>(params).await {
+                    info!("unable to create progress for {}: {:?}", operation, error);
+                    return None;
+                }
+                token
+            }
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.progress_tokens.cancelled.lock().unwrap_or_else(PoisonError::into_inner).insert(token.clone(), cancelled.clone());
+        let begin = WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(true),
+            message: None,
+            percentage: Some(0),
+        });
+        send_progress(&self.client, token.clone(), begin).await;
+        return Some(WorkDone {
+            client: self.client.clone(),
+            token,
+            cancelled,
+            tokens: self.progress_tokens.cancelled.clone(),
+            ended: false,
+        });
+    }
+
+    /// Handles `window/workDoneProgress/cancel`, which the client sends when the user cancels an
+    /// operation whose progress is shown.
+    pub async fn cancel_progress(&self, params: WorkDoneProgressCancelParams) {
+        debug!("cancel_progress {:?}", params.token);
+        if let Some(cancelled) = self.progress_tokens.cancelled.lock().unwrap_or_else(PoisonError::into_inner).get(&params.token) {
+            cancelled.store(true, Ordering::Release);
+        }
+    }
+}
+
+async fn send_progress(client: &Client, token: NumberOrString, progress: WorkDoneProgress) {
+    let params = ProgressParams {
+        token,
+        value: ProgressParamsValue::WorkDone(progress),
+    };
+    client.send_notification::<Progress>(params).await;
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+
+use log::info;
+use serde::Serialize;
+
+use crate::{gradle, maven, search, Backend};
+
+/// The names of the files that describe projects, or change how they resolve.
+pub const BUILD_FILE_NAMES: [&str; 7] = [
+    maven::POM,
+    gradle::SETTINGS_FILES[0],
+    gradle::SETTINGS_FILES[1],
+    gradle::BUILD_FILES[0],
+    gradle::BUILD_FILES[1],
+    gradle::PROPERTY_FILES[0],
+    gradle::PROPERTY_FILES[1],
+];
+
+/// A project of a build tool in the workspace: where its sources are and the jars they compile
+/// against.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub build_file: PathBuf,
+    pub source_directories: Vec<PathBuf>,
+    pub test_source_directories: Vec<PathBuf>,
+    pub classpath: Vec<PathBuf>,
+    // coordinates of dependencies whose jars aren't available locally
+    pub unresolved_dependencies: Vec<String>,
+}
+
+/// What the `project` settings add to the projects build tools describe.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectSettings {
+    // jars on the classpath of every project
+    pub classpath: Vec<PathBuf>,
+    // directories of sources indexed along with the workspace roots
+    pub source_roots: Vec<PathBuf>,
+}
+
+impl Backend {
+    /// Finds the build tool projects under the workspace roots and works out their classpaths,
+    /// with the jars the `project.classpath` setting adds, replacing the ones found before, then
+    /// indexes the jars on them.
+    pub async fn load_projects(&self) {
+        let roots = self.workspace_roots.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let maven_roots = roots.clone();
+        let mut projects = tokio::task::spawn_blocking(move || maven::projects(&maven_roots))
+            .await
+            .unwrap_or_default();
+        projects.extend(self.gradle_projects(&roots).await);
+        let configured_classpath = self.config.read().unwrap_or_else(PoisonError::into_inner).project.classpath.clone();
+        for project in &mut projects {
+            for entry in &configured_classpath {
+                if !project.classpath.contains(entry) {
+                    project.classpath.push(entry.clone());
+                }
+            }
+        }
+        for project in &projects {
+            info!(
+                "project {:?}: {} source directories, {} jars, unresolved {:?}",
+                project.build_file,
+                project.source_directories.len() + project.test_source_directories.len(),
+                project.classpath.len(),
+                project.unresolved_dependencies
+            );
+        }
+        *self.projects.write().unwrap_or_else(PoisonError::into_inner) = projects;
+        self.index_dependencies().await;
+    }
+}
+
+/// Whether the projects need loading again when the file at `path` changes.
+pub fn is_build_file(path: &Path) -> bool {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    return BUILD_FILE_NAMES.iter().any(|build_file_name| file_name == *build_file_name);
+}
+
+/// The files named one of `file_names` under `roots`, leaving out skipped directories.
+pub fn find_files(roots: &[PathBuf], file_names: &[&str]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = roots.to_vec();
+    while let Some(path) = pending.pop() {
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                if !search::is_skipped_directory(&entry_path) {
+                    pending.push(entry_path);
+                }
+            } else if file_names.iter().any(|file_name| entry.file_name() == *file_name) {
+                files.push(entry_path);
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+    return files;
+}
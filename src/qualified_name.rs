@@ -0,0 +1,147 @@
+use tower_lsp::lsp_types::{Location, Url};
+use tree_sitter::Node;
+
+use crate::class_contents;
+use crate::imports::ResolvedClass;
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+/// What a leading segment of a dotted name like `com.example.Helper.Inner.run` stands for.
+#[derive(Debug, Clone)]
+pub enum NameSegment {
+    Package(String), // the package name up to the segment
+    Class(ResolvedClass),
+}
+
+/// The identifiers of an expression that's a plain dotted name, like `com.example.Helper.MAX`,
+/// in order. `None` for other expressions.
+pub fn dotted_segments(expression_node: Node) -> Option<Vec<Node>> {
+    return match expression_node.kind() {
+        "identifier" => Some(vec![expression_node]),
+        "field_access" => {
+            let field_node = expression_node.child_by_field_name("field")?;
+            if field_node.kind() != "identifier" {
+                return None;
+            }
+            let mut segments = dotted_segments(expression_node.child_by_field_name("object")?)?;
+            segments.push(field_node);
+            Some(segments)
+        }
+        _ => None,
+    };
+}
+
+impl Backend {
+    /// Resolves the leading segments of a dotted name from left to right: package names until one
+    /// leads to a class, then the class and the classes nested in it. What follows the last class
+    /// is a member. A name that never reaches a class resolves to nothing.
+    pub fn resolve_dotted_name(&self, uri: &str, segments: &[&str]) -> Vec<NameSegment> {
+        let mut resolved: Vec<NameSegment> = Vec::new();
+        for end in 1..=segments.len() {
+            let prefix = segments[..end].join(".");
+            match self.resolve_class(uri, &prefix) {
+                Some(class) => resolved.push(NameSegment::Class(class)),
+                None if resolved.iter().all(|segment| matches!(segment, NameSegment::Package(_))) => {
+                    resolved.push(NameSegment::Package(prefix))
+                }
+                None => break,
+            }
+        }
+        while matches!(resolved.last(), Some(NameSegment::Package(_))) {
+            resolved.pop();
+        }
+        return resolved;
+    }
+
+    /// The fully qualified name of the class a dotted name expression like `com.example.Helper`
+    /// names, when it names one rather than a variable or a field.
+    pub fn dotted_name_class(&self, uri: &str, expression_node: Node, source: &str) -> Option<String> {
+        let segment_nodes = dotted_segments(expression_node)?;
+        let segments: Vec<&str> = segment_nodes
+            .iter()
+            .map(|segment_node| segment_node.utf8_text(source.as_bytes()).unwrap())
+            .collect();
+        if self.find_declaration(uri, segment_nodes[0], segments[0]).is_some() {
+            return None;
+        }
+        let resolved = self.resolve_dotted_name(uri, &segments);
+        if resolved.len() != segments.len() {
+            return None;
+        }
+        return match resolved.last()? {
+            NameSegment::Class(class) => Some(class.qualified_name()),
+            NameSegment::Package(_) => None,
+        };
+    }
+
+    /// The declaration the segment `name_node` of a dotted name stands for: the package, class or
+    /// nested class it names, or the member of the class before it. Names starting with a variable
+    /// aren't dotted names but member accesses, and are left alone.
+    pub fn qualified_name_definition(&self, uri: &str, name_node: Node, source: &str) -> Option<Location> {
+        let parent_node = name_node.parent()?;
+        let is_method = parent_node.kind() == "method_invocation" && parent_node.child_by_field_name("name") == Some(name_node);
+        let segment_nodes = match is_method {
+            true => {
+                let mut segment_nodes = dotted_segments(parent_node.child_by_field_name("object")?)?;
+                segment_nodes.push(name_node);
+                segment_nodes
+            }
+            false => {
+                let mut expression_node = name_node;
+                while let Some(parent_node) = expression_node.parent().filter(|parent_node| parent_node.kind() == "field_access") {
+                    expression_node = parent_node;
+                }
+                dotted_segments(expression_node)?
+            }
+        };
+        let index = segment_nodes.iter().position(|segment_node| *segment_node == name_node)?;
+        let segments: Vec<&str> = segment_nodes
+            .iter()
+            .map(|segment_node| segment_node.utf8_text(source.as_bytes()).unwrap())
+            .collect();
+        if segments.len() < 2 || self.find_declaration(uri, segment_nodes[0], segments[0]).is_some() {
+            return None;
+        }
+        let name_end = if is_method { segments.len() - 1 } else { segments.len() };
+        let resolved = self.resolve_dotted_name(uri, &segments[..name_end]);
+        return match resolved.get(index) {
+            Some(NameSegment::Package(package)) => self.package_definition(package),
+            Some(NameSegment::Class(class)) => self.class_location(class, None),
+            None if index == resolved.len() => match resolved.last() {
+                Some(NameSegment::Class(class)) => self.class_location(class, Some(segments[index])),
+                _ => None,
+            },
+            None => None,
+        };
+    }
+
+    /// Where `class`, or its member `member_name`, is declared.
+    fn class_location(&self, class: &ResolvedClass, member_name: Option<&str>) -> Option<Location> {
+        return match class {
+            ResolvedClass::Source(source_uri, class_name) => self.source_class_location(source_uri, class_name, member_name),
+            ResolvedClass::Dependency(class) => class_contents::dependency_location(class, member_name),
+        };
+    }
+
+    /// The package declaration of the workspace's `package-info.java` for `package`, if it has one.
+    fn package_definition(&self, package: &str) -> Option<Location> {
+        let package_infos: Vec<String> = self
+            .indexed_uris()
+            .into_iter()
+            .filter(|uri| uri.ends_with("/package-info.java"))
+            .collect();
+        let uri = package_infos
+            .into_iter()
+            .find(|uri| self.package_of(uri).as_deref() == Some(package))?;
+        let (tree, source) = self.document_source(&uri)?;
+        let root = tree.root_node();
+        let package_node = root
+            .named_children(&mut root.walk())
+            .find(|node| node.kind() == "package_declaration")?;
+        let name_node = package_node.named_child(package_node.named_child_count().checked_sub(1)?)?;
+        return Some(Location {
+            uri: Url::parse(&uri).ok()?,
+            range: LineIndex::new(&source).range(name_node.start_position(), name_node.end_position()),
+        });
+    }
+}
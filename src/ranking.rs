@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+use serde_json::json;
+use tower_lsp::lsp_types::{Command, CompletionItem};
+
+use crate::commands;
+
+/// How many accepted completions are remembered.
+const MAX_RECENT: usize = 50;
+
+/// How near to the cursor what an item completes is declared, nearest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Proximity {
+    /// a member of the type itself, or an element of the annotation being written
+    Member,
+    Keyword,
+    /// a class of the document's package, or one it imports
+    Package,
+    /// declared in the workspace
+    Workspace,
+    /// declared by a dependency or the JDK
+    Library,
+}
+
+impl Proximity {
+    /// The key producers leave in the `sort_text` of their items for [`rank`] to find.
+    pub fn key(self) -> Option<String> {
+        return Some((self as u8).to_string());
+    }
+
+    fn of_key(key: Option<&str>) -> Proximity {
+        return match key {
+            Some("0") => Proximity::Member,
+            Some("1") => Proximity::Keyword,
+            Some("2") => Proximity::Package,
+            Some("3") => Proximity::Workspace,
+            _ => Proximity::Library,
+        };
+    }
+
+    /// Members of dependency classes are as far as the classes.
+    pub fn of_declaring_uri(declaring_uri: &str) -> Proximity {
+        return if declaring_uri.starts_with("jar:") { Proximity::Library } else { Proximity::Workspace };
+    }
+}
+
+/// How well what was typed matches a label, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchQuality {
+    Exact,
+    Prefix,
+    CaseInsensitivePrefix,
+    // like `NPE` or `NulPoEx` for `NullPointerException`
+    CamelCase,
+    // the typed characters in order, starting with the first
+    Subsequence,
+}
+
+/// How `typed` matches `label`, `None` when it doesn't. Nothing typed matches anything as a
+/// prefix.
+pub fn match_quality(typed: &str, label: &str) -> Option<MatchQuality> {
+    if label == typed {
+        return Some(MatchQuality::Exact);
+    }
+    if label.starts_with(typed) {
+        return Some(MatchQuality::Prefix);
+    }
+    let typed_lowercase = typed.to_lowercase();
+    let label_lowercase = label.to_lowercase();
+    if label_lowercase.starts_with(&typed_lowercase) {
+        return Some(MatchQuality::CaseInsensitivePrefix);
+    }
+    if is_camel_case_match(typed, label) {
+        return Some(MatchQuality::CamelCase);
+    }
+    let mut label_chars = label_lowercase.chars();
+    let is_subsequence = typed_lowercase.chars().all(|typed_char| label_chars.any(|label_char| label_char == typed_char));
+    if is_subsequence && label_lowercase.chars().next() == typed_lowercase.chars().next() {
+        return Some(MatchQuality::Subsequence);
+    }
+    return None;
+}
+
+/// Whether `typed` is made of the starts of the humps of `label`, in order, the first hump
+/// included.
+fn is_camel_case_match(typed: &str, label: &str) -> bool {
+    let typed: Vec<char> = typed.chars().collect();
+    let humps: Vec<Vec<char>> = humps(label).iter().map(|hump| hump.chars().collect()).collect();
+    return !typed.is_empty() && !humps.is_empty() && matches_humps(&typed, &humps[0], &humps[1..]);
+}
+
+/// Whether `typed` starts with a start of `hump` after which the rest matches some of the
+/// humps that follow, trying the longest start first.
+fn matches_humps(typed: &[char], hump: &[char], rest: &[Vec<char>]) -> bool {
+    let matched = typed
+        .iter()
+        .zip(hump)
+        .take_while(|(typed_char, hump_char)| typed_char.eq_ignore_ascii_case(hump_char))
+        .count();
+    for length in (1..=matched).rev() {
+        let remaining = &typed[length..];
+        if remaining.is_empty() || rest.iter().enumerate().any(|(index, next)| matches_humps(remaining, next, &rest[index + 1..])) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// The parts of a camelCase, PascalCase or snake_case name, like `Null`, `Pointer` and
+/// `Exception`.
+fn humps(label: &str) -> Vec<&str> {
+    let mut humps = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<char> = None;
+    for (index, c) in label.char_indices() {
+        let starts_hump = match previous {
+            Some(previous) => (c.is_uppercase() && !previous.is_uppercase()) || previous == '_',
+            None => false,
+        };
+        if starts_hump && index > start {
+            humps.push(label[start..index].trim_start_matches('_'));
+            start = index;
+        }
+        previous = Some(c);
+    }
+    humps.push(label[start..].trim_start_matches('_'));
+    return humps.into_iter().filter(|hump| !hump.is_empty()).collect();
+}
+
+/// The labels of the items most recently accepted, newest first, which rank higher the next time.
+/// Clients report accepting an item by running the command it carries.
+#[derive(Debug, Default)]
+pub struct RecentCompletions {
+    labels: Mutex<VecDeque<String>>,
+}
+
+impl RecentCompletions {
+    pub fn record(&self, label: String) {
+        let mut labels = self.labels.lock().unwrap_or_else(PoisonError::into_inner);
+        labels.retain(|recent| *recent != label);
+        labels.push_front(label);
+        labels.truncate(MAX_RECENT);
+    }
+
+    fn contains(&self, label: &str) -> bool {
+        return self.labels.lock().unwrap_or_else(PoisonError::into_inner).iter().any(|recent| recent == label);
+    }
+}
+
+/// Orders `items` for the word `typed`, by how well they match it, then by whether they were
+/// accepted recently, then by the proximity their producers left in their `sort_text`, and then
+/// by label. Items are given the command that reports their acceptance.
+pub fn rank(items: &mut [CompletionItem], typed: &str, recent: &RecentCompletions) {
+    for item in items.iter_mut() {
+        let proximity = Proximity::of_key(item.sort_text.as_deref());
+        let quality = match match_quality(typed, &item.label) {
+            Some(quality) => quality as u8,
+            None => MatchQuality::Subsequence as u8 + 1,
+        };
+        let recency = if recent.contains(&item.label) { 0 } else { 1 };
+        item.sort_text = Some(format!("{}{}{}{}", quality, recency, proximity as u8, item.label));
+        item.command = Some(Command {
+            title: String::new(),
+            command: commands::COMPLETION_ACCEPTED.to_string(),
+            arguments: Some(vec![json!(item.label)]),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(label: &str, proximity: Proximity) -> CompletionItem {
+        return CompletionItem {
+            label: label.to_string(),
+            sort_text: proximity.key(),
+            ..CompletionItem::default()
+        };
+    }
+
+    fn ranked_labels(items: &mut [CompletionItem], typed: &str, recent: &RecentCompletions) -> Vec<String> {
+        rank(items, typed, recent);
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+        return items.iter().map(|item| item.label.clone()).collect();
+    }
+
+    #[test]
+    fn grades_how_typed_text_matches() {
+        assert_eq!(match_quality("List", "List"), Some(MatchQuality::Exact));
+        assert_eq!(match_quality("Lis", "List"), Some(MatchQuality::Prefix));
+        assert_eq!(match_quality("", "List"), Some(MatchQuality::Prefix));
+        assert_eq!(match_quality("lis", "List"), Some(MatchQuality::CaseInsensitivePrefix));
+        assert_eq!(match_quality("NPE", "NullPointerException"), Some(MatchQuality::CamelCase));
+        assert_eq!(match_quality("NulPoEx", "NullPointerException"), Some(MatchQuality::CamelCase));
+        assert_eq!(match_quality("npe", "NullPointerException"), Some(MatchQuality::CamelCase));
+        assert_eq!(match_quality("nlpr", "NullPointerException"), Some(MatchQuality::Subsequence));
+        assert_eq!(match_quality("PE", "NullPointerException"), None);
+        assert_eq!(match_quality("xyz", "NullPointerException"), None);
+    }
+
+    #[test]
+    fn matches_humps_trying_shorter_starts() {
+        assert!(is_camel_case_match("getN", "getUserName"));
+        assert!(is_camel_case_match("MAX_V", "MAX_VALUE"));
+        // `Ar` can't all come from `Array`, since `r` has to start a later hump
+        assert!(is_camel_case_match("ALR", "ArrayListReader"));
+        assert!(!is_camel_case_match("AXL", "ArrayList"));
+        assert_eq!(humps("NullPointerException"), vec!["Null", "Pointer", "Exception"]);
+        // the underscore stays with the hump before it, so it can be typed
+        assert_eq!(humps("MAX_VALUE"), vec!["MAX_", "VALUE"]);
+        assert_eq!(humps("_private"), vec!["private"]);
+    }
+
+    #[test]
+    fn ranks_by_match_then_recency_then_proximity() {
+        let recent = RecentCompletions::default();
+        let mut items = vec![
+            item("lastIndex", Proximity::Library),
+            item("list", Proximity::Workspace),
+            item("lst", Proximity::Member),
+            item("List", Proximity::Library),
+            item("length", Proximity::Member),
+        ];
+        assert_eq!(ranked_labels(&mut items, "list", &recent), vec!["list", "List", "length", "lst", "lastIndex"]);
+        recent.record("lastIndex".to_string());
+        let mut items = vec![
+            item("lastIndex", Proximity::Library),
+            item("listOf", Proximity::Member),
+            item("listIterator", Proximity::Library),
+        ];
+        assert_eq!(ranked_labels(&mut items, "l", &recent), vec!["lastIndex", "listOf", "listIterator"]);
+        assert_eq!(items[0].command.as_ref().unwrap().command, commands::COMPLETION_ACCEPTED);
+    }
+
+    #[test]
+    fn remembers_a_bounded_number_of_recent_labels() {
+        let recent = RecentCompletions::default();
+        for index in 0..=MAX_RECENT {
+            recent.record(format!("item{}", index));
+        }
+        recent.record("item1".to_string());
+        assert!(!recent.contains("item0"));
+        assert!(recent.contains("item1"));
+        assert!(recent.contains(&format!("item{}", MAX_RECENT)));
+        assert_eq!(recent.labels.lock().unwrap_or_else(PoisonError::into_inner).front().map(String::as_str), Some("item1"));
+    }
+}
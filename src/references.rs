@@ -0,0 +1,244 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+
+use log::debug;
+use serde::Deserialize;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tree_sitter::Tree;
+
+use crate::cancellation::Cancellation;
+use crate::error;
+use crate::line_index::LineIndex;
+use crate::{Backend, TokenType};
+
+/// Parameters of the `javals/findReferences` request: a regular reference search restricted to
+/// the documents in `scope`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindReferencesParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    #[serde(default)]
+    pub scope: SearchScope,
+    #[serde(default)]
+    pub include_declaration: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchScope {
+    File,
+    Package,
+    Module,
+    #[default]
+    Workspace,
+}
+
+impl Backend {
+    pub async fn find_scoped_references(&self, params: FindReferencesParams) -> Result<Option<Vec<Location>>> {
+        debug!("find_scoped_references {} {:?} {:?}", params.text_document.uri, params.position, params.scope);
+        let references = async {
+            self.pending_changes.settled().await;
+            let progress = match params.scope {
+                SearchScope::File => None,
+                _ => self.begin_progress("findReferences", "Finding references", None).await,
+            };
+            let cancellation = &mut Cancellation::with_progress(progress);
+            Ok(self.find_references(&params.text_document.uri, params.position, params.scope, params.include_declaration, cancellation).await)
+        };
+        return error::guarded("javals/findReferences", references).await;
+    }
+
+    /// Finds every identifier in `scope` that resolves to the declaration of the identifier at
+    /// `position`. Identifiers in other documents can't be resolved through local scopes, so for
+    /// classes, methods and fields they are matched by name. Checks for cancellation between
+    /// documents, and reports the documents searched to the progress, if any, returning the
+    /// references found so far when the user cancels it.
+    pub async fn find_references(
+        &self,
+        uri: &Url,
+        position: Position,
+        scope: SearchScope,
+        include_declaration: bool,
+        cancellation: &mut Cancellation,
+    ) -> Option<Vec<Location>> {
+        let (declaration, token) = {
+            let tree = self.parsed_document_map.get(uri.as_str())?;
+            let source_text = self.document_map.get(uri.as_str())?;
+            let point = LineIndex::new(&source_text).point(position);
+            let node = tree.root_node().named_descendant_for_point_range(point, point)?;
+            if node.kind() != "identifier" && node.kind() != "type_identifier" {
+                return None;
+            }
+            let token = node.utf8_text(source_text.as_bytes()).unwrap().to_string();
+            (self.find_declaration(uri.as_str(), node, &token)?, token)
+        };
+        let visible_across_files = matches!(
+            declaration.token_type,
+            TokenType::ClassName | TokenType::MemberVariable(_) | TokenType::MethodName(_)
+        );
+        let origin_package = self.package_of(uri.as_str());
+        let origin_module = module_root(uri);
+        // looking for build files takes a few reads per directory, and documents share them
+        let mut module_roots: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+        let mut references = Vec::new();
+        // documents whose text was dropped are only read again when they may contain the token
+        let candidate_paths: HashSet<PathBuf> = self
+            .search_index
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .candidate_files(&token)
+            .into_iter()
+            .map(Path::to_path_buf)
+            .collect();
+        let may_contain_token = |document_uri: &str| {
+            if self.parsed_document_map.contains_key(document_uri) {
+                return true;
+            }
+            let path = Url::parse(document_uri).ok().and_then(|document_url| document_url.to_file_path().ok());
+            return path.map(|path| candidate_paths.contains(&path)).unwrap_or(false);
+        };
+        let document_uris = self.indexed_uris();
+        let mut reported_percentage = 0;
+        for (searched, document_uri) in document_uris.iter().enumerate() {
+            if document_uri != uri.as_str() && !visible_across_files {
+                continue;
+            }
+            if !may_contain_token(document_uri) {
+                continue;
+            }
+            let in_scope = match scope {
+                SearchScope::File => document_uri == uri.as_str(),
+                SearchScope::Package => true, // once the document is read
+                SearchScope::Module => {
+                    let path = Url::parse(document_uri).ok().and_then(|document_url| document_url.to_file_path().ok());
+                    match (path.as_deref().and_then(Path::parent), &origin_module) {
+                        (Some(directory), Some(origin_module)) => {
+                            let module = module_roots
+                                .entry(directory.to_path_buf())
+                                .or_insert_with_key(|directory| directory_module_root(directory));
+                            module == origin_module
+                        }
+                        // documents that aren't files are in no module
+                        _ => false,
+                    }
+                }
+                SearchScope::Workspace => true,
+            };
+            if !in_scope {
+                continue;
+            }
+            cancellation.checkpoint().await;
+            if cancellation.is_cancelled() {
+                break;
+            }
+            if let Some(progress) = cancellation.progress() {
+                let percentage = searched * 100 / document_uris.len();
+                if percentage > reported_percentage {
+                    reported_percentage = percentage;
+                    progress.report(format!("{}/{} files", searched, document_uris.len()), percentage).await;
+                }
+            }
+            let (tree, source_text) = match self.document_source(document_uri) {
+                Some(source) => source,
+                None => continue,
+            };
+            if scope == SearchScope::Package && package_name(&tree, &source_text) != origin_package {
+                continue;
+            }
+            let document_url = match Url::parse(document_uri) {
+                Ok(document_url) => document_url,
+                Err(_) => continue,
+            };
+            let lines = LineIndex::new(&source_text);
+            for node in tree_sitter_traversal::traverse(tree.walk(), tree_sitter_traversal::Order::Pre) {
+                if node.kind() != "identifier" && node.kind() != "type_identifier" {
+                    continue;
+                }
+                if node.utf8_text(source_text.as_bytes()).unwrap() != token {
+                    continue;
+                }
+                let is_reference = match self.find_declaration(document_uri, node, &token) {
+                    Some(location) => location.uri == declaration.uri && location.start_position == declaration.start_position,
+                    None => document_uri != &declaration.uri && visible_across_files,
+                };
+                if !is_reference {
+                    continue;
+                }
+                let is_declaration = document_uri == &declaration.uri && node.start_position() == declaration.start_position;
+                if is_declaration && !include_declaration {
+                    continue;
+                }
+                references.push(Location {
+                    uri: document_url.clone(),
+                    range: lines.range(node.start_position(), node.end_position()),
+                });
+            }
+        }
+        return Some(references);
+    }
+
+    /// The occurrences in the document at `uri` of what the identifier at `position` declares or
+    /// refers to, its declaration included.
+    pub async fn document_highlights(&self, uri: &Url, position: Position, cancellation: &mut Cancellation) -> Option<Vec<DocumentHighlight>> {
+        let references = self.find_references(uri, position, SearchScope::File, true, cancellation).await?;
+        return Some(
+            references
+                .into_iter()
+                .map(|location| DocumentHighlight {
+                    range: location.range,
+                    kind: Some(DocumentHighlightKind::TEXT),
+                })
+                .collect(),
+        );
+    }
+
+    pub fn package_of(&self, uri: &str) -> Option<String> {
+        let (tree, source_text) = self.document_source(uri)?;
+        return package_name(&tree, &source_text);
+    }
+}
+
+/// The name in the `package` declaration of a compilation unit.
+pub fn package_name(tree: &Tree, source: &str) -> Option<String> {
+    let root = tree.root_node();
+    let package_node = root
+        .named_children(&mut root.walk())
+        .find(|n| n.kind() == "package_declaration")?;
+    let name_node = package_node
+        .named_children(&mut package_node.walk())
+        .find(|n| n.kind() == "scoped_identifier" || n.kind() == "identifier")?;
+    return Some(name_node.utf8_text(source.as_bytes()).unwrap().to_string());
+}
+
+/// The directory of the build module containing `uri`: the nearest ancestor with a build file,
+/// or else the directory above `src/`, or else the file's own directory. `None` when `uri` isn't
+/// a file.
+pub fn module_root(uri: &Url) -> Option<PathBuf> {
+    let path = uri.to_file_path().ok()?;
+    return Some(directory_module_root(path.parent()?));
+}
+
+/// The directory of the build module containing the files of `directory`, like `module_root`.
+fn directory_module_root(directory: &Path) -> PathBuf {
+    for ancestor in directory.ancestors() {
+        let has_build_file = ["pom.xml", "build.gradle", "build.gradle.kts"]
+            .iter()
+            .any(|build_file| ancestor.join(build_file).is_file());
+        if has_build_file {
+            return ancestor.to_path_buf();
+        }
+    }
+    for ancestor in directory.ancestors() {
+        if ancestor.file_name().map(|name| name == "src").unwrap_or(false) {
+            // a directory with a name has a parent
+            if let Some(parent) = ancestor.parent() {
+                return parent.to_path_buf();
+            }
+        }
+    }
+    return directory.to_path_buf();
+}
@@ -0,0 +1,167 @@
+use std::sync::{PoisonError, RwLock};
+
+use log::info;
+use tower_lsp::lsp_types::*;
+
+use crate::settings::Features;
+use crate::Backend;
+
+/// The providers the `features` settings turn off. Those the client can register dynamically are
+/// registered while they're on, and left out of the capabilities the server starts with; the
+/// others are always advertised, and answer nothing while they're off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionalProvider {
+    Formatting,
+    RangeFormatting,
+    OnTypeFormatting,
+    DocumentHighlight,
+}
+
+impl OptionalProvider {
+    pub const ALL: [OptionalProvider; 4] = [
+        OptionalProvider::Formatting,
+        OptionalProvider::RangeFormatting,
+        OptionalProvider::OnTypeFormatting,
+        OptionalProvider::DocumentHighlight,
+    ];
+
+    fn method(self) -> &'static str {
+        return match self {
+            OptionalProvider::Formatting => "textDocument/formatting",
+            OptionalProvider::RangeFormatting => "textDocument/rangeFormatting",
+            OptionalProvider::OnTypeFormatting => "textDocument/onTypeFormatting",
+            OptionalProvider::DocumentHighlight => "textDocument/documentHighlight",
+        };
+    }
+
+    pub fn is_enabled(self, features: &Features) -> bool {
+        return match self {
+            OptionalProvider::Formatting | OptionalProvider::RangeFormatting => features.formatting,
+            OptionalProvider::OnTypeFormatting => features.on_type_formatting,
+            OptionalProvider::DocumentHighlight => features.document_highlight,
+        };
+    }
+
+    fn can_register(self, capabilities: &TextDocumentClientCapabilities) -> bool {
+        let dynamic_registration = match self {
+            OptionalProvider::Formatting => capabilities.formatting.as_ref().and_then(|formatting| formatting.dynamic_registration),
+            OptionalProvider::RangeFormatting => capabilities
+                .range_formatting
+                .as_ref()
+                .and_then(|formatting| formatting.dynamic_registration),
+            OptionalProvider::OnTypeFormatting => capabilities
+                .on_type_formatting
+                .as_ref()
+                .and_then(|formatting| formatting.dynamic_registration),
+            OptionalProvider::DocumentHighlight => capabilities
+                .document_highlight
+                .as_ref()
+                .and_then(|highlight| highlight.dynamic_registration),
+        };
+        return dynamic_registration.unwrap_or(false);
+    }
+
+    fn registration(self) -> Registration {
+        let document_selector = Some(vec![
+            DocumentFilter {
+                language: Some("java".to_string()),
+                scheme: None,
+                pattern: None,
+            },
+            DocumentFilter {
+                language: None,
+                scheme: None,
+                pattern: Some("**/*.java".to_string()),
+            },
+        ]);
+        let register_options = match self {
+            OptionalProvider::OnTypeFormatting => serde_json::to_value(DocumentOnTypeFormattingRegistrationOptions {
+                document_selector,
+                first_trigger_character: ON_TYPE_FORMATTING_TRIGGER.to_string(),
+                more_trigger_character: Some(ON_TYPE_FORMATTING_MORE_TRIGGERS.iter().map(|trigger| trigger.to_string()).collect()),
+            }),
+            _ => serde_json::to_value(TextDocumentRegistrationOptions { document_selector }),
+        };
+        return Registration {
+            id: format!("javals/{}", self.method()),
+            method: self.method().to_string(),
+            register_options: register_options.ok(),
+        };
+    }
+}
+
+pub const ON_TYPE_FORMATTING_TRIGGER: &str = "}";
+
+pub const ON_TYPE_FORMATTING_MORE_TRIGGERS: [&str; 2] = [";", "\n"];
+
+/// Which optional providers the client registers dynamically, and which of those are registered.
+#[derive(Debug, Default)]
+pub struct ProviderRegistrations {
+    dynamic: RwLock<Vec<OptionalProvider>>,
+    registered: RwLock<Vec<OptionalProvider>>,
+}
+
+impl ProviderRegistrations {
+    /// Notes the providers the client can register dynamically, which is what
+    /// [`ProviderRegistrations::is_static`] is told by.
+    pub fn negotiate(&self, capabilities: Option<&TextDocumentClientCapabilities>) {
+        *self.dynamic.write().unwrap_or_else(PoisonError::into_inner) = match capabilities {
+            Some(capabilities) => OptionalProvider::ALL.into_iter().filter(|provider| provider.can_register(capabilities)).collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Whether `provider` is among the capabilities the server starts with.
+    pub fn is_static(&self, provider: OptionalProvider) -> bool {
+        return !self.dynamic.read().unwrap_or_else(PoisonError::into_inner).contains(&provider);
+    }
+}
+
+impl Backend {
+    /// Whether `provider` answers, as the `features` settings say.
+    pub fn is_provider_enabled(&self, provider: OptionalProvider) -> bool {
+        return provider.is_enabled(&self.config.read().unwrap_or_else(PoisonError::into_inner).features);
+    }
+
+    /// Registers the dynamically registered providers that the settings turned on, and
+    /// unregisters those they turned off.
+    pub async fn update_provider_registrations(&self) {
+        let (to_register, to_unregister) = {
+            let features = self.config.read().unwrap_or_else(PoisonError::into_inner).features;
+            let dynamic = self.provider_registrations.dynamic.read().unwrap_or_else(PoisonError::into_inner);
+            let registered = self.provider_registrations.registered.read().unwrap_or_else(PoisonError::into_inner);
+            let wanted: Vec<OptionalProvider> = dynamic.iter().copied().filter(|provider| provider.is_enabled(&features)).collect();
+            (
+                wanted.iter().copied().filter(|provider| !registered.contains(provider)).collect::<Vec<_>>(),
+                registered.iter().copied().filter(|provider| !wanted.contains(provider)).collect::<Vec<_>>(),
+            )
+        };
+        if !to_register.is_empty() {
+            match self.client.register_capability(to_register.iter().map(|provider| provider.registration()).collect()).await {
+                Ok(()) => self.provider_registrations.registered.write().unwrap_or_else(PoisonError::into_inner).extend(to_register),
+                Err(error) => info!("unable to register {:?}: {:?}", to_register, error),
+            }
+        }
+        if !to_unregister.is_empty() {
+            let unregistrations = to_unregister
+                .iter()
+                .map(|provider| {
+                    let registration = provider.registration();
+                    return Unregistration {
+                        id: registration.id,
+                        method: registration.method,
+                    };
+                })
+                .collect();
+            match self.client.unregister_capability(unregistrations).await {
+                Ok(()) => self
+                    .provider_registrations
+                    .registered
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .retain(|provider| !to_unregister.contains(provider)),
+                Err(error) => info!("unable to unregister {:?}: {:?}", to_unregister, error),
+            }
+        }
+    }
+}
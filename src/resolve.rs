@@ -0,0 +1,474 @@
+use tree_sitter::Node;
+
+use crate::hierarchy;
+use crate::{Backend, TokenLocation, TokenType};
+
+/// Methods every class inherits from `java.lang.Object`.
+pub const OBJECT_METHOD_NAMES: [&str; 9] = [
+    "clone",
+    "equals",
+    "finalize",
+    "getClass",
+    "hashCode",
+    "notify",
+    "notifyAll",
+    "toString",
+    "wait",
+];
+
+impl Backend {
+    /// The simple name of the class a method invocation is dispatched on: for unqualified calls
+    /// the innermost enclosing class having the method, or else the class a static import
+    /// provides it from, the enclosing class for `this.` calls, the superclass or interface named
+    /// for `super.` calls, the declared type of a variable receiver, or the class itself for a
+    /// static call through a class name.
+    pub fn invocation_receiver_type(&self, uri: &str, invocation_node: Node, source: &str) -> Option<String> {
+        let object_node = match invocation_node.child_by_field_name("object") {
+            Some(object_node) => object_node,
+            None => {
+                let method_name = invocation_node.child_by_field_name("name")?.utf8_text(source.as_bytes()).unwrap();
+                // the innermost class declaring or inheriting the method, or that might
+                let mut class_node = enclosing_class_node(invocation_node);
+                while let Some(current_node) = class_node {
+                    if current_node.kind() == "class_body" {
+                        let declares_method = hierarchy::member_method_declarations(current_node).iter().any(|method_node| {
+                            method_node.child_by_field_name("name").map(|name_node| name_node.utf8_text(source.as_bytes()).unwrap()) == Some(method_name)
+                        });
+                        if declares_method {
+                            // an anonymous class's own methods are in no indexed type
+                            return None;
+                        }
+                    }
+                    let class_name = class_name(current_node, source)?;
+                    if self.declares_method(uri, &class_name, method_name) != Some(false) {
+                        return Some(class_name);
+                    }
+                    class_node = enclosing_class_node(current_node);
+                }
+                if let Some((class_name, _)) = self.static_import_owner(uri, method_name, true) {
+                    return Some(class_name);
+                }
+                return this_type_name(invocation_node, source);
+            }
+        };
+        // `Outer.super.run()` and `Interface.super.run()` keep `super` beside the object
+        if invocation_node.children(&mut invocation_node.walk()).any(|child| child.kind() == "super" && child != object_node) {
+            return qualified_super_type(object_node, source);
+        }
+        return self.expression_type(uri, object_node, source);
+    }
+
+    /// A best-effort static type for simple expressions: literals, variables with a declared type,
+    /// inherited fields, `this` and `super`, plain or qualified by an enclosing class, class names,
+    /// constructor calls, array elements, switch expressions, and fields and methods of those
+    /// through their declared types. Generic arguments are erased.
+    pub fn expression_type(&self, uri: &str, expression_node: Node, source: &str) -> Option<String> {
+        let text = expression_node.utf8_text(source.as_bytes()).unwrap();
+        let expression_type = match expression_node.kind() {
+            "this" => return this_type_name(expression_node, source),
+            "super" => return super_type_name(enclosing_class_node(expression_node)?, source),
+            "decimal_integer_literal" | "hex_integer_literal" | "octal_integer_literal" | "binary_integer_literal" => {
+                if text.ends_with('l') || text.ends_with('L') {
+                    "long"
+                } else {
+                    "int"
+                }
+            }
+            "decimal_floating_point_literal" | "hex_floating_point_literal" => {
+                if text.ends_with('f') || text.ends_with('F') {
+                    "float"
+                } else {
+                    "double"
+                }
+            }
+            "true" | "false" => "boolean",
+            "character_literal" => "char",
+            "string_literal" => "String",
+            "null_literal" => "Object",
+            "object_creation_expression" => {
+                let type_node = expression_node.child_by_field_name("type")?;
+                return Some(hierarchy::erased_type(type_node, source));
+            }
+            "parenthesized_expression" => {
+                return self.expression_type(uri, expression_node.named_child(0)?, source);
+            }
+            "array_access" => {
+                let array_type = self.expression_type(uri, expression_node.child_by_field_name("array")?, source)?;
+                return array_type.strip_suffix("[]").map(|element_type| element_type.to_string());
+            }
+            "cast_expression" => {
+                let type_node = expression_node.child_by_field_name("type")?;
+                return Some(hierarchy::erased_type(type_node, source));
+            }
+            "field_access" => {
+                if let Some(class_node) = qualified_this_node(expression_node, source) {
+                    return class_name(class_node, source);
+                }
+                if expression_node.child_by_field_name("field")?.kind() == "super" {
+                    return qualified_super_type(expression_node.child_by_field_name("object")?, source);
+                }
+                if let Some(class_name) = self.dotted_name_class(uri, expression_node, source) {
+                    return Some(class_name);
+                }
+                let object_node = expression_node.child_by_field_name("object")?;
+                let object_type = self.expression_type(uri, object_node, source)?;
+                let field_name = expression_node.child_by_field_name("field")?.utf8_text(source.as_bytes()).unwrap();
+                return self.field_type(uri, &object_type, &self.type_arguments(uri, object_node, source), field_name);
+            }
+            "method_invocation" => {
+                return self.invocation_type(uri, expression_node, source);
+            }
+            "switch_expression" => {
+                return switch_results(expression_node)
+                    .into_iter()
+                    .find_map(|result_node| self.expression_type(uri, result_node, source));
+            }
+            // error recovery can turn an incomplete expression like `list.` into a type
+            "identifier" | "type_identifier" => {
+                if let Some(location) = self.find_declaration(uri, expression_node, text) {
+                    if let Some(initializer_node) = var_initializer(expression_node, &location) {
+                        return self.expression_type(uri, initializer_node, source);
+                    }
+                    if let Some((lambda_node, index)) = lambda_parameter(expression_node, &location) {
+                        return self.lambda_parameter_type(uri, lambda_node, index, source);
+                    }
+                    let declared_type = match location.token_type {
+                        // a class named in its own document, used as a static receiver
+                        TokenType::ClassName => return Some(text.to_string()),
+                        TokenType::MemberVariable(declared_type) => declared_type,
+                        TokenType::ParameterName(declared_type) => declared_type,
+                        TokenType::LocalVariable(declared_type) => declared_type,
+                        _ => None,
+                    };
+                    return declared_type.map(|t| erase(&t));
+                }
+                if let Some((declaring_uri, declaration)) = self.inherited_field(uri, expression_node, source) {
+                    let field = declaration.fields.iter().find(|field| field.name == text)?;
+                    return Some(self.qualify_type(&self.type_context(uri, &declaring_uri), &field.field_type));
+                }
+                if let Some((class_name, _)) = self.static_import_owner(uri, text, false) {
+                    return self.field_type(uri, &class_name, &[], text);
+                }
+                // an unresolved capitalized name is most likely a class used as a static receiver
+                if text.chars().next().map(|c| c.is_uppercase()).unwrap_or(false) {
+                    return Some(text.to_string());
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        return Some(expression_type.to_string());
+    }
+
+    /// The type arguments of the type of a simple expression, as written in the declaration of
+    /// the variable or member it reads or the type it creates or casts to, like `String` for a
+    /// variable declared as `List<String>`. They're qualified like types `expression_type` finds.
+    pub fn type_arguments(&self, uri: &str, expression_node: Node, source: &str) -> Vec<String> {
+        let text = |node: Node| node.utf8_text(source.as_bytes()).unwrap().to_string();
+        let written = match expression_node.kind() {
+            "identifier" => {
+                let location = self.find_declaration(uri, expression_node, &text(expression_node));
+                if let Some(initializer_node) = location.as_ref().and_then(|location| var_initializer(expression_node, location)) {
+                    return self.type_arguments(uri, initializer_node, source);
+                }
+                match location.map(|location| location.token_type) {
+                    Some(TokenType::MemberVariable(Some(declared_type)))
+                    | Some(TokenType::ParameterName(Some(declared_type)))
+                    | Some(TokenType::LocalVariable(Some(declared_type))) => Some((uri.to_string(), declared_type)),
+                    _ => None,
+                }
+            }
+            "object_creation_expression" | "cast_expression" => {
+                expression_node.child_by_field_name("type").map(|type_node| (uri.to_string(), text(type_node)))
+            }
+            "parenthesized_expression" => {
+                return match expression_node.named_child(0) {
+                    Some(inner_node) => self.type_arguments(uri, inner_node, source),
+                    None => Vec::new(),
+                };
+            }
+            "field_access" | "method_invocation" => {
+                let is_method = expression_node.kind() == "method_invocation";
+                let (receiver_type, member_node) = match is_method {
+                    true => (self.invocation_receiver_type(uri, expression_node, source), expression_node.child_by_field_name("name")),
+                    false => (
+                        expression_node.child_by_field_name("object").and_then(|object_node| self.expression_type(uri, object_node, source)),
+                        expression_node.child_by_field_name("field"),
+                    ),
+                };
+                let member_name = member_node.map(text).unwrap_or_default();
+                receiver_type
+                    .and_then(|receiver_type| self.member_declaration(uri, &receiver_type, &member_name, is_method))
+                    .and_then(|(declaring_uri, declaration)| {
+                        let written = match is_method {
+                            true => declaration.methods.iter().find(|method| method.name == member_name)?.return_type.clone(),
+                            false => declaration.fields.iter().find(|field| field.name == member_name)?.field_type.clone(),
+                        };
+                        Some((self.type_context(uri, &declaring_uri), written))
+                    })
+            }
+            _ => None,
+        };
+        return match written {
+            Some((context_uri, written)) => written_type_arguments(&written)
+                .iter()
+                .map(|type_argument| self.qualify_type(&context_uri, type_argument))
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Whether `method_name` is declared by `type_name` or any of its supertypes. Returns `None`
+    /// when a supertype isn't indexed, since the method could be inherited from it.
+    pub fn declares_method(&self, uri: &str, type_name: &str, method_name: &str) -> Option<bool> {
+        let hierarchy = self.type_hierarchy(uri, type_name);
+        let is_declared = hierarchy.types.iter().enumerate().any(|(index, (_, declaration))| {
+            declaration.methods.iter().any(|m| m.name == method_name && (index == 0 || declaration.is_inherited(m)))
+        });
+        if is_declared {
+            return Some(true);
+        }
+        if !hierarchy.missing.is_empty() {
+            return None;
+        }
+        return Some(OBJECT_METHOD_NAMES.contains(&method_name));
+    }
+}
+
+/// The initializer of the local `location` declares when it's declared with `var`. `node` is the
+/// name being resolved, which gets none when read within the initializer itself, since its type
+/// would depend on itself.
+fn var_initializer<'t>(node: Node<'t>, location: &TokenLocation) -> Option<Node<'t>> {
+    match &location.token_type {
+        TokenType::LocalVariable(Some(declared_type)) if declared_type == "var" => {}
+        _ => return None,
+    }
+    let declarator_node = declared_name_node(node, location)?.parent().filter(|n| n.kind() == "variable_declarator")?;
+    let initializer_node = declarator_node.child_by_field_name("value")?;
+    if initializer_node.byte_range().contains(&node.start_byte()) {
+        return None;
+    }
+    return Some(initializer_node);
+}
+
+/// The lambda declaring the parameter `location` is for and the parameter's position, when the
+/// parameter's type isn't written out. `node` is the name being resolved.
+fn lambda_parameter<'t>(node: Node<'t>, location: &TokenLocation) -> Option<(Node<'t>, usize)> {
+    match &location.token_type {
+        TokenType::ParameterName(None) => {}
+        TokenType::ParameterName(Some(declared_type)) if declared_type == "var" => {}
+        _ => return None,
+    }
+    let name_node = declared_name_node(node, location)?;
+    let parent_node = name_node.parent()?;
+    return match parent_node.kind() {
+        "lambda_expression" => Some((parent_node, 0)),
+        "inferred_parameters" => {
+            let index = parent_node.named_children(&mut parent_node.walk()).position(|n| n == name_node)?;
+            Some((parent_node.parent()?, index))
+        }
+        "formal_parameter" => {
+            let parameters_node = parent_node.parent()?;
+            let index = parameters_node
+                .named_children(&mut parameters_node.walk())
+                .filter(|n| n.kind() == "formal_parameter")
+                .position(|n| n == parent_node)?;
+            Some((parameters_node.parent().filter(|n| n.kind() == "lambda_expression")?, index))
+        }
+        _ => None,
+    };
+}
+
+/// The name `location` declares, found in the tree holding `node`.
+fn declared_name_node<'t>(node: Node<'t>, location: &TokenLocation) -> Option<Node<'t>> {
+    let mut root_node = node;
+    while let Some(parent_node) = root_node.parent() {
+        root_node = parent_node;
+    }
+    return root_node.descendant_for_point_range(location.start_position, location.end_position);
+}
+
+/// The expressions the cases of a switch expression result in: those after an arrow, and those
+/// the blocks of arrow cases or the statements of old-style cases `yield`.
+fn switch_results(switch_node: Node) -> Vec<Node> {
+    let mut results = Vec::new();
+    let body_node = match switch_node.child_by_field_name("body") {
+        Some(body_node) => body_node,
+        None => return results,
+    };
+    for case_node in body_node.named_children(&mut body_node.walk()) {
+        for statement_node in case_node.named_children(&mut case_node.walk()) {
+            match statement_node.kind() {
+                "switch_label" => continue,
+                "expression_statement" if case_node.kind() == "switch_rule" => results.extend(statement_node.named_child(0)),
+                _ => results.extend(yielded_values(statement_node)),
+            }
+        }
+    }
+    return results;
+}
+
+/// The values the `yield` statements in `node` yield, leaving those of nested switches alone.
+fn yielded_values(node: Node) -> Vec<Node> {
+    if node.kind() == "yield_statement" {
+        return node.named_children(&mut node.walk()).filter(|n| n.kind() != "comment").take(1).collect();
+    }
+    if node.kind() == "switch_expression" || node.kind() == "lambda_expression" || hierarchy::is_type_declaration(node.kind()) {
+        return Vec::new();
+    }
+    return node.named_children(&mut node.walk()).flat_map(yielded_values).collect();
+}
+
+/// The nearest enclosing class, interface, enum or record declaration. Anonymous class bodies stop
+/// the search, since they have no name to resolve against.
+pub fn enclosing_type_node(node: Node) -> Option<Node> {
+    let mut current = node.parent();
+    while let Some(candidate) = current {
+        if hierarchy::is_type_declaration(candidate.kind()) {
+            return Some(candidate);
+        }
+        if candidate.kind() == "class_body" && candidate.parent().map(|p| p.kind()) == Some("object_creation_expression") {
+            return None;
+        }
+        current = candidate.parent();
+    }
+    return None;
+}
+
+pub fn enclosing_type_name(node: Node, source: &str) -> Option<String> {
+    let type_node = enclosing_type_node(node)?;
+    let name_node = type_node.child_by_field_name("name")?;
+    return Some(name_node.utf8_text(source.as_bytes()).unwrap().to_string());
+}
+
+/// The class an unqualified `this` at `node` refers to: the nearest enclosing type declaration,
+/// or the body of an anonymous class.
+pub fn enclosing_class_node(node: Node) -> Option<Node> {
+    let mut current = node.parent();
+    while let Some(candidate) = current {
+        if hierarchy::is_type_declaration(candidate.kind()) || is_anonymous_class_body(candidate) {
+            return Some(candidate);
+        }
+        current = candidate.parent();
+    }
+    return None;
+}
+
+fn is_anonymous_class_body(node: Node) -> bool {
+    return node.kind() == "class_body" && node.parent().map(|p| p.kind()) == Some("object_creation_expression");
+}
+
+/// The name of the type `this` has at `node`.
+pub fn this_type_name(node: Node, source: &str) -> Option<String> {
+    return class_name(enclosing_class_node(node)?, source);
+}
+
+/// The name of a type declaration. An anonymous class has none, so it's named by the class or
+/// interface it's created from.
+pub fn class_name(class_node: Node, source: &str) -> Option<String> {
+    if is_anonymous_class_body(class_node) {
+        let type_node = class_node.parent()?.child_by_field_name("type")?;
+        return Some(hierarchy::erased_type(type_node, source));
+    }
+    let name_node = class_node.child_by_field_name("name")?;
+    return Some(name_node.utf8_text(source.as_bytes()).unwrap().to_string());
+}
+
+/// The name of the class `super` refers to in `class_node`, as returned by `enclosing_class_node`:
+/// its superclass, the implicit one of enums and records, or the type an anonymous class is
+/// created from. Interfaces have none.
+pub fn super_type_name(class_node: Node, source: &str) -> Option<String> {
+    if is_anonymous_class_body(class_node) {
+        let type_node = class_node.parent()?.child_by_field_name("type")?;
+        return Some(hierarchy::erased_type(type_node, source));
+    }
+    let super_type = match class_node.kind() {
+        "class_declaration" => match class_node.child_by_field_name("superclass").and_then(|n| n.named_child(0)) {
+            Some(type_node) => hierarchy::erased_type(type_node, source),
+            None => "Object".to_string(),
+        },
+        "enum_declaration" => "Enum".to_string(),
+        "record_declaration" => "Record".to_string(),
+        _ => return None,
+    };
+    return Some(super_type);
+}
+
+/// The enclosing type declaration `Outer.this` refers to, for a field access of that form.
+pub fn qualified_this_node<'a>(field_access_node: Node<'a>, source: &str) -> Option<Node<'a>> {
+    if field_access_node.kind() != "field_access" || field_access_node.child_by_field_name("field")?.kind() != "this" {
+        return None;
+    }
+    let class_name = erase(field_access_node.child_by_field_name("object")?.utf8_text(source.as_bytes()).unwrap());
+    return enclosing_declaration_named(field_access_node, &class_name, source);
+}
+
+/// The type `Outer.super` or `Interface.super` refers to, given its qualifier: the superclass of the enclosing class
+/// `Outer`, or the directly implemented interface whose default methods it calls.
+pub fn qualified_super_type(qualifier_node: Node, source: &str) -> Option<String> {
+    let type_name = erase(qualifier_node.utf8_text(source.as_bytes()).unwrap());
+    return match enclosing_declaration_named(qualifier_node, &type_name, source) {
+        Some(class_node) => super_type_name(class_node, source),
+        None => Some(type_name),
+    };
+}
+
+fn enclosing_declaration_named<'a>(node: Node<'a>, name: &str, source: &str) -> Option<Node<'a>> {
+    let mut current = node.parent();
+    while let Some(candidate) = current {
+        let is_named = candidate
+            .child_by_field_name("name")
+            .map(|name_node| name_node.utf8_text(source.as_bytes()).unwrap() == name)
+            .unwrap_or(false);
+        if hierarchy::is_type_declaration(candidate.kind()) && is_named {
+            return Some(candidate);
+        }
+        current = candidate.parent();
+    }
+    return None;
+}
+
+/// The type arguments in type text, e.g. `String` and `List<T>` for `Map<String, List<T>>`.
+/// Wildcards stand for their upper bound.
+pub fn written_type_arguments(type_text: &str) -> Vec<String> {
+    let (start, end) = match (type_text.find('<'), type_text.rfind('>')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Vec::new(),
+    };
+    let mut type_arguments = Vec::new();
+    let mut depth = 0;
+    let mut argument_start = start + 1;
+    for (index, c) in type_text[..end].char_indices().filter(|(index, _)| *index > start) {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                type_arguments.push(type_text[argument_start..index].trim().to_string());
+                argument_start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    type_arguments.push(type_text[argument_start..end].trim().to_string());
+    return type_arguments
+        .into_iter()
+        .filter(|type_argument| !type_argument.is_empty())
+        .map(|type_argument| match type_argument.strip_prefix("? extends ") {
+            Some(bound) => bound.trim().to_string(),
+            None if type_argument.starts_with('?') => "Object".to_string(),
+            None => type_argument,
+        })
+        .collect();
+}
+
+/// Strips generic arguments from type text, e.g. `Map<String, List<T>>` becomes `Map`.
+pub fn erase(type_text: &str) -> String {
+    let raw = match type_text.find('<') {
+        Some(index) => {
+            let suffix = &type_text[type_text.rfind('>').map(|i| i + 1).unwrap_or(type_text.len())..];
+            format!("{}{}", &type_text[..index], suffix)
+        }
+        None => type_text.to_string(),
+    };
+    return raw.rsplit('.').next().unwrap_or(&raw).trim().to_string();
+}
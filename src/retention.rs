@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use tower_lsp::lsp_types::Url;
+use tree_sitter::Tree;
+
+use crate::workspace_index;
+use crate::Backend;
+
+/// How many documents the client doesn't have open keep their text and tree, by default.
+const DEFAULT_RETAINED_TREES: usize = 200;
+
+/// The documents the client doesn't have open whose text and tree are kept, least recently used
+/// first. What they declare stays indexed either way, which is all most features read across
+/// documents; those that need the text are given it from disk once it's dropped.
+#[derive(Debug)]
+pub struct RetainedTrees {
+    recent: Mutex<VecDeque<String>>,
+    limit: AtomicUsize,
+}
+
+impl Default for RetainedTrees {
+    fn default() -> RetainedTrees {
+        return RetainedTrees {
+            recent: Mutex::new(VecDeque::new()),
+            limit: AtomicUsize::new(DEFAULT_RETAINED_TREES),
+        };
+    }
+}
+
+impl RetainedTrees {
+    /// Keeps the document at `uri` as the most recently used, returning those beyond the limit,
+    /// whose text and tree are to be dropped.
+    pub fn retain(&self, uri: &str) -> Vec<String> {
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        recent.retain(|retained_uri| retained_uri != uri);
+        recent.push_back(uri.to_string());
+        let excess = recent.len().saturating_sub(self.limit.load(Ordering::Relaxed).max(1));
+        return recent.drain(..excess).collect();
+    }
+
+    /// Marks the document at `uri` as just used, if it's kept.
+    pub fn touch(&self, uri: &str) {
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = recent.iter().position(|retained_uri| retained_uri == uri) {
+            if let Some(retained_uri) = recent.remove(index) {
+                recent.push_back(retained_uri);
+            }
+        }
+    }
+
+    pub fn forget(&self, uri: &str) {
+        self.recent.lock().unwrap_or_else(PoisonError::into_inner).retain(|retained_uri| retained_uri != uri);
+    }
+
+    /// Takes effect as documents are next retained.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+}
+
+impl Backend {
+    /// Keeps the text and tree just stored of the document at `uri`: for as long as it's open,
+    /// or else as the most recently used of the closed documents, dropping those of the least
+    /// recently used beyond the limit.
+    pub fn retain_tree(&self, uri: &str) {
+        if self.document_version(uri).is_some() {
+            self.retained_trees.forget(uri);
+            return;
+        }
+        for evicted_uri in self.retained_trees.retain(uri) {
+            if self.document_version(&evicted_uri).is_some() {
+                continue;
+            }
+            self.document_map.remove(&evicted_uri);
+            self.parsed_document_map.remove(&evicted_uri);
+        }
+    }
+
+    /// The tree and text of the indexed document at `uri`, read and parsed again from disk when
+    /// they were dropped, without keeping them, so that callers may hold on to other documents'
+    /// entries meanwhile.
+    pub fn document_source(&self, uri: &str) -> Option<(Tree, String)> {
+        let retained = {
+            let tree = self.parsed_document_map.get(uri);
+            let text = self.document_map.get(uri);
+            tree.zip(text).map(|(tree, text)| (tree.clone(), text.clone()))
+        };
+        if let Some(retained) = retained {
+            self.retained_trees.touch(uri);
+            return Some(retained);
+        }
+        if !self.type_declaration_map.contains_key(uri) {
+            return None;
+        }
+        let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+        let (_, text, tree) = workspace_index::read_and_parse(&path)?;
+        return Some((tree, text));
+    }
+
+    /// The uris of every indexed document, whether or not its text and tree are kept.
+    pub fn indexed_uris(&self) -> Vec<String> {
+        return self.type_declaration_map.iter().map(|entry| entry.key().clone()).collect();
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+/// The revisions of what data derived from a document, like its diagnostics, is computed from:
+/// the document itself, and the declarations across the workspace and its dependencies, which
+/// can be read from any document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inputs {
+    document: u64,
+    declarations: u64,
+}
+
+/// Counts the changes to the inputs of derived data, so it's only computed again when what it
+/// was computed from changed. A document's revision moves on whenever it's stored, and the
+/// revision of the declarations only when a document declares other types, members, classes or
+/// modules than it did, or the dependencies change. Editing a method body leaves the derived
+/// data of every other document as it is.
+///
+/// Only diagnostics are memoized this way. The stages before them are kept per document and
+/// redone for each document stored: its tree is parsed again reusing the old one, its symbols
+/// are scanned from that, and its entries in the name index are replaced by the new ones.
+#[derive(Debug, Default)]
+pub struct Revisions {
+    next: AtomicU64,
+    documents: DashMap<String, u64>,
+    declarations: AtomicU64,
+    // a hash of what each document declares for other documents to read
+    declaration_hashes: DashMap<String, u64>,
+}
+
+impl Revisions {
+    /// Records that the document at `uri` was stored, declaring `declarations`.
+    pub fn document_changed<T: Serialize>(&self, uri: &str, declarations: &T) {
+        let revision = self.next.fetch_add(1, Ordering::SeqCst) + 1;
+        self.documents.insert(uri.to_string(), revision);
+        let hash = declaration_hash(declarations);
+        if self.declaration_hashes.insert(uri.to_string(), hash) != Some(hash) {
+            self.declarations_changed();
+        }
+    }
+
+    pub fn document_removed(&self, uri: &str) {
+        self.documents.remove(uri);
+        if self.declaration_hashes.remove(uri).is_some() {
+            self.declarations_changed();
+        }
+    }
+
+    /// Records that declarations changed outside of the documents, in the dependencies say.
+    pub fn declarations_changed(&self) {
+        let revision = self.next.fetch_add(1, Ordering::SeqCst) + 1;
+        self.declarations.store(revision, Ordering::SeqCst);
+    }
+
+    pub fn declarations(&self) -> u64 {
+        return self.declarations.load(Ordering::SeqCst);
+    }
+
+    /// The current revisions of the inputs of data derived from the document at `uri`.
+    pub fn inputs(&self, uri: &str) -> Inputs {
+        return Inputs {
+            document: self.documents.get(uri).map(|revision| *revision).unwrap_or(0),
+            declarations: self.declarations(),
+        };
+    }
+}
+
+/// Data derived from documents, kept along with the revisions of the inputs it was computed
+/// from, like the published diagnostics.
+#[derive(Debug)]
+pub struct Memo<T> {
+    by_document: DashMap<String, (Inputs, T)>,
+}
+
+impl<T> Default for Memo<T> {
+    fn default() -> Memo<T> {
+        return Memo { by_document: DashMap::new() };
+    }
+}
+
+impl<T: Clone> Memo<T> {
+    /// The data derived from the document at `uri`, when it was computed from `inputs`.
+    pub fn get(&self, uri: &str, inputs: Inputs) -> Option<T> {
+        let memoized = self.by_document.get(uri)?;
+        if memoized.0 != inputs {
+            return None;
+        }
+        return Some(memoized.1.clone());
+    }
+
+    pub fn insert(&self, uri: &str, inputs: Inputs, value: T) {
+        self.by_document.insert(uri.to_string(), (inputs, value));
+    }
+
+    pub fn remove(&self, uri: &str) {
+        self.by_document.remove(uri);
+    }
+
+    pub fn clear(&self) {
+        self.by_document.clear();
+    }
+}
+
+fn declaration_hash<T: Serialize>(declarations: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(declarations).unwrap_or_default().hash(&mut hasher);
+    return hasher.finish();
+}
@@ -0,0 +1,89 @@
+use log::debug;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::request::{GotoImplementationParams, GotoImplementationResponse};
+use tower_lsp::lsp_types::Location;
+use tree_sitter::Node;
+
+use crate::hierarchy::{self, TypeDeclaration};
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+impl Backend {
+    /// The types the sealed type `declaration` of the document at `declaring_uri` permits to
+    /// extend or implement it, with the URIs of their documents: those its `permits` clause
+    /// names, or without one the types of its own document naming it as a supertype.
+    pub fn permitted_subtypes(&self, uri: &str, declaring_uri: &str, declaration: &TypeDeclaration) -> Vec<(String, TypeDeclaration)> {
+        if declaration.permitted_subtypes.is_empty() {
+            return match self.type_declaration_map.get(declaring_uri) {
+                Some(declarations) => declarations
+                    .iter()
+                    .filter(|subtype| subtype.supertypes.contains(&declaration.name))
+                    .map(|subtype| (declaring_uri.to_string(), subtype.clone()))
+                    .collect(),
+                None => Vec::new(),
+            };
+        }
+        let context_uri = self.type_context(uri, declaring_uri);
+        return declaration
+            .permitted_subtypes
+            .iter()
+            .filter_map(|subtype| self.locate_type(&context_uri, subtype))
+            .collect();
+    }
+
+    /// Whether the sealed type `declaration` of the document at `declaring_uri` permits the type
+    /// named `subtype_name`, declared in the document at `uri`, to extend or implement it.
+    pub fn permits_subtype(&self, uri: &str, declaring_uri: &str, declaration: &TypeDeclaration, subtype_name: &str) -> bool {
+        if declaration.permitted_subtypes.is_empty() {
+            return uri == declaring_uri;
+        }
+        return declaration
+            .permitted_subtypes
+            .iter()
+            .any(|permitted| permitted.rsplit(['.', '$']).next() == Some(subtype_name));
+    }
+
+    /// Lists the subtypes a sealed type permits, for the type named or declared at the position.
+    pub async fn implementations(&self, params: GotoImplementationParams) -> Result<Option<GotoImplementationResponse>> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        debug!("goto_implementation {} {:?}", uri.to_string(), position);
+        let (tree, source_text) = match (self.parsed_document_map.get(uri.as_str()), self.document_map.get(uri.as_str())) {
+            (Some(tree), Some(source_text)) => (tree.clone(), source_text.clone()),
+            _ => return Ok(None),
+        };
+        let point = LineIndex::new(&source_text).point(position);
+        let node = match tree.root_node().named_descendant_for_point_range(point, point) {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        let type_name = match named_type(node, &source_text) {
+            Some(type_name) => type_name,
+            None => return Ok(None),
+        };
+        let (declaring_uri, declaration) = match self.locate_type(uri.as_str(), &type_name) {
+            Some(found) if found.1.modifiers.is_sealed => found,
+            _ => return Ok(None),
+        };
+        let locations: Vec<Location> = self
+            .permitted_subtypes(uri.as_str(), &declaring_uri, &declaration)
+            .iter()
+            .filter_map(|(subtype_uri, subtype)| self.type_location(subtype_uri, &subtype.name))
+            .collect();
+        return Ok(Some(GotoImplementationResponse::Array(locations)));
+    }
+}
+
+/// The type a type identifier refers to, or the one whose declaration an identifier names.
+fn named_type(node: Node, source: &str) -> Option<String> {
+    let text = node.utf8_text(source.as_bytes()).unwrap().to_string();
+    return match node.kind() {
+        "type_identifier" => Some(text),
+        "identifier" => {
+            let parent_node = node.parent()?;
+            let is_declared_name = hierarchy::is_type_declaration(parent_node.kind()) && parent_node.child_by_field_name("name") == Some(node);
+            is_declared_name.then_some(text)
+        }
+        _ => None,
+    };
+}
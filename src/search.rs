@@ -0,0 +1,511 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::PoisonError;
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+
+use crate::cancellation::Cancellation;
+use crate::error;
+use crate::heuristic;
+use crate::ignore::IgnoreRules;
+use crate::line_index;
+use crate::Backend;
+
+/// Directories that never contain sources worth searching.
+const SKIPPED_DIRECTORIES: [&str; 6] = ["target", "build", "out", "bin", "node_modules", ".git"];
+
+/// Where annotation processors and other code generators write sources by default, relative to
+/// the directory of the project they build.
+pub const DEFAULT_GENERATED_SOURCE_DIRECTORIES: [&str; 2] = ["target/generated-sources", "build/generated"];
+
+const DEFAULT_MAX_TEXT_SEARCH_RESULTS: usize = 1000;
+
+/// Parameters of the `javals/textSearch` request: a literal search across the workspace sources.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextSearchParams {
+    pub query: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub whole_word: bool,
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextSearchMatch {
+    pub uri: Url,
+    pub range: Range,
+    pub line_text: String,
+}
+
+/// A trigram index over the `.java` files of the workspace. It is cheap to build compared to
+/// parsing every file, and narrows a search for a name down to the few files containing all of its
+/// trigrams, which are then scanned line by line. It also remembers the names each file appears to
+/// declare, for fuzzy symbol search without reading any file.
+#[derive(Debug, Default)]
+pub struct TrigramIndex {
+    // a changed or deleted file leaves `None` behind, so the ids in the maps below stay valid
+    files: Vec<Option<PathBuf>>,
+    file_ids: HashMap<PathBuf, u32>,
+    trigrams: HashMap<[u8; 3], Vec<u32>>,
+    declared_names: HashMap<String, Vec<u32>>,
+}
+
+impl TrigramIndex {
+    pub fn build(roots: &[PathBuf], excludes: &[String], generated_directories: &[String]) -> TrigramIndex {
+        let mut index = TrigramIndex::default();
+        for path in java_files(roots, excludes, generated_directories) {
+            if let Ok(text) = fs::read_to_string(&path) {
+                index.update_file(path, &text);
+            }
+        }
+        return index;
+    }
+
+    /// Indexes `text` as the new content of `path`, replacing what was indexed for it before.
+    pub fn update_file(&mut self, path: PathBuf, text: &str) {
+        self.remove_file(&path);
+        let file_id = self.files.len() as u32;
+        for trigram in trigrams(text) {
+            self.trigrams.entry(trigram).or_default().push(file_id);
+        }
+        let mut names: Vec<String> = heuristic::declarations(text).into_iter().map(|d| d.name).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            self.declared_names.entry(name).or_default().push(file_id);
+        }
+        self.file_ids.insert(path.clone(), file_id);
+        self.files.push(Some(path));
+    }
+
+    pub fn remove_file(&mut self, path: &Path) {
+        if let Some(file_id) = self.file_ids.remove(path) {
+            self.files[file_id as usize] = None;
+        }
+    }
+
+    pub fn file_count(&self) -> usize {
+        return self.file_ids.len();
+    }
+
+    /// Every indexed file, in no particular order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        return self.file_ids.keys().cloned().collect();
+    }
+
+    pub fn contains_file(&self, path: &Path) -> bool {
+        return self.file_ids.contains_key(path);
+    }
+
+    fn paths(&self, file_ids: impl IntoIterator<Item = u32>) -> Vec<&Path> {
+        let mut file_ids: Vec<u32> = file_ids.into_iter().collect();
+        file_ids.sort();
+        file_ids.dedup();
+        return file_ids
+            .into_iter()
+            .filter_map(|id| self.files[id as usize].as_deref())
+            .collect();
+    }
+
+    /// Files that may contain `term`, ignoring case. Terms shorter than a trigram match every file.
+    pub fn candidate_files(&self, term: &str) -> Vec<&Path> {
+        let term_trigrams = trigrams(term);
+        if term_trigrams.is_empty() {
+            return self.paths(0..self.files.len() as u32);
+        }
+        let mut candidates: Option<HashSet<u32>> = None;
+        for trigram in term_trigrams {
+            let file_ids: HashSet<u32> = match self.trigrams.get(&trigram) {
+                Some(file_ids) => file_ids.iter().copied().collect(),
+                None => return Vec::new(),
+            };
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&file_ids).copied().collect(),
+                None => file_ids,
+            });
+        }
+        return self.paths(candidates.unwrap_or_default());
+    }
+
+    /// Files appearing to declare a name accepted by `matches`.
+    pub fn files_declaring(&self, matches: impl Fn(&str) -> bool) -> Vec<&Path> {
+        let file_ids = self
+            .declared_names
+            .iter()
+            .filter(|(name, _)| matches(name))
+            .flat_map(|(_, file_ids)| file_ids.iter().copied());
+        return self.paths(file_ids);
+    }
+}
+
+/// The distinct lowercase trigrams of the identifiers in `text`.
+fn trigrams(text: &str) -> HashSet<[u8; 3]> {
+    let mut result = HashSet::new();
+    let bytes: Vec<u8> = text.bytes().map(|b| b.to_ascii_lowercase()).collect();
+    for window in bytes.windows(3) {
+        if window.iter().all(|b| is_identifier_byte(*b)) {
+            result.insert([window[0], window[1], window[2]]);
+        }
+    }
+    return result;
+}
+
+pub fn is_identifier_byte(byte: u8) -> bool {
+    return byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$';
+}
+
+/// Scores `candidate` against a fuzzy `query` whose characters must all appear in order, ignoring
+/// case. Matches at the start, on camelCase humps and after underscores, and runs of consecutive
+/// matches score higher, so `gUN` ranks `getUserName` above `giantUnknownNumber`. `None` when the
+/// characters don't all appear.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for query_char in query.chars() {
+        let lowercase_query_char = query_char.to_lowercase().next().unwrap_or(query_char);
+        let found = (position..candidate_chars.len())
+            .find(|i| candidate_chars[*i].to_lowercase().next() == Some(lowercase_query_char))?;
+        let is_boundary = found == 0
+            || candidate_chars[found].is_uppercase()
+            || candidate_chars[found - 1] == '_'
+            || candidate_chars[found - 1] == '$';
+        if found == 0 {
+            score += 15;
+        } else if is_boundary {
+            score += 10;
+        } else {
+            // skipping ahead into the middle of a word is a weak match
+            score -= (found - position) as i32;
+        }
+        if previous_match.map(|previous| previous + 1 == found).unwrap_or(false) {
+            score += 5;
+        }
+        if candidate_chars[found] == query_char {
+            score += 1;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    // prefer shorter names among equally good matches
+    score -= (candidate_chars.len() - position) as i32 / 4;
+    return Some(score);
+}
+
+/// Every occurrence of `query` in `text`, optionally ignoring ASCII case and requiring the match
+/// to stand alone as a word.
+fn find_in_text(text: &str, query: &str, case_sensitive: bool, whole_word: bool, uri: &Url, results: &mut Vec<TextSearchMatch>, limit: usize) {
+    let needle = if case_sensitive { query.to_string() } else { query.to_ascii_lowercase() };
+    for (line_number, line) in text.lines().enumerate() {
+        let haystack = if case_sensitive { line.to_string() } else { line.to_ascii_lowercase() };
+        let mut start = 0;
+        while let Some(offset) = haystack[start..].find(&needle) {
+            let match_start = start + offset;
+            let match_end = match_start + needle.len();
+            start = match_end.max(match_start + 1);
+            let bytes = haystack.as_bytes();
+            let standalone = (match_start == 0 || !is_identifier_byte(bytes[match_start - 1]))
+                && (match_end == bytes.len() || !is_identifier_byte(bytes[match_end]));
+            if whole_word && !standalone {
+                continue;
+            }
+            results.push(TextSearchMatch {
+                uri: uri.clone(),
+                range: Range {
+                    start: Position {
+                        line: line_number as u32,
+                        character: line_index::character(&line[..match_start]),
+                    },
+                    end: Position {
+                        line: line_number as u32,
+                        character: line_index::character(&line[..match_end]),
+                    },
+                },
+                line_text: line.to_string(),
+            });
+            if results.len() >= limit {
+                return;
+            }
+        }
+    }
+}
+
+/// Whether `directory` is hidden or one of the `SKIPPED_DIRECTORIES`.
+pub fn is_skipped_directory(directory: &Path) -> bool {
+    let file_name = match directory.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => return false,
+    };
+    return file_name.starts_with('.') || SKIPPED_DIRECTORIES.contains(&file_name.as_ref());
+}
+
+/// Whether `directory` is one of the `generated_directories`, at any depth.
+pub fn is_generated_source_directory(directory: &Path, generated_directories: &[String]) -> bool {
+    return generated_directories.iter().any(|generated| directory.ends_with(generated));
+}
+
+/// Whether `directory` is on the way to one of the `generated_directories`, like the `target` of
+/// `target/generated-sources`.
+fn leads_to_generated_source_directory(directory: &Path, generated_directories: &[String]) -> bool {
+    return generated_directories.iter().any(|generated| {
+        let components: Vec<Component> = Path::new(generated).components().collect();
+        return (1..components.len()).any(|length| directory.ends_with(components[..length].iter().collect::<PathBuf>()));
+    });
+}
+
+/// How the files under a directory are indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Walked {
+    // workspace sources, left out when ignored
+    Sources,
+    // generated sources, indexed even though build output is usually ignored
+    Generated,
+    // skipped or ignored, but with generated sources further down
+    Passage,
+}
+
+/// The `.java` files under `roots`, leaving out skipped directories and what `.gitignore` files
+/// or the configured `excludes` ignore, except in `generated_directories`.
+fn java_files(roots: &[PathBuf], excludes: &[String], generated_directories: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<(PathBuf, IgnoreRules, Walked)> = roots
+        .iter()
+        .map(|root| (root.clone(), IgnoreRules::at_root(root, excludes), Walked::Sources))
+        .collect();
+    while let Some((path, rules, walked)) = pending.pop() {
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                let entry_walked = if walked == Walked::Generated || is_generated_source_directory(&entry_path, generated_directories) {
+                    Walked::Generated
+                } else if walked == Walked::Sources && !is_skipped_directory(&entry_path) && !rules.is_ignored(&entry_path, true) {
+                    Walked::Sources
+                } else if leads_to_generated_source_directory(&entry_path, generated_directories) {
+                    Walked::Passage
+                } else {
+                    continue;
+                };
+                let entry_rules = rules.enter(&entry_path);
+                pending.push((entry_path, entry_rules, entry_walked));
+            } else if entry_path.extension().map(|extension| extension == "java").unwrap_or(false) {
+                let is_indexed = match walked {
+                    Walked::Sources => !rules.is_ignored(&entry_path, false),
+                    Walked::Generated => true,
+                    Walked::Passage => false,
+                };
+                if is_indexed {
+                    files.push(entry_path);
+                }
+            }
+        }
+    }
+    // nested roots reach the same files twice
+    files.sort();
+    files.dedup();
+    return files;
+}
+
+impl Backend {
+    /// Builds the index over the workspace roots and the source roots the `project.sourceRoots`
+    /// setting adds from scratch, replacing the current one, and returns the number of files
+    /// indexed.
+    pub async fn build_search_index(&self) -> usize {
+        let mut roots = self.workspace_roots.read().unwrap_or_else(PoisonError::into_inner).clone();
+        roots.extend(self.config.read().unwrap_or_else(PoisonError::into_inner).project.source_roots.clone());
+        let excludes = self.config.read().unwrap_or_else(PoisonError::into_inner).index_excludes.clone();
+        let generated_directories = self.config.read().unwrap_or_else(PoisonError::into_inner).generated_source_directories.clone();
+        let index = tokio::task::spawn_blocking(move || TrigramIndex::build(&roots, &excludes, &generated_directories))
+            .await
+            .unwrap_or_default();
+        let file_count = index.file_count();
+        info!("search index built over {} files", file_count);
+        *self.search_index.write().unwrap_or_else(PoisonError::into_inner) = index;
+        return file_count;
+    }
+
+    /// Adds the files under new workspace `roots` to the index and returns them.
+    pub async fn add_roots_to_search_index(&self, roots: Vec<PathBuf>) -> Vec<PathBuf> {
+        let excludes = self.config.read().unwrap_or_else(PoisonError::into_inner).index_excludes.clone();
+        let generated_directories = self.config.read().unwrap_or_else(PoisonError::into_inner).generated_source_directories.clone();
+        let files = tokio::task::spawn_blocking(move || {
+            return java_files(&roots, &excludes, &generated_directories)
+                .into_iter()
+                .filter_map(|path| Some((fs::read_to_string(&path).ok()?, path)))
+                .collect::<Vec<_>>();
+        })
+        .await
+        .unwrap_or_default();
+        let mut index = self.search_index.write().unwrap_or_else(PoisonError::into_inner);
+        return files
+            .into_iter()
+            .map(|(text, path)| {
+                index.update_file(path.clone(), &text);
+                return path;
+            })
+            .collect();
+    }
+
+    /// Removes the files under workspace roots that were removed, but not under the roots that
+    /// remain, from the index and returns them.
+    pub fn remove_roots_from_search_index(&self, removed_roots: &[PathBuf], remaining_roots: &[PathBuf]) -> Vec<PathBuf> {
+        let mut index = self.search_index.write().unwrap_or_else(PoisonError::into_inner);
+        let removed_files: Vec<PathBuf> = index
+            .files()
+            .into_iter()
+            .filter(|path| removed_roots.iter().any(|root| path.starts_with(root)))
+            .filter(|path| !remaining_roots.iter().any(|root| path.starts_with(root)))
+            .collect();
+        for path in &removed_files {
+            index.remove_file(path);
+        }
+        return removed_files;
+    }
+
+    /// Keeps the index in step with `.java` files created, changed or deleted on disk.
+    pub fn update_search_index(&self, changes: &[FileEvent]) {
+        // the `.gitignore` files of a directory are read once for every change under it
+        let mut directory_rules = HashMap::new();
+        let changes: Vec<(PathBuf, FileChangeType)> = changes
+            .iter()
+            .filter_map(|change| Some((change.uri.to_file_path().ok()?, change.typ)))
+            .filter(|(path, _)| path.extension().map(|extension| extension == "java").unwrap_or(false))
+            .filter(|(path, typ)| *typ == FileChangeType::DELETED || !self.is_excluded(path, &mut directory_rules))
+            .collect();
+        let mut index = self.search_index.write().unwrap_or_else(PoisonError::into_inner);
+        for (path, typ) in changes {
+            if typ == FileChangeType::DELETED {
+                index.remove_file(&path);
+            } else if let Ok(text) = fs::read_to_string(&path) {
+                index.update_file(path, &text);
+            }
+        }
+    }
+
+    /// Handles `javals/textSearch`. Open documents are searched as edited rather than as saved.
+    /// Checks for cancellation between files.
+    pub async fn text_search(&self, params: TextSearchParams) -> Result<Option<Vec<TextSearchMatch>>> {
+        debug!("text_search {:?}", params);
+        let search = async {
+            self.pending_changes.settled().await;
+            let mut cancellation = Cancellation::default();
+            let mut results = Vec::new();
+            if params.query.is_empty() {
+                return Ok(Some(results));
+            }
+            let limit = params.max_results.unwrap_or(DEFAULT_MAX_TEXT_SEARCH_RESULTS);
+            let mut open_uris: Vec<String> = self.document_map.iter().map(|entry| entry.key().clone()).collect();
+            open_uris.sort();
+            for open_uri in &open_uris {
+                if results.len() >= limit {
+                    break;
+                }
+                cancellation.checkpoint().await;
+                let (uri, text) = match (Url::parse(open_uri), self.document_map.get(open_uri)) {
+                    (Ok(uri), Some(text)) => (uri, text),
+                    _ => continue,
+                };
+                find_in_text(&text, &params.query, params.case_sensitive, params.whole_word, &uri, &mut results, limit);
+            }
+            let candidates: Vec<PathBuf> = self
+                .search_index
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .candidate_files(&params.query)
+                .into_iter()
+                .map(Path::to_path_buf)
+                .collect();
+            for path in &candidates {
+                if results.len() >= limit {
+                    break;
+                }
+                cancellation.checkpoint().await;
+                let uri = match Url::from_file_path(path) {
+                    Ok(uri) => uri,
+                    Err(_) => continue,
+                };
+                if open_uris.contains(&uri.to_string()) {
+                    continue;
+                }
+                if let Ok(text) = fs::read_to_string(path) {
+                    find_in_text(&text, &params.query, params.case_sensitive, params.whole_word, &uri, &mut results, limit);
+                }
+            }
+            return Ok(Some(results));
+        };
+        return error::guarded("javals/textSearch", search).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_humps_above_the_middles_of_words() {
+        let humps = fuzzy_score("gUN", "getUserName").unwrap();
+        let middles = fuzzy_score("gUN", "giantUnknownNumber").unwrap();
+        let scattered = fuzzy_score("gUN", "gaugeunion").unwrap();
+        assert!(humps > scattered);
+        assert!(middles > scattered);
+        assert!(fuzzy_score("user", "getUserName").unwrap() < fuzzy_score("user", "userName").unwrap());
+        assert!(fuzzy_score("name", "NAME_COUNT").unwrap() > fuzzy_score("name", "rename").unwrap());
+    }
+
+    #[test]
+    fn prefers_shorter_names_and_matching_case() {
+        assert!(fuzzy_score("list", "List").unwrap() < fuzzy_score("List", "List").unwrap());
+        assert!(fuzzy_score("List", "ArrayList").unwrap() > fuzzy_score("List", "ArrayListSpliterator").unwrap());
+    }
+
+    #[test]
+    fn needs_every_character_in_order() {
+        assert_eq!(fuzzy_score("nmu", "getUserName"), None);
+        assert_eq!(fuzzy_score("names", "name"), None);
+        assert!(fuzzy_score("", "anything").is_some());
+    }
+
+    #[test]
+    fn narrows_searches_to_files_with_every_trigram() {
+        let mut index = TrigramIndex::default();
+        index.update_file(PathBuf::from("/A.java"), "class UserService { void save() {} }");
+        index.update_file(PathBuf::from("/B.java"), "class Order { User user; }");
+        assert_eq!(index.candidate_files("userservice"), vec![Path::new("/A.java")]);
+        assert_eq!(index.candidate_files("User"), vec![Path::new("/A.java"), Path::new("/B.java")]);
+        assert_eq!(index.candidate_files("ab").len(), 2);
+        assert_eq!(index.files_declaring(|name| name == "Order"), vec![Path::new("/B.java")]);
+        index.update_file(PathBuf::from("/A.java"), "class Account {}");
+        assert!(index.candidate_files("UserService").is_empty());
+        index.remove_file(Path::new("/B.java"));
+        assert_eq!(index.file_count(), 1);
+        assert!(index.files_declaring(|name| name == "Order").is_empty());
+    }
+
+    #[test]
+    fn finds_text_as_a_word_and_ignoring_case() {
+        let uri = Url::parse("file:///A.java").unwrap();
+        let text = "int count = 0;\nCount++; recount();";
+        let mut results = Vec::new();
+        find_in_text(text, "count", false, true, &uri, &mut results, 10);
+        let ranges: Vec<(u32, u32)> = results.iter().map(|result| (result.range.start.line, result.range.start.character)).collect();
+        assert_eq!(ranges, vec![(0, 4), (1, 0)]);
+        results.clear();
+        find_in_text(text, "count", true, false, &uri, &mut results, 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].line_text, "Count++; recount();");
+        results.clear();
+        find_in_text(text, "count", false, false, &uri, &mut results, 2);
+        assert_eq!(results.len(), 2);
+    }
+}
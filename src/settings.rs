@@ -0,0 +1,344 @@
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde_json::Value;
+use tower_lsp::lsp_types::ConfigurationItem;
+
+use crate::lombok::LombokAnnotations;
+use crate::on_save::OnSaveActions;
+use crate::postfix::PostfixTemplates;
+use crate::project::ProjectSettings;
+use crate::{external_format, logging, search, Backend};
+
+/// The section of the client's settings that are the server's.
+pub const SECTION: &str = "javals";
+
+/// The settings there are, by section, to warn about the others, which are ignored.
+const KNOWN_SETTINGS: [(&str, &[&str]); 10] = [
+    ("completion", &["showInaccessibleMembers", "postfixTemplates"]),
+    ("latency", &["budgets", "telemetry"]),
+    ("formatting", &["googleJavaFormatJar", "externalCommand"]),
+    ("onSave", &["organizeImports", "formatDocument", "trimTrailingWhitespace"]),
+    ("indexing", &["exclude", "generatedSources", "reindexDelay", "retainedTrees"]),
+    ("java", &["home"]),
+    ("logging", &["level", "forwardToClient"]),
+    ("lombok", &["annotations"]),
+    ("project", &["classpath", "sourceRoots"]),
+    ("features", &["diagnostics", "textSearchFallback", "formatting", "onTypeFormatting", "documentHighlight"]),
+];
+
+/// The features the `features` settings turn off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub diagnostics: bool,
+    // text search standing in for the index until it's ready
+    pub text_search_fallback: bool,
+    // whole-document and range formatting
+    pub formatting: bool,
+    pub on_type_formatting: bool,
+    pub document_highlight: bool,
+}
+
+impl Default for Features {
+    fn default() -> Features {
+        return Features {
+            diagnostics: true,
+            text_search_fallback: true,
+            formatting: true,
+            on_type_formatting: true,
+            document_highlight: true,
+        };
+    }
+}
+
+/// The settings the providers read, as the client last set them. Those that take effect
+/// elsewhere, like the log level, aren't kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // whether completion also offers members that access modifiers hide at the cursor
+    pub show_inaccessible_members: bool,
+    // the templates completion offers after `expression.`, by name
+    pub postfix_templates: PostfixTemplates,
+    // whether requests over their latency budget are reported as telemetry events
+    pub slow_request_telemetry: bool,
+    // gitignore-style patterns for workspace files left out of the index
+    pub index_excludes: Vec<String>,
+    // directories of generated sources, relative to a project's, indexed even when ignored
+    pub generated_source_directories: Vec<String>,
+    // the JDK home the `java.home` setting names
+    pub java_home: Option<PathBuf>,
+    // the command whole-document formatting is delegated to, if any
+    pub external_formatter: Option<Vec<String>>,
+    pub on_save_actions: OnSaveActions,
+    // the members Lombok annotations generate, which the type declarations include
+    pub lombok_annotations: LombokAnnotations,
+    pub project: ProjectSettings,
+    pub features: Features,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        return Config {
+            show_inaccessible_members: false,
+            postfix_templates: PostfixTemplates::default(),
+            slow_request_telemetry: false,
+            index_excludes: Vec::new(),
+            generated_source_directories: search::DEFAULT_GENERATED_SOURCE_DIRECTORIES.map(str::to_string).to_vec(),
+            java_home: None,
+            external_formatter: None,
+            on_save_actions: OnSaveActions::default(),
+            lombok_annotations: LombokAnnotations::default(),
+            project: ProjectSettings::default(),
+            features: Features::default(),
+        };
+    }
+}
+
+impl Config {
+    /// Applies the settings kept here, but the external formatter, which depends on `java.home`.
+    /// Relative paths in the `project` settings are relative to `base`.
+    fn apply(&mut self, settings: &Value, base: &Path) {
+        if let Some(show) = settings.pointer("/completion/showInaccessibleMembers").and_then(Value::as_bool) {
+            self.show_inaccessible_members = show;
+        }
+        if let Some(templates) = settings.pointer("/completion/postfixTemplates").and_then(Value::as_object) {
+            self.postfix_templates.configure(templates);
+        }
+        if let Some(telemetry) = settings.pointer("/latency/telemetry").and_then(Value::as_bool) {
+            self.slow_request_telemetry = telemetry;
+        }
+        for (pointer, action) in [
+            ("/onSave/organizeImports", &mut self.on_save_actions.organize_imports),
+            ("/onSave/formatDocument", &mut self.on_save_actions.format_document),
+            ("/onSave/trimTrailingWhitespace", &mut self.on_save_actions.trim_trailing_whitespace),
+        ] {
+            if let Some(enabled) = settings.pointer(pointer).and_then(Value::as_bool) {
+                *action = enabled;
+            }
+        }
+        if let Some(excludes) = settings.pointer("/indexing/exclude").and_then(Value::as_array) {
+            self.index_excludes = excludes.iter().filter_map(Value::as_str).map(str::to_string).collect();
+        }
+        if let Some(annotations) = settings.pointer("/lombok/annotations").and_then(Value::as_object) {
+            self.lombok_annotations.configure(annotations);
+        }
+        if let Some(directories) = settings.pointer("/indexing/generatedSources").and_then(Value::as_array) {
+            self.generated_source_directories = directories.iter().filter_map(Value::as_str).map(str::to_string).collect();
+        }
+        if let Some(java_home) = settings.pointer("/java/home") {
+            self.java_home = java_home.as_str().filter(|home| !home.is_empty()).map(PathBuf::from);
+        }
+        for (pointer, paths) in [
+            ("/project/classpath", &mut self.project.classpath),
+            ("/project/sourceRoots", &mut self.project.source_roots),
+        ] {
+            if let Some(entries) = settings.pointer(pointer).and_then(Value::as_array) {
+                *paths = entries.iter().filter_map(Value::as_str).map(|entry| base.join(entry)).collect();
+            }
+        }
+        for (pointer, enabled) in [
+            ("/features/diagnostics", &mut self.features.diagnostics),
+            ("/features/textSearchFallback", &mut self.features.text_search_fallback),
+            ("/features/formatting", &mut self.features.formatting),
+            ("/features/onTypeFormatting", &mut self.features.on_type_formatting),
+            ("/features/documentHighlight", &mut self.features.document_highlight),
+        ] {
+            if let Some(setting) = settings.pointer(pointer).and_then(Value::as_bool) {
+                *enabled = setting;
+            }
+        }
+    }
+}
+
+impl Backend {
+    /// Applies client settings, sent as `initializationOptions`, through
+    /// `workspace/didChangeConfiguration` or in answer to `workspace/configuration`. Settings that
+    /// are absent keep their current value.
+    ///
+    /// ```json
+    /// {
+    ///     "completion": { "showInaccessibleMembers": false, "postfixTemplates": { "sout": "System.out.println($expr);", "nn": null } },
+    ///     "latency": { "budgets": { "textDocument/completion": 100, "workspace/symbol": null }, "telemetry": false },
+    ///     "formatting": { "googleJavaFormatJar": "/opt/google-java-format.jar" },
+    ///     "onSave": { "organizeImports": true, "formatDocument": false, "trimTrailingWhitespace": true },
+    ///     "indexing": { "exclude": ["generated/", "**/*Test.java"], "generatedSources": ["target/generated-sources"], "reindexDelay": 200, "retainedTrees": 200 },
+    ///     "java": { "home": "/usr/lib/jvm/java-17-openjdk" },
+    ///     "logging": { "level": "info,lsp::jdk=debug", "forwardToClient": false },
+    ///     "lombok": { "annotations": { "Data": ["getters", "setters", "toString"], "Immutable": ["getters"] } },
+    ///     "project": { "classpath": ["lib/annotations.jar"], "sourceRoots": ["../shared/src/main/java"] },
+    ///     "features": { "diagnostics": true, "textSearchFallback": true, "formatting": true, "onTypeFormatting": true, "documentHighlight": true }
+    /// }
+    /// ```
+    ///
+    /// `completion.postfixTemplates` adds templates to those completion offers after an expression
+    /// starting a statement, like `items.for`, or replaces them by name: `var`, `if`, `else`,
+    /// `for`, `while`, `null`, `nn`, `return` and `throw`. A template set to `null` is no longer
+    /// offered. Templates are snippets in which `$expr` stands for the expression and `$name` for
+    /// a name for its value.
+    ///
+    /// `latency.budgets` sets, in milliseconds, how long requests of a method may take before
+    /// they're logged as slow and counted in `javals/indexStatus`; `null` takes a method's budget
+    /// away. With `latency.telemetry`, slow requests are also sent to the client as
+    /// `telemetry/event` notifications.
+    ///
+    /// Instead of a jar, `formatting.externalCommand` can name any command that reads a document
+    /// on standard input and writes it formatted, like `["google-java-format", "-"]`. Setting
+    /// either to `null` goes back to the built-in formatter.
+    ///
+    /// `indexing.exclude` takes patterns in `.gitignore` syntax, relative to each workspace root,
+    /// for files to leave out of the index on top of those `.gitignore` files ignore.
+    ///
+    /// `indexing.generatedSources` names the directories code generators like annotation
+    /// processors write to, relative to the project they build, which are indexed even though
+    /// build output is ignored. It defaults to `target/generated-sources` and `build/generated`.
+    ///
+    /// `indexing.reindexDelay` is how many milliseconds a changed document waits for more changes
+    /// before it's parsed and indexed again, so that the keystrokes of a burst of typing are
+    /// indexed once. Requests about the document don't wait that long.
+    ///
+    /// `indexing.retainedTrees` is how many documents the client doesn't have open keep their
+    /// text and syntax tree in memory, the most recently used ones. The others only keep what
+    /// they declare, and are read from disk again when a feature needs their text.
+    ///
+    /// `java.home` picks the JDK to index and to run tools like the formatter jar with. Without
+    /// it, or set to `null`, the one in `JAVA_HOME` or on the `PATH` is used.
+    ///
+    /// `logging.level` is what gets written to the log: a level, `error`, `warn`, `info`, `debug`
+    /// or `trace`, optionally followed by levels for modules of the server or its dependencies.
+    /// Until it's set, the `--log-level` flag decides, then the `JAVALS_LOG` environment variable,
+    /// or else it's `info`. With
+    /// `logging.forwardToClient`, the warnings and errors logged are also shown by the client.
+    ///
+    /// `lombok.annotations` maps annotations, by simple name, to the members they generate, on top
+    /// of Lombok's own: any of `getters`, `setters`, `withers`, `toString`, `equalsAndHashCode`,
+    /// `noArgsConstructor`, `requiredArgsConstructor`, `allArgsConstructor` and `builder`.
+    ///
+    /// `project.classpath` lists jars every project compiles against on top of those its build
+    /// tool resolves, which is all the classpath there is for sources no build file covers.
+    /// `project.sourceRoots` names directories of sources to index along with the workspace
+    /// folders. Relative paths in both are relative to the first workspace folder.
+    ///
+    /// `features.diagnostics` set to `false` stops diagnostics from being published, and
+    /// `features.textSearchFallback` set to `false` leaves navigation empty until the index is
+    /// ready instead of answering it with text search. `features.formatting`,
+    /// `features.onTypeFormatting` and `features.documentHighlight` turn those providers off, and
+    /// unregister them when the client registered them dynamically.
+    ///
+    /// Settings this server doesn't know are logged and ignored.
+    pub fn apply_settings(&self, settings: &Value) {
+        warn_unknown_settings(settings);
+        if let Some(budgets) = settings.pointer("/latency/budgets").and_then(Value::as_object) {
+            self.latency.set_budgets(budgets);
+        }
+        if let Some(delay) = settings.pointer("/indexing/reindexDelay").and_then(Value::as_u64) {
+            self.pending_changes.set_delay(Duration::from_millis(delay));
+        }
+        if let Some(limit) = settings.pointer("/indexing/retainedTrees").and_then(Value::as_u64) {
+            self.retained_trees.set_limit(limit as usize);
+        }
+        if let Some(level) = settings.pointer("/logging/level").and_then(Value::as_str) {
+            if let Err(error) = logging::set_level(level) {
+                warn!("ignoring logging.level {:?}: {}", level, error);
+            }
+        }
+        if let Some(forwarded) = settings.pointer("/logging/forwardToClient").and_then(Value::as_bool) {
+            logging::set_forwarded_to_client(forwarded);
+        }
+        let base = self.workspace_roots.read().unwrap_or_else(PoisonError::into_inner).first().cloned().unwrap_or_default();
+        self.config.write().unwrap_or_else(PoisonError::into_inner).apply(settings, &base);
+        if let Some(formatting) = settings.get("formatting").and_then(Value::as_object) {
+            let command = formatting
+                .get("externalCommand")
+                .and_then(Value::as_array)
+                .map(|command| command.iter().filter_map(Value::as_str).map(str::to_string).collect::<Vec<String>>())
+                .filter(|command| !command.is_empty())
+                .or_else(|| {
+                    let jar = formatting.get("googleJavaFormatJar").and_then(Value::as_str)?;
+                    return Some(external_format::google_java_format_command(&self.java_launcher(), jar));
+                });
+            if command.is_some() || formatting.contains_key("externalCommand") || formatting.contains_key("googleJavaFormatJar") {
+                self.config.write().unwrap_or_else(PoisonError::into_inner).external_formatter = command;
+            }
+        }
+    }
+
+    /// Applies settings that changed after the workspace was indexed, and redoes what depends
+    /// on those that changed: indexing the workspace, the projects or the JDK again, and
+    /// publishing diagnostics anew.
+    pub async fn apply_changed_settings(&self, settings: &Value) {
+        let previous = self.config.read().unwrap_or_else(PoisonError::into_inner).clone();
+        self.apply_settings(settings);
+        let current = self.config.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let is_reindexed = current.index_excludes != previous.index_excludes
+            || current.generated_source_directories != previous.generated_source_directories
+            || current.lombok_annotations != previous.lombok_annotations
+            || current.project.source_roots != previous.project.source_roots;
+        if is_reindexed {
+            self.reindex_workspace().await;
+        }
+        if current.project.classpath != previous.project.classpath {
+            self.load_projects().await;
+        }
+        if current.java_home != previous.java_home {
+            self.index_jdk().await;
+        }
+        if current.features != previous.features {
+            self.update_provider_registrations().await;
+            self.memoized_diagnostics.clear();
+            let open_uris = self.document_versions.iter().map(|entry| entry.key().clone()).collect();
+            self.publish_affected_diagnostics(open_uris).await;
+        }
+    }
+
+    /// Asks the client for the server's section of its settings, when it answers
+    /// `workspace/configuration`. `None` when it doesn't, or has none.
+    pub async fn pull_settings(&self) -> Option<Value> {
+        if !self.can_pull_configuration.load(Ordering::Acquire) {
+            return None;
+        }
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some(SECTION.to_string()),
+        };
+        let settings = match self.client.configuration(vec![item]).await {
+            Ok(settings) => settings,
+            Err(error) => {
+                info!("unable to pull settings: {:?}", error);
+                return None;
+            }
+        };
+        return settings.into_iter().next().filter(Value::is_object);
+    }
+}
+
+fn warn_unknown_settings(settings: &Value) {
+    let sections = match settings.as_object() {
+        Some(sections) => sections,
+        None => {
+            warn!("ignoring settings that aren't an object: {}", settings);
+            return;
+        }
+    };
+    for (section, keys) in sections {
+        let known_keys = match KNOWN_SETTINGS.iter().find(|(known_section, _)| known_section == section) {
+            Some((_, known_keys)) => known_keys,
+            None => {
+                warn!("ignoring unknown setting {:?}", section);
+                continue;
+            }
+        };
+        let keys = match keys.as_object() {
+            Some(keys) => keys,
+            None => {
+                warn!("ignoring setting {:?}, which isn't an object", section);
+                continue;
+            }
+        };
+        for key in keys.keys().filter(|key| !known_keys.contains(&key.as_str())) {
+            warn!("ignoring unknown setting {}.{}", section, key);
+        }
+    }
+}
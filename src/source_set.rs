@@ -0,0 +1,28 @@
+/// The Maven/Gradle source set a document belongs to. Test sources can see main sources, but
+/// main sources must never depend on test sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceSet {
+    Main,
+    Test,
+}
+
+impl SourceSet {
+    pub fn of(uri: &str) -> SourceSet {
+        if uri.contains("/src/test/") {
+            return SourceSet::Test;
+        }
+        return SourceSet::Main;
+    }
+
+    /// Whether code in `self` may reference types declared in `other`.
+    pub fn can_see(self, other: SourceSet) -> bool {
+        return match self {
+            SourceSet::Test => true,
+            SourceSet::Main => other == SourceSet::Main,
+        };
+    }
+}
+
+pub fn is_visible(from_uri: &str, target_uri: &str) -> bool {
+    return SourceSet::of(from_uri).can_see(SourceSet::of(target_uri));
+}
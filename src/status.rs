@@ -0,0 +1,83 @@
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+
+use log::debug;
+use serde::Serialize;
+use tower_lsp::jsonrpc::Result;
+
+use crate::coverage::UnhandledNodeReport;
+use crate::jdk::Jdk;
+use crate::latency::LatencyReport;
+use crate::project::Project;
+use crate::workspace_index::IndexingRun;
+use crate::Backend;
+
+/// Result of the `javals/indexStatus` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatus {
+    pub indexed_documents: usize,
+    // the documents whose text and tree are in memory, the open ones and the retained closed ones
+    pub parsed_documents: usize,
+    pub open_documents: usize,
+    // declared names, and declarations by any of them
+    pub symbol_names: usize,
+    pub symbols: usize,
+    pub dependency_classes: usize,
+    pub memory: MemoryEstimate,
+    pub pending: PendingWork,
+    pub last_indexing: Option<IndexingRun>,
+    pub unhandled_nodes: Vec<UnhandledNodeReport>,
+    pub latency: Vec<LatencyReport>,
+    pub projects: Vec<Project>,
+    pub jdk: Option<Jdk>,
+}
+
+/// Rough estimates of the memory the index takes, in bytes. Syntax trees aren't counted.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryEstimate {
+    pub document_text: usize,
+    pub symbols: usize,
+}
+
+/// What the server is still working on.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingWork {
+    // changes to open documents not yet parsed and indexed
+    pub document_changes: usize,
+    pub indexing_workspace: bool,
+    // false until every workspace file is indexed, while navigation falls back to text search
+    pub index_ready: bool,
+}
+
+impl Backend {
+    /// Handles `javals/indexStatus`, which takes no parameters. Meant for clients to show in a
+    /// status bar and for users to attach to bug reports.
+    pub async fn index_status(&self) -> Result<IndexStatus> {
+        debug!("index_status");
+        return Ok(IndexStatus {
+            indexed_documents: self.type_declaration_map.len(),
+            parsed_documents: self.parsed_document_map.len(),
+            open_documents: self.document_versions.len(),
+            symbol_names: self.symbol_index.name_count(),
+            symbols: self.symbol_index.declaration_count(),
+            dependency_classes: self.dependency_index.class_count(),
+            memory: MemoryEstimate {
+                document_text: self.document_map.iter().map(|entry| entry.key().len() + entry.value().len()).sum(),
+                symbols: self.symbol_index.estimated_size(),
+            },
+            pending: PendingWork {
+                document_changes: self.pending_changes.pending(),
+                indexing_workspace: self.indexing.load(Ordering::Acquire),
+                index_ready: self.index_ready.load(Ordering::Acquire),
+            },
+            last_indexing: self.last_indexing.read().unwrap_or_else(PoisonError::into_inner).clone(),
+            unhandled_nodes: self.unhandled_nodes.report(),
+            latency: self.latency.report(),
+            projects: self.projects.read().unwrap_or_else(PoisonError::into_inner).clone(),
+            jdk: self.jdk.read().unwrap_or_else(PoisonError::into_inner).clone(),
+        });
+    }
+}
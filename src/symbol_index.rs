@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::TokenLocation;
+
+/// The declarations indexed from one document, by name.
+pub type SymbolTable = HashMap<String, Vec<TokenLocation>>;
+
+/// Declarations, in a table per document. A document's table is replaced as a whole when it's
+/// reparsed and dropped when it's closed. The name index, derived from the tables, finds the
+/// documents declaring a name without going through all of them.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    tables: DashMap<String, Arc<SymbolTable>>,
+    documents_by_name: DashMap<String, HashSet<String>>,
+}
+
+impl SymbolIndex {
+    /// Makes `table` the declarations of the document at `uri`.
+    pub fn replace(&self, uri: &str, table: SymbolTable) {
+        let table = Arc::new(table);
+        if let Some(previous) = self.tables.insert(uri.to_string(), table.clone()) {
+            self.unlink(uri, &previous, &table);
+        }
+        for name in table.keys() {
+            self.documents_by_name
+                .entry(name.clone())
+                .or_default()
+                .insert(uri.to_string());
+        }
+    }
+
+    /// Forgets the declarations of the document at `uri`.
+    pub fn remove(&self, uri: &str) {
+        if let Some((_, previous)) = self.tables.remove(uri) {
+            self.unlink(uri, &previous, &SymbolTable::new());
+        }
+    }
+
+    /// Removes `uri` from the name index for the names `previous` declared and `current` doesn't.
+    fn unlink(&self, uri: &str, previous: &SymbolTable, current: &SymbolTable) {
+        for name in previous.keys().filter(|name| !current.contains_key(*name)) {
+            if let Some(mut uris) = self.documents_by_name.get_mut(name) {
+                uris.remove(uri);
+            }
+            self.documents_by_name.remove_if(name, |_, uris| uris.is_empty());
+        }
+    }
+
+    /// The declarations of the document at `uri`.
+    pub fn table(&self, uri: &str) -> Option<Arc<SymbolTable>> {
+        return self.tables.get(uri).map(|table| table.clone());
+    }
+
+    /// The declarations named `name` in every document.
+    pub fn declarations(&self, name: &str) -> Vec<TokenLocation> {
+        let uris: Vec<String> = match self.documents_by_name.get(name) {
+            Some(uris) => uris.iter().cloned().collect(),
+            None => return Vec::new(),
+        };
+        let mut declarations = Vec::new();
+        for uri in uris {
+            if let Some(locations) = self.table(&uri).as_ref().and_then(|table| table.get(name)) {
+                declarations.extend(locations.iter().cloned());
+            }
+        }
+        return declarations;
+    }
+
+    /// Every name declared in some document.
+    pub fn names(&self) -> Vec<String> {
+        return self.documents_by_name.iter().map(|entry| entry.key().clone()).collect();
+    }
+
+    pub fn name_count(&self) -> usize {
+        return self.documents_by_name.len();
+    }
+
+    /// How many declarations there are across documents.
+    pub fn declaration_count(&self) -> usize {
+        return self.tables.iter().map(|entry| entry.value().values().map(Vec::len).sum::<usize>()).sum();
+    }
+
+    /// A rough estimate of the memory the declarations take, in bytes.
+    pub fn estimated_size(&self) -> usize {
+        let mut size = 0;
+        for entry in self.tables.iter() {
+            for (name, locations) in entry.value().iter() {
+                size += name.len() + locations.iter().map(|location| mem::size_of::<TokenLocation>() + location.uri.len()).sum::<usize>();
+            }
+        }
+        return size;
+    }
+}
@@ -0,0 +1,91 @@
+use tower_lsp::lsp_types::*;
+
+use crate::cancellation::Cancellation;
+use crate::latency::{self, Phase};
+use crate::search::fuzzy_score;
+use crate::source_set;
+use crate::{Backend, TokenLocation, TokenType};
+
+impl Backend {
+    /// Classes, methods and fields fuzzily matching `query`, best matches first. Until the index
+    /// is ready, matches from a text search of the remaining workspace files are included.
+    /// Checks for cancellation between names and between files.
+    pub async fn workspace_symbols(&self, query: &str, cancellation: &mut Cancellation) -> Vec<SymbolInformation> {
+        let mut symbols = Vec::new();
+        for name in self.symbol_index.names() {
+            if fuzzy_score(query, &name).is_none() {
+                continue;
+            }
+            cancellation.checkpoint().await;
+            let declarations = latency::phase(Phase::IndexQuery, || self.symbol_index.declarations(&name));
+            for location in declarations {
+                let kind = match symbol_kind(&location) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                let uri = match Url::parse(&location.uri) {
+                    Ok(uri) => uri,
+                    Err(_) => continue,
+                };
+                #[allow(deprecated)]
+                symbols.push(SymbolInformation {
+                    name: name.clone(),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location { uri, range: location.range },
+                    container_name: None,
+                });
+            }
+        }
+        symbols.extend(self.heuristic_workspace_symbols(query, cancellation).await);
+        // parsed symbols (without a container) go before heuristic ones of the same score
+        symbols.sort_by_cached_key(|symbol| {
+            (
+                std::cmp::Reverse(fuzzy_score(query, &symbol.name)),
+                symbol.container_name.is_some(),
+                symbol.name.clone(),
+                symbol.location.uri.to_string(),
+            )
+        });
+        return symbols;
+    }
+
+    /// Declarations of `name` in documents other than `uri` that are visible from it, of one of
+    /// `kinds` when it's not empty. Only classes, methods and fields are visible across
+    /// documents.
+    pub fn indexed_definitions(&self, uri: &Url, name: &str, kinds: &[SymbolKind]) -> Vec<Location> {
+        return latency::phase(Phase::IndexQuery, || {
+            let mut locations: Vec<Location> = self
+                .symbol_index
+                .declarations(name)
+                .into_iter()
+                .filter(|location| location.uri != uri.as_str() && source_set::is_visible(uri.as_str(), &location.uri))
+                .filter(|location| match symbol_kind(location) {
+                    Some(kind) => kinds.is_empty() || kinds.contains(&kind),
+                    None => false,
+                })
+                .filter_map(|location| {
+                    let target_uri = Url::parse(&location.uri).ok()?;
+                    return Some(Location {
+                        uri: target_uri,
+                        range: location.range,
+                    });
+                })
+                .collect();
+            locations.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()).then_with(|| a.range.start.cmp(&b.range.start)));
+            return locations;
+        });
+    }
+}
+
+/// The kind of symbol a declaration visible across documents is, `None` for other declarations.
+fn symbol_kind(location: &TokenLocation) -> Option<SymbolKind> {
+    return match location.token_type {
+        TokenType::ClassName => Some(SymbolKind::CLASS),
+        TokenType::MethodName(_) => Some(SymbolKind::METHOD),
+        TokenType::MemberVariable(_) if location.modifiers.is_static && location.modifiers.is_final => Some(SymbolKind::CONSTANT),
+        TokenType::MemberVariable(_) => Some(SymbolKind::FIELD),
+        _ => None,
+    };
+}
@@ -0,0 +1,110 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier};
+use tree_sitter::Node;
+
+use crate::error::{self, Error};
+use crate::line_index::LineIndex;
+use crate::Backend;
+
+/// Parameters of the `javals/syntaxTree` request: the document whose syntax tree to show, or
+/// only the smallest node spanning `range` in it, in `format`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeParams {
+    pub text_document: TextDocumentIdentifier,
+    #[serde(default)]
+    pub range: Option<Range>,
+    #[serde(default)]
+    pub format: SyntaxTreeFormat,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SyntaxTreeFormat {
+    /// tree-sitter's S-expression of the named nodes, with their field names
+    #[default]
+    SExpression,
+    /// every node, anonymous ones included, with its range
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SyntaxTree {
+    SExpression(String),
+    Json(SyntaxNode),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxNode {
+    pub kind: String,
+    // the field of its parent the node is in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<&'static str>,
+    pub named: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub error: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
+    pub range: Range,
+    // the text of a leaf
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub children: Vec<SyntaxNode>,
+}
+
+impl Backend {
+    /// Handles `javals/syntaxTree`, which shows the syntax tree the server parsed a document
+    /// into, for bug reports and for working on the server.
+    pub async fn syntax_tree(&self, params: SyntaxTreeParams) -> Result<SyntaxTree> {
+        debug!("syntax_tree {} {:?}", params.text_document.uri, params.range);
+        let syntax_tree = async {
+            self.pending_changes.settled().await;
+            return self.document_syntax_tree(&params);
+        };
+        return error::guarded("javals/syntaxTree", syntax_tree).await;
+    }
+
+    fn document_syntax_tree(&self, params: &SyntaxTreeParams) -> Result<SyntaxTree> {
+        let uri = &params.text_document.uri;
+        let (tree, source_text) = self.document_source(uri.as_str()).ok_or_else(|| Error::UnknownDocument(uri.to_string()))?;
+        let lines = LineIndex::new(&source_text);
+        let node = match params.range {
+            Some(range) => {
+                let (start, end) = (lines.point(range.start), lines.point(range.end));
+                tree.root_node().named_descendant_for_point_range(start, end).unwrap_or(tree.root_node())
+            }
+            None => tree.root_node(),
+        };
+        return Ok(match params.format {
+            SyntaxTreeFormat::SExpression => SyntaxTree::SExpression(node.to_sexp()),
+            SyntaxTreeFormat::Json => SyntaxTree::Json(syntax_node(node, None, &source_text, &lines)),
+        });
+    }
+}
+
+fn syntax_node(node: Node, field: Option<&'static str>, source: &str, lines: &LineIndex) -> SyntaxNode {
+    let mut children = Vec::new();
+    let mut cursor = node.walk();
+    if cursor.goto_first_child() {
+        loop {
+            children.push(syntax_node(cursor.node(), cursor.field_name(), source, lines));
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    return SyntaxNode {
+        kind: node.kind().to_string(),
+        field,
+        named: node.is_named(),
+        error: node.is_error(),
+        missing: node.is_missing(),
+        range: lines.range(node.start_position(), node.end_position()),
+        text: (node.child_count() == 0).then(|| source[node.byte_range()].to_string()),
+        children,
+    };
+}
@@ -0,0 +1,360 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use log::info;
+use tokio::sync::{Mutex, Notify};
+use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
+use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, Url};
+use tree_sitter::InputEdit;
+
+use crate::error::Recover;
+use crate::line_index::LineIndex;
+use crate::literals;
+use crate::{Backend, TextDocumentItem};
+
+/// How long a changed document waits for more changes before it's parsed again, by default.
+const DEFAULT_REINDEX_DELAY_MS: u64 = 200;
+
+/// The changes to open documents being applied. Their documents are parsed off the async
+/// runtime, so other requests and notifications are handled meanwhile: changes are applied one
+/// at a time, in the order they arrive, and requests wait for them to be stored, so they answer
+/// from the text the client has. A changed document waits a while for the changes that follow
+/// while typing before it's parsed again, unless a request is waiting for it.
+#[derive(Debug)]
+pub struct PendingChanges {
+    order: Mutex<()>,
+    count: AtomicUsize,
+    settled: Notify,
+    // requests waiting for the changes to be applied, which cut the wait for more changes short
+    waiting: AtomicUsize,
+    flush: Notify,
+    delay_ms: AtomicU64,
+}
+
+impl Default for PendingChanges {
+    fn default() -> PendingChanges {
+        return PendingChanges {
+            order: Mutex::new(()),
+            count: AtomicUsize::new(0),
+            settled: Notify::new(),
+            waiting: AtomicUsize::new(0),
+            flush: Notify::new(),
+            delay_ms: AtomicU64::new(DEFAULT_REINDEX_DELAY_MS),
+        };
+    }
+}
+
+impl PendingChanges {
+    /// Awaits `change` once the changes that arrived before it are applied.
+    pub async fn run<F: Future>(&self, change: F) -> F::Output {
+        // counts the change as applied even if it panics or is dropped
+        let _pending = Counted::new(&self.count, Some(&self.settled));
+        let _order = self.order.lock().await;
+        return change.await;
+    }
+
+    /// Waits for more changes to follow, for the reindex delay or until a request waits for the
+    /// changes to be applied, counting as a change meanwhile.
+    pub async fn debounce(&self) {
+        let _pending = Counted::new(&self.count, Some(&self.settled));
+        let flushed = self.flush.notified();
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        let delay = Duration::from_millis(self.delay_ms.load(Ordering::Relaxed));
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = flushed => {}
+        }
+    }
+
+    /// Waits until no change is being applied, cutting short the wait of changed documents for
+    /// more changes.
+    pub async fn settled(&self) {
+        let _waiting = Counted::new(&self.waiting, None);
+        loop {
+            let settled = self.settled.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            self.flush.notify_waiters();
+            settled.await;
+        }
+    }
+
+    /// How many changes are being applied or waiting to be.
+    pub fn pending(&self) -> usize {
+        return self.count.load(Ordering::SeqCst);
+    }
+
+    pub fn set_delay(&self, delay: Duration) {
+        self.delay_ms.store(delay.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counts one in `count` for as long as it lives, notifying `idle` once the count is back to
+/// zero.
+struct Counted<'a> {
+    count: &'a AtomicUsize,
+    idle: Option<&'a Notify>,
+}
+
+impl<'a> Counted<'a> {
+    fn new(count: &'a AtomicUsize, idle: Option<&'a Notify>) -> Counted<'a> {
+        count.fetch_add(1, Ordering::SeqCst);
+        return Counted { count, idle };
+    }
+}
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            if let Some(idle) = self.idle {
+                idle.notify_waiters();
+            }
+        }
+    }
+}
+
+impl Backend {
+    /// Awaits `request`, timed and traced as a `method` request, once the changes to documents
+    /// that arrived before it are stored.
+    pub async fn answer<F>(&self, method: &'static str, request: F) -> F::Output
+    where
+        F: Future,
+        F::Output: Recover,
+    {
+        let request = async {
+            self.pending_changes.settled().await;
+            return request.await;
+        };
+        return self.traced(method, request).await;
+    }
+
+    /// Applies `changes` to the text of the document at `uri`, in order, and stores it as
+    /// `version` of the document right away, ahead of parsing it. The stored syntax tree is
+    /// edited along with the text, so reparsing reuses the parts that didn't change. A change
+    /// replacing the whole text edits it over the span between the prefix and suffix the old and
+    /// new text share. Ignored when `version` isn't newer than the stored one, since the changes
+    /// were made to text the document no longer has, or when the document isn't open and the
+    /// changes don't replace it.
+    pub fn apply_content_changes(&self, uri: &Url, version: i32, changes: Vec<TextDocumentContentChangeEvent>) {
+        if let Some(current_version) = self.document_version(uri.as_str()) {
+            if version <= current_version {
+                info!("ignoring version {} of {}, which is at version {}", version, uri, current_version);
+                return;
+            }
+        }
+        let mut text = self.document_map.get(uri.as_str()).map(|text| text.clone());
+        // respelling can change the text away from the edits the stored tree went through, so
+        // the tree of a respelled text isn't kept for reparsing to reuse
+        let was_respelled = text.as_ref().map(|text| literals::parseable_text(text) != text.as_str()).unwrap_or(false);
+        let mut tree = self.parsed_document_map.get(uri.as_str()).map(|tree| tree.clone()).filter(|_| !was_respelled);
+        for change in changes {
+            let current = match (text.as_mut(), change.range) {
+                (Some(current), _) => current,
+                (None, None) => {
+                    text = Some(change.text);
+                    continue;
+                }
+                (None, Some(_)) => {
+                    info!("change to {} before it was opened", uri);
+                    return;
+                }
+            };
+            let edit = apply_change(current, &change);
+            if let Some(tree) = tree.as_mut() {
+                tree.edit(&edit);
+            }
+        }
+        let text = match text {
+            Some(text) => text,
+            None => return,
+        };
+        self.document_map.insert(uri.to_string(), text);
+        match tree {
+            Some(tree) => self.parsed_document_map.insert(uri.to_string(), tree),
+            None => self.parsed_document_map.remove(uri.as_str()).map(|(_, tree)| tree),
+        };
+        self.document_versions.insert(uri.to_string(), version);
+    }
+
+    /// Parses and indexes the stored text of the document at `uri` again, unless the document
+    /// has moved on from `version`, in which case the change that moved it on does.
+    pub async fn reparse_document(&self, uri: &Url, version: i32) {
+        if self.document_version(uri.as_str()) != Some(version) {
+            return;
+        }
+        let text = match self.document_map.get(uri.as_str()) {
+            Some(text) => text.clone(),
+            None => return,
+        };
+        let tree = self.parsed_document_map.get(uri.as_str()).map(|tree| tree.clone());
+        self.on_change(TextDocumentItem {
+            uri: uri.clone(),
+            text,
+            version,
+            tree,
+        })
+        .await;
+    }
+
+    /// Goes back to the file on disk once the client closes the document at `uri`: a workspace
+    /// file is indexed as it's saved, and the text, tree and declarations of any other document
+    /// are forgotten.
+    pub async fn close_document(&self, uri: &Url) {
+        let affected_uris = match self.index_from_disk(uri) {
+            Some(affected_uris) => affected_uris,
+            None => self.remove_document(uri.as_str()),
+        };
+        self.publish_affected_diagnostics(affected_uris).await;
+    }
+
+    /// The version of the open document at `uri` its stored text, tree and index entries are of.
+    pub fn document_version(&self, uri: &str) -> Option<i32> {
+        return self.document_versions.get(uri).map(|version| *version);
+    }
+
+    /// Fails with `ContentModified` when the document at `uri` has moved on from `version`, for
+    /// requests that await something between reading the document and answering.
+    pub fn ensure_version(&self, uri: &str, version: Option<i32>) -> Result<()> {
+        let current_version = self.document_version(uri);
+        if current_version != version {
+            info!("{} changed from version {:?} to {:?} during the request", uri, version, current_version);
+            return Err(Error::new(ErrorCode::ContentModified));
+        }
+        return Ok(());
+    }
+}
+
+/// Whether the document at `uri` is a `.java` file. Documents the client opens in other
+/// languages, like the build files and docs of a mixed project, are left alone unless it opens
+/// them as Java.
+pub fn is_java_file(uri: &Url) -> bool {
+    return uri.path().ends_with(".java");
+}
+
+/// Applies `change` to `text`, returning the edit it makes for the syntax tree of the text.
+fn apply_change(text: &mut String, change: &TextDocumentContentChangeEvent) -> InputEdit {
+    let lines = LineIndex::new(text);
+    let (start_byte, old_end_byte, inserted) = match change.range {
+        Some(range) => {
+            let start_byte = lines.offset(range.start);
+            let old_end_byte = lines.offset(range.end).max(start_byte);
+            (start_byte, old_end_byte, change.text.as_str())
+        }
+        None => {
+            let (prefix, suffix) = common_affixes(text, &change.text);
+            (prefix, text.len() - suffix, &change.text[prefix..change.text.len() - suffix])
+        }
+    };
+    let (start_position, old_end_position) = (lines.point_at(start_byte), lines.point_at(old_end_byte));
+    text.replace_range(start_byte..old_end_byte, inserted);
+    let new_end_byte = start_byte + inserted.len();
+    return InputEdit {
+        start_byte,
+        old_end_byte,
+        new_end_byte,
+        start_position,
+        old_end_position,
+        new_end_position: LineIndex::new(text).point_at(new_end_byte),
+    };
+}
+
+/// The lengths in bytes of the longest prefix and suffix `old` and `new` share, not overlapping
+/// each other and not splitting a character.
+fn common_affixes(old: &str, new: &str) -> (usize, usize) {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = old_bytes.iter().zip(new_bytes).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old_bytes
+        .iter()
+        .rev()
+        .zip(new_bytes.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) {
+        suffix -= 1;
+    }
+    return (prefix, suffix);
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    use tree_sitter::{Parser, Tree};
+
+    use super::{apply_change, common_affixes};
+
+    fn parse(text: &str, old_tree: Option<&Tree>) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_java::language()).unwrap();
+        return parser.parse(text, old_tree).unwrap();
+    }
+
+    fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        return TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            }),
+            range_length: None,
+            text: text.to_string(),
+        };
+    }
+
+    #[test]
+    fn applies_incremental_changes_in_order() {
+        let mut text = "class A {\n    int a;\n}\n".to_string();
+        let mut tree = parse(&text, None);
+        // each change is made to the text the ones before it left
+        let changes = [
+            change((1, 8), (1, 9), "count"),
+            change((1, 13), (1, 13), " = 1"),
+            change((2, 0), (2, 0), "    void run() {}\n"),
+            change((0, 6), (0, 7), "Counter"),
+        ];
+        for change in &changes {
+            let edit = apply_change(&mut text, change);
+            tree.edit(&edit);
+        }
+        assert_eq!(text, "class Counter {\n    int count = 1;\n    void run() {}\n}\n");
+        assert_eq!(tree.root_node().end_byte(), text.len());
+        let reparsed = parse(&text, Some(&tree));
+        assert!(!reparsed.root_node().has_error());
+        assert_eq!(reparsed.root_node().to_sexp(), parse(&text, None).root_node().to_sexp());
+    }
+
+    #[test]
+    fn finds_the_affixes_a_whole_text_change_keeps() {
+        assert_eq!(common_affixes("int a = 1;", "int b = 1;"), (4, 5));
+        // they don't overlap when text is repeated or removed
+        assert_eq!(common_affixes("aaa", "aa"), (2, 0));
+        assert_eq!(common_affixes("", "new"), (0, 0));
+        assert_eq!(common_affixes("same", "same"), (4, 0));
+        // é and è share their first byte, and ä and ë their last
+        assert_eq!(common_affixes("\u{e9}", "\u{e8}"), (0, 0));
+        assert_eq!(common_affixes("x\u{e4}", "x\u{eb}"), (1, 0));
+    }
+
+    #[test]
+    fn edits_the_tree_for_a_whole_text_change() {
+        let mut text = "class A {\n    int a;\n}\n".to_string();
+        let mut tree = parse(&text, None);
+        let replacement = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "class A {\n    int a;\n    int b;\n}\n".to_string(),
+        };
+        let edit = apply_change(&mut text, &replacement);
+        assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (21, 21, 32));
+        tree.edit(&edit);
+        let reparsed = parse(&text, Some(&tree));
+        assert_eq!(reparsed.root_node().to_sexp(), parse(&text, None).root_node().to_sexp());
+    }
+}
@@ -0,0 +1,77 @@
+use std::future::Future;
+use std::sync::PoisonError;
+
+use log::debug;
+use serde::Serialize;
+use tower_lsp::lsp_types::notification::LogTrace;
+use tower_lsp::lsp_types::{LogTraceParams, SetTraceParams, TraceValue};
+
+use crate::error::Recover;
+use crate::latency::{self, SlowRequest, Timing};
+use crate::Backend;
+
+/// The `telemetry/event` a request over its budget is reported with, when the
+/// `latency.telemetry` setting asks for them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SlowRequestEvent<'a> {
+    event: &'static str,
+    method: &'static str,
+    #[serde(flatten)]
+    slow_request: &'a SlowRequest,
+}
+
+impl Backend {
+    /// Handles `$/setTrace`, which changes how much the server traces with `$/logTrace`, as the
+    /// `trace` of the initialize request first set it.
+    pub async fn set_trace(&self, params: SetTraceParams) {
+        debug!("set_trace {:?}", params.value);
+        *self.trace.write().unwrap_or_else(PoisonError::into_inner) = params.value;
+    }
+
+    /// Awaits `request`, timed as a `method` request, and traces how long it took when the client
+    /// asked for traces: the whole time with `messages`, and the time of each phase too with
+    /// `verbose`. A request over its budget is also reported as a telemetry event, if the
+    /// settings say so.
+    pub async fn traced<F>(&self, method: &'static str, request: F) -> F::Output
+    where
+        F: Future,
+        F::Output: Recover,
+    {
+        let (output, timing) = self.latency.timed(method, request).await;
+        self.log_trace(method, &timing).await;
+        if let Some(slow_request) = &timing.slow_request {
+            let is_reported = self.config.read().unwrap_or_else(PoisonError::into_inner).slow_request_telemetry;
+            if is_reported {
+                let event = SlowRequestEvent {
+                    event: "slowRequest",
+                    method,
+                    slow_request,
+                };
+                self.client.telemetry_event(event).await;
+            }
+        }
+        return output;
+    }
+
+    async fn log_trace(&self, method: &'static str, timing: &Timing) {
+        let trace = *self.trace.read().unwrap_or_else(PoisonError::into_inner);
+        let verbose = match trace {
+            TraceValue::Off => return,
+            TraceValue::Messages => None,
+            TraceValue::Verbose => Some(
+                timing
+                    .phase_durations()
+                    .iter()
+                    .map(|(phase, duration)| format!("{}: {}ms", phase, latency::millis(*duration)))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+        };
+        let params = LogTraceParams {
+            message: format!("{} handled in {}ms", method, latency::millis(timing.elapsed)),
+            verbose,
+        };
+        self.client.send_notification::<LogTrace>(params).await;
+    }
+}
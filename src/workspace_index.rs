@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::time::Instant;
+
+use log::info;
+use serde::Serialize;
+use tower_lsp::lsp_types::*;
+use tree_sitter::{Parser, Tree};
+
+use crate::error;
+use crate::index_cache::{CachedFile, WorkspaceCache};
+use crate::lombok::LombokAnnotations;
+use crate::progress::WorkDone;
+use crate::{literals, scan_document, Backend, ScannedDocument};
+
+/// Files are read, parsed and scanned off the async runtime this many at a time on each thread.
+const BATCH_SIZE: usize = 32;
+
+/// The last time workspace files were indexed in bulk, at startup or for added folders.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexingRun {
+    pub files: usize,
+    pub indexed_files: usize,
+    pub duration_ms: u64,
+    // false when the user cancelled it
+    pub completed: bool,
+}
+
+impl Backend {
+    /// Parses and indexes every workspace file the search index found, so navigation and
+    /// symbol search cover files that were never opened. Text search stands in for the index
+    /// until it's done. Files that haven't changed since they were last indexed are restored
+    /// from the workspace cache rather than parsed and scanned, unless `use_cache` is false, and
+    /// the cache is written anew once every file is indexed.
+    pub async fn index_workspace(&self, use_cache: bool) {
+        let paths = self.search_index.read().unwrap_or_else(PoisonError::into_inner).files();
+        let roots = self.workspace_roots.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let lombok_annotations = self.config.read().unwrap_or_else(PoisonError::into_inner).lombok_annotations.clone();
+        let cache = if use_cache {
+            let (cache_roots, cache_lombok_annotations) = (roots.clone(), lombok_annotations.clone());
+            tokio::task::spawn_blocking(move || WorkspaceCache::read(&cache_roots, &cache_lombok_annotations))
+                .await
+                .unwrap_or_default()
+        } else {
+            WorkspaceCache::default()
+        };
+        let cached_files = match self.index_files(paths, Arc::new(cache)).await {
+            Some(cached_files) => cached_files,
+            None => return,
+        };
+        self.index_ready.store(true, Ordering::Release);
+        let _ = tokio::task::spawn_blocking(move || WorkspaceCache::write(&roots, lombok_annotations, cached_files)).await;
+    }
+
+    /// Rebuilds the index from what is on disk, after the files to leave out changed say, returning
+    /// the number of workspace files. Files that are no longer in the workspace are removed.
+    pub async fn reindex_workspace(&self) -> usize {
+        let previous_paths = self.search_index.read().unwrap_or_else(PoisonError::into_inner).files();
+        let indexed_files = self.build_search_index().await;
+        let mut affected_uris = Vec::new();
+        {
+            let index = self.search_index.read().unwrap_or_else(PoisonError::into_inner);
+            for path in previous_paths.iter().filter(|path| !index.contains_file(path)) {
+                let uri = match Url::from_file_path(path) {
+                    Ok(uri) => uri,
+                    Err(_) => continue,
+                };
+                if self.document_version(uri.as_str()).is_none() {
+                    affected_uris.extend(self.remove_document(uri.as_str()));
+                }
+            }
+        }
+        self.publish_affected_diagnostics(affected_uris).await;
+        self.index_workspace(true).await;
+        return indexed_files;
+    }
+
+    /// Drops everything indexed and scans the workspace from disk again, for when the index got
+    /// out of sync with changes made outside the client, returning the number of workspace
+    /// files. Open documents are indexed again from the client's text, and their diagnostics
+    /// published afresh.
+    pub async fn rebuild_workspace_index(&self) -> usize {
+        self.index_ready.store(false, Ordering::Release);
+        let mut open_documents = Vec::new();
+        for uri in self.indexed_uris() {
+            match self.document_version(&uri) {
+                Some(version) => open_documents.push((uri, version)),
+                None => {
+                    self.remove_document(&uri);
+                }
+            }
+        }
+        self.memoized_diagnostics.clear();
+        self.revisions.declarations_changed();
+        let indexed_files = self.build_search_index().await;
+        for (uri, version) in open_documents {
+            if let Ok(uri) = Url::parse(&uri) {
+                self.pending_changes.run(self.reparse_document(&uri, version)).await;
+            }
+        }
+        self.index_workspace(false).await;
+        return indexed_files;
+    }
+
+    /// Handles the `javals/reindexWorkspace` notification, which does what the command of the
+    /// same name does for clients that don't wait for the answer.
+    pub async fn rebuild_workspace_index_notification(&self) {
+        let rebuild = async {
+            self.rebuild_workspace_index().await;
+        };
+        error::guarded("javals/reindexWorkspace", rebuild).await;
+    }
+
+    /// Adds the roots of workspace folders the client added to the index, and removes the files
+    /// that were only under the roots of folders it removed.
+    pub async fn change_workspace_folders(&self, event: WorkspaceFoldersChangeEvent) {
+        let folder_roots = |folders: Vec<WorkspaceFolder>| -> Vec<PathBuf> {
+            return folders.iter().filter_map(|folder| folder.uri.to_file_path().ok()).collect();
+        };
+        let (added_roots, removed_roots) = (folder_roots(event.added), folder_roots(event.removed));
+        let remaining_roots = {
+            let mut roots = self.workspace_roots.write().unwrap_or_else(PoisonError::into_inner);
+            roots.retain(|root| !removed_roots.contains(root));
+            for root in &added_roots {
+                if !roots.contains(root) {
+                    roots.push(root.clone());
+                }
+            }
+            roots.clone()
+        };
+        info!("workspace roots are now {:?}", remaining_roots);
+        let mut indexed_roots = remaining_roots;
+        indexed_roots.extend(self.config.read().unwrap_or_else(PoisonError::into_inner).project.source_roots.clone());
+        let mut affected_uris = Vec::new();
+        for path in self.remove_roots_from_search_index(&removed_roots, &indexed_roots) {
+            let uri = match Url::from_file_path(&path) {
+                Ok(uri) => uri,
+                Err(_) => continue,
+            };
+            if self.document_version(uri.as_str()).is_none() {
+                affected_uris.extend(self.remove_document(uri.as_str()));
+            }
+        }
+        self.publish_affected_diagnostics(affected_uris).await;
+        let added_files = self.add_roots_to_search_index(added_roots).await;
+        self.index_files(added_files, Arc::default()).await;
+    }
+
+    /// Parses and indexes the files at `paths`, in batches spread across the blocking thread
+    /// pool with requests served in between, showing the progress to clients that support it.
+    /// Each batch is searchable as soon as it's stored. Documents the client has open are left as
+    /// it has them. Files that haven't changed since they were cached are restored from
+    /// `cache`. Returns what's to be cached of the files, or `None` when the user cancelled.
+    async fn index_files(&self, paths: Vec<PathBuf>, cache: Arc<WorkspaceCache>) -> Option<HashMap<PathBuf, CachedFile>> {
+        let mut cached_files = HashMap::new();
+        if paths.is_empty() {
+            return Some(cached_files);
+        }
+        let total = paths.len();
+        let start = Instant::now();
+        self.indexing.store(true, Ordering::Release);
+        let progress = self.begin_progress("indexWorkspace", "Indexing workspace", None).await;
+        let threads = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        let lombok_annotations = self.config.read().unwrap_or_else(PoisonError::into_inner).lombok_annotations.clone();
+        let mut indexed = 0;
+        for batch in paths.chunks(BATCH_SIZE * threads) {
+            if progress.as_ref().map(WorkDone::is_cancelled).unwrap_or(false) {
+                break;
+            }
+            let handles: Vec<_> = batch
+                .chunks(BATCH_SIZE)
+                .map(|share| {
+                    let (share, lombok_annotations, cache) = (share.to_vec(), lombok_annotations.clone(), cache.clone());
+                    return tokio::task::spawn_blocking(move || {
+                        share.into_iter().filter_map(|path| read_and_scan(path, &lombok_annotations, &cache)).collect::<Vec<_>>()
+                    });
+                })
+                .collect();
+            for handle in handles {
+                for mut file in handle.await.unwrap_or_default() {
+                    if let Some(cached) = file.cached.take() {
+                        cached_files.insert(file.path.clone(), cached);
+                    }
+                    // opened while indexing, so the client's text is newer
+                    if self.document_version(file.uri.as_str()).is_some() {
+                        continue;
+                    }
+                    self.index_file(file);
+                }
+            }
+            indexed += batch.len();
+            if let Some(progress) = &progress {
+                let current_file = batch.last().and_then(|path| path.file_name()).map(|name| name.to_string_lossy());
+                let message = format!("{}/{} files, {}", indexed, total, current_file.unwrap_or_default());
+                progress.report(message, indexed * 100 / total.max(1)).await;
+            }
+            tokio::task::yield_now().await;
+        }
+        let completed = indexed == total;
+        info!("indexed {} of {} workspace files", indexed, total);
+        *self.last_indexing.write().unwrap_or_else(PoisonError::into_inner) = Some(IndexingRun {
+            files: total,
+            indexed_files: indexed,
+            duration_ms: start.elapsed().as_millis() as u64,
+            completed,
+        });
+        self.indexing.store(false, Ordering::Release);
+        if let Some(progress) = progress {
+            let message = if completed {
+                format!("{} files indexed", total)
+            } else {
+                format!("cancelled after {}/{} files", indexed, total)
+            };
+            progress.end(message).await;
+        }
+        // diagnostics of open documents may depend on files that weren't indexed before
+        let open_uris: Vec<String> = self.document_versions.iter().map(|entry| entry.key().clone()).collect();
+        self.publish_affected_diagnostics(open_uris).await;
+        return completed.then_some(cached_files);
+    }
+
+    /// Keeps the index in step with `.java` files created, changed or deleted on disk, by a
+    /// checkout or a code generator say. Documents the client has open are left as it has them.
+    pub async fn update_workspace_index(&self, changes: &[FileEvent]) {
+        let mut affected_uris = Vec::new();
+        for change in changes {
+            if !change.uri.path().ends_with(".java") || self.document_version(change.uri.as_str()).is_some() {
+                continue;
+            }
+            info!("{} {:?} on disk", change.uri, change.typ);
+            let file_affected_uris = match change.typ {
+                FileChangeType::DELETED => self.remove_document(change.uri.as_str()),
+                _ => self.index_from_disk(&change.uri).unwrap_or_default(),
+            };
+            affected_uris.extend(file_affected_uris);
+        }
+        affected_uris.sort();
+        affected_uris.dedup();
+        self.publish_affected_diagnostics(affected_uris).await;
+    }
+
+    /// Indexes the workspace file at `uri` as it is on disk, returning the documents whose
+    /// diagnostics that affects. `None` when it isn't a workspace file or can't be read.
+    pub fn index_from_disk(&self, uri: &Url) -> Option<Vec<String>> {
+        let path = uri.to_file_path().ok()?;
+        if !self.search_index.read().unwrap_or_else(PoisonError::into_inner).contains_file(&path) {
+            return None;
+        }
+        let lombok_annotations = self.config.read().unwrap_or_else(PoisonError::into_inner).lombok_annotations.clone();
+        let file = read_and_scan(path, &lombok_annotations, &WorkspaceCache::default())?;
+        return Some(self.index_file(file));
+    }
+
+    fn index_file(&self, file: ScannedFile) -> Vec<String> {
+        let uri = file.uri.clone();
+        let store = || {
+            return match file.source {
+                FileSource::Parsed(text, tree) => self.store_document(&file.uri, text, tree, file.scanned, None),
+                FileSource::Cached(package) => self.store_cached_document(&file.uri, file.scanned, &package),
+            };
+        };
+        // a file the indexer can't handle shouldn't take the server down with it
+        return match panic::catch_unwind(AssertUnwindSafe(store)) {
+            Ok(affected_uris) => affected_uris,
+            Err(_) => {
+                info!("unable to index {}", uri);
+                Vec::new()
+            }
+        };
+    }
+
+    /// Removes the document at `uri` from every index, returning the documents whose diagnostics
+    /// that affects.
+    pub fn remove_document(&self, uri: &str) -> Vec<String> {
+        self.revisions.document_removed(uri);
+        self.memoized_diagnostics.remove(uri);
+        self.document_map.remove(uri);
+        self.parsed_document_map.remove(uri);
+        self.retained_trees.forget(uri);
+        self.document_versions.remove(uri);
+        self.symbol_index.remove(uri);
+        self.type_declaration_map.remove(uri);
+        self.module_declarations.remove(uri);
+        self.unhandled_nodes.remove(uri);
+        return self.class_registry.remove_source(uri);
+    }
+}
+
+/// A workspace file scanned or restored from the workspace cache, ready to be stored.
+struct ScannedFile {
+    path: PathBuf,
+    uri: Url,
+    source: FileSource,
+    scanned: ScannedDocument,
+    // what the workspace cache is to keep of it
+    cached: Option<CachedFile>,
+}
+
+/// What a workspace file is stored from besides what scanning it found.
+enum FileSource {
+    /// Its text, and the tree it was parsed into.
+    Parsed(String, Tree),
+    /// Nothing but the workspace cache, which keeps the package it declares.
+    Cached(String),
+}
+
+/// Reads, parses and scans the file at `path`. Files that haven't changed since they were in
+/// `cache` are restored from it instead, without being parsed, or read at all when they have the
+/// same size and modification time.
+fn read_and_scan(path: PathBuf, lombok_annotations: &LombokAnnotations, cache: &WorkspaceCache) -> Option<ScannedFile> {
+    let uri = Url::from_file_path(&path).ok()?;
+    let metadata = fs::metadata(&path).ok()?;
+    let restored_file = |cached: CachedFile| {
+        let scanned = cached.restore(&uri)?;
+        return Some(ScannedFile {
+            path: path.clone(),
+            uri: uri.clone(),
+            source: FileSource::Cached(cached.package().to_string()),
+            scanned,
+            cached: Some(cached),
+        });
+    };
+    let cached = cache.file(&path);
+    if let Some(file) = cached.filter(|cached| cached.is_unchanged(&metadata)).and_then(|cached| restored_file(cached.clone())) {
+        return Some(file);
+    }
+    let text = fs::read_to_string(&path).ok()?;
+    if let Some(file) = cached.filter(|cached| cached.has_text(&text)).and_then(|cached| restored_file(cached.touched(&metadata))) {
+        return Some(file);
+    }
+    let tree = parse(&text)?;
+    let scan = || {
+        let mut scanned = scan_document(&uri, &text, &tree);
+        lombok_annotations.add_generated_members(&tree, &text, &mut scanned.type_declarations);
+        let cached = CachedFile::new(&metadata, &text, &tree, &scanned);
+        return (scanned, cached);
+    };
+    // a file the indexer can't handle shouldn't take the server down with it
+    let (scanned, cached) = match panic::catch_unwind(AssertUnwindSafe(scan)) {
+        Ok(scanned) => scanned,
+        Err(_) => {
+            info!("unable to index {}", uri);
+            return None;
+        }
+    };
+    return Some(ScannedFile {
+        path,
+        uri,
+        source: FileSource::Parsed(text, tree),
+        scanned,
+        cached: Some(cached),
+    });
+}
+
+pub fn read_and_parse(path: &Path) -> Option<(Url, String, Tree)> {
+    let text = fs::read_to_string(path).ok()?;
+    let uri = Url::from_file_path(path).ok()?;
+    let tree = parse(&text)?;
+    return Some((uri, text, tree));
+}
+
+fn parse(text: &str) -> Option<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_java::language()).ok()?;
+    return parser.parse(literals::parseable_text(text).as_ref(), None);
+}
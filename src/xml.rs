@@ -0,0 +1,196 @@
+/// An XML element, with just what build files need: names, nesting and text. Attributes,
+/// namespaces and processing instructions are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    pub name: String,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    /// The first child element named `name`.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        return self.children.iter().find(|child| child.name == name);
+    }
+
+    /// The child elements named `name`.
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        return self.children.iter().filter(move |child| child.name == name);
+    }
+
+    /// The trimmed text of the first child element named `name`, unless it's empty.
+    pub fn child_text(&self, name: &str) -> Option<String> {
+        let text = self.child(name)?.text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        return Some(text.to_string());
+    }
+
+    /// Follows `path`, child names from this element down.
+    pub fn descendant(&self, path: &[&str]) -> Option<&Element> {
+        let mut current = self;
+        for name in path {
+            current = current.child(name)?;
+        }
+        return Some(current);
+    }
+}
+
+/// Parses the root element of `text`. `None` when it isn't well-formed enough to find one.
+pub fn parse(text: &str) -> Option<Element> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let tag_start = match rest.find('<') {
+            Some(tag_start) => tag_start,
+            None => {
+                stack.last_mut()?.text.push_str(&unescape(rest));
+                break;
+            }
+        };
+        stack.last_mut()?.text.push_str(&unescape(&rest[..tag_start]));
+        rest = &rest[tag_start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = &comment[comment.find("-->")? + 3..];
+        } else if let Some(data) = rest.strip_prefix("<![CDATA[") {
+            let end = data.find("]]>")?;
+            stack.last_mut()?.text.push_str(&data[..end]);
+            rest = &data[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else if let Some(closing) = rest.strip_prefix("</") {
+            let end = closing.find('>')?;
+            let element = stack.pop()?;
+            if element.name != closing[..end].trim() || stack.is_empty() {
+                return None;
+            }
+            stack.last_mut()?.children.push(element);
+            rest = &closing[end + 1..];
+        } else {
+            let end = tag_end(rest)?;
+            let tag = &rest[1..end];
+            let (tag, self_closing) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let name = tag.split(|c: char| c.is_whitespace()).next().unwrap_or("");
+            let element = Element {
+                name: name.to_string(),
+                ..Element::default()
+            };
+            if self_closing {
+                stack.last_mut()?.children.push(element);
+            } else {
+                stack.push(element);
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+    if stack.len() != 1 {
+        return None;
+    }
+    return stack.pop()?.children.into_iter().next();
+}
+
+/// The index of the `>` closing the tag at the start of `text`, skipping over quoted attribute
+/// values.
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+    }
+    return None;
+}
+
+/// Replaces the predefined entities and character references in `text`.
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let replacement = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match replacement {
+            Some(replacement) => {
+                unescaped.push(replacement);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    return unescaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_elements_and_their_text() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- a comment with <tags> -->
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <groupId>org.example</groupId>
+  <build attribute="a > b"><sourceDirectory>src</sourceDirectory></build>
+  <modules>
+    <module>core</module>
+    <module>app</module>
+  </modules>
+  <empty/>
+</project>"#;
+        let project = parse(text).unwrap();
+        assert_eq!(project.name, "project");
+        assert_eq!(project.child_text("groupId").as_deref(), Some("org.example"));
+        assert_eq!(project.descendant(&["build", "sourceDirectory"]).unwrap().text, "src");
+        let modules: Vec<&str> = project.child("modules").unwrap().children("module").map(|module| module.text.as_str()).collect();
+        assert_eq!(modules, vec!["core", "app"]);
+        assert!(project.child("empty").is_some());
+        assert_eq!(project.child_text("empty"), None);
+        assert_eq!(project.child_text("missing"), None);
+    }
+
+    #[test]
+    fn unescapes_entities_and_keeps_character_data() {
+        let element = parse("<a>&lt;T&gt; &amp; &#65;&#x42; &unknown; <![CDATA[<raw> &amp;]]></a>").unwrap();
+        assert_eq!(element.text, "<T> & AB &unknown; <raw> &amp;");
+    }
+
+    #[test]
+    fn rejects_mismatched_and_unclosed_elements() {
+        assert!(parse("<a><b></a></b>").is_none());
+        assert!(parse("<a><b></b>").is_none());
+        assert!(parse("<a></a></b>").is_none());
+        assert!(parse("<a><!-- unclosed </a>").is_none());
+        assert!(parse("just text").is_none());
+    }
+}